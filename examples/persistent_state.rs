@@ -42,7 +42,7 @@
 use std::error::Error;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, ErrorKind, Read, Seek, Write};
-use std::os::unix::prelude::{AsRawFd, FromRawFd, IntoRawFd};
+use std::os::unix::prelude::{AsRawFd, OwnedFd};
 use std::result::Result;
 
 use libsystemd::activation;
@@ -55,6 +55,7 @@ fn create_and_store_persistent_state() -> Result<File, Box<dyn Error>> {
         .read(true)
         .write(true)
         .create(true)
+        .truncate(true)
         .open(&path)?;
     fs::remove_file(&path)?;
 
@@ -84,7 +85,7 @@ fn run() -> Result<i32, Box<dyn Error>> {
     let mut persistent_state = if let Some((fd, name)) = descriptors.pop() {
         println!("Fetched persistent state from systemd");
         if name == "persistent-state" {
-            unsafe { File::from_raw_fd(fd.into_raw_fd()) }
+            File::from(OwnedFd::from(fd))
         } else {
             let err = io::Error::new(ErrorKind::Other, "Got the wrong file descriptor.");
             return Err(Box::new(err));
@@ -19,7 +19,10 @@ fn main() {
         return;
     };
 
-    let timeout = daemon::watchdog_enabled(true).expect("watchdog disabled");
+    let timeout = daemon::watchdog_config(true)
+        .filter(|config| config.pid_matched())
+        .expect("watchdog disabled")
+        .timeout();
     for i in 0..20 {
         let _sent = daemon::notify(false, &[NotifyState::Watchdog]).expect("notify failed");
         println!("Notification #{} sent...", i);
@@ -0,0 +1,2365 @@
+use crate::errors::{Context, SdError};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::sys::socket::{getpeername, getsockname, getsockopt, sockopt};
+use nix::sys::socket::{AddressFamily, SockType, SockaddrLike, SockaddrStorage};
+use nix::sys::stat::fstat;
+use nix::unistd;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::{CString, OsStr, OsString};
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6, TcpListener, TcpStream, UdpSocket};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{self, Child, Command};
+use std::sync::Mutex;
+
+pub mod testing;
+
+/// Minimum FD number used by systemd for passing sockets.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Trait for checking the type of a file descriptor.
+pub trait IsType {
+    /// Returns true if a file descriptor is a FIFO.
+    fn is_fifo(&self) -> bool;
+
+    /// Returns true if a file descriptor is a special file.
+    fn is_special(&self) -> bool;
+
+    /// Returns true if a file descriptor is a `PF_INET` socket.
+    fn is_inet(&self) -> bool;
+
+    /// Returns true if a file descriptor is a `PF_UNIX` socket.
+    fn is_unix(&self) -> bool;
+
+    /// Returns true if a file descriptor is a POSIX message queue descriptor.
+    fn is_mq(&self) -> bool;
+
+    /// Returns true if a file descriptor is a `AF_VSOCK` socket.
+    fn is_vsock(&self) -> bool;
+
+    /// Returns true if a file descriptor is a `AF_NETLINK` socket.
+    fn is_netlink(&self) -> bool;
+}
+
+/// File descriptor passed by systemd to socket-activated services.
+///
+/// The descriptor is owned, and closed when this value is dropped. It can be
+/// converted into an [`OwnedFd`], or into a standard socket type after checking
+/// its kind (e.g. `TcpListener::try_from(fd)`).
+///
+/// Since it owns its descriptor, this type does not implement `Clone`
+/// anymore (unlike in 0.7 and earlier releases): use
+/// [`try_clone`](Self::try_clone) to duplicate it instead.
+///
+/// See <https://www.freedesktop.org/software/systemd/man/systemd.socket.html>.
+#[derive(Debug)]
+pub struct FileDescriptor(SocketFd);
+
+/// Possible types of sockets.
+#[derive(Debug)]
+enum SocketFd {
+    /// A FIFO named pipe (see `man 7 fifo`)
+    Fifo(OwnedFd),
+    /// A special file, such as character device nodes or special files in
+    /// `/proc` and `/sys`.
+    Special(OwnedFd),
+    /// A `PF_INET` socket, such as UDP/TCP sockets.
+    Inet(OwnedFd),
+    /// A `PF_UNIX` socket (see `man 7 unix`).
+    Unix(OwnedFd),
+    /// A POSIX message queue (see `man 7 mq_overview`).
+    Mq(OwnedFd),
+    /// A `AF_VSOCK` socket, for communication with virtual machines (see `man 7 vsock`).
+    Vsock(OwnedFd),
+    /// A `AF_NETLINK` socket (see `man 7 netlink`).
+    Netlink(OwnedFd),
+    /// An unknown descriptor (possibly invalid, use with caution).
+    Unknown(OwnedFd),
+}
+
+impl IsType for FileDescriptor {
+    fn is_fifo(&self) -> bool {
+        matches!(self.0, SocketFd::Fifo(_))
+    }
+
+    fn is_special(&self) -> bool {
+        matches!(self.0, SocketFd::Special(_))
+    }
+
+    fn is_unix(&self) -> bool {
+        matches!(self.0, SocketFd::Unix(_))
+    }
+
+    fn is_inet(&self) -> bool {
+        matches!(self.0, SocketFd::Inet(_))
+    }
+
+    fn is_mq(&self) -> bool {
+        matches!(self.0, SocketFd::Mq(_))
+    }
+
+    fn is_vsock(&self) -> bool {
+        matches!(self.0, SocketFd::Vsock(_))
+    }
+
+    fn is_netlink(&self) -> bool {
+        matches!(self.0, SocketFd::Netlink(_))
+    }
+}
+
+/// Check for file descriptors passed by systemd.
+///
+/// Invoked by socket activated daemons to check for file descriptors needed by the service.
+/// If `unset_env` is true, the environment variables used by systemd will be cleared.
+pub fn receive_descriptors(unset_env: bool) -> Result<Vec<FileDescriptor>, SdError> {
+    let (fds, _) = listen_env(unset_env)?;
+    socks_from_fds(fds)
+}
+
+/// Check for named file descriptors passed by systemd.
+///
+/// Like `receive_descriptors`, but this will also return a vector of names
+/// associated with each file descriptor.
+pub fn receive_descriptors_with_names(
+    unset_env: bool,
+) -> Result<Vec<(FileDescriptor, String)>, SdError> {
+    let (fds, fdnames) = listen_env(unset_env)?;
+
+    let fdnames = fdnames.context("failed to get LISTEN_FDNAMES")?;
+    let names = fdnames.split(':').map(String::from);
+    let vec = socks_from_fds(fds).context("failed to get sockets from file descriptor")?;
+    let out = vec.into_iter().zip(names).collect();
+
+    Ok(out)
+}
+
+/// Check for file descriptors passed by systemd, indexed by name.
+///
+/// Names are set through `FileDescriptorName=` in socket units (or `FDNAME=`
+/// for the file descriptor store), and multiple descriptors may share the same
+/// name. Descriptors without a name are indexed as `unknown`, like in
+/// `sd_listen_fds_with_names(3)`.
+pub fn receive_descriptors_map(
+    unset_env: bool,
+) -> Result<HashMap<String, Vec<FileDescriptor>>, SdError> {
+    let (fds, fdnames) = listen_env(unset_env)?;
+    let names = fd_names(fds, fdnames.as_deref());
+
+    let mut map: HashMap<String, Vec<FileDescriptor>> = HashMap::new();
+    for (fd, name) in socks_from_fds(fds)?.into_iter().zip(names) {
+        map.entry(name).or_default().push(fd);
+    }
+
+    Ok(map)
+}
+
+/// Return the first file descriptor passed by systemd with the given name.
+///
+/// Only the matching descriptor is taken ownership of, other ones are left
+/// untouched and can be requested later. The environment is not cleared, and
+/// requesting the same name again (or receiving all descriptors after it)
+/// returns an error, as each descriptor is only handed out once. Use
+/// [`receive_descriptors_map`] to get all descriptors at once.
+pub fn descriptor_named(name: &str) -> Result<Option<FileDescriptor>, SdError> {
+    let (fds, fdnames) = listen_env(false)?;
+    let position = fd_names(fds, fdnames.as_deref())
+        .iter()
+        .position(|fdname| fdname == name);
+
+    match position {
+        Some(position) => Ok(take_descriptors([position])?.pop()),
+        None => Ok(None),
+    }
+}
+
+/// Source of environment variables for socket activation.
+///
+/// This allows receiving descriptors based on an explicit snapshot of the
+/// environment, instead of the one of the current process.
+pub trait EnvSource {
+    /// Return the value of the variable `key`, if set.
+    fn get(&self, key: &str) -> Option<String>;
+}
+
+/// Environment of the current process.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProcessEnv;
+
+impl EnvSource for ProcessEnv {
+    fn get(&self, key: &str) -> Option<String> {
+        env::var(key).ok()
+    }
+}
+
+impl EnvSource for HashMap<String, String> {
+    fn get(&self, key: &str) -> Option<String> {
+        HashMap::get(self, key).cloned()
+    }
+}
+
+/// How to check `LISTEN_PID` before taking ownership of descriptors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PidCheck {
+    /// `LISTEN_PID` must match the current process (default).
+    #[default]
+    CurrentProcess,
+    /// `LISTEN_PID` must match the given PID.
+    Expect(u32),
+    /// `LISTEN_PID` is not checked, and may be unset.
+    ///
+    /// This is useful when descriptors are inherited across a PID namespace
+    /// boundary, where the manager-side PID is meaningless.
+    Skip,
+}
+
+/// Options for [`receive_descriptors_from_env`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReceiveOptions {
+    pid_check: PidCheck,
+}
+
+impl ReceiveOptions {
+    /// Create new options, checking `LISTEN_PID` against the current process.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how `LISTEN_PID` is checked.
+    pub fn pid_check(mut self, pid_check: PidCheck) -> Self {
+        self.pid_check = pid_check;
+        self
+    }
+}
+
+/// Check for named file descriptors, based on an explicit environment.
+///
+/// Like [`receive_descriptors_with_names`], but variables are read from
+/// `source` and the environment is never modified. Descriptors without a
+/// name are labeled as `unknown`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::activation::{self, PidCheck, ProcessEnv, ReceiveOptions};
+///
+/// let options = ReceiveOptions::new().pid_check(PidCheck::Skip);
+/// let fds = activation::receive_descriptors_from_env(&ProcessEnv, &options)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn receive_descriptors_from_env(
+    source: &impl EnvSource,
+    options: &ReceiveOptions,
+) -> Result<Vec<(FileDescriptor, String)>, SdError> {
+    let (fds, fdnames) = parse_listen_env(source, options)?;
+    let names = fd_names(fds, fdnames.as_deref());
+    let vec = socks_from_fds(fds).context("failed to get sockets from file descriptor")?;
+
+    Ok(vec.into_iter().zip(names).collect())
+}
+
+/// File descriptors passed by systemd, split by origin.
+///
+/// See [`receive_descriptors_partitioned`].
+#[derive(Debug, Default)]
+pub struct PartitionedDescriptors {
+    sockets: Vec<(FileDescriptor, String)>,
+    restored: Vec<(FileDescriptor, String)>,
+}
+
+impl PartitionedDescriptors {
+    /// Split named descriptors, based on the names used for the fd store.
+    pub fn new<S: AsRef<str>>(fds: Vec<(FileDescriptor, String)>, fdstore_names: &[S]) -> Self {
+        let (restored, sockets) = fds.into_iter().partition(|(_, name)| {
+            fdstore_names
+                .iter()
+                .any(|fdstore_name| fdstore_name.as_ref() == name)
+        });
+        Self { sockets, restored }
+    }
+
+    /// Descriptors passed from socket units, with their names.
+    pub fn sockets(&self) -> &[(FileDescriptor, String)] {
+        &self.sockets
+    }
+
+    /// Descriptors restored from the file descriptor store, with their names.
+    pub fn restored(&self) -> &[(FileDescriptor, String)] {
+        &self.restored
+    }
+
+    /// Return the first restored descriptor with the given name, if any.
+    pub fn take_restored(&mut self, name: &str) -> Option<FileDescriptor> {
+        let position = self.restored.iter().position(|(_, n)| n == name)?;
+        Some(self.restored.remove(position).0)
+    }
+
+    /// Take all descriptors passed from socket units, leaving restored ones.
+    pub fn take_sockets(&mut self) -> Vec<(FileDescriptor, String)> {
+        std::mem::take(&mut self.sockets)
+    }
+}
+
+/// Check for named file descriptors passed by systemd, split by origin.
+///
+/// Descriptors are passed both by socket units and, after a restart, from
+/// the file descriptor store (see [`FdStore`](crate::daemon::FdStore)).
+/// As both arrive in the same way, they are told apart by name: descriptors
+/// named after one of `fdstore_names` (the names the service uses with
+/// `FDNAME=`) are considered restored from the store, all others are
+/// considered socket unit ones. This allows recovering state after a crash.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::activation;
+///
+/// let mut fds = activation::receive_descriptors_partitioned(true, &["cache"])?;
+/// if let Some(cache) = fds.take_restored("cache") {
+///     // Recover state from the stored descriptor...
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn receive_descriptors_partitioned<S: AsRef<str>>(
+    unset_env: bool,
+    fdstore_names: &[S],
+) -> Result<PartitionedDescriptors, SdError> {
+    let fds = receive_descriptors_with_names(unset_env)?;
+    Ok(PartitionedDescriptors::new(fds, fdstore_names))
+}
+
+/// Set of file descriptors passed by systemd, claimed by type or by name.
+///
+/// This is meant to be constructed once at startup, taking ownership of all
+/// passed descriptors. Descriptors are then claimed through typed getters,
+/// and any descriptor left unclaimed is reported with a warning on drop.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::activation::Listeners;
+///
+/// let mut listeners = Listeners::receive(true).unwrap_or_default();
+/// let metrics = listeners.named("metrics");
+/// let http = listeners.tcp_listeners();
+/// ```
+#[derive(Debug, Default)]
+pub struct Listeners {
+    entries: Vec<(String, Option<FileDescriptor>)>,
+}
+
+impl Listeners {
+    /// Take ownership of all file descriptors passed by systemd.
+    ///
+    /// If `unset_env` is true, the environment variables used by systemd will be cleared.
+    pub fn receive(unset_env: bool) -> Result<Self, SdError> {
+        let (fds, fdnames) = listen_env(unset_env)?;
+        let names = fd_names(fds, fdnames.as_deref());
+        let descriptors = socks_from_fds(fds)?;
+        Ok(Self::from_descriptors(descriptors.into_iter().zip(names)))
+    }
+
+    /// Build a set from already received file descriptors and their names.
+    pub fn from_descriptors<I>(descriptors: I) -> Self
+    where
+        I: IntoIterator<Item = (FileDescriptor, String)>,
+    {
+        let entries = descriptors
+            .into_iter()
+            .map(|(fd, name)| (name, Some(fd)))
+            .collect();
+        Self { entries }
+    }
+
+    /// Claim all listening TCP sockets.
+    pub fn tcp_listeners(&mut self) -> Vec<TcpListener> {
+        self.claim_all()
+    }
+
+    /// Claim all UDP sockets.
+    pub fn udp_sockets(&mut self) -> Vec<UdpSocket> {
+        self.claim_all()
+    }
+
+    /// Claim all listening Unix stream sockets.
+    pub fn unix_listeners(&mut self) -> Vec<UnixListener> {
+        self.claim_all()
+    }
+
+    /// Claim all Unix datagram sockets.
+    pub fn unix_datagrams(&mut self) -> Vec<UnixDatagram> {
+        self.claim_all()
+    }
+
+    /// Claim all file descriptors with the given name.
+    pub fn named(&mut self, name: &str) -> Vec<FileDescriptor> {
+        self.entries
+            .iter_mut()
+            .filter(|(fdname, _)| fdname == name)
+            .filter_map(|(_, fd)| fd.take())
+            .collect()
+    }
+
+    /// Claim all remaining file descriptors, along with their names.
+    pub fn remaining(&mut self) -> Vec<(FileDescriptor, String)> {
+        self.entries
+            .iter_mut()
+            .filter_map(|(name, fd)| fd.take().map(|fd| (fd, name.clone())))
+            .collect()
+    }
+
+    /// Return the names of the file descriptors which have not been claimed yet.
+    pub fn unclaimed(&self) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(_, fd)| fd.is_some())
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Claim all file descriptors which can be converted into `T`.
+    fn claim_all<T>(&mut self) -> Vec<T>
+    where
+        T: TryFrom<FileDescriptor, Error = (SdError, FileDescriptor)>,
+    {
+        let mut claimed = vec![];
+        for (_, entry) in self.entries.iter_mut() {
+            if let Some(fd) = entry.take() {
+                match T::try_from(fd) {
+                    Ok(socket) => claimed.push(socket),
+                    Err((_, fd)) => *entry = Some(fd),
+                }
+            }
+        }
+        claimed
+    }
+}
+
+impl Drop for Listeners {
+    fn drop(&mut self) {
+        for (name, fd) in &self.entries {
+            if let Some(fd) = fd {
+                log::warn!(
+                    "closing unclaimed file descriptor {} (name '{}')",
+                    fd.as_raw_fd(),
+                    name
+                );
+            }
+        }
+    }
+}
+
+/// Spawn a child process, passing it file descriptors through the socket activation protocol.
+///
+/// In the child, the given descriptors are renumbered starting at fd 3
+/// (without `O_CLOEXEC`), any other inherited descriptor above them is
+/// closed, and `LISTEN_FDS`, `LISTEN_FDNAMES` and `LISTEN_PID` are set
+/// accordingly. This is meant for handing sockets over to a helper process,
+/// e.g. for zero-downtime upgrades.
+///
+/// As `LISTEN_PID` is only known after forking, the child environment is
+/// prepared in advance (the current one, amended by the variables set on
+/// `cmd`) and the program is executed directly. Clearing the whole
+/// environment via `Command::env_clear` and overriding `argv[0]` are not
+/// supported.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::activation;
+/// use std::net::TcpListener;
+/// use std::process::Command;
+///
+/// let listener = TcpListener::bind("127.0.0.1:8080")?;
+/// let mut cmd = Command::new("/usr/libexec/my-helper");
+/// let child = activation::spawn_with_descriptors(&mut cmd, vec![(listener, "http")])?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn spawn_with_descriptors<I, F, S>(cmd: &mut Command, fds: I) -> Result<Child, SdError>
+where
+    I: IntoIterator<Item = (F, S)>,
+    F: Into<OwnedFd>,
+    S: Into<String>,
+{
+    let setup = ChildSetup::new(cmd, fds)?;
+    // SAFETY: the closure only performs async-signal-safe operations,
+    // without allocating.
+    unsafe { cmd.pre_exec(setup.into_closure()) };
+    cmd.spawn()
+        .with_context(|| format!("failed to spawn {:?}", cmd.get_program()))
+}
+
+/// Re-execute a program in place of the current process, passing it file descriptors.
+///
+/// This behaves like [`spawn_with_descriptors`], but replaces the current
+/// process (e.g. with `/proc/self/exe` to re-execute itself) instead of
+/// forking a child. On success it never returns.
+pub fn exec_with_descriptors<I, F, S>(cmd: &mut Command, fds: I) -> SdError
+where
+    I: IntoIterator<Item = (F, S)>,
+    F: Into<OwnedFd>,
+    S: Into<String>,
+{
+    let setup = match ChildSetup::new(cmd, fds) {
+        Ok(setup) => setup,
+        Err(e) => return e,
+    };
+    // SAFETY: see `spawn_with_descriptors`.
+    unsafe { cmd.pre_exec(setup.into_closure()) };
+    let err = cmd.exec();
+    format!("failed to execute {:?}: {}", cmd.get_program(), err).into()
+}
+
+/// Everything needed to set up and execute a child process, allocated
+/// in advance so that no allocation happens after forking.
+struct ChildSetup {
+    fds: Vec<OwnedFd>,
+    scratch: Vec<RawFd>,
+    path: CString,
+    argv: Vec<CString>,
+    envp: Vec<CString>,
+    /// `LISTEN_PID=` entry (NUL-terminated), with room for the PID filled
+    /// in the child.
+    listen_pid: Vec<u8>,
+}
+
+impl ChildSetup {
+    fn new<I, F, S>(cmd: &Command, fds: I) -> Result<Self, SdError>
+    where
+        I: IntoIterator<Item = (F, S)>,
+        F: Into<OwnedFd>,
+        S: Into<String>,
+    {
+        let mut owned = vec![];
+        let mut names = vec![];
+        for (fd, name) in fds {
+            let name = name.into();
+            crate::daemon::validate_fdname(&name)
+                .with_context(|| format!("invalid name for file descriptor #{}", owned.len()))?;
+            owned.push(fd.into());
+            names.push(name);
+        }
+        i32::try_from(owned.len())
+            .ok()
+            .and_then(|count| SD_LISTEN_FDS_START.checked_add(count))
+            .with_context(|| format!("too many file descriptors: {}", owned.len()))?;
+
+        let mut vars: HashMap<OsString, OsString> = env::vars_os().collect();
+        for (key, value) in cmd.get_envs() {
+            match value {
+                Some(value) => vars.insert(key.to_os_string(), value.to_os_string()),
+                None => vars.remove(key),
+            };
+        }
+        vars.remove(OsStr::new("LISTEN_PID"));
+        vars.insert("LISTEN_FDS".into(), owned.len().to_string().into());
+        vars.insert("LISTEN_FDNAMES".into(), names.join(":").into());
+
+        // The program is looked up before forking, as searching `PATH`
+        // in the child is not async-signal-safe.
+        let program = cmd.get_program();
+        let path = resolve_program(program, vars.get(OsStr::new("PATH")))?;
+        let mut argv = vec![to_cstring(program)?];
+        for arg in cmd.get_args() {
+            argv.push(to_cstring(arg)?);
+        }
+        let mut envp = vec![];
+        for (key, value) in vars {
+            let mut entry = key;
+            entry.push("=");
+            entry.push(value);
+            envp.push(to_cstring(&entry)?);
+        }
+
+        // Room for a decimal PID and a trailing NUL.
+        let mut listen_pid = b"LISTEN_PID=".to_vec();
+        listen_pid.resize(listen_pid.len() + 11, 0);
+
+        Ok(Self {
+            scratch: vec![-1; owned.len()],
+            fds: owned,
+            path: to_cstring(path.as_os_str())?,
+            argv,
+            envp,
+            listen_pid,
+        })
+    }
+
+    fn into_closure(mut self) -> impl FnMut() -> io::Result<()> + Send + Sync + 'static {
+        let mut argv: Vec<*const libc::c_char> = self.argv.iter().map(|a| a.as_ptr()).collect();
+        argv.push(std::ptr::null());
+        let mut envp: Vec<*const libc::c_char> = self.envp.iter().map(|e| e.as_ptr()).collect();
+        envp.push(self.listen_pid.as_mut_ptr().cast());
+        envp.push(std::ptr::null());
+        // Raw pointers are neither `Send` nor `Sync`; they only point into
+        // buffers owned by the closure, which are never moved nor reallocated.
+        let pointers = PointerArrays { argv, envp };
+        move || self.exec_child(&pointers)
+    }
+
+    /// Renumber descriptors, fill `LISTEN_PID` and execute the program.
+    ///
+    /// This runs in a freshly forked child, and must only use
+    /// async-signal-safe functions.
+    fn exec_child(&mut self, pointers: &PointerArrays) -> io::Result<()> {
+        // First move all descriptors out of the target range, so that
+        // renumbering does not clobber any of them.
+        let first_free = SD_LISTEN_FDS_START + self.fds.len() as RawFd;
+        for (fd, tmp) in self.fds.iter().zip(self.scratch.iter_mut()) {
+            *tmp = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, first_free) };
+            if *tmp < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        for (index, tmp) in self.scratch.iter().enumerate() {
+            // `dup2` clears `FD_CLOEXEC` on the target descriptor.
+            let target = SD_LISTEN_FDS_START + index as RawFd;
+            if unsafe { libc::dup2(*tmp, target) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            unsafe { libc::close(*tmp) };
+        }
+        cloexec_from(first_free)?;
+
+        let mut pid = unsafe { libc::getpid() } as u32;
+        let mut digits = [0u8; 10];
+        let mut len = 0;
+        loop {
+            digits[len] = b'0' + (pid % 10) as u8;
+            pid /= 10;
+            len += 1;
+            if pid == 0 {
+                break;
+            }
+        }
+        let prefix = b"LISTEN_PID=".len();
+        for (offset, digit) in digits[..len].iter().rev().enumerate() {
+            self.listen_pid[prefix + offset] = *digit;
+        }
+        self.listen_pid[prefix + len] = 0;
+
+        unsafe {
+            libc::execve(
+                self.path.as_ptr(),
+                pointers.argv.as_ptr(),
+                pointers.envp.as_ptr(),
+            )
+        };
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// NULL-terminated pointer arrays for `execve`.
+struct PointerArrays {
+    argv: Vec<*const libc::c_char>,
+    envp: Vec<*const libc::c_char>,
+}
+
+// SAFETY: the arrays are only ever used in the forked child.
+unsafe impl Send for PointerArrays {}
+unsafe impl Sync for PointerArrays {}
+
+fn to_cstring(value: &OsStr) -> Result<CString, SdError> {
+    CString::new(value.as_bytes()).with_context(|| format!("invalid NUL byte in {:?}", value))
+}
+
+/// Look up a program through `PATH`, like `execvp` does.
+fn resolve_program(program: &OsStr, path: Option<&OsString>) -> Result<PathBuf, SdError> {
+    if program.as_bytes().contains(&b'/') {
+        return Ok(PathBuf::from(program));
+    }
+    let path = path.map_or(OsStr::new("/bin:/usr/bin"), |p| p.as_os_str());
+    env::split_paths(path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| {
+            candidate.metadata().map_or(false, |m| {
+                m.is_file() && m.permissions().mode() & 0o111 != 0
+            })
+        })
+        .with_context(|| format!("failed to find {:?} in PATH", program))
+}
+
+/// Mark all descriptors starting at `first` as close-on-exec.
+///
+/// Descriptors are not closed right away, as the standard library still
+/// needs its own ones to report a failed exec. This must only use
+/// async-signal-safe functions.
+fn cloexec_from(first: RawFd) -> io::Result<()> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_close_range,
+            first as libc::c_uint,
+            libc::c_uint::MAX,
+            libc::CLOSE_RANGE_CLOEXEC,
+        )
+    };
+    if res == 0 {
+        return Ok(());
+    }
+
+    // Fallback for kernels older than 5.11.
+    let mut limit: libc::rlimit = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let max_fd = RawFd::try_from(limit.rlim_cur).unwrap_or(RawFd::MAX);
+    for fd in first..max_fd {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags >= 0 && flags & libc::FD_CLOEXEC == 0 {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) };
+        }
+    }
+    Ok(())
+}
+
+/// Parse socket activation variables from the environment.
+///
+/// This returns the number of passed descriptors and their names (if any),
+/// after checking that they are meant for the current process.
+fn listen_env(unset_env: bool) -> Result<(usize, Option<String>), SdError> {
+    let parsed = parse_listen_env(&ProcessEnv, &ReceiveOptions::default());
+
+    if unset_env {
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+    }
+
+    parsed
+}
+
+/// Parse socket activation variables, checking `LISTEN_PID` as requested.
+fn parse_listen_env(
+    source: &impl EnvSource,
+    options: &ReceiveOptions,
+) -> Result<(usize, Option<String>), SdError> {
+    let pid = source.get("LISTEN_PID");
+    let fds = source.get("LISTEN_FDS");
+    let fdnames = source.get("LISTEN_FDNAMES");
+    log::trace!(
+        "LISTEN_PID = {:?}; LISTEN_FDS = {:?}; LISTEN_FDNAMES = {:?}",
+        pid,
+        fds,
+        fdnames
+    );
+
+    let fds = fds
+        .context("failed to get LISTEN_FDS")?
+        .parse::<usize>()
+        .context("failed to parse LISTEN_FDS")?;
+
+    let expected = match options.pid_check {
+        PidCheck::CurrentProcess => Some(process::id()),
+        PidCheck::Expect(pid) => Some(pid),
+        PidCheck::Skip => None,
+    };
+    if let Some(expected) = expected {
+        let pid = pid
+            .context("failed to get LISTEN_PID")?
+            .parse::<u32>()
+            .context("failed to parse LISTEN_PID")?;
+        if expected != pid {
+            return Err("PID mismatch".into());
+        }
+    }
+
+    Ok((fds, fdnames))
+}
+
+/// Return the names of `num_fds` descriptors, defaulting to `unknown`.
+fn fd_names(num_fds: usize, fdnames: Option<&str>) -> Vec<String> {
+    let mut names: Vec<String> = fdnames
+        .map(|names| names.split(':').take(num_fds).map(String::from).collect())
+        .unwrap_or_default();
+    names.resize(num_fds, "unknown".to_string());
+    names
+}
+
+/// Raw descriptors already taken ownership of by this process.
+///
+/// Passed descriptors are wrapped into owned values at most once, so that
+/// later calls cannot produce a second owner for the same descriptor.
+static TAKEN_FDS: Mutex<Vec<RawFd>> = Mutex::new(Vec::new());
+
+/// Mark `fds` as taken, failing if any of them already was.
+fn claim_fds(fds: &[RawFd]) -> Result<(), SdError> {
+    let mut taken = TAKEN_FDS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(fd) = fds.iter().find(|fd| taken.contains(fd)) {
+        return Err(format!("file descriptor {} has already been received", fd).into());
+    }
+    taken.extend_from_slice(fds);
+    Ok(())
+}
+
+fn socks_from_fds(num_fds: usize) -> Result<Vec<FileDescriptor>, SdError> {
+    take_descriptors(0..num_fds)
+}
+
+/// Take ownership of the passed descriptors at the given offsets.
+///
+/// This fails without taking any descriptor if one of them has already been
+/// taken ownership of, by this or a previous call.
+fn take_descriptors(
+    offsets: impl IntoIterator<Item = usize>,
+) -> Result<Vec<FileDescriptor>, SdError> {
+    let fds = offsets
+        .into_iter()
+        .map(|fd_offset| {
+            i32::try_from(fd_offset)
+                .ok()
+                .and_then(|offset| SD_LISTEN_FDS_START.checked_add(offset))
+                .with_context(|| format!("overlarge file descriptor index: {}", fd_offset))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    claim_fds(&fds)?;
+
+    let descriptors = fds
+        .into_iter()
+        .map(|index| {
+            // SAFETY: descriptors starting at `SD_LISTEN_FDS_START` are passed to
+            // this process by the service manager, after checking `LISTEN_PID`,
+            // and `claim_fds` ensures that each of them is only owned once.
+            let owned = unsafe { OwnedFd::from_raw_fd(index) };
+            FileDescriptor::try_from(owned).unwrap_or_else(|(msg, val)| {
+                log::warn!("{}", msg);
+                FileDescriptor(SocketFd::Unknown(val))
+            })
+        })
+        .collect();
+
+    Ok(descriptors)
+}
+
+impl IsType for RawFd {
+    fn is_fifo(&self) -> bool {
+        fstat(*self)
+            .map(|stat| (stat.st_mode & 0o0_170_000) == 0o010_000)
+            .unwrap_or(false)
+    }
+
+    fn is_special(&self) -> bool {
+        // Like `sd_is_special(3)`, either a regular file or a character device.
+        fstat(*self)
+            .map(|stat| {
+                let kind = stat.st_mode & libc::S_IFMT;
+                kind == libc::S_IFREG || kind == libc::S_IFCHR
+            })
+            .unwrap_or(false)
+    }
+
+    fn is_inet(&self) -> bool {
+        getsockname::<SockaddrStorage>(*self)
+            .map(|addr| {
+                matches!(
+                    addr.family(),
+                    Some(AddressFamily::Inet) | Some(AddressFamily::Inet6)
+                )
+            })
+            .unwrap_or(false)
+    }
+
+    fn is_unix(&self) -> bool {
+        getsockname::<SockaddrStorage>(*self)
+            .map(|addr| matches!(addr.family(), Some(AddressFamily::Unix)))
+            .unwrap_or(false)
+    }
+
+    fn is_mq(&self) -> bool {
+        // `nix` does not enable us to test if a raw fd is a mq, so we must drop to libc here.
+        // SAFETY: `mq_getattr` is specified to return -1 when passed a fd which is not a mq.
+        //         Furthermore, we ignore `attr` and rely only on the return value.
+        let mut attr = std::mem::MaybeUninit::<libc::mq_attr>::uninit();
+        let res = unsafe { libc::mq_getattr(*self, attr.as_mut_ptr()) };
+        res == 0
+    }
+
+    fn is_vsock(&self) -> bool {
+        getsockname::<SockaddrStorage>(*self)
+            .map(|addr| matches!(addr.family(), Some(AddressFamily::Vsock)))
+            .unwrap_or(false)
+    }
+
+    fn is_netlink(&self) -> bool {
+        getsockname::<SockaddrStorage>(*self)
+            .map(|addr| matches!(addr.family(), Some(AddressFamily::Netlink)))
+            .unwrap_or(false)
+    }
+}
+
+impl IsType for BorrowedFd<'_> {
+    fn is_fifo(&self) -> bool {
+        self.as_raw_fd().is_fifo()
+    }
+
+    fn is_special(&self) -> bool {
+        self.as_raw_fd().is_special()
+    }
+
+    fn is_inet(&self) -> bool {
+        self.as_raw_fd().is_inet()
+    }
+
+    fn is_unix(&self) -> bool {
+        self.as_raw_fd().is_unix()
+    }
+
+    fn is_mq(&self) -> bool {
+        self.as_raw_fd().is_mq()
+    }
+
+    fn is_vsock(&self) -> bool {
+        self.as_raw_fd().is_vsock()
+    }
+
+    fn is_netlink(&self) -> bool {
+        self.as_raw_fd().is_netlink()
+    }
+}
+
+/// Owned file descriptor conversion.
+///
+/// A raw descriptor owned by the caller can be converted by first wrapping
+/// it with the `unsafe` `OwnedFd::from_raw_fd()`. On failure, ownership is
+/// returned to the caller.
+impl TryFrom<OwnedFd> for FileDescriptor {
+    type Error = (SdError, OwnedFd);
+
+    fn try_from(value: OwnedFd) -> Result<Self, Self::Error> {
+        let fd = value.as_fd();
+        if fd.is_fifo() {
+            return Ok(FileDescriptor(SocketFd::Fifo(value)));
+        }
+
+        // Sockets are classified by the family of their local address.
+        let info = SocketInfo::query(fd);
+        if info.sock_type.is_some() {
+            match info.family() {
+                Some(AddressFamily::Inet) | Some(AddressFamily::Inet6) => {
+                    return Ok(FileDescriptor(SocketFd::Inet(value)))
+                }
+                Some(AddressFamily::Unix) => return Ok(FileDescriptor(SocketFd::Unix(value))),
+                Some(AddressFamily::Vsock) => return Ok(FileDescriptor(SocketFd::Vsock(value))),
+                Some(AddressFamily::Netlink) => {
+                    return Ok(FileDescriptor(SocketFd::Netlink(value)))
+                }
+                _ => {}
+            }
+        }
+
+        // Message queues look like regular files, so they must be checked
+        // before special files.
+        if fd.is_mq() {
+            return Ok(FileDescriptor(SocketFd::Mq(value)));
+        } else if fd.is_special() {
+            return Ok(FileDescriptor(SocketFd::Special(value)));
+        }
+
+        let err_msg = format!(
+            "conversion failure, possibly invalid or unknown file descriptor {}",
+            value.as_raw_fd()
+        );
+        Err((err_msg.into(), value))
+    }
+}
+
+impl FileDescriptor {
+    /// Duplicate this file descriptor.
+    pub fn try_clone(&self) -> Result<Self, SdError> {
+        let fd = self
+            .as_fd()
+            .try_clone_to_owned()
+            .context("failed to duplicate file descriptor")?;
+        Ok(self.with_fd(fd))
+    }
+
+    /// Duplicate this file descriptor to the lowest number greater than or
+    /// equal to `min_fd`, with `FD_CLOEXEC` set.
+    ///
+    /// This is useful to move descriptors away from the low numbers used
+    /// by socket activation, e.g. before passing other descriptors to a child.
+    pub fn dup_above(&self, min_fd: RawFd) -> Result<Self, SdError> {
+        let raw = fcntl(self.as_raw_fd(), FcntlArg::F_DUPFD_CLOEXEC(min_fd))
+            .with_context(|| format!("failed to duplicate file descriptor above {}", min_fd))?;
+        // SAFETY: `raw` is a freshly duplicated descriptor, owned by nobody else.
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+        Ok(self.with_fd(fd))
+    }
+
+    /// Returns whether the close-on-exec flag (`FD_CLOEXEC`) is set.
+    ///
+    /// Descriptors are passed by systemd without this flag, so they are
+    /// inherited by all child processes unless it is set.
+    pub fn is_cloexec(&self) -> Result<bool, SdError> {
+        let flags = fcntl(self.as_raw_fd(), FcntlArg::F_GETFD)
+            .context("failed to get file descriptor flags")?;
+        Ok(FdFlag::from_bits_truncate(flags).contains(FdFlag::FD_CLOEXEC))
+    }
+
+    /// Set or clear the close-on-exec flag (`FD_CLOEXEC`).
+    pub fn set_cloexec(&self, cloexec: bool) -> Result<(), SdError> {
+        let flags = fcntl(self.as_raw_fd(), FcntlArg::F_GETFD)
+            .context("failed to get file descriptor flags")?;
+        let mut flags = FdFlag::from_bits_truncate(flags);
+        flags.set(FdFlag::FD_CLOEXEC, cloexec);
+        fcntl(self.as_raw_fd(), FcntlArg::F_SETFD(flags))
+            .context("failed to set file descriptor flags")?;
+        Ok(())
+    }
+
+    /// Wrap `fd` as a descriptor of the same kind as this one.
+    fn with_fd(&self, fd: OwnedFd) -> Self {
+        let socket_fd = match self.0 {
+            SocketFd::Fifo(_) => SocketFd::Fifo(fd),
+            SocketFd::Special(_) => SocketFd::Special(fd),
+            SocketFd::Inet(_) => SocketFd::Inet(fd),
+            SocketFd::Unix(_) => SocketFd::Unix(fd),
+            SocketFd::Mq(_) => SocketFd::Mq(fd),
+            SocketFd::Vsock(_) => SocketFd::Vsock(fd),
+            SocketFd::Netlink(_) => SocketFd::Netlink(fd),
+            SocketFd::Unknown(_) => SocketFd::Unknown(fd),
+        };
+        FileDescriptor(socket_fd)
+    }
+
+    /// Convert into a socket of type `T`, after checking that the socket
+    /// has the expected family, type, and listening state.
+    fn into_socket<T: From<OwnedFd>>(
+        self,
+        families: &[AddressFamily],
+        sock_type: SockType,
+        listening: bool,
+    ) -> Result<T, (SdError, Self)> {
+        let fd = self.as_raw_fd();
+        let info = SocketInfo::query(self.as_fd());
+        let family = info.family();
+        let actual_type = info.sock_type;
+
+        if !info.matches(families, Some(sock_type), Some(listening)) {
+            let err_msg = format!(
+                "file descriptor {} is not a {:?} socket of type {:?}{} (found {:?} socket of type {:?})",
+                fd,
+                families,
+                sock_type,
+                if listening { " in listening state" } else { "" },
+                family,
+                actual_type,
+            );
+            return Err((err_msg.into(), self));
+        }
+
+        Ok(T::from(OwnedFd::from(self)))
+    }
+
+    /// Returns true if the descriptor is an Internet socket matching the given
+    /// criteria, like `sd_is_socket_inet(3)`.
+    ///
+    /// The socket must be of the given `family` (either `Inet` or `Inet6`) and
+    /// `sock_type`, be in the given `listening` state, and be bound to `port`.
+    /// Criteria set to `None` match any value.
+    pub fn is_socket_inet(
+        &self,
+        family: Option<AddressFamily>,
+        sock_type: Option<SockType>,
+        listening: Option<bool>,
+        port: Option<u16>,
+    ) -> bool {
+        let inet_families = [AddressFamily::Inet, AddressFamily::Inet6];
+        let families = match family {
+            Some(f) if inet_families.contains(&f) => vec![f],
+            Some(_) => return false,
+            None => inet_families.to_vec(),
+        };
+
+        let info = SocketInfo::query(self.as_fd());
+        if !info.matches(&families, sock_type, listening) {
+            return false;
+        }
+
+        match port {
+            Some(port) => info.port() == Some(port),
+            None => true,
+        }
+    }
+
+    /// Returns true if the descriptor is a socket bound to exactly the given
+    /// address, like `sd_is_socket_sockaddr(3)`.
+    ///
+    /// The socket must also be of the given `sock_type` and be in the given
+    /// `listening` state. Criteria set to `None` match any value.
+    pub fn is_socket_sockaddr<A: SockaddrLike>(
+        &self,
+        sock_type: Option<SockType>,
+        addr: &A,
+        listening: Option<bool>,
+    ) -> bool {
+        // SAFETY: `addr` is a valid socket address, `addr.len()` bytes long.
+        let expected = match unsafe { SockaddrStorage::from_raw(addr.as_ptr(), Some(addr.len())) } {
+            Some(a) => a,
+            None => return false,
+        };
+        let family = match expected.family() {
+            Some(f) => f,
+            None => return false,
+        };
+
+        let info = SocketInfo::query(self.as_fd());
+        if !info.matches(&[family], sock_type, listening) {
+            return false;
+        }
+
+        info.local_addr
+            .as_ref()
+            .map_or(false, |local| same_address(local, &expected))
+    }
+
+    /// Returns the local address the socket is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddress, SdError> {
+        let addr = getsockname::<SockaddrStorage>(self.as_raw_fd())
+            .with_context(|| format!("failed to get local address of fd {}", self.as_raw_fd()))?;
+        SocketAddress::from_storage(&addr)
+            .with_context(|| format!("unsupported address family {:?}", addr.family()))
+    }
+
+    /// Returns the address of the peer the socket is connected to.
+    pub fn peer_addr(&self) -> Result<SocketAddress, SdError> {
+        let addr = getpeername::<SockaddrStorage>(self.as_raw_fd())
+            .with_context(|| format!("failed to get peer address of fd {}", self.as_raw_fd()))?;
+        SocketAddress::from_storage(&addr)
+            .with_context(|| format!("unsupported address family {:?}", addr.family()))
+    }
+
+    /// Returns the type of the socket (`SO_TYPE`), e.g. stream, datagram or
+    /// sequential packet, or `None` if the descriptor is not a socket.
+    pub fn socket_type(&self) -> Option<SockType> {
+        getsockopt(&self.as_fd(), sockopt::SockType).ok()
+    }
+
+    /// Returns whether the socket is in listening state (`SO_ACCEPTCONN`),
+    /// or `None` if the descriptor is not a socket.
+    ///
+    /// Services with `Accept=no` receive listening sockets for stream and
+    /// sequential packet types, while `Accept=yes` ones receive connected sockets.
+    pub fn is_listening(&self) -> Option<bool> {
+        getsockopt(&self.as_fd(), sockopt::AcceptConn).ok()
+    }
+
+    /// Returns the protocol of a `AF_NETLINK` socket (e.g. `NETLINK_ROUTE`),
+    /// as set through `ListenNetlink=`.
+    pub fn netlink_protocol(&self) -> Option<i32> {
+        if !self.as_fd().is_netlink() {
+            return None;
+        }
+        socket_protocol(self.as_fd())
+    }
+
+    /// Returns true if the descriptor is a `AF_NETLINK` socket of the given
+    /// `protocol`. A protocol set to `None` matches any value.
+    pub fn is_socket_netlink(&self, protocol: Option<i32>) -> bool {
+        match (self.netlink_protocol(), protocol) {
+            (Some(_), None) => true,
+            (Some(actual), Some(expected)) => actual == expected,
+            (None, _) => false,
+        }
+    }
+}
+
+/// Query the protocol of a socket (`SO_PROTOCOL`).
+fn socket_protocol(fd: BorrowedFd) -> Option<i32> {
+    // `nix` does not expose `SO_PROTOCOL`, so we must drop to libc here.
+    let mut protocol: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `protocol` is valid for writes of `len` bytes.
+    let res = unsafe {
+        libc::getsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PROTOCOL,
+            (&mut protocol as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+    if res != 0 {
+        return None;
+    }
+    Some(protocol)
+}
+
+/// Address of a socket passed by systemd.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SocketAddress {
+    /// An Internet address (`AF_INET` or `AF_INET6`).
+    Inet(SocketAddr),
+    /// A Unix socket bound to a filesystem path.
+    UnixPath(PathBuf),
+    /// A Unix socket bound to a name in the abstract namespace (without
+    /// the leading NUL byte).
+    UnixAbstract(Vec<u8>),
+    /// A Unix socket without an address (e.g. from `socketpair(2)`).
+    UnixUnnamed,
+    /// A `AF_VSOCK` address.
+    Vsock {
+        /// Context identifier.
+        cid: u32,
+        /// Port number.
+        port: u32,
+    },
+    /// A `AF_NETLINK` address.
+    Netlink {
+        /// Port ID (usually the PID of the owning process, or 0 for the kernel).
+        pid: u32,
+        /// Bitmask of multicast groups.
+        groups: u32,
+    },
+}
+
+impl SocketAddress {
+    fn from_storage(addr: &SockaddrStorage) -> Option<Self> {
+        if let Some(a) = addr.as_sockaddr_in() {
+            return Some(SocketAddress::Inet(SocketAddrV4::from(*a).into()));
+        }
+        if let Some(a) = addr.as_sockaddr_in6() {
+            return Some(SocketAddress::Inet(SocketAddrV6::from(*a).into()));
+        }
+        if let Some(a) = addr.as_unix_addr() {
+            if let Some(path) = a.path() {
+                return Some(SocketAddress::UnixPath(path.to_path_buf()));
+            }
+            if let Some(name) = a.as_abstract() {
+                return Some(SocketAddress::UnixAbstract(name.to_vec()));
+            }
+            return Some(SocketAddress::UnixUnnamed);
+        }
+        if let Some(a) = addr.as_vsock_addr() {
+            return Some(SocketAddress::Vsock {
+                cid: a.cid(),
+                port: a.port(),
+            });
+        }
+        if let Some(a) = addr.as_netlink_addr() {
+            return Some(SocketAddress::Netlink {
+                pid: a.pid(),
+                groups: a.groups(),
+            });
+        }
+        None
+    }
+}
+
+/// Format addresses like systemd does in socket units (e.g. `@name` for
+/// abstract Unix sockets, `vsock:CID:PORT` for vsock ones).
+impl fmt::Display for SocketAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SocketAddress::Inet(addr) => write!(f, "{}", addr),
+            SocketAddress::UnixPath(path) => write!(f, "{}", path.display()),
+            SocketAddress::UnixAbstract(name) => write!(f, "@{}", String::from_utf8_lossy(name)),
+            SocketAddress::UnixUnnamed => f.write_str("(unnamed)"),
+            SocketAddress::Vsock { cid, port } => write!(f, "vsock:{}:{}", cid, port),
+            SocketAddress::Netlink { pid, groups } => write!(f, "netlink:{}:{}", pid, groups),
+        }
+    }
+}
+
+/// Compare two socket addresses, for the families supported by socket activation.
+fn same_address(a: &SockaddrStorage, b: &SockaddrStorage) -> bool {
+    if let (Some(a), Some(b)) = (a.as_sockaddr_in(), b.as_sockaddr_in()) {
+        return SocketAddrV4::from(*a) == SocketAddrV4::from(*b);
+    }
+    if let (Some(a), Some(b)) = (a.as_sockaddr_in6(), b.as_sockaddr_in6()) {
+        return a.ip() == b.ip() && a.port() == b.port();
+    }
+    if let (Some(a), Some(b)) = (a.as_unix_addr(), b.as_unix_addr()) {
+        return a.path() == b.path() && a.as_abstract() == b.as_abstract();
+    }
+    false
+}
+
+/// Socket properties of a file descriptor, used for validation.
+struct SocketInfo {
+    local_addr: Option<SockaddrStorage>,
+    sock_type: Option<SockType>,
+    listening: Option<bool>,
+}
+
+impl SocketInfo {
+    /// Query socket properties, which are `None` if not available (e.g. not a socket).
+    fn query(fd: BorrowedFd) -> Self {
+        Self {
+            local_addr: getsockname::<SockaddrStorage>(fd.as_raw_fd()).ok(),
+            sock_type: getsockopt(&fd, sockopt::SockType).ok(),
+            listening: getsockopt(&fd, sockopt::AcceptConn).ok(),
+        }
+    }
+
+    /// Return the address family of the socket.
+    fn family(&self) -> Option<AddressFamily> {
+        self.local_addr.as_ref().and_then(|addr| addr.family())
+    }
+
+    /// Return the local port of an Internet socket.
+    fn port(&self) -> Option<u16> {
+        let addr = self.local_addr.as_ref()?;
+        addr.as_sockaddr_in()
+            .map(|a| a.port())
+            .or_else(|| addr.as_sockaddr_in6().map(|a| a.port()))
+    }
+
+    /// Check whether the socket matches the given family, type, and listening state.
+    ///
+    /// Criteria set to `None` match any value.
+    fn matches(
+        &self,
+        families: &[AddressFamily],
+        sock_type: Option<SockType>,
+        listening: Option<bool>,
+    ) -> bool {
+        self.family().map_or(false, |f| families.contains(&f))
+            && self.sock_type.is_some()
+            && (sock_type.is_none() || self.sock_type == sock_type)
+            && (listening.is_none() || self.listening == listening)
+    }
+}
+
+impl TryFrom<FileDescriptor> for TcpListener {
+    type Error = (SdError, FileDescriptor);
+
+    /// Convert into a TCP listener, checking that the descriptor is
+    /// a listening `SOCK_STREAM` socket of the `PF_INET`/`PF_INET6` family.
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        let families = [AddressFamily::Inet, AddressFamily::Inet6];
+        value.into_socket(&families, SockType::Stream, true)
+    }
+}
+
+impl TryFrom<FileDescriptor> for UdpSocket {
+    type Error = (SdError, FileDescriptor);
+
+    /// Convert into a UDP socket, checking that the descriptor is
+    /// a `SOCK_DGRAM` socket of the `PF_INET`/`PF_INET6` family.
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        let families = [AddressFamily::Inet, AddressFamily::Inet6];
+        value.into_socket(&families, SockType::Datagram, false)
+    }
+}
+
+impl TryFrom<FileDescriptor> for UnixListener {
+    type Error = (SdError, FileDescriptor);
+
+    /// Convert into a Unix listener, checking that the descriptor is
+    /// a listening `SOCK_STREAM` socket of the `PF_UNIX` family.
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        value.into_socket(&[AddressFamily::Unix], SockType::Stream, true)
+    }
+}
+
+impl TryFrom<FileDescriptor> for UnixDatagram {
+    type Error = (SdError, FileDescriptor);
+
+    /// Convert into a Unix datagram socket, checking that the descriptor is
+    /// a `SOCK_DGRAM` socket of the `PF_UNIX` family.
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        value.into_socket(&[AddressFamily::Unix], SockType::Datagram, false)
+    }
+}
+
+/// Connected socket passed to a per-connection service (`Accept=yes`).
+///
+/// With `Accept=yes`, the service manager accepts incoming connections itself
+/// and spawns one service instance per connection, passing it a single
+/// connected socket instead of a listening one.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::activation;
+/// use std::net::TcpStream;
+/// use std::convert::TryFrom;
+///
+/// let conn = activation::receive_connection(true)?;
+/// println!("connection from {}", conn.peer_addr()?);
+/// let stream = TcpStream::try_from(conn).map_err(|(err, _)| err)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct Connection {
+    fd: FileDescriptor,
+}
+
+/// Credentials of the peer process of a Unix socket, at connection time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pid: unistd::Pid,
+    uid: unistd::Uid,
+    gid: unistd::Gid,
+}
+
+impl PeerCredentials {
+    /// Return the PID of the peer process.
+    pub fn pid(&self) -> unistd::Pid {
+        self.pid
+    }
+
+    /// Return the UID of the peer process.
+    pub fn uid(&self) -> unistd::Uid {
+        self.uid
+    }
+
+    /// Return the GID of the peer process.
+    pub fn gid(&self) -> unistd::Gid {
+        self.gid
+    }
+}
+
+/// Receive the connected socket passed to a per-connection service.
+///
+/// This expects exactly one descriptor, which must be a connected stream or
+/// sequential packet socket. If `unset_env` is true, the environment variables
+/// used by systemd will be cleared.
+pub fn receive_connection(unset_env: bool) -> Result<Connection, SdError> {
+    let mut fds = receive_descriptors(unset_env)?;
+    if fds.len() != 1 {
+        return Err(format!("expected a single connection descriptor, got {}", fds.len()).into());
+    }
+    Connection::try_from(fds.remove(0)).map_err(|(err, _)| err)
+}
+
+impl Connection {
+    /// Returns the local address of the connection.
+    pub fn local_addr(&self) -> Result<SocketAddress, SdError> {
+        self.fd.local_addr()
+    }
+
+    /// Returns the address of the connected peer.
+    pub fn peer_addr(&self) -> Result<SocketAddress, SdError> {
+        self.fd.peer_addr()
+    }
+
+    /// Returns the credentials of the connected peer, for Unix sockets.
+    pub fn peer_credentials(&self) -> Result<PeerCredentials, SdError> {
+        if !self.fd.is_unix() {
+            return Err(format!(
+                "peer credentials are only available for Unix sockets (fd {})",
+                self.fd.as_raw_fd()
+            )
+            .into());
+        }
+        let creds = getsockopt(&self.fd.as_fd(), sockopt::PeerCredentials)
+            .context("failed to get peer credentials")?;
+        Ok(PeerCredentials {
+            pid: unistd::Pid::from_raw(creds.pid()),
+            uid: unistd::Uid::from_raw(creds.uid()),
+            gid: unistd::Gid::from_raw(creds.gid()),
+        })
+    }
+}
+
+impl TryFrom<FileDescriptor> for Connection {
+    type Error = (SdError, FileDescriptor);
+
+    /// Check that the descriptor is a connected stream or sequential packet socket.
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        let sock_type = value.socket_type();
+        let connection_oriented = matches!(sock_type, Some(SockType::Stream | SockType::SeqPacket));
+        if !connection_oriented || value.is_listening() != Some(false) {
+            let err_msg = format!(
+                "file descriptor {} is not a connected socket (type {:?}, listening {:?})",
+                value.as_raw_fd(),
+                sock_type,
+                value.is_listening(),
+            );
+            return Err((err_msg.into(), value));
+        }
+        Ok(Connection { fd: value })
+    }
+}
+
+impl TryFrom<Connection> for TcpStream {
+    type Error = (SdError, Connection);
+
+    /// Convert into a TCP stream, checking that the connection is a
+    /// `SOCK_STREAM` socket of the `PF_INET`/`PF_INET6` family.
+    fn try_from(value: Connection) -> Result<Self, Self::Error> {
+        let families = [AddressFamily::Inet, AddressFamily::Inet6];
+        value
+            .fd
+            .into_socket(&families, SockType::Stream, false)
+            .map_err(|(err, fd)| (err, Connection { fd }))
+    }
+}
+
+impl TryFrom<Connection> for UnixStream {
+    type Error = (SdError, Connection);
+
+    /// Convert into a Unix stream, checking that the connection is a
+    /// `SOCK_STREAM` socket of the `PF_UNIX` family.
+    fn try_from(value: Connection) -> Result<Self, Self::Error> {
+        value
+            .fd
+            .into_socket(&[AddressFamily::Unix], SockType::Stream, false)
+            .map_err(|(err, fd)| (err, Connection { fd }))
+    }
+}
+
+impl From<Connection> for FileDescriptor {
+    fn from(value: Connection) -> Self {
+        value.fd
+    }
+}
+
+impl AsFd for Connection {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for Connection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Convert into a `socket2` socket, checking that the descriptor is a socket.
+///
+/// This allows tweaking socket options (e.g. `TCP_NODELAY`, `SO_REUSEPORT`
+/// or buffer sizes) through a typed API, before converting the socket into
+/// a standard type.
+#[cfg(feature = "socket2")]
+impl TryFrom<FileDescriptor> for socket2::Socket {
+    type Error = (SdError, FileDescriptor);
+
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        if value.socket_type().is_none() {
+            let err_msg = format!("file descriptor {} is not a socket", value.as_raw_fd());
+            return Err((err_msg.into(), value));
+        }
+        Ok(socket2::Socket::from(OwnedFd::from(value)))
+    }
+}
+
+#[cfg(feature = "socket2")]
+impl FileDescriptor {
+    /// Borrow the descriptor as a `socket2` socket, to tweak socket options
+    /// without taking ownership. Returns `None` if it is not a socket.
+    pub fn sock_ref(&self) -> Option<socket2::SockRef<'_>> {
+        self.socket_type()?;
+        Some(socket2::SockRef::from(self))
+    }
+}
+
+/// Register a checked standard socket with the Tokio runtime, in non-blocking mode.
+#[cfg(feature = "tokio")]
+fn into_tokio<S, T>(
+    value: FileDescriptor,
+    set_nonblocking: fn(&S, bool) -> io::Result<()>,
+    from_std: fn(S) -> io::Result<T>,
+) -> Result<T, SdError>
+where
+    S: TryFrom<FileDescriptor, Error = (SdError, FileDescriptor)>,
+{
+    let socket = S::try_from(value).map_err(|(err, _)| err)?;
+    set_nonblocking(&socket, true).context("failed to set socket as non-blocking")?;
+    from_std(socket).context("failed to register socket with the runtime")
+}
+
+/// Convert into a Tokio TCP listener, after the same checks as for
+/// [`TcpListener`].
+///
+/// This must be called from within a Tokio runtime. On failure, the
+/// descriptor is closed.
+#[cfg(feature = "tokio")]
+impl TryFrom<FileDescriptor> for tokio::net::TcpListener {
+    type Error = SdError;
+
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        into_tokio(
+            value,
+            TcpListener::set_nonblocking,
+            tokio::net::TcpListener::from_std,
+        )
+    }
+}
+
+/// Convert into a Tokio UDP socket, after the same checks as for
+/// [`UdpSocket`].
+///
+/// This must be called from within a Tokio runtime. On failure, the
+/// descriptor is closed.
+#[cfg(feature = "tokio")]
+impl TryFrom<FileDescriptor> for tokio::net::UdpSocket {
+    type Error = SdError;
+
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        into_tokio(
+            value,
+            UdpSocket::set_nonblocking,
+            tokio::net::UdpSocket::from_std,
+        )
+    }
+}
+
+/// Convert into a Tokio Unix listener, after the same checks as for
+/// [`UnixListener`].
+///
+/// This must be called from within a Tokio runtime. On failure, the
+/// descriptor is closed.
+#[cfg(feature = "tokio")]
+impl TryFrom<FileDescriptor> for tokio::net::UnixListener {
+    type Error = SdError;
+
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        into_tokio(
+            value,
+            UnixListener::set_nonblocking,
+            tokio::net::UnixListener::from_std,
+        )
+    }
+}
+
+/// Convert into a Tokio Unix datagram socket, after the same checks as for
+/// [`UnixDatagram`].
+///
+/// This must be called from within a Tokio runtime. On failure, the
+/// descriptor is closed.
+#[cfg(feature = "tokio")]
+impl TryFrom<FileDescriptor> for tokio::net::UnixDatagram {
+    type Error = SdError;
+
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        into_tokio(
+            value,
+            UnixDatagram::set_nonblocking,
+            tokio::net::UnixDatagram::from_std,
+        )
+    }
+}
+
+/// POSIX message queue passed by systemd (`ListenMessageQueue=`).
+///
+/// This is obtained from a [`FileDescriptor`] classified as a message queue,
+/// and closes the queue when dropped.
+#[derive(Debug)]
+pub struct MessageQueue {
+    fd: OwnedFd,
+}
+
+/// Attributes of a POSIX message queue, as returned by `mq_getattr(3)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageQueueAttributes {
+    max_messages: i64,
+    message_size: i64,
+    current_messages: i64,
+    nonblocking: bool,
+}
+
+impl MessageQueueAttributes {
+    /// Maximum number of messages on the queue.
+    pub fn max_messages(&self) -> i64 {
+        self.max_messages
+    }
+
+    /// Maximum size of a single message, in bytes.
+    pub fn message_size(&self) -> i64 {
+        self.message_size
+    }
+
+    /// Number of messages currently queued.
+    pub fn current_messages(&self) -> i64 {
+        self.current_messages
+    }
+
+    /// Whether the queue descriptor is in non-blocking mode.
+    pub fn nonblocking(&self) -> bool {
+        self.nonblocking
+    }
+}
+
+impl MessageQueue {
+    /// Query the attributes of this queue.
+    pub fn attributes(&self) -> Result<MessageQueueAttributes, SdError> {
+        let mut attr = std::mem::MaybeUninit::<libc::mq_attr>::uninit();
+        // SAFETY: the descriptor is a valid mq and `attr` is only read on success.
+        let res = unsafe { libc::mq_getattr(self.fd.as_raw_fd(), attr.as_mut_ptr()) };
+        if res != 0 {
+            return Err(io::Error::last_os_error()).context("mq_getattr failed");
+        }
+        let attr = unsafe { attr.assume_init() };
+        // Fields are `c_long`, which is narrower than `i64` on 32-bit targets.
+        #[allow(clippy::unnecessary_cast)]
+        Ok(MessageQueueAttributes {
+            max_messages: attr.mq_maxmsg as i64,
+            message_size: attr.mq_msgsize as i64,
+            current_messages: attr.mq_curmsgs as i64,
+            nonblocking: attr.mq_flags & (libc::O_NONBLOCK as libc::c_long) != 0,
+        })
+    }
+
+    /// Receive the oldest message with the highest priority.
+    ///
+    /// The message is written into `buf`, which must be at least as large as
+    /// the queue message size. Returns the message length and its priority.
+    pub fn receive(&self, buf: &mut [u8]) -> Result<(usize, u32), SdError> {
+        let mut priority: libc::c_uint = 0;
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes.
+        let len = unsafe {
+            libc::mq_receive(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                &mut priority,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error()).context("mq_receive failed");
+        }
+        Ok((len as usize, priority))
+    }
+
+    /// Send a message with the given priority.
+    pub fn send(&self, msg: &[u8], priority: u32) -> Result<(), SdError> {
+        // SAFETY: `msg` is valid for reads of `msg.len()` bytes.
+        let res = unsafe {
+            libc::mq_send(
+                self.fd.as_raw_fd(),
+                msg.as_ptr().cast(),
+                msg.len(),
+                priority,
+            )
+        };
+        if res != 0 {
+            return Err(io::Error::last_os_error()).context("mq_send failed");
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<FileDescriptor> for MessageQueue {
+    type Error = (SdError, FileDescriptor);
+
+    /// Convert into a message queue, checking that the descriptor is a POSIX mq.
+    fn try_from(value: FileDescriptor) -> Result<Self, Self::Error> {
+        if !value.as_fd().is_mq() {
+            let err_msg = format!(
+                "file descriptor {} is not a POSIX message queue",
+                value.as_raw_fd()
+            );
+            return Err((err_msg.into(), value));
+        }
+        Ok(MessageQueue {
+            fd: OwnedFd::from(value),
+        })
+    }
+}
+
+impl From<MessageQueue> for OwnedFd {
+    fn from(value: MessageQueue) -> Self {
+        value.fd
+    }
+}
+
+impl AsFd for MessageQueue {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for MessageQueue {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<FileDescriptor> for OwnedFd {
+    fn from(value: FileDescriptor) -> Self {
+        match value.0 {
+            SocketFd::Fifo(fd) => fd,
+            SocketFd::Special(fd) => fd,
+            SocketFd::Inet(fd) => fd,
+            SocketFd::Unix(fd) => fd,
+            SocketFd::Mq(fd) => fd,
+            SocketFd::Vsock(fd) => fd,
+            SocketFd::Netlink(fd) => fd,
+            SocketFd::Unknown(fd) => fd,
+        }
+    }
+}
+
+impl AsFd for FileDescriptor {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self.0 {
+            SocketFd::Fifo(ref fd) => fd.as_fd(),
+            SocketFd::Special(ref fd) => fd.as_fd(),
+            SocketFd::Inet(ref fd) => fd.as_fd(),
+            SocketFd::Unix(ref fd) => fd.as_fd(),
+            SocketFd::Mq(ref fd) => fd.as_fd(),
+            SocketFd::Vsock(ref fd) => fd.as_fd(),
+            SocketFd::Netlink(ref fd) => fd.as_fd(),
+            SocketFd::Unknown(ref fd) => fd.as_fd(),
+        }
+    }
+}
+
+impl AsRawFd for FileDescriptor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.as_fd().as_raw_fd()
+    }
+}
+
+/// Raw file descriptor escape hatch, giving up ownership.
+///
+/// **Deprecated**: use `From<FileDescriptor> for OwnedFd` instead, or the
+/// checked conversions into standard socket types. This impl will be
+/// removed in a future release (trait impls cannot carry a `#[deprecated]`
+/// attribute).
+impl IntoRawFd for FileDescriptor {
+    fn into_raw_fd(self) -> RawFd {
+        OwnedFd::from(self).into_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::socket::{socket, SockFlag, SockProtocol};
+
+    fn dummy_fd() -> OwnedFd {
+        std::fs::File::open("/dev/null").unwrap().into()
+    }
+
+    #[test]
+    fn test_socketype_is_unix() {
+        let sock = FileDescriptor(SocketFd::Unix(dummy_fd()));
+        assert!(sock.is_unix());
+    }
+
+    #[test]
+    fn test_socketype_is_special() {
+        let sock = FileDescriptor(SocketFd::Special(dummy_fd()));
+        assert!(sock.is_special());
+    }
+
+    #[test]
+    fn test_socketype_is_inet() {
+        let sock = FileDescriptor(SocketFd::Inet(dummy_fd()));
+        assert!(sock.is_inet());
+    }
+
+    #[test]
+    fn test_socketype_is_fifo() {
+        let sock = FileDescriptor(SocketFd::Fifo(dummy_fd()));
+        assert!(sock.is_fifo());
+    }
+
+    #[test]
+    fn test_socketype_is_mq() {
+        let sock = FileDescriptor(SocketFd::Mq(dummy_fd()));
+        assert!(sock.is_mq());
+    }
+
+    #[test]
+    fn test_socketype_is_vsock() {
+        let sock = FileDescriptor(SocketFd::Vsock(dummy_fd()));
+        assert!(sock.is_vsock());
+        assert!(!sock.is_unix());
+
+        // The vsock address family may be unavailable, e.g. in containers.
+        if let Ok(fd) = socket(
+            AddressFamily::Vsock,
+            SockType::Stream,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        ) {
+            let fd = FileDescriptor::try_from(fd).unwrap();
+            assert!(fd.is_vsock());
+            assert!(!fd.is_inet());
+        }
+    }
+
+    #[test]
+    fn test_socketype_is_netlink() {
+        let sock = FileDescriptor(SocketFd::Netlink(dummy_fd()));
+        assert!(sock.is_netlink());
+        assert!(!sock.is_vsock());
+
+        let fd = socket(
+            AddressFamily::Netlink,
+            SockType::Raw,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkRoute,
+        )
+        .unwrap();
+        let fd = FileDescriptor::try_from(fd).unwrap();
+        assert!(fd.is_netlink());
+        assert_eq!(fd.netlink_protocol(), Some(libc::NETLINK_ROUTE));
+        assert!(fd.is_socket_netlink(None));
+        assert!(fd.is_socket_netlink(Some(libc::NETLINK_ROUTE)));
+        assert!(!fd.is_socket_netlink(Some(libc::NETLINK_AUDIT)));
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(udp)).unwrap();
+        assert_eq!(fd.netlink_protocol(), None);
+        assert!(!fd.is_socket_netlink(None));
+    }
+
+    #[test]
+    fn test_is_socket_inet() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let fd = FileDescriptor::try_from(OwnedFd::from(listener)).unwrap();
+
+        assert!(fd.is_socket_inet(None, None, None, None));
+        assert!(fd.is_socket_inet(
+            Some(AddressFamily::Inet),
+            Some(SockType::Stream),
+            Some(true),
+            Some(port)
+        ));
+        assert!(!fd.is_socket_inet(Some(AddressFamily::Inet6), None, None, None));
+        assert!(!fd.is_socket_inet(Some(AddressFamily::Unix), None, None, None));
+        assert!(!fd.is_socket_inet(None, Some(SockType::Datagram), None, None));
+        assert!(!fd.is_socket_inet(None, None, Some(false), None));
+        assert!(!fd.is_socket_inet(None, None, None, Some(port.wrapping_add(1))));
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(udp)).unwrap();
+        assert!(fd.is_socket_inet(None, Some(SockType::Datagram), Some(false), None));
+    }
+
+    #[test]
+    fn test_is_socket_sockaddr() {
+        use nix::sys::socket::UnixAddr;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut local_addr = listener.local_addr().unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(listener)).unwrap();
+
+        let addr = SockaddrStorage::from(local_addr);
+        assert!(fd.is_socket_sockaddr(Some(SockType::Stream), &addr, Some(true)));
+        assert!(!fd.is_socket_sockaddr(Some(SockType::Datagram), &addr, None));
+        local_addr.set_port(local_addr.port().wrapping_add(1));
+        let addr = SockaddrStorage::from(local_addr);
+        assert!(!fd.is_socket_sockaddr(None, &addr, None));
+
+        let name = format!("libsystemd-rs-sockaddr-{}", std::process::id());
+        let addr = UnixAddr::new_abstract(name.as_bytes()).unwrap();
+        let datagram = nix::sys::socket::socket(
+            AddressFamily::Unix,
+            SockType::Datagram,
+            nix::sys::socket::SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .unwrap();
+        nix::sys::socket::bind(datagram.as_raw_fd(), &addr).unwrap();
+        let fd = FileDescriptor::try_from(datagram).unwrap();
+        assert!(fd.is_socket_sockaddr(Some(SockType::Datagram), &addr, Some(false)));
+        let other = UnixAddr::new_abstract(b"other").unwrap();
+        assert!(!fd.is_socket_sockaddr(None, &other, None));
+        assert!(!fd.is_socket_sockaddr(None, &SockaddrStorage::from(local_addr), None));
+    }
+
+    #[test]
+    fn test_fd_names() {
+        assert_eq!(fd_names(2, Some("http:https")), vec!["http", "https"]);
+        assert_eq!(fd_names(2, Some("http")), vec!["http", "unknown"]);
+        assert_eq!(fd_names(1, Some("http:https")), vec!["http"]);
+        assert_eq!(fd_names(1, None), vec!["unknown"]);
+        assert!(fd_names(0, None).is_empty());
+    }
+
+    #[test]
+    fn test_listeners() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let metrics = TcpListener::bind("127.0.0.1:0").unwrap();
+        let datagram = UnixDatagram::unbound().unwrap();
+        let descriptors = vec![
+            (
+                FileDescriptor::try_from(OwnedFd::from(tcp)).unwrap(),
+                "http".to_string(),
+            ),
+            (
+                FileDescriptor::try_from(OwnedFd::from(metrics)).unwrap(),
+                "metrics".to_string(),
+            ),
+            (
+                FileDescriptor::try_from(OwnedFd::from(datagram)).unwrap(),
+                "log".to_string(),
+            ),
+        ];
+
+        let mut listeners = Listeners::from_descriptors(descriptors);
+        assert_eq!(listeners.unclaimed(), vec!["http", "metrics", "log"]);
+        assert_eq!(listeners.named("metrics").len(), 1);
+        assert!(listeners.named("metrics").is_empty());
+        assert_eq!(listeners.tcp_listeners().len(), 1);
+        assert!(listeners.unix_listeners().is_empty());
+        assert_eq!(listeners.unclaimed(), vec!["log"]);
+        assert_eq!(listeners.unix_datagrams().len(), 1);
+        assert!(listeners.unclaimed().is_empty());
+        assert!(listeners.remaining().is_empty());
+    }
+
+    #[test]
+    fn test_spawn_with_descriptors() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let datagram = UnixDatagram::unbound().unwrap();
+        // Inheritable, and well above the range of passed descriptors.
+        let (socket, _peer) = UnixDatagram::pair().unwrap();
+        let leaked = fcntl(socket.as_raw_fd(), FcntlArg::F_DUPFD(16)).unwrap();
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(format!(
+            r#"[ "$LISTEN_PID" = "$$" ] && [ ! -e /proc/$$/fd/{} ] && echo "$LISTEN_FDS $LISTEN_FDNAMES" && readlink /proc/$$/fd/3 /proc/$$/fd/4"#,
+            leaked
+        ));
+        cmd.stdout(process::Stdio::piped())
+            .env("LISTEN_PID", "1")
+            .env_remove("LISTEN_FDNAMES");
+        let fds: Vec<(OwnedFd, &str)> = vec![(tcp.into(), "http"), (datagram.into(), "log")];
+        let child = spawn_with_descriptors(&mut cmd, fds).unwrap();
+        unistd::close(leaked).unwrap();
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let mut lines = stdout.lines();
+        assert_eq!(lines.next(), Some("2 http:log"));
+        assert!(lines.all(|l| l.starts_with("socket:")));
+
+        let mut cmd = Command::new("true");
+        let invalid: Vec<(OwnedFd, &str)> = vec![(dummy_fd(), "in:valid")];
+        spawn_with_descriptors(&mut cmd, invalid).unwrap_err();
+    }
+
+    #[test]
+    fn test_spawn_receive_descriptors() {
+        // The child is this test binary, running only this test.
+        if env::var_os("LIBSYSTEMD_TEST_RECEIVE_CHILD").is_some() {
+            let fds = receive_descriptors_with_names(false).unwrap();
+            let names: Vec<&str> = fds.iter().map(|(_, name)| name.as_str()).collect();
+            assert_eq!(names, ["http", "log"]);
+            assert!(fds[0].0.is_inet());
+            assert!(fds[1].0.is_unix());
+            return;
+        }
+
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let datagram = UnixDatagram::unbound().unwrap();
+        let mut cmd = Command::new(env::current_exe().unwrap());
+        cmd.args([
+            "--exact",
+            "activation::tests::test_spawn_receive_descriptors",
+        ])
+        .env("LIBSYSTEMD_TEST_RECEIVE_CHILD", "1")
+        .stdout(process::Stdio::piped());
+        let fds: Vec<(OwnedFd, &str)> = vec![(tcp.into(), "http"), (datagram.into(), "log")];
+        let child = spawn_with_descriptors(&mut cmd, fds).unwrap();
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("1 passed"), "{}", stdout);
+    }
+
+    #[test]
+    fn test_message_queue() {
+        let name = format!("/libsystemd-test-{}\0", process::id());
+        let mut attr: libc::mq_attr = unsafe { std::mem::zeroed() };
+        attr.mq_maxmsg = 4;
+        attr.mq_msgsize = 64;
+        let raw = unsafe {
+            libc::mq_open(
+                name.as_ptr().cast(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_NONBLOCK,
+                0o600,
+                &attr,
+            )
+        };
+        assert!(raw >= 0, "{}", io::Error::last_os_error());
+        unsafe { libc::mq_unlink(name.as_ptr().cast()) };
+
+        let fd = FileDescriptor::try_from(unsafe { OwnedFd::from_raw_fd(raw) }).unwrap();
+        assert!(fd.is_mq());
+        assert!(!fd.is_special());
+        let mq = MessageQueue::try_from(fd).unwrap();
+        mq.send(b"low", 1).unwrap();
+        mq.send(b"high", 5).unwrap();
+
+        let attrs = mq.attributes().unwrap();
+        assert_eq!(attrs.max_messages(), 4);
+        assert_eq!(attrs.message_size(), 64);
+        assert_eq!(attrs.current_messages(), 2);
+        assert!(attrs.nonblocking());
+
+        let mut buf = [0u8; 64];
+        assert_eq!(mq.receive(&mut buf).unwrap(), (4, 5));
+        assert_eq!(&buf[..4], b"high");
+        assert_eq!(mq.receive(&mut buf).unwrap(), (3, 1));
+        mq.receive(&mut buf).unwrap_err();
+
+        let fd = FileDescriptor(SocketFd::Unknown(dummy_fd()));
+        MessageQueue::try_from(fd).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_listen_env() {
+        let pid = process::id().to_string();
+        let mut source: HashMap<String, String> = HashMap::new();
+        source.insert("LISTEN_FDS".to_string(), "2".to_string());
+        source.insert("LISTEN_FDNAMES".to_string(), "a:b".to_string());
+
+        let default = ReceiveOptions::new();
+        parse_listen_env(&source, &default).unwrap_err();
+        let skip = ReceiveOptions::new().pid_check(PidCheck::Skip);
+        let (fds, names) = parse_listen_env(&source, &skip).unwrap();
+        assert_eq!(fds, 2);
+        assert_eq!(names.as_deref(), Some("a:b"));
+
+        source.insert("LISTEN_PID".to_string(), pid);
+        parse_listen_env(&source, &default).unwrap();
+        let other = ReceiveOptions::new().pid_check(PidCheck::Expect(1));
+        parse_listen_env(&source, &other).unwrap_err();
+        source.insert("LISTEN_PID".to_string(), "1".to_string());
+        parse_listen_env(&source, &other).unwrap();
+        parse_listen_env(&source, &default).unwrap_err();
+
+        source.insert("LISTEN_FDS".to_string(), "0".to_string());
+        let fds = receive_descriptors_from_env(&source, &skip).unwrap();
+        assert!(fds.is_empty());
+    }
+
+    #[test]
+    fn test_claim_fds() {
+        let (a, b) = (i32::MAX - 2, i32::MAX - 1);
+        claim_fds(&[a]).unwrap();
+        claim_fds(&[a]).unwrap_err();
+        claim_fds(&[b, a]).unwrap_err();
+        claim_fds(&[b]).unwrap();
+        claim_fds(&[]).unwrap();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_tokio_conversions() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(tcp)).unwrap();
+        let listener = tokio::net::TcpListener::try_from(fd).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        let _client = std::net::TcpStream::connect(addr).unwrap();
+        listener.accept().await.unwrap();
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(udp)).unwrap();
+        tokio::net::TcpListener::try_from(fd.try_clone().unwrap()).unwrap_err();
+        tokio::net::UnixDatagram::try_from(fd.try_clone().unwrap()).unwrap_err();
+        let socket = tokio::net::UdpSocket::try_from(fd).unwrap();
+        let mut buf = [0u8; 4];
+        assert!(socket.try_recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_owned_conversions() {
+        let sock = FileDescriptor::try_from(dummy_fd())
+            .unwrap_or_else(|(_, fd)| FileDescriptor(SocketFd::Unknown(fd)));
+        let raw = sock.as_raw_fd();
+        let cloned = sock.try_clone().unwrap();
+        assert_ne!(cloned.as_raw_fd(), raw);
+
+        let owned = OwnedFd::from(sock);
+        assert_eq!(owned.as_raw_fd(), raw);
+    }
+
+    #[test]
+    fn test_socket_conversions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(listener)).unwrap();
+        assert!(fd.is_inet());
+        let (_, fd) = UdpSocket::try_from(fd).unwrap_err();
+        let (_, fd) = UnixListener::try_from(fd).unwrap_err();
+        let listener = TcpListener::try_from(fd).unwrap();
+        listener.local_addr().unwrap();
+
+        let datagram = UnixDatagram::unbound().unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(datagram)).unwrap();
+        assert!(fd.is_unix());
+        let (_, fd) = UnixListener::try_from(fd).unwrap_err();
+        let (_, fd) = TcpListener::try_from(fd).unwrap_err();
+        UnixDatagram::try_from(fd).unwrap();
+    }
+
+    #[test]
+    fn test_classification() {
+        let fd = FileDescriptor::try_from(dummy_fd()).unwrap();
+        assert!(fd.is_special());
+        assert_eq!(fd.socket_type(), None);
+        assert_eq!(fd.is_listening(), None);
+
+        let file = std::fs::File::open("/proc/self/status").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(file)).unwrap();
+        assert!(fd.is_special());
+
+        let (rx, _tx) = std::os::unix::net::UnixStream::pair().unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(rx)).unwrap();
+        assert!(fd.is_unix());
+        assert!(!fd.is_inet());
+        assert_eq!(fd.socket_type(), Some(SockType::Stream));
+        assert_eq!(fd.is_listening(), Some(false));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(listener)).unwrap();
+        assert!(fd.is_inet());
+        assert!(!fd.is_unix());
+        assert_eq!(fd.socket_type(), Some(SockType::Stream));
+        assert_eq!(fd.is_listening(), Some(true));
+
+        let seqpacket = socket(
+            AddressFamily::Unix,
+            SockType::SeqPacket,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .unwrap();
+        let fd = FileDescriptor::try_from(seqpacket).unwrap();
+        assert!(fd.is_unix());
+        assert_eq!(fd.socket_type(), Some(SockType::SeqPacket));
+    }
+
+    #[test]
+    fn test_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::net::TcpStream::connect(addr).unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(listener)).unwrap();
+        assert_eq!(fd.local_addr().unwrap(), SocketAddress::Inet(addr));
+        fd.peer_addr().unwrap_err();
+        let fd = FileDescriptor::try_from(OwnedFd::from(client)).unwrap();
+        assert_eq!(fd.peer_addr().unwrap(), SocketAddress::Inet(addr));
+
+        let (rx, _tx) = std::os::unix::net::UnixStream::pair().unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(rx)).unwrap();
+        assert_eq!(fd.local_addr().unwrap(), SocketAddress::UnixUnnamed);
+        assert_eq!(fd.peer_addr().unwrap().to_string(), "(unnamed)");
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("socket");
+        let datagram = UnixDatagram::bind(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(datagram)).unwrap();
+        assert_eq!(fd.local_addr().unwrap(), SocketAddress::UnixPath(path));
+
+        let fd = FileDescriptor::try_from(dummy_fd()).unwrap();
+        fd.local_addr().unwrap_err();
+
+        let vsock = SocketAddress::Vsock { cid: 2, port: 1024 };
+        assert_eq!(vsock.to_string(), "vsock:2:1024");
+        let abstract_name = SocketAddress::UnixAbstract(b"name".to_vec());
+        assert_eq!(abstract_name.to_string(), "@name");
+    }
+
+    #[test]
+    fn test_partitioned_descriptors() {
+        let fds = vec![
+            (
+                FileDescriptor(SocketFd::Inet(dummy_fd())),
+                "http".to_string(),
+            ),
+            (
+                FileDescriptor(SocketFd::Special(dummy_fd())),
+                "cache".to_string(),
+            ),
+            (
+                FileDescriptor(SocketFd::Unix(dummy_fd())),
+                "unknown".to_string(),
+            ),
+            (
+                FileDescriptor(SocketFd::Special(dummy_fd())),
+                "state".to_string(),
+            ),
+        ];
+        let mut parts = PartitionedDescriptors::new(fds, &["cache", "state"]);
+        let sockets: Vec<&str> = parts.sockets().iter().map(|(_, n)| n.as_str()).collect();
+        assert_eq!(sockets, vec!["http", "unknown"]);
+        assert_eq!(parts.restored().len(), 2);
+
+        assert!(parts.take_restored("cache").unwrap().is_special());
+        assert!(parts.take_restored("cache").is_none());
+        assert_eq!(parts.take_sockets().len(), 2);
+        assert!(parts.sockets().is_empty());
+        assert_eq!(parts.restored()[0].1, "state");
+    }
+
+    #[test]
+    fn test_cloexec() {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(udp)).unwrap();
+        assert!(fd.is_cloexec().unwrap());
+        fd.set_cloexec(false).unwrap();
+        assert!(!fd.is_cloexec().unwrap());
+
+        let dup = fd.dup_above(100).unwrap();
+        assert!(dup.as_raw_fd() >= 100);
+        assert!(dup.is_inet());
+        assert!(dup.is_cloexec().unwrap());
+        assert_eq!(dup.local_addr().unwrap(), fd.local_addr().unwrap());
+
+        fd.set_cloexec(true).unwrap();
+        assert!(fd.is_cloexec().unwrap());
+    }
+
+    #[cfg(feature = "socket2")]
+    #[test]
+    fn test_socket2() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(listener)).unwrap();
+        let sock_ref = fd.sock_ref().unwrap();
+        sock_ref.set_nodelay(true).unwrap();
+        assert!(sock_ref.nodelay().unwrap());
+        sock_ref.set_reuse_port(true).unwrap();
+
+        let socket = socket2::Socket::try_from(fd).unwrap();
+        assert!(socket.reuse_port().unwrap());
+        assert!(socket.local_addr().unwrap().as_socket_ipv4().is_some());
+
+        let fd = FileDescriptor::try_from(dummy_fd()).unwrap();
+        assert!(fd.sock_ref().is_none());
+        socket2::Socket::try_from(fd).unwrap_err();
+    }
+
+    #[test]
+    fn test_connection() {
+        let (rx, _tx) = UnixStream::pair().unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(rx)).unwrap();
+        let conn = Connection::try_from(fd).unwrap();
+        let creds = conn.peer_credentials().unwrap();
+        assert_eq!(creds.pid(), unistd::getpid());
+        assert_eq!(creds.uid(), unistd::getuid());
+        let (_, conn) = TcpStream::try_from(conn).unwrap_err();
+        UnixStream::try_from(conn).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::net::TcpStream::connect(addr).unwrap();
+        let local = client.local_addr().unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(listener)).unwrap();
+        let (_, fd) = Connection::try_from(fd).unwrap_err();
+        let (accepted, _) = TcpListener::try_from(fd).unwrap().accept().unwrap();
+
+        let fd = FileDescriptor::try_from(OwnedFd::from(accepted)).unwrap();
+        let conn = Connection::try_from(fd).unwrap();
+        assert_eq!(conn.peer_addr().unwrap(), SocketAddress::Inet(local));
+        assert_eq!(conn.local_addr().unwrap(), SocketAddress::Inet(addr));
+        conn.peer_credentials().unwrap_err();
+        let stream = TcpStream::try_from(conn).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), local);
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let fd = FileDescriptor::try_from(OwnedFd::from(udp)).unwrap();
+        Connection::try_from(fd).unwrap_err();
+    }
+}
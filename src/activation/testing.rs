@@ -0,0 +1,157 @@
+//! Emulation of socket activation, for integration tests.
+//!
+//! This binds sockets and spawns a child process with them, following the
+//! same protocol as the service manager: descriptors are renumbered starting
+//! at fd 3, and `LISTEN_FDS`, `LISTEN_PID` and `LISTEN_FDNAMES` are set in
+//! the child environment. This allows testing socket-activated services
+//! without a running systemd instance.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use libsystemd::activation::testing::Harness;
+//! use std::process::Command;
+//!
+//! let mut harness = Harness::new();
+//! let addr = harness.listen_tcp("http", "127.0.0.1:0")?;
+//! let mut child = harness.spawn(&mut Command::new("./my-service"))?;
+//! // Connect to `addr` and exercise the service...
+//! child.kill()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::errors::{Context, SdError};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs, UdpSocket};
+use std::os::unix::io::OwnedFd;
+use std::os::unix::net::{UnixDatagram, UnixListener};
+use std::path::Path;
+use std::process::{Child, Command};
+
+/// Set of named descriptors to pass to a socket-activated child process.
+#[derive(Debug, Default)]
+pub struct Harness {
+    fds: Vec<(OwnedFd, String)>,
+}
+
+impl Harness {
+    /// Create an empty harness.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind a listening TCP socket, returning its local address.
+    ///
+    /// Binding on port 0 picks a free port, which can be retrieved
+    /// from the returned address.
+    pub fn listen_tcp(
+        &mut self,
+        name: &str,
+        addr: impl ToSocketAddrs,
+    ) -> Result<SocketAddr, SdError> {
+        let listener = TcpListener::bind(addr).context("failed to bind TCP listener")?;
+        let local = listener
+            .local_addr()
+            .context("failed to get TCP listener address")?;
+        self.add_fd(name, listener);
+        Ok(local)
+    }
+
+    /// Bind a UDP socket, returning its local address.
+    pub fn bind_udp(
+        &mut self,
+        name: &str,
+        addr: impl ToSocketAddrs,
+    ) -> Result<SocketAddr, SdError> {
+        let socket = UdpSocket::bind(addr).context("failed to bind UDP socket")?;
+        let local = socket
+            .local_addr()
+            .context("failed to get UDP socket address")?;
+        self.add_fd(name, socket);
+        Ok(local)
+    }
+
+    /// Bind a listening Unix stream socket at `path`.
+    pub fn listen_unix(&mut self, name: &str, path: impl AsRef<Path>) -> Result<(), SdError> {
+        let path = path.as_ref();
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed to bind Unix listener at {}", path.display()))?;
+        self.add_fd(name, listener);
+        Ok(())
+    }
+
+    /// Bind a Unix datagram socket at `path`.
+    pub fn bind_unix_datagram(
+        &mut self,
+        name: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<(), SdError> {
+        let path = path.as_ref();
+        let socket = UnixDatagram::bind(path)
+            .with_context(|| format!("failed to bind Unix datagram at {}", path.display()))?;
+        self.add_fd(name, socket);
+        Ok(())
+    }
+
+    /// Add an arbitrary descriptor (e.g. a FIFO or an already bound socket).
+    ///
+    /// The name is validated when spawning.
+    pub fn add_fd(&mut self, name: &str, fd: impl Into<OwnedFd>) -> &mut Self {
+        self.fds.push((fd.into(), name.to_string()));
+        self
+    }
+
+    /// Return the number of descriptors to be passed.
+    pub fn len(&self) -> usize {
+        self.fds.len()
+    }
+
+    /// Return true if no descriptors are to be passed.
+    pub fn is_empty(&self) -> bool {
+        self.fds.is_empty()
+    }
+
+    /// Spawn `cmd` as a socket-activated child process.
+    ///
+    /// Descriptors are handed over in the order they were added, and the
+    /// harness is consumed. See [`spawn_with_descriptors`](super::spawn_with_descriptors).
+    pub fn spawn(self, cmd: &mut Command) -> Result<Child, SdError> {
+        super::spawn_with_descriptors(cmd, self.fds)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::process::Stdio;
+
+    #[test]
+    fn test_harness() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+
+        let mut harness = Harness::new();
+        assert!(harness.is_empty());
+        let addr = harness.listen_tcp("http", "127.0.0.1:0").unwrap();
+        assert_ne!(addr.port(), 0);
+        harness.bind_udp("dns", "127.0.0.1:0").unwrap();
+        harness.listen_unix("control", dir.join("control")).unwrap();
+        assert_eq!(harness.len(), 3);
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(r#"[ "$LISTEN_PID" = "$$" ] && echo "$LISTEN_FDS $LISTEN_FDNAMES" && ls /proc/$$/fd"#)
+            .stdout(Stdio::piped());
+        let output = harness.spawn(&mut cmd).unwrap().wait_with_output().unwrap();
+
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let mut lines = stdout.lines();
+        assert_eq!(lines.next(), Some("3 http:dns:control"));
+        let fds: Vec<&str> = lines.collect();
+        assert!(["3", "4", "5"].iter().all(|fd| fds.contains(fd)));
+
+        let mut harness = Harness::new();
+        harness.add_fd("not:valid", UdpSocket::bind("127.0.0.1:0").unwrap());
+        harness.spawn(&mut Command::new("true")).unwrap_err();
+    }
+}
@@ -0,0 +1,376 @@
+//! Helpers for working with `binfmt.d` configuration files.
+//!
+//! For the complete documentation see
+//! <https://www.freedesktop.org/software/systemd/man/binfmt.d.html> and
+//! the kernel `binfmt_misc` documentation.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # fn doctest_parse() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::binfmt::{BinfmtRule, MatchType};
+//!
+//! let config_fragment = r#"
+//! ## Start WINE on Windows executables
+//! :DOSWin:M::MZ::/usr/bin/wine:
+//! "#;
+//!
+//! let rules = BinfmtRule::parse_lines(config_fragment)?;
+//! assert_eq!(rules[0].name(), "DOSWin");
+//! assert_eq!(rules[0].match_type(), MatchType::Magic);
+//! assert_eq!(rules[0].interpreter(), "/usr/bin/wine");
+//! # Ok(())
+//! # }
+//! # doctest_parse().unwrap();
+//! ```
+
+use crate::conffiles::{conf_dirs, conf_files};
+use crate::errors::{Context, SdError};
+use std::fmt;
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How a `binfmt_misc` rule recognizes binaries.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MatchType {
+    /// Match on magic bytes at a given offset (`M`).
+    Magic,
+    /// Match on the file-name extension (`E`).
+    Extension,
+}
+
+impl MatchType {
+    fn as_char(self) -> char {
+        match self {
+            MatchType::Magic => 'M',
+            MatchType::Extension => 'E',
+        }
+    }
+}
+
+/// Single `binfmt_misc` registration rule, as found in `binfmt.d` files
+/// (`:name:type:offset:magic:mask:interpreter:flags`).
+///
+/// Magic and mask are kept in their escaped form (e.g. `\x7fELF`), as
+/// expected by the kernel.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BinfmtRule {
+    delimiter: char,
+    name: String,
+    match_type: MatchType,
+    offset: Option<u32>,
+    magic: String,
+    mask: Option<String>,
+    interpreter: String,
+    flags: String,
+}
+
+impl BinfmtRule {
+    /// Create a new rule matching on magic bytes at the start of the file.
+    pub fn new_magic(
+        name: impl Into<String>,
+        magic: impl Into<String>,
+        interpreter: impl Into<String>,
+    ) -> Result<Self, SdError> {
+        Self::new(
+            name.into(),
+            MatchType::Magic,
+            magic.into(),
+            interpreter.into(),
+        )
+    }
+
+    /// Create a new rule matching on the file-name extension (without dot).
+    pub fn new_extension(
+        name: impl Into<String>,
+        extension: impl Into<String>,
+        interpreter: impl Into<String>,
+    ) -> Result<Self, SdError> {
+        Self::new(
+            name.into(),
+            MatchType::Extension,
+            extension.into(),
+            interpreter.into(),
+        )
+    }
+
+    fn new(
+        name: String,
+        match_type: MatchType,
+        magic: String,
+        interpreter: String,
+    ) -> Result<Self, SdError> {
+        let rule = Self {
+            delimiter: ':',
+            name,
+            match_type,
+            offset: None,
+            magic,
+            mask: None,
+            interpreter,
+            flags: String::new(),
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Set the offset of the magic bytes in the file.
+    pub fn offset(mut self, offset: u32) -> Result<Self, SdError> {
+        self.offset = Some(offset);
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Set the mask applied to the file content before comparing it with the
+    /// magic bytes.
+    pub fn mask(mut self, mask: impl Into<String>) -> Result<Self, SdError> {
+        self.mask = Some(mask.into());
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Set the flags of this rule (among `P`, `O`, `C` and `F`).
+    pub fn flags(mut self, flags: impl Into<String>) -> Result<Self, SdError> {
+        self.flags = flags.into();
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Parse all rules from the content of a `binfmt.d` file.
+    pub fn parse_lines(input: &str) -> Result<Vec<BinfmtRule>, SdError> {
+        Self::from_reader(input.as_bytes())
+    }
+
+    /// Parse all rules of a `binfmt.d` file from a buffered reader.
+    pub fn from_reader(reader: impl BufRead) -> Result<Vec<BinfmtRule>, SdError> {
+        let mut output = vec![];
+        for (index, item) in reader.lines().enumerate() {
+            let linenumber = index.saturating_add(1);
+            let line = item.map_err(|e| format!("failed to read line {}: {}", linenumber, e))?;
+
+            let data = line.trim();
+            // Skip empty lines and comments.
+            if data.is_empty() || data.starts_with('#') || data.starts_with(';') {
+                continue;
+            }
+
+            let rule = data.parse().map_err(|e: SdError| {
+                format!(
+                    "failed to parse binfmt rule at line {}: {}",
+                    linenumber, e.msg
+                )
+            })?;
+            output.push(rule);
+        }
+        Ok(output)
+    }
+
+    /// Return the name of this rule, as registered in `binfmt_misc`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return how binaries are recognized.
+    pub fn match_type(&self) -> MatchType {
+        self.match_type
+    }
+
+    /// Return the offset of the magic bytes, if set.
+    pub fn get_offset(&self) -> Option<u32> {
+        self.offset
+    }
+
+    /// Return the magic bytes (escaped) or the extension.
+    pub fn magic(&self) -> &str {
+        &self.magic
+    }
+
+    /// Return the mask for the magic bytes (escaped), if set.
+    pub fn get_mask(&self) -> Option<&str> {
+        self.mask.as_deref()
+    }
+
+    /// Return the path of the interpreter.
+    pub fn interpreter(&self) -> &str {
+        &self.interpreter
+    }
+
+    /// Return the flags of this rule.
+    pub fn get_flags(&self) -> &str {
+        &self.flags
+    }
+
+    /// Format this rule as a `binfmt.d` configuration line.
+    pub fn to_config_line(&self) -> String {
+        self.to_string()
+    }
+
+    fn validate(&self) -> Result<(), SdError> {
+        let fields = [&self.name, &self.magic, &self.interpreter, &self.flags];
+        let mask = self.mask.as_deref().unwrap_or_default();
+        if fields.iter().any(|f| f.contains(self.delimiter)) || mask.contains(self.delimiter) {
+            return Err(format!("fields must not contain delimiter '{}'", self.delimiter).into());
+        }
+        if self.name.is_empty() || self.name == "." || self.name == ".." || self.name.contains('/')
+        {
+            return Err(format!("invalid rule name '{}'", self.name).into());
+        }
+        if self.magic.is_empty() {
+            return Err("empty magic".into());
+        }
+        if self.match_type == MatchType::Extension && (self.offset.is_some() || !mask.is_empty()) {
+            return Err("offset and mask are not supported for extension matching".into());
+        }
+        if self.interpreter.is_empty() {
+            return Err("empty interpreter".into());
+        }
+        if let Some(flag) = self.flags.chars().find(|c| !"POCF".contains(*c)) {
+            return Err(format!("unknown flag '{}'", flag).into());
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BinfmtRule {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let delimiter = s.chars().next().ok_or("empty rule")?;
+        let fields: Vec<&str> = s[delimiter.len_utf8()..].split(delimiter).collect();
+        if fields.len() < 6 || fields.len() > 7 {
+            return Err(format!("expected 6 or 7 fields, found {}", fields.len()).into());
+        }
+        let match_type = match fields[1] {
+            "M" => MatchType::Magic,
+            "E" => MatchType::Extension,
+            t => return Err(format!("unknown match type '{}'", t).into()),
+        };
+        let offset = match fields[2] {
+            "" => None,
+            o => Some(o.parse().map_err(|_| format!("invalid offset '{}'", o))?),
+        };
+        let rule = Self {
+            delimiter,
+            name: fields[0].to_string(),
+            match_type,
+            offset,
+            magic: fields[3].to_string(),
+            mask: Some(fields[4].to_string()).filter(|m| !m.is_empty()),
+            interpreter: fields[5].to_string(),
+            flags: fields.get(6).copied().unwrap_or_default().to_string(),
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for BinfmtRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = self.delimiter;
+        write!(
+            f,
+            "{}{}{}{}{}",
+            d,
+            self.name,
+            d,
+            self.match_type.as_char(),
+            d
+        )?;
+        if let Some(offset) = self.offset {
+            write!(f, "{}", offset)?;
+        }
+        write!(
+            f,
+            "{}{}{}{}{}{}{}",
+            d,
+            self.magic,
+            d,
+            self.mask.as_deref().unwrap_or_default(),
+            d,
+            self.interpreter,
+            d
+        )?;
+        write!(f, "{}", self.flags)
+    }
+}
+
+/// Load the effective `binfmt.d` configuration below the given roots.
+///
+/// Roots are given in precedence order (use `["/"]` for the running
+/// system). For each of them, `etc/binfmt.d`, `run/binfmt.d`,
+/// `usr/local/lib/binfmt.d` and `usr/lib/binfmt.d` are scanned, with
+/// file-name overriding and masking. Rules are returned in file-name order.
+pub fn load_config<P: AsRef<Path>>(roots: &[P]) -> Result<Vec<BinfmtRule>, SdError> {
+    let mut output = vec![];
+    for path in conf_files(&conf_dirs(roots, "binfmt.d"), ".conf")? {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        let rules = BinfmtRule::parse_lines(&content)
+            .with_context(|| format!("failed to parse '{}'", path.display()))?;
+        output.extend(rules);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_parse() {
+        let input = r":qemu-aarch64:M:0:\x7fELF\x02\x01\x01:\xff\xff\xff\xff\xff\xff\xff:/usr/bin/qemu-aarch64-static:FP";
+        let rule: BinfmtRule = input.parse().unwrap();
+        assert_eq!(rule.name(), "qemu-aarch64");
+        assert_eq!(rule.get_offset(), Some(0));
+        assert_eq!(rule.magic(), r"\x7fELF\x02\x01\x01");
+        assert_eq!(rule.get_flags(), "FP");
+        assert_eq!(rule.to_config_line(), input);
+
+        let rule: BinfmtRule = ",Python,E,,py,,/usr/bin/python3".parse().unwrap();
+        assert_eq!(rule.match_type(), MatchType::Extension);
+        assert_eq!(rule.get_mask(), None);
+        assert_eq!(rule.to_config_line(), ",Python,E,,py,,/usr/bin/python3,");
+
+        for input in [
+            "",
+            ":name:M::MZ::",
+            ":name:X::MZ::/bin/sh:",
+            ":name:E:2:py::/bin/sh:",
+            ":a/b:M::MZ::/bin/sh:",
+            ":name:M::MZ::/bin/sh:Z",
+            ":name:M::MZ::/bin/sh:F:extra",
+        ] {
+            input.parse::<BinfmtRule>().unwrap_err();
+        }
+
+        let rule = BinfmtRule::new_magic("DOSWin", "MZ", "/usr/bin/wine")
+            .unwrap()
+            .flags("C")
+            .unwrap();
+        assert_eq!(rule.to_config_line(), ":DOSWin:M::MZ::/usr/bin/wine:C");
+        BinfmtRule::new_extension("py", "py", "/bin/py")
+            .unwrap()
+            .offset(2)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_load_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let (etc, usr) = (root.join("etc/binfmt.d"), root.join("usr/lib/binfmt.d"));
+        fs::create_dir_all(&etc).unwrap();
+        fs::create_dir_all(&usr).unwrap();
+        fs::write(usr.join("10-wine.conf"), ":DOSWin:M::MZ::/usr/bin/wine:\n").unwrap();
+        fs::write(usr.join("20-masked.conf"), ":jar:E::jar::/usr/bin/jexec:\n").unwrap();
+        symlink("/dev/null", etc.join("20-masked.conf")).unwrap();
+        fs::write(etc.join("10-wine.conf"), ":DOSWin:M::MZ::/opt/wine:\n").unwrap();
+
+        let rules = load_config(&[&root]).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].interpreter(), "/opt/wine");
+    }
+}
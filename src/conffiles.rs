@@ -0,0 +1,92 @@
+//! Lookup of `*.d` configuration files across the standard directories.
+
+use crate::errors::SdError;
+use crate::unit::file::is_masked;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Standard configuration directories, relative to a root, from highest to
+/// lowest precedence.
+const CONF_DIRS: [&str; 4] = ["etc", "run", "usr/local/lib", "usr/lib"];
+
+/// Return the configuration directories named `name` (e.g. `sysusers.d`)
+/// under each of `roots`, in precedence order.
+pub(crate) fn conf_dirs<P: AsRef<Path>>(roots: &[P], name: &str) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .flat_map(|root| {
+            CONF_DIRS
+                .iter()
+                .map(move |dir| root.as_ref().join(dir).join(name))
+        })
+        .collect()
+}
+
+/// List the files ending with `suffix` in `dirs`, sorted by file name.
+///
+/// Directories are given in precedence order: a file overrides the ones
+/// with the same name in later directories, and masked files (symlinks to
+/// `/dev/null`, or empty files) hide them without being returned.
+pub(crate) fn conf_files<P: AsRef<Path>>(
+    dirs: &[P],
+    suffix: &str,
+) -> Result<Vec<PathBuf>, SdError> {
+    let mut files: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for dir in dirs {
+        let dir = dir.as_ref();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(format!("failed to read directory '{}': {}", dir.display(), e).into())
+            }
+        };
+        for entry in entries {
+            let entry = entry
+                .map_err(|e| format!("failed to read directory '{}': {}", dir.display(), e))?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) if name.ends_with(suffix) && !name.starts_with('.') => name,
+                _ => continue,
+            };
+            files.entry(name).or_insert_with(|| entry.path());
+        }
+    }
+    Ok(files
+        .into_values()
+        .filter(|path| !path.is_dir() && !is_masked(path))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_conf_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let dirs = conf_dirs(&[&root], "test.d");
+        assert_eq!(dirs[0], root.join("etc/test.d"));
+        assert_eq!(dirs[3], root.join("usr/lib/test.d"));
+        for dir in &dirs {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let (etc, run, usr) = (&dirs[0], &dirs[1], &dirs[3]);
+        fs::write(usr.join("a.conf"), "usr").unwrap();
+        fs::write(etc.join("a.conf"), "etc").unwrap();
+        fs::write(usr.join("b.conf"), "usr").unwrap();
+        fs::write(run.join("c.conf"), "run").unwrap();
+        fs::write(usr.join("ignored.txt"), "usr").unwrap();
+        fs::write(usr.join("masked.conf"), "usr").unwrap();
+        symlink("/dev/null", etc.join("masked.conf")).unwrap();
+
+        let files = conf_files(&dirs, ".conf").unwrap();
+        assert_eq!(
+            files,
+            vec![etc.join("a.conf"), usr.join("b.conf"), run.join("c.conf")]
+        );
+    }
+}
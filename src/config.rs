@@ -0,0 +1,288 @@
+//! Daemon configuration files, like `journald.conf` or `system.conf`.
+//!
+//! For the complete documentation see
+//! <https://www.freedesktop.org/software/systemd/man/systemd.syntax.html>.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # fn doctest_load() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::config::DaemonConfig;
+//!
+//! let journald = DaemonConfig::journald()?;
+//! let compress = journald.boolean("Journal", "Compress")?.unwrap_or(true);
+//! let max_use = journald.size("Journal", "SystemMaxUse")?;
+//! println!("compression: {}, max use: {:?}", compress, max_use);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::conffiles::{conf_dirs, conf_files};
+use crate::errors::SdError;
+use crate::unit::file::{is_masked, parse_boolean, Entry, UnitFile};
+use crate::unit::parse_timespan;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Effective configuration of a daemon, merged from its main configuration
+/// file and all its drop-ins.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DaemonConfig {
+    file: UnitFile,
+    sources: Vec<PathBuf>,
+}
+
+impl DaemonConfig {
+    /// Load the configuration of `systemd-journald` (`systemd/journald.conf`).
+    pub fn journald() -> Result<Self, SdError> {
+        Self::load("systemd/journald.conf")
+    }
+
+    /// Load the configuration of the system manager (`systemd/system.conf`).
+    pub fn system() -> Result<Self, SdError> {
+        Self::load("systemd/system.conf")
+    }
+
+    /// Load the configuration of `systemd-logind` (`systemd/logind.conf`).
+    pub fn logind() -> Result<Self, SdError> {
+        Self::load("systemd/logind.conf")
+    }
+
+    /// Load the configuration file `name` (e.g. `systemd/journald.conf`) of
+    /// the running system.
+    pub fn load(name: &str) -> Result<Self, SdError> {
+        Self::load_in(&["/"], name)
+    }
+
+    /// Load the configuration file `name` below the given roots.
+    ///
+    /// Roots are given in precedence order. The main file is the first one
+    /// found in `etc`, `run`, `usr/local/lib` and `usr/lib` below each root,
+    /// and it is optional. Drop-ins from `<name>.d/*.conf` in the same
+    /// directories are then applied in file-name order, with files
+    /// overriding same-named ones in lower-precedence directories and
+    /// masking through symlinks to `/dev/null`.
+    pub fn load_in<P: AsRef<Path>>(roots: &[P], name: &str) -> Result<Self, SdError> {
+        let mut config = Self::default();
+        let main = conf_dirs(roots, "")
+            .into_iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.symlink_metadata().is_ok());
+        if let Some(path) = main.filter(|path| !is_masked(path)) {
+            config.merge(&path)?;
+        }
+        for path in conf_files(&conf_dirs(roots, &format!("{}.d", name)), ".conf")? {
+            config.merge(&path)?;
+        }
+        Ok(config)
+    }
+
+    fn merge(&mut self, path: &Path) -> Result<(), SdError> {
+        self.file.merge(UnitFile::from_path(path)?);
+        self.sources.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Return the paths of the files making up this configuration, in the
+    /// order they were applied.
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// Return the merged configuration, as a unit file.
+    pub fn file(&self) -> &UnitFile {
+        &self.file
+    }
+
+    /// Return the effective raw value of a setting, if set.
+    pub fn value(&self, section: &str, key: &str) -> Option<&str> {
+        self.file.value(section, key)
+    }
+
+    /// Return the assignment providing the effective value of a setting,
+    /// e.g. to report the file it comes from.
+    pub fn entry(&self, section: &str, key: &str) -> Option<&Entry> {
+        self.file.section(section)?.entry(key)
+    }
+
+    /// Return the effective value of a boolean setting, if set.
+    pub fn boolean(&self, section: &str, key: &str) -> Result<Option<bool>, SdError> {
+        self.parsed(section, key, parse_boolean)
+    }
+
+    /// Return the effective value of a time span setting, if set.
+    ///
+    /// Plain numbers are interpreted as seconds.
+    pub fn timespan(&self, section: &str, key: &str) -> Result<Option<Duration>, SdError> {
+        self.parsed(section, key, parse_timespan)
+    }
+
+    /// Return the effective value of a size setting in bytes, if set.
+    ///
+    /// See [`parse_size`] for the accepted syntax.
+    pub fn size(&self, section: &str, key: &str) -> Result<Option<u64>, SdError> {
+        self.parsed(section, key, parse_size)
+    }
+
+    /// Return the effective value of a setting, parsed with `parse`.
+    pub fn parsed<T>(
+        &self,
+        section: &str,
+        key: &str,
+        parse: impl FnOnce(&str) -> Result<T, SdError>,
+    ) -> Result<Option<T>, SdError> {
+        let entry = match self.entry(section, key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        parse(entry.value()).map(Some).map_err(|e| {
+            let source = entry
+                .source()
+                .map(|p| format!(" ({}:{})", p.display(), entry.line()))
+                .unwrap_or_default();
+            format!("invalid value for {}.{}{}: {}", section, key, source, e.msg).into()
+        })
+    }
+}
+
+/// Parse a size in bytes, like systemd's `parse_size()` with base 1024.
+///
+/// Values are integers or decimals with an optional unit suffix among `B`,
+/// `K`, `M`, `G`, `T`, `P` and `E` (powers of 1024), possibly combined
+/// (e.g. `1G 512M`).
+pub fn parse_size(value: &str) -> Result<u64, SdError> {
+    const UNITS: [(&str, u64); 7] = [
+        ("E", 1 << 60),
+        ("P", 1 << 50),
+        ("T", 1 << 40),
+        ("G", 1 << 30),
+        ("M", 1 << 20),
+        ("K", 1 << 10),
+        ("B", 1),
+    ];
+    let invalid = || SdError::from(format!("invalid size '{}'", value));
+
+    let mut total: u64 = 0;
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number = &rest[..digits];
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() {
+            return Err(invalid());
+        }
+        let whole: u64 = whole.parse().map_err(|_| invalid())?;
+
+        rest = rest[digits..].trim_start();
+        let (unit, factor) = UNITS
+            .iter()
+            .find(|(unit, _)| rest.starts_with(unit))
+            .copied()
+            .unwrap_or(("", 1));
+        rest = rest[unit.len()..].trim_start();
+
+        let mut amount = whole.checked_mul(factor).ok_or_else(invalid)?;
+        if !fraction.is_empty() {
+            if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            // Digits beyond the 18th are below the precision of any unit.
+            let fraction = &fraction[..fraction.len().min(18)];
+            let scale = 10u128.pow(fraction.len() as u32);
+            let numerator: u128 = fraction.parse().map_err(|_| invalid())?;
+            amount = amount
+                .checked_add((numerator * u128::from(factor) / scale) as u64)
+                .ok_or_else(invalid)?;
+        }
+        total = total.checked_add(amount).ok_or_else(invalid)?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_parse_size() {
+        let cases = [
+            ("0", 0),
+            ("512", 512),
+            ("1K", 1024),
+            ("1.5M", 1_572_864),
+            ("1G 512M", 1_610_612_736),
+            ("4 K", 4096),
+            ("100B", 100),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_size(input).unwrap(), expected, "{}", input);
+        }
+        for input in ["", "-1", "1X", "K", ".5K", "1.x", "100000E"] {
+            parse_size(input).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let etc = root.join("etc/systemd");
+        let usr = root.join("usr/lib/systemd");
+        for dir in ["journald.conf.d", "logind.conf.d"] {
+            fs::create_dir_all(etc.join(dir)).unwrap();
+            fs::create_dir_all(usr.join(dir)).unwrap();
+        }
+        fs::write(
+            etc.join("journald.conf"),
+            "[Journal]\nCompress=no\nSystemMaxUse=1G\nMaxRetentionSec=1month\n",
+        )
+        .unwrap();
+        fs::write(
+            usr.join("journald.conf.d/10-vendor.conf"),
+            "[Journal]\nCompress=yes\nSystemMaxUse=100M\n",
+        )
+        .unwrap();
+        fs::write(
+            usr.join("journald.conf.d/20-masked.conf"),
+            "[Journal]\nSystemMaxUse=1K\n",
+        )
+        .unwrap();
+        symlink("/dev/null", etc.join("journald.conf.d/20-masked.conf")).unwrap();
+        fs::write(
+            etc.join("journald.conf.d/30-local.conf"),
+            "[Journal]\nMaxRetentionSec=\nSyncIntervalSec=bogus\n",
+        )
+        .unwrap();
+
+        let config = DaemonConfig::load_in(&[&root], "systemd/journald.conf").unwrap();
+        assert_eq!(config.sources().len(), 3);
+        assert_eq!(config.boolean("Journal", "Compress").unwrap(), Some(true));
+        assert_eq!(
+            config.size("Journal", "SystemMaxUse").unwrap(),
+            Some(100 << 20)
+        );
+        assert_eq!(config.timespan("Journal", "MaxRetentionSec").unwrap(), None);
+        let err = config.timespan("Journal", "SyncIntervalSec").unwrap_err();
+        assert!(err.to_string().contains("30-local.conf:3"), "{}", err);
+        assert_eq!(config.value("Journal", "Storage"), None);
+
+        // A missing main file is not an error.
+        fs::write(
+            usr.join("logind.conf.d/10.conf"),
+            "[Login]\nKillUserProcesses=1\n",
+        )
+        .unwrap();
+        let config = DaemonConfig::load_in(&[&root], "systemd/logind.conf").unwrap();
+        assert_eq!(
+            config.boolean("Login", "KillUserProcesses").unwrap(),
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,383 @@
+use crate::errors::{Context, SdError};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default location of the host credential secret.
+pub const HOST_SECRET_PATH: &str = "/var/lib/systemd/credential.secret";
+
+/// Credential envelope identifier for AES-256-GCM keyed by the host secret.
+const CRED_AES256_GCM_BY_HOST: [u8; 16] = [
+    0x5a, 0x1c, 0x6a, 0x86, 0xdf, 0x9d, 0x40, 0x96, 0xb1, 0xd5, 0xa6, 0x5e, 0x08, 0x62, 0xf1, 0x9a,
+];
+
+/// Credential envelope identifiers for TPM2-bound keys.
+const CRED_AES256_GCM_BY_TPM2: [[u8; 16]; 4] = [
+    // TPM2 HMAC.
+    [
+        0x0c, 0x7c, 0xc0, 0x7b, 0x11, 0x76, 0x45, 0x91, 0x9c, 0x4b, 0x0b, 0xea, 0x08, 0xbc, 0x20,
+        0xfe,
+    ],
+    // TPM2 HMAC with public key.
+    [
+        0xfa, 0xf7, 0xeb, 0x93, 0x41, 0xe3, 0x41, 0x2c, 0xa1, 0xa4, 0x36, 0xf9, 0x5a, 0x29, 0x36,
+        0x2f,
+    ],
+    // Host and TPM2 HMAC.
+    [
+        0x93, 0xa8, 0x94, 0x09, 0x48, 0x74, 0x44, 0x90, 0x90, 0xca, 0xf2, 0xfc, 0x93, 0xca, 0xb5,
+        0x53,
+    ],
+    // Host and TPM2 HMAC with public key.
+    [
+        0xaf, 0x49, 0x50, 0xa8, 0x49, 0x13, 0x4e, 0xb1, 0xab, 0xb3, 0xb1, 0x31, 0x16, 0x1e, 0x2a,
+        0x1c,
+    ],
+];
+
+/// Size of the (hashed) machine ID prefixing the host secret.
+const HOST_SECRET_HEADER_SIZE: usize = 16;
+
+/// Size of the fixed part of the envelope header (ID and four sizes).
+const HEADER_SIZE: usize = 32;
+
+/// Size of the fixed part of the metadata header (two timestamps and the name size).
+const METADATA_SIZE: usize = 20;
+
+/// Timestamp value meaning "unset", in microseconds.
+const USEC_INFINITY: u64 = u64::MAX;
+
+/// Read the host credential secret from `path` (usually [`HOST_SECRET_PATH`]).
+pub fn read_host_secret(path: impl AsRef<Path>) -> Result<Vec<u8>, SdError> {
+    let path = path.as_ref();
+    let secret = fs::read(path)
+        .with_context(|| format!("Reading credential secret at {}", path.display()))?;
+    if secret.len() <= HOST_SECRET_HEADER_SIZE {
+        return Err(format!("Credential secret at {} is too short", path.display()).into());
+    }
+    Ok(secret)
+}
+
+/// Encrypt a credential in the `systemd-creds encrypt` format, with the host
+/// key.
+///
+/// `host_secret` is the content of the host credential secret file (see
+/// [`read_host_secret`]) of the machine which will consume the credential,
+/// and `name` is the credential name it will be loaded as (or empty, to skip
+/// the name check on decryption). The result is base64-encoded, suitable for
+/// `SetCredentialEncrypted=` and `LoadCredentialEncrypted=`.
+pub fn encrypt(name: &str, data: &[u8], host_secret: &[u8]) -> Result<String, SdError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default();
+    encrypt_at(name, data, host_secret, now)
+}
+
+fn encrypt_at(
+    name: &str,
+    data: &[u8],
+    host_secret: &[u8],
+    now_usec: u64,
+) -> Result<String, SdError> {
+    if name == "." || name == ".." || name.contains('/') || name.contains('\0') || name.len() > 255
+    {
+        return Err(format!("Invalid credential name '{}'", name).into());
+    }
+    let cipher = Aes256Gcm::new(&host_key(host_secret)?);
+
+    let mut header = Vec::with_capacity(align8(HEADER_SIZE + 12));
+    header.extend_from_slice(&CRED_AES256_GCM_BY_HOST);
+    for size in [32u32, 1, 12, 16] {
+        header.extend_from_slice(&size.to_le_bytes());
+    }
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    header.extend_from_slice(&nonce);
+    header.resize(align8(header.len()), 0);
+
+    let mut plaintext = Vec::with_capacity(align8(METADATA_SIZE + name.len()) + data.len());
+    plaintext.extend_from_slice(&now_usec.to_le_bytes());
+    plaintext.extend_from_slice(&USEC_INFINITY.to_le_bytes());
+    plaintext.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    plaintext.extend_from_slice(name.as_bytes());
+    plaintext.resize(align8(plaintext.len()), 0);
+    plaintext.extend_from_slice(data);
+
+    let payload = Payload {
+        msg: &plaintext,
+        aad: &header,
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| SdError::from("Failed to encrypt credential"))?;
+    header.extend_from_slice(&ciphertext);
+    Ok(base64::engine::general_purpose::STANDARD.encode(header))
+}
+
+/// Decrypt a credential in the `systemd-creds encrypt` format.
+///
+/// `data` is the encrypted credential, either base64-encoded or raw, and
+/// `host_secret` is the content of the host credential secret file (see
+/// [`read_host_secret`]). Only credentials encrypted with the host key are
+/// supported, TPM2-bound ones are refused.
+///
+/// If `name` is given, it must match the name embedded in the credential.
+/// Credentials past their expiry time are refused.
+pub fn decrypt(name: Option<&str>, data: &[u8], host_secret: &[u8]) -> Result<Vec<u8>, SdError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default();
+    decrypt_at(name, data, host_secret, now)
+}
+
+fn decrypt_at(
+    name: Option<&str>,
+    data: &[u8],
+    host_secret: &[u8],
+    now_usec: u64,
+) -> Result<Vec<u8>, SdError> {
+    let decoded = decode_base64(data);
+    let data = decoded.as_deref().unwrap_or(data);
+
+    if data.len() < HEADER_SIZE {
+        return Err("Encrypted credential too short".into());
+    }
+    let id = &data[..16];
+    if CRED_AES256_GCM_BY_TPM2.iter().any(|tpm2| tpm2 == id) {
+        return Err("TPM2-bound credentials are not supported".into());
+    }
+    if id != CRED_AES256_GCM_BY_HOST {
+        return Err("Unknown encrypted credential format".into());
+    }
+    let (key_size, block_size, iv_size, tag_size) = (
+        read_u32(data, 16),
+        read_u32(data, 20),
+        read_u32(data, 24),
+        read_u32(data, 28),
+    );
+    if (key_size, block_size, iv_size, tag_size) != (32, 1, 12, 16) {
+        return Err("Unsupported encrypted credential parameters".into());
+    }
+    let header_end = align8(HEADER_SIZE + 12);
+    if data.len() < header_end + 16 {
+        return Err("Encrypted credential too short".into());
+    }
+
+    let cipher = Aes256Gcm::new(&host_key(host_secret)?);
+    let payload = Payload {
+        msg: &data[header_end..],
+        aad: &data[..header_end],
+    };
+    let mut plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&data[HEADER_SIZE..HEADER_SIZE + 12]),
+            payload,
+        )
+        .map_err(|_| SdError::from("Failed to decrypt credential"))?;
+
+    let metadata_end = match check_metadata(&plaintext, name, now_usec) {
+        Ok(metadata_end) => metadata_end,
+        Err(e) => {
+            #[cfg(feature = "zeroize")]
+            zeroize::Zeroize::zeroize(&mut plaintext);
+            return Err(e);
+        }
+    };
+    // Return the secret in place, without leaving another copy around.
+    plaintext.drain(..metadata_end);
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(plaintext.spare_capacity_mut());
+
+    Ok(plaintext)
+}
+
+/// Check the metadata of a decrypted credential, returning where the secret starts.
+fn check_metadata(plaintext: &[u8], name: Option<&str>, now_usec: u64) -> Result<usize, SdError> {
+    if plaintext.len() < METADATA_SIZE {
+        return Err("Decrypted credential lacks metadata".into());
+    }
+    let not_after = read_u64(plaintext, 8);
+    let name_size = read_u32(plaintext, 16) as usize;
+    let metadata_end = align8(METADATA_SIZE + name_size);
+    if plaintext.len() < metadata_end {
+        return Err("Decrypted credential has invalid metadata".into());
+    }
+    let embedded_name = std::str::from_utf8(&plaintext[METADATA_SIZE..METADATA_SIZE + name_size])
+        .map_err(|_| SdError::from("Embedded credential name is not valid"))?;
+
+    if let Some(name) = name {
+        if name != embedded_name {
+            let msg = format!(
+                "Embedded credential name '{}' does not match '{}'",
+                embedded_name, name
+            );
+            return Err(msg.into());
+        }
+    }
+    if not_after != USEC_INFINITY && not_after < now_usec {
+        return Err("Credential has expired".into());
+    }
+
+    Ok(metadata_end)
+}
+
+/// Derive the encryption key from the host secret, skipping its machine ID.
+fn host_key(host_secret: &[u8]) -> Result<Key<Aes256Gcm>, SdError> {
+    let secret = host_secret
+        .get(HOST_SECRET_HEADER_SIZE..)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| SdError::from("Credential secret too short"))?;
+    Ok(Sha256::digest(secret))
+}
+
+/// Decode base64 data, ignoring whitespace, or return `None` if not base64.
+fn decode_base64(data: &[u8]) -> Option<Vec<u8>> {
+    let stripped: Vec<u8> = data
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(stripped)
+        .ok()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn align8(size: usize) -> usize {
+    (size + 7) & !7
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Host secret: 16 bytes of machine ID (not part of the key), then 4096
+    /// bytes counting from 0 to 255 repeatedly.
+    fn host_secret() -> Vec<u8> {
+        let mut secret = vec![0u8; HOST_SECRET_HEADER_SIZE];
+        secret.extend((0..4096).map(|i| i as u8));
+        secret
+    }
+
+    // Produced by systemd 252 with `printf 'hunter2\n' | systemd-creds encrypt
+    // --with-key=host --name=mytoken --timestamp=@1700000000
+    // --not-after=@1900000000 - -`, with `$SYSTEMD_CREDENTIAL_SECRET` pointing
+    // to a copy of the host secret above. Its first 16 bytes must be replaced
+    // by the ones of a secret generated by `systemd-creds` on the same machine,
+    // otherwise the file is discarded and regenerated.
+    const MYTOKEN: &str = "\
+Whxqht+dQJax1aZeCGLxmiAAAAABAAAADAAAABAAAAAICkaBQH+2DZggbcMAAAAA0cWd3iLic/NHJIs
+pty8I6Lwl6H9lcoSkj28MTJvtukqg23TCXV4VIHEqgNGPK+Nw+b1xpejJ4sY=
+";
+
+    // Produced likewise with `printf 'no name' | systemd-creds encrypt
+    // --with-key=host --name= --timestamp=@1700000000 - -`.
+    const UNNAMED: &str = "\
+Whxqht+dQJax1aZeCGLxmiAAAAABAAAADAAAABAAAACSB5FGBI8y0t7r4D0AAAAAz2TXW+98faZs4YB
+ZIYY8qzJRR87kGRimP1G7KKmxzep7lM1sIoh8p+BTz14+wa8=
+";
+
+    #[test]
+    fn test_decrypt() {
+        let secret = host_secret();
+        let now = 1_800_000_000_000_000;
+
+        let data = decrypt_at(Some("mytoken"), MYTOKEN.as_bytes(), &secret, now).unwrap();
+        assert_eq!(data, b"hunter2\n");
+        let data = decrypt_at(None, MYTOKEN.as_bytes(), &secret, now).unwrap();
+        assert_eq!(data, b"hunter2\n");
+        let data = decrypt_at(Some(""), UNNAMED.as_bytes(), &secret, now).unwrap();
+        assert_eq!(data, b"no name");
+
+        // Raw (non-base64) credentials.
+        let raw = decode_base64(MYTOKEN.as_bytes()).unwrap();
+        decrypt_at(Some("mytoken"), &raw, &secret, now).unwrap();
+
+        decrypt_at(Some("other"), MYTOKEN.as_bytes(), &secret, now).unwrap_err();
+        decrypt_at(Some("mytoken"), UNNAMED.as_bytes(), &secret, now).unwrap_err();
+        decrypt_at(None, MYTOKEN.as_bytes(), &secret, 1_950_000_000_000_000).unwrap_err();
+
+        let mut wrong_secret = secret.clone();
+        wrong_secret[20] ^= 0x01;
+        decrypt_at(None, MYTOKEN.as_bytes(), &wrong_secret, now).unwrap_err();
+        let mut tampered = raw.clone();
+        tampered[40] ^= 0x01;
+        decrypt_at(None, &tampered, &secret, now).unwrap_err();
+        decrypt_at(None, &raw[..40], &secret, now).unwrap_err();
+        decrypt_at(None, b"", &secret, now).unwrap_err();
+    }
+
+    /// Cross-check against `systemd-creds`, which generates the host secret.
+    #[test]
+    #[ignore = "requires systemd-creds and root privileges"]
+    fn test_systemd_creds_interop() {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let secret_path = dir.join("credential.secret");
+        let systemd_creds = |args: &[&str], input: &[u8]| {
+            let mut child = Command::new("systemd-creds")
+                .args(args)
+                .env("SYSTEMD_CREDENTIAL_SECRET", &secret_path)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(input).unwrap();
+            let output = child.wait_with_output().unwrap();
+            assert!(output.status.success(), "{:?}", output);
+            output.stdout
+        };
+
+        let blob = systemd_creds(
+            &["encrypt", "--with-key=host", "--name=token", "-", "-"],
+            b"s3cr3t",
+        );
+        let secret = read_host_secret(&secret_path).unwrap();
+        assert_eq!(decrypt(Some("token"), &blob, &secret).unwrap(), b"s3cr3t");
+
+        let blob = encrypt("token", b"hunter2", &secret).unwrap();
+        let data = systemd_creds(&["decrypt", "--name=token", "-", "-"], blob.as_bytes());
+        assert_eq!(data, b"hunter2");
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let secret = host_secret();
+        let now = 1_800_000_000_000_000;
+
+        let blob = encrypt_at("token", b"s3cr3t", &secret, now).unwrap();
+        assert!(blob.starts_with("Whxqht+dQJax1aZeCGLxmi"));
+        let data = decrypt_at(Some("token"), blob.as_bytes(), &secret, now).unwrap();
+        assert_eq!(data, b"s3cr3t");
+        decrypt_at(Some("other"), blob.as_bytes(), &secret, now).unwrap_err();
+
+        // A fresh IV is used for each credential.
+        let other = encrypt_at("token", b"s3cr3t", &secret, now).unwrap();
+        assert_ne!(blob, other);
+
+        let blob = encrypt("", b"", &secret).unwrap();
+        assert_eq!(decrypt(Some(""), blob.as_bytes(), &secret).unwrap(), b"");
+
+        encrypt("a/b", b"", &secret).unwrap_err();
+        encrypt("token", b"", &secret[..HOST_SECRET_HEADER_SIZE]).unwrap_err();
+    }
+}
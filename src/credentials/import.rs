@@ -0,0 +1,233 @@
+use crate::errors::{Context, SdError};
+use base64::Engine;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Directory exposing the SMBIOS tables.
+const SMBIOS_ENTRIES_DIRECTORY: &str = "/sys/firmware/dmi/entries";
+
+/// Directory exposing the credentials passed through qemu `fw_cfg`.
+const QEMU_FW_CFG_DIRECTORY: &str = "/sys/firmware/qemu_fw_cfg/by_name/opt/io.systemd.credentials";
+
+/// SMBIOS structure type for OEM strings.
+const SMBIOS_TYPE_OEM_STRINGS: u8 = 11;
+
+/// Prefix of OEM strings carrying a text credential.
+const CREDENTIAL_PREFIX: &[u8] = b"io.systemd.credential:";
+
+/// Prefix of OEM strings carrying a base64-encoded binary credential.
+const CREDENTIAL_BINARY_PREFIX: &[u8] = b"io.systemd.credential.binary:";
+
+/// Credential passed by the firmware or the hypervisor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FirmwareCredential {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl FirmwareCredential {
+    /// Return the credential ID.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the credential content.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consume the credential, returning its content.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Import credentials from SMBIOS and qemu `fw_cfg`, like `systemd` does
+/// for system credentials at boot.
+///
+/// Missing firmware interfaces are not an error. When the same credential
+/// is passed multiple times, the first one wins.
+pub fn import_firmware_credentials() -> Result<Vec<FirmwareCredential>, SdError> {
+    let mut creds = import_smbios_credentials()?;
+    for cred in import_qemu_fw_cfg_credentials()? {
+        if !creds.iter().any(|c| c.name == cred.name) {
+            creds.push(cred);
+        }
+    }
+    Ok(creds)
+}
+
+/// Import credentials from SMBIOS Type 11 (OEM strings) entries.
+///
+/// Strings of the form `io.systemd.credential:<name>=<value>` and
+/// `io.systemd.credential.binary:<name>=<base64 value>` are decoded, others
+/// are ignored. Reading SMBIOS tables usually requires privileges.
+pub fn import_smbios_credentials() -> Result<Vec<FirmwareCredential>, SdError> {
+    import_smbios_in(Path::new(SMBIOS_ENTRIES_DIRECTORY))
+}
+
+/// Import credentials passed through qemu `fw_cfg`
+/// (`-fw_cfg name=opt/io.systemd.credentials/<name>,...`).
+pub fn import_qemu_fw_cfg_credentials() -> Result<Vec<FirmwareCredential>, SdError> {
+    import_qemu_fw_cfg_in(Path::new(QEMU_FW_CFG_DIRECTORY))
+}
+
+fn import_smbios_in(dir: &Path) -> Result<Vec<FirmwareCredential>, SdError> {
+    let mut creds: Vec<FirmwareCredential> = vec![];
+    for index in 0.. {
+        let path = dir.join(format!("{}-{}/raw", SMBIOS_TYPE_OEM_STRINGS, index));
+        let raw = match fs::read(&path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == ErrorKind::NotFound => break,
+            Err(e) => return Err(format!("Reading {}: {}", path.display(), e).into()),
+        };
+        for cred in parse_oem_strings(&raw) {
+            if !creds.iter().any(|c| c.name == cred.name) {
+                creds.push(cred);
+            }
+        }
+    }
+    Ok(creds)
+}
+
+/// Parse credentials out of a raw SMBIOS Type 11 structure.
+fn parse_oem_strings(raw: &[u8]) -> Vec<FirmwareCredential> {
+    let mut creds = vec![];
+    // Header: type, length of the formatted area, handle.
+    if raw.len() < 4 || raw[0] != SMBIOS_TYPE_OEM_STRINGS {
+        return creds;
+    }
+    let strings = match raw.get(usize::from(raw[1])..) {
+        Some(strings) => strings,
+        None => return creds,
+    };
+
+    // Strings are NUL-terminated, with an empty string ending the set.
+    for string in strings.split(|b| *b == 0).take_while(|s| !s.is_empty()) {
+        let (value, binary) = if let Some(v) = string.strip_prefix(CREDENTIAL_PREFIX) {
+            (v, false)
+        } else if let Some(v) = string.strip_prefix(CREDENTIAL_BINARY_PREFIX) {
+            (v, true)
+        } else {
+            continue;
+        };
+        match parse_oem_credential(value, binary) {
+            Ok(cred) => creds.push(cred),
+            Err(e) => log::warn!("Ignoring SMBIOS credential: {}", e),
+        }
+    }
+    creds
+}
+
+fn parse_oem_credential(value: &[u8], binary: bool) -> Result<FirmwareCredential, SdError> {
+    let eq = value
+        .iter()
+        .position(|b| *b == b'=')
+        .ok_or("missing '=' separator")?;
+    let name = std::str::from_utf8(&value[..eq])
+        .ok()
+        .filter(|name| credential_name_valid(name))
+        .ok_or("invalid credential name")?;
+    let data = if binary {
+        base64::engine::general_purpose::STANDARD
+            .decode(&value[eq + 1..])
+            .map_err(|e| format!("invalid base64 data for '{}': {}", name, e))?
+    } else {
+        value[eq + 1..].to_vec()
+    };
+    Ok(FirmwareCredential {
+        name: name.to_string(),
+        data,
+    })
+}
+
+fn import_qemu_fw_cfg_in(dir: &Path) -> Result<Vec<FirmwareCredential>, SdError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(format!("Reading {}: {}", dir.display(), e).into()),
+    };
+    let mut creds = vec![];
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Reading {}", dir.display()))?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) if credential_name_valid(&name) => name,
+            _ => {
+                log::warn!("Ignoring fw_cfg credential with invalid name");
+                continue;
+            }
+        };
+        let path = entry.path().join("raw");
+        let data = fs::read(&path).with_context(|| format!("Reading {}", path.display()))?;
+        creds.push(FirmwareCredential { name, data });
+    }
+    creds.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(creds)
+}
+
+fn credential_name_valid(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 255 && name != "." && name != ".." && !name.contains('/')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn oem_strings(strings: &[&str]) -> Vec<u8> {
+        let mut raw = vec![SMBIOS_TYPE_OEM_STRINGS, 5, 0x00, 0x01, strings.len() as u8];
+        for s in strings {
+            raw.extend_from_slice(s.as_bytes());
+            raw.push(0);
+        }
+        raw.push(0);
+        raw
+    }
+
+    #[test]
+    fn test_parse_oem_strings() {
+        let raw = oem_strings(&[
+            "io.systemd.credential:system.hostname=vm=1",
+            "unrelated",
+            "io.systemd.credential.binary:key=AAH/",
+            "io.systemd.credential:../evil=x",
+            "io.systemd.credential.binary:bad=!!",
+            "io.systemd.credential:noeq",
+        ]);
+        let creds = parse_oem_strings(&raw);
+        assert_eq!(creds.len(), 2);
+        assert_eq!(creds[0].name(), "system.hostname");
+        assert_eq!(creds[0].data(), b"vm=1");
+        assert_eq!(creds[1].name(), "key");
+        assert_eq!(creds[1].data(), [0x00, 0x01, 0xff]);
+
+        assert!(parse_oem_strings(&[]).is_empty());
+        assert!(parse_oem_strings(&[1, 5, 0, 0, 0, 0]).is_empty());
+        assert!(parse_oem_strings(&[11, 200, 0, 0]).is_empty());
+    }
+
+    #[test]
+    fn test_import() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let (smbios, fw_cfg) = (root.join("dmi"), root.join("fw_cfg"));
+        for (index, content) in ["a=1", "a=2\0io.systemd.credential:b=3"].iter().enumerate() {
+            let dir = smbios.join(format!("11-{}", index));
+            fs::create_dir_all(&dir).unwrap();
+            let string = format!("io.systemd.credential:{}", content);
+            fs::write(dir.join("raw"), oem_strings(&[&string])).unwrap();
+        }
+        fs::create_dir_all(fw_cfg.join("c")).unwrap();
+        fs::write(fw_cfg.join("c/raw"), "4").unwrap();
+
+        let creds = import_smbios_in(&smbios).unwrap();
+        let values: Vec<_> = creds.iter().map(|c| (c.name(), c.data())).collect();
+        assert_eq!(values, vec![("a", &b"1"[..]), ("b", &b"3"[..])]);
+        let creds = import_qemu_fw_cfg_in(&fw_cfg).unwrap();
+        assert_eq!(creds[0].clone().into_data(), b"4");
+        assert!(import_qemu_fw_cfg_in(&root.join("missing"))
+            .unwrap()
+            .is_empty());
+        assert!(import_smbios_in(&root.join("missing")).unwrap().is_empty());
+    }
+}
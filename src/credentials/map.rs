@@ -0,0 +1,92 @@
+use crate::errors::{Context, SdError};
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use std::fs::File;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::{fmt, slice};
+
+/// Read-only memory mapping of a credential, as returned by
+/// [`CredentialsLoader::map`](super::CredentialsLoader::map).
+///
+/// The mapping dereferences to the credential content, and is unmapped
+/// when dropped.
+pub struct CredentialMap {
+    /// Start of the mapping, or `None` for empty credentials (which cannot be mapped).
+    ptr: Option<NonNull<u8>>,
+    len: usize,
+}
+
+// SAFETY: the mapping is private and read-only, and only exposed as `&[u8]`.
+unsafe impl Send for CredentialMap {}
+// SAFETY: see above.
+unsafe impl Sync for CredentialMap {}
+
+impl CredentialMap {
+    /// Map the whole content of `file`.
+    pub(crate) fn new(file: &File) -> Result<Self, SdError> {
+        let size = file
+            .metadata()
+            .context("Reading credential metadata")?
+            .len();
+        let len = usize::try_from(size).map_err(|_| "Credential too large to be mapped")?;
+        let length = match NonZeroUsize::new(len) {
+            Some(length) => length,
+            None => return Ok(Self { ptr: None, len: 0 }),
+        };
+        // SAFETY: a fresh private read-only mapping of a whole file, not
+        // aliasing any Rust object. Credentials are immutable once set up.
+        let addr = unsafe {
+            mmap(
+                None,
+                length,
+                ProtFlags::PROT_READ,
+                MapFlags::MAP_PRIVATE,
+                Some(file),
+                0,
+            )
+        }
+        .context("Mapping credential")?;
+        Ok(Self {
+            ptr: NonNull::new(addr.cast()),
+            len,
+        })
+    }
+}
+
+impl Deref for CredentialMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.ptr {
+            // SAFETY: the mapping is `len` bytes long and lives as long as `self`.
+            Some(ptr) => unsafe { slice::from_raw_parts(ptr.as_ptr(), self.len) },
+            None => &[],
+        }
+    }
+}
+
+impl AsRef<[u8]> for CredentialMap {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for CredentialMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CredentialMap")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for CredentialMap {
+    fn drop(&mut self) {
+        if let Some(ptr) = self.ptr {
+            // SAFETY: the mapping was created in `new` and is not used anymore.
+            if let Err(e) = unsafe { munmap(ptr.as_ptr().cast(), self.len) } {
+                log::warn!("Failed to unmap credential: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,581 @@
+#[cfg(feature = "encrypted-credentials")]
+pub use self::encrypted::{decrypt, encrypt, read_host_secret, HOST_SECRET_PATH};
+pub use self::import::{
+    import_firmware_credentials, import_qemu_fw_cfg_credentials, import_smbios_credentials,
+    FirmwareCredential,
+};
+pub use self::map::CredentialMap;
+
+use crate::errors::{Context, SdError};
+use nix::dir;
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "encrypted-credentials")]
+mod encrypted;
+mod import;
+mod map;
+
+/// Location of system credentials, as passed to the system manager.
+pub const SYSTEM_CREDENTIALS_DIRECTORY: &str = "/run/credentials/@system";
+
+/// Credential loader for units.
+///
+/// Credentials are read by systemd on unit startup and exported by their ID.
+///
+/// **Note**: only the user associated with the unit and the superuser may access credentials.
+///
+/// More documentation: <https://www.freedesktop.org/software/systemd/man/systemd.exec.html#Credentials>
+#[derive(Debug)]
+pub struct CredentialsLoader {
+    path: PathBuf,
+    _dirfd: dir::Dir,
+}
+
+impl CredentialsLoader {
+    /// Try to open credentials directory.
+    pub fn open() -> Result<Self, SdError> {
+        let path = Self::path_from_env().ok_or_else(|| {
+            SdError::from("No valid environment variable 'CREDENTIALS_DIRECTORY' found")
+        })?;
+
+        Self::open_path(path)
+    }
+
+    /// Try to open the system credentials directory.
+    ///
+    /// This does not rely on `CREDENTIALS_DIRECTORY`, and gives access to the
+    /// credentials passed to the system manager (e.g. via SMBIOS, the kernel
+    /// command line or a container manager), from early-boot programs and
+    /// generators.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use libsystemd::credentials::CredentialsLoader;
+    ///
+    /// let loader = CredentialsLoader::system()?;
+    /// let hostname = loader.get_string("system.hostname")?;
+    /// println!("provisioned hostname: {}", hostname);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn system() -> Result<Self, SdError> {
+        Self::open_path(PathBuf::from(SYSTEM_CREDENTIALS_DIRECTORY))
+    }
+
+    /// Try to open the credentials directory at `path`.
+    fn open_path(path: PathBuf) -> Result<Self, SdError> {
+        // NOTE(lucab): we try to open the directory and then store its dirfd, so
+        // that we know it exists. We don't further use it now, but in the
+        // future we may couple it to something like 'cap-std' helpers.
+        let _dirfd = dir::Dir::open(&path, OFlag::O_RDONLY | OFlag::O_DIRECTORY, Mode::empty())
+            .with_context(|| format!("Opening credentials directory at '{}'", path.display()))?;
+
+        let loader = Self { path, _dirfd };
+        Ok(loader)
+    }
+
+    /// Return the location of the credentials directory, if any.
+    pub fn path_from_env() -> Option<PathBuf> {
+        env::var("CREDENTIALS_DIRECTORY").map(|v| v.into()).ok()
+    }
+
+    /// Get credential by ID.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use libsystemd::credentials::CredentialsLoader;
+    ///
+    /// let loader = CredentialsLoader::open()?;
+    /// let token = loader.get("token")?;
+    /// let token_metadata = token.metadata()?;
+    /// println!("token size: {}", token_metadata.len());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn get(&self, id: impl AsRef<str>) -> Result<File, SdError> {
+        let cred_path = self.cred_absolute_path(id.as_ref())?;
+        File::open(&cred_path).map_err(|e| {
+            let msg = format!("Opening credential at {}: {}", cred_path.display(), e);
+            SdError::from(msg)
+        })
+    }
+
+    /// Get credential by ID, without blocking the executor.
+    ///
+    /// This is the async counterpart of [`get`](Self::get), for use in
+    /// services based on the Tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub async fn get_async(&self, id: impl AsRef<str>) -> Result<tokio::fs::File, SdError> {
+        let cred_path = self.cred_absolute_path(id.as_ref())?;
+        tokio::fs::File::open(&cred_path)
+            .await
+            .with_context(|| format!("Opening credential at {}", cred_path.display()))
+    }
+
+    /// Get credential by ID, as a UTF-8 string.
+    ///
+    /// A single trailing newline is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use libsystemd::credentials::CredentialsLoader;
+    ///
+    /// let loader = CredentialsLoader::open()?;
+    /// let user = loader.get_string("db-user")?;
+    /// println!("database user: {}", user);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn get_string(&self, id: impl AsRef<str>) -> Result<String, SdError> {
+        let cred_path = self.cred_absolute_path(id.as_ref())?;
+        let mut content = fs::read_to_string(&cred_path)
+            .with_context(|| format!("Reading credential at {}", cred_path.display()))?;
+        if content.ends_with('\n') {
+            content.pop();
+            if content.ends_with('\r') {
+                content.pop();
+            }
+        }
+        Ok(content)
+    }
+
+    /// Get credential by ID, as a read-only memory mapping.
+    ///
+    /// This avoids copying large credentials (e.g. certificate bundles)
+    /// through buffers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use libsystemd::credentials::CredentialsLoader;
+    ///
+    /// let loader = CredentialsLoader::open()?;
+    /// let bundle = loader.map("ca-bundle")?;
+    /// println!("bundle size: {}", bundle.len());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn map(&self, id: impl AsRef<str>) -> Result<CredentialMap, SdError> {
+        let id = id.as_ref();
+        let cred_path = self.cred_absolute_path(id)?;
+        let file = self.get(id)?;
+        CredentialMap::new(&file).with_context(|| format!("Credential at {}", cred_path.display()))
+    }
+
+    /// Get credential by ID, in a buffer which is zeroed when dropped.
+    ///
+    /// The credential is read into a buffer of its exact size, so that no
+    /// partial copies are left behind in freed memory.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use libsystemd::credentials::CredentialsLoader;
+    ///
+    /// let loader = CredentialsLoader::open()?;
+    /// let key = loader.get_secret("signing-key")?;
+    /// println!("key size: {}", key.len());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "zeroize")]
+    pub fn get_secret(&self, id: impl AsRef<str>) -> Result<zeroize::Zeroizing<Vec<u8>>, SdError> {
+        use std::io::Read;
+
+        let id = id.as_ref();
+        let cred_path = self.cred_absolute_path(id)?;
+        let mut file = self.get(id)?;
+        let size = file
+            .metadata()
+            .with_context(|| format!("Reading credential at {}", cred_path.display()))?
+            .len();
+        let size = usize::try_from(size)
+            .map_err(|_| format!("Credential at {} is too large", cred_path.display()))?;
+        let mut secret = zeroize::Zeroizing::new(vec![0u8; size]);
+        file.read_exact(&mut secret)
+            .with_context(|| format!("Reading credential at {}", cred_path.display()))?;
+        Ok(secret)
+    }
+
+    /// Get credential by ID, deserialized from the given format.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[cfg(feature = "serde_json")]
+    /// # {
+    /// use libsystemd::credentials::{CredentialFormat, CredentialsLoader};
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct Database {
+    ///     host: String,
+    ///     port: u16,
+    /// }
+    ///
+    /// let loader = CredentialsLoader::open()?;
+    /// let db: Database = loader.get_parsed("database", CredentialFormat::Json)?;
+    /// println!("database at {}:{}", db.host, db.port);
+    /// # }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(any(feature = "serde_json", feature = "toml"))]
+    pub fn get_parsed<T: serde::de::DeserializeOwned>(
+        &self,
+        id: impl AsRef<str>,
+        format: CredentialFormat,
+    ) -> Result<T, SdError> {
+        let id = id.as_ref();
+        let content = self.get_string(id)?;
+        let parsed = match format {
+            #[cfg(feature = "serde_json")]
+            CredentialFormat::Json => serde_json::from_str(&content).map_err(|e| e.to_string()),
+            #[cfg(feature = "toml")]
+            CredentialFormat::Toml => toml::from_str(&content).map_err(|e| e.to_string()),
+        };
+        parsed.map_err(|e| format!("Parsing credential '{}': {}", id, e).into())
+    }
+
+    /// Get and decrypt an encrypted credential by ID, using the host key.
+    ///
+    /// This handles credentials in the `systemd-creds encrypt` format which
+    /// have not been decrypted by systemd, e.g. `*.cred` files passed through
+    /// `LoadCredential=`. The host secret is read from [`HOST_SECRET_PATH`],
+    /// and the name embedded in the credential must match the ID (without a
+    /// `.cred` suffix).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use libsystemd::credentials::CredentialsLoader;
+    ///
+    /// let loader = CredentialsLoader::open()?;
+    /// let token = loader.get_encrypted("token.cred")?;
+    /// println!("token size: {}", token.len());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "encrypted-credentials")]
+    pub fn get_encrypted(&self, id: impl AsRef<str>) -> Result<Vec<u8>, SdError> {
+        let host_secret = read_host_secret(HOST_SECRET_PATH)?;
+        self.get_encrypted_with_key(id, &host_secret)
+    }
+
+    /// Get and decrypt an encrypted credential by ID, using the given host
+    /// secret (see [`read_host_secret`]).
+    #[cfg(feature = "encrypted-credentials")]
+    pub fn get_encrypted_with_key(
+        &self,
+        id: impl AsRef<str>,
+        host_secret: &[u8],
+    ) -> Result<Vec<u8>, SdError> {
+        let id = id.as_ref();
+        let cred_path = self.cred_absolute_path(id)?;
+        let data = fs::read(&cred_path)
+            .with_context(|| format!("Reading credential at {}", cred_path.display()))?;
+        let name = id.strip_suffix(".cred").unwrap_or(id);
+        decrypt(Some(name), &data, host_secret)
+            .with_context(|| format!("Decrypting credential at {}", cred_path.display()))
+    }
+
+    /// Validate credential ID and return its absolute path.
+    fn cred_absolute_path(&self, id: &str) -> Result<PathBuf, SdError> {
+        if id.contains('/') {
+            return Err(SdError::from("Invalid credential ID"));
+        }
+
+        let abs_path = self.path.join(id);
+        Ok(abs_path)
+    }
+
+    /// Return an iterator over all existing credentials.
+    ///
+    /// Entries which are not regular files are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use libsystemd::credentials::CredentialsLoader;
+    ///
+    /// let loader = CredentialsLoader::open()?;
+    /// for entry in loader.iter()? {
+    ///   let credential = entry?;
+    ///   println!("Credential ID: {}, size: {}", credential.name(), credential.size());
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn iter(&self) -> Result<CredentialsIter, SdError> {
+        let entries = fs::read_dir(&self.path)
+            .with_context(|| format!("Opening credential directory at {}", self.path.display()))?;
+        Ok(CredentialsIter { entries })
+    }
+
+    /// Return an asynchronous iterator over all existing credentials.
+    ///
+    /// This is the async counterpart of [`iter`](Self::iter), for use in
+    /// services based on the Tokio runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use libsystemd::credentials::CredentialsLoader;
+    ///
+    /// # async fn doctest_iter() -> Result<(), Box<dyn std::error::Error>> {
+    /// let loader = CredentialsLoader::open()?;
+    /// let mut credentials = loader.iter_async().await?;
+    /// while let Some(credential) = credentials.next_entry().await? {
+    ///     println!("Credential ID: {}", credential.name());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn iter_async(&self) -> Result<CredentialsAsyncIter, SdError> {
+        let entries = tokio::fs::read_dir(&self.path)
+            .await
+            .with_context(|| format!("Opening credential directory at {}", self.path.display()))?;
+        Ok(CredentialsAsyncIter { entries })
+    }
+}
+
+/// Serialization format of a structured credential, for
+/// [`CredentialsLoader::get_parsed`].
+#[cfg(any(feature = "serde_json", feature = "toml"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CredentialFormat {
+    /// JSON document (requires the `serde_json` feature).
+    #[cfg(feature = "serde_json")]
+    Json,
+    /// TOML document (requires the `toml` feature).
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+/// Metadata of a credential, as listed by [`CredentialsLoader::iter`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credential {
+    name: String,
+    size: u64,
+    path: PathBuf,
+}
+
+impl Credential {
+    /// Build a credential from a directory entry, skipping entries which are
+    /// not regular files.
+    fn from_entry(
+        path: PathBuf,
+        file_name: OsString,
+        metadata: io::Result<fs::Metadata>,
+    ) -> Result<Option<Self>, SdError> {
+        let metadata =
+            metadata.with_context(|| format!("Reading credential at {}", path.display()))?;
+        if !metadata.is_file() {
+            return Ok(None);
+        }
+        let name = file_name
+            .into_string()
+            .map_err(|_| format!("Invalid credential ID at {}", path.display()))?;
+        Ok(Some(Self {
+            name,
+            size: metadata.len(),
+            path,
+        }))
+    }
+
+    /// Return the credential ID.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the size of the credential in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Return the absolute path of the credential.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Iterator over the credentials in a credentials directory.
+#[derive(Debug)]
+pub struct CredentialsIter {
+    entries: fs::ReadDir,
+}
+
+impl Iterator for CredentialsIter {
+    type Item = Result<Credential, SdError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.entries.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(format!("Reading credential directory: {}", e).into())),
+            };
+            let path = entry.path();
+            let metadata = fs::metadata(&path);
+            if let Some(item) =
+                Credential::from_entry(path, entry.file_name(), metadata).transpose()
+            {
+                return Some(item);
+            }
+        }
+    }
+}
+
+/// Asynchronous iterator over the credentials in a credentials directory,
+/// as returned by [`CredentialsLoader::iter_async`].
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct CredentialsAsyncIter {
+    entries: tokio::fs::ReadDir,
+}
+
+#[cfg(feature = "tokio")]
+impl CredentialsAsyncIter {
+    /// Return the next credential, or `None` once all of them have been
+    /// listed.
+    pub async fn next_entry(&mut self) -> Result<Option<Credential>, SdError> {
+        loop {
+            let entry = match self
+                .entries
+                .next_entry()
+                .await
+                .context("Reading credential directory")?
+            {
+                Some(entry) => entry,
+                None => return Ok(None),
+            };
+            let path = entry.path();
+            let metadata = tokio::fs::metadata(&path).await;
+            if let Some(credential) = Credential::from_entry(path, entry.file_name(), metadata)? {
+                return Ok(Some(credential));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_iter() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        fs::create_dir_all(path.join("subdir")).unwrap();
+        fs::write(path.join("token"), "hunter2").unwrap();
+        fs::write(path.join("empty"), "").unwrap();
+
+        let loader = CredentialsLoader::open_path(path.to_path_buf()).unwrap();
+        let mut creds: Vec<_> = loader.iter().unwrap().map(|c| c.unwrap()).collect();
+        creds.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(creds.len(), 2);
+        assert_eq!((creds[0].name(), creds[0].size()), ("empty", 0));
+        assert_eq!((creds[1].name(), creds[1].size()), ("token", 7));
+        assert_eq!(creds[1].path(), path.join("token"));
+        CredentialsLoader::open_path(path.join("missing")).unwrap_err();
+    }
+
+    #[test]
+    fn test_get_string() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        fs::write(path.join("plain"), "value").unwrap();
+        fs::write(path.join("newline"), "value\n\n").unwrap();
+        fs::write(path.join("crlf"), "value\r\n").unwrap();
+        fs::write(path.join("binary"), [0xff, 0xfe]).unwrap();
+
+        let loader = CredentialsLoader::open_path(path.to_path_buf()).unwrap();
+        assert_eq!(loader.get_string("plain").unwrap(), "value");
+        assert_eq!(loader.get_string("newline").unwrap(), "value\n");
+        assert_eq!(loader.get_string("crlf").unwrap(), "value");
+        loader.get_string("binary").unwrap_err();
+        loader.get_string("missing").unwrap_err();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_async() {
+        use tokio::io::AsyncReadExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        fs::create_dir_all(path.join("subdir")).unwrap();
+        fs::write(path.join("token"), "hunter2").unwrap();
+
+        let loader = CredentialsLoader::open_path(path.to_path_buf()).unwrap();
+        let mut content = String::new();
+        let mut file = loader.get_async("token").await.unwrap();
+        file.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "hunter2");
+        loader.get_async("missing").await.unwrap_err();
+
+        let mut credentials = loader.iter_async().await.unwrap();
+        let credential = credentials.next_entry().await.unwrap().unwrap();
+        assert_eq!((credential.name(), credential.size()), ("token", 7));
+        assert!(credentials.next_entry().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_map() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        fs::write(path.join("bundle"), "-----BEGIN CERTIFICATE-----\n").unwrap();
+        fs::write(path.join("empty"), "").unwrap();
+
+        let loader = CredentialsLoader::open_path(path.to_path_buf()).unwrap();
+        let bundle = loader.map("bundle").unwrap();
+        assert_eq!(&bundle[..], b"-----BEGIN CERTIFICATE-----\n");
+        assert!(loader.map("empty").unwrap().is_empty());
+        loader.map("missing").unwrap_err();
+        assert_eq!(bundle.len(), 28);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_get_secret() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        fs::write(path.join("key"), [0x00, 0xff, 0x42]).unwrap();
+
+        let loader = CredentialsLoader::open_path(path.to_path_buf()).unwrap();
+        let secret = loader.get_secret("key").unwrap();
+        assert_eq!(secret.as_slice(), [0x00, 0xff, 0x42]);
+        assert_eq!(secret.capacity(), 3);
+        loader.get_secret("missing").unwrap_err();
+    }
+
+    #[cfg(all(feature = "serde_json", feature = "toml"))]
+    #[test]
+    fn test_get_parsed() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Database {
+            host: String,
+            port: u16,
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        fs::write(path.join("json"), r#"{"host": "db", "port": 5432}"#).unwrap();
+        fs::write(path.join("toml"), "host = \"db\"\nport = 5432\n").unwrap();
+
+        let loader = CredentialsLoader::open_path(path.to_path_buf()).unwrap();
+        let expected = Database {
+            host: "db".to_string(),
+            port: 5432,
+        };
+        let db: Database = loader.get_parsed("json", CredentialFormat::Json).unwrap();
+        assert_eq!(db, expected);
+        let db: Database = loader.get_parsed("toml", CredentialFormat::Toml).unwrap();
+        assert_eq!(db, expected);
+        let err = loader
+            .get_parsed::<Database>("toml", CredentialFormat::Json)
+            .unwrap_err();
+        assert!(err.to_string().contains("'toml'"), "{}", err);
+    }
+}
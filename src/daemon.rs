@@ -1,12 +1,19 @@
 use crate::errors::{Context, SdError};
 use libc::pid_t;
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::poll::{poll, PollFd, PollFlags};
 use nix::sys::socket;
+use nix::time::{clock_gettime, ClockId};
 use nix::unistd;
 use std::io::{self, IoSlice};
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{AsFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
 use std::os::unix::net::UnixDatagram;
 use std::os::unix::prelude::AsRawFd;
-use std::{env, fmt, fs, time};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::{env, fmt, fs, thread, time};
 
 /// Check for systemd presence at runtime.
 ///
@@ -19,120 +26,1789 @@ pub fn booted() -> bool {
         .unwrap_or(false)
 }
 
+/// Scope of the service manager supervising the current process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ManagerScope {
+    /// Running under the system service manager.
+    System,
+    /// Running under a per-user service manager, or in a login session.
+    User,
+    /// Not running under a systemd service manager.
+    None,
+}
+
+/// Detect which service manager (if any) supervises the current process.
+///
+/// This relies on `$MANAGERPID` (only set by user managers) and on
+/// the cgroup path of the current process, as reported by `/proc/self/cgroup`.
+pub fn manager_scope() -> ManagerScope {
+    if !booted() {
+        return ManagerScope::None;
+    }
+    if env::var_os("MANAGERPID").is_some() {
+        return ManagerScope::User;
+    }
+
+    fs::read_to_string("/proc/self/cgroup")
+        .map(|content| scope_from_cgroup(&content))
+        .unwrap_or(ManagerScope::None)
+}
+
+/// Determine the manager scope from the content of `/proc/<pid>/cgroup`.
+fn scope_from_cgroup(content: &str) -> ManagerScope {
+    let path = match cgroup_path(content) {
+        Some(p) => p,
+        None => return ManagerScope::None,
+    };
+
+    let mut scope = ManagerScope::None;
+    for segment in path.split('/') {
+        // Login sessions belong to the user, like units of their user manager.
+        if (segment.starts_with("user@") && segment.ends_with(".service"))
+            || (segment.starts_with("session-") && segment.ends_with(".scope"))
+        {
+            return ManagerScope::User;
+        }
+        if segment.ends_with(".service") || segment.ends_with(".scope") {
+            scope = ManagerScope::System;
+        }
+    }
+    scope
+}
+
+/// Return the control group path tracked by systemd, from the content of
+/// `/proc/<pid>/cgroup`.
+fn cgroup_path(content: &str) -> Option<&str> {
+    // Prefer the unified hierarchy, falling back to the legacy named one.
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .or_else(|| {
+            content
+                .lines()
+                .find_map(|line| line.split_once(":name=systemd:").map(|(_, p)| p))
+        })
+        .map(str::trim)
+}
+
+/// Return the control group of the unit owning a control group, i.e. the
+/// path up to its first `.service` or `.scope` component.
+fn unit_cgroup(path: &str) -> &str {
+    let mut end = 0;
+    for segment in path.split('/') {
+        end += segment.len();
+        if segment.ends_with(".service") || segment.ends_with(".scope") {
+            return &path[..end];
+        }
+        end += 1;
+    }
+    path
+}
+
+/// Check whether the control group `path` is `parent` or one of its children.
+fn cgroup_is_below(path: &str, parent: &str) -> bool {
+    match path.strip_prefix(parent.trim_end_matches('/')) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Check whether the current process runs inside a container.
+///
+/// This is a shorthand for [`virt::detect_container`](crate::virt::detect_container),
+/// which checks for markers left by container managers (e.g.
+/// `/run/systemd/container` or `/.dockerenv`), the `container` variable in
+/// the environment of PID 1 (when readable), and cgroup hints.
+pub fn in_container() -> bool {
+    !crate::virt::detect_container().is_none()
+}
+
+/// Path where the service manager looks for the reboot parameter.
+const REBOOT_PARAM_PATH: &str = "/run/systemd/reboot-param";
+
+/// Set the parameter to pass to the kernel on the next reboot.
+///
+/// This writes `/run/systemd/reboot-param`, which the service manager passes
+/// to `reboot(2)` when the system is rebooted, similarly to
+/// `systemctl reboot <arg>`. The parameter is interpreted by firmware or
+/// bootloader, e.g. to select a recovery mode. It requires privileges to
+/// write to `/run/systemd`.
+pub fn set_reboot_parameter(param: &str) -> Result<(), SdError> {
+    write_reboot_parameter(Path::new(REBOOT_PARAM_PATH), param)
+}
+
+/// Clear any parameter previously set through [`set_reboot_parameter`].
+pub fn clear_reboot_parameter() -> Result<(), SdError> {
+    match fs::remove_file(REBOOT_PARAM_PATH) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).context("failed to remove reboot parameter")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Atomically write a reboot parameter to the given path.
+fn write_reboot_parameter(path: &Path, param: &str) -> Result<(), SdError> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    if param.is_empty() {
+        return Err("empty reboot parameter".into());
+    }
+    if param.contains(['\0', '\n']) {
+        return Err(format!("invalid reboot parameter '{}'", param.escape_default()).into());
+    }
+
+    let tmp_path = path.with_extension(format!("tmp-{}", unistd::getpid()));
+    let mut tmp_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o644)
+        .open(&tmp_path)
+        .with_context(|| format!("failed to create '{}'", tmp_path.display()))?;
+    // Explicitly set permissions, regardless of the process umask.
+    let written = tmp_file
+        .set_permissions(fs::Permissions::from_mode(0o644))
+        .and_then(|_| tmp_file.write_all(param.as_bytes()))
+        .and_then(|_| tmp_file.sync_all())
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    written.with_context(|| format!("failed to write '{}'", path.display()))
+}
+
+/// Result of a service run, as reported by the service manager in `$SERVICE_RESULT`.
+///
+/// See `systemd.exec(5)` for the meaning of each value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ServiceResult {
+    /// The service completed successfully.
+    Success,
+    /// The service did not follow its notification protocol.
+    Protocol,
+    /// A timeout elapsed.
+    Timeout,
+    /// The main process exited with a non-zero exit code.
+    ExitCode,
+    /// The main process was terminated by a signal.
+    Signal,
+    /// The main process was terminated by a signal and dumped core.
+    CoreDump,
+    /// The watchdog was not pinged in time.
+    Watchdog,
+    /// The start rate limit was hit.
+    StartLimitHit,
+    /// A resource required by the service could not be set up.
+    Resources,
+    /// The service was killed by the out-of-memory killer.
+    OomKill,
+    /// Any other result, not known to this library.
+    Other(String),
+}
+
+impl ServiceResult {
+    /// Return the raw value, as used in `$SERVICE_RESULT`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ServiceResult::Success => "success",
+            ServiceResult::Protocol => "protocol",
+            ServiceResult::Timeout => "timeout",
+            ServiceResult::ExitCode => "exit-code",
+            ServiceResult::Signal => "signal",
+            ServiceResult::CoreDump => "core-dump",
+            ServiceResult::Watchdog => "watchdog",
+            ServiceResult::StartLimitHit => "start-limit-hit",
+            ServiceResult::Resources => "resources",
+            ServiceResult::OomKill => "oom-kill",
+            ServiceResult::Other(s) => s,
+        }
+    }
+}
+
+impl From<&str> for ServiceResult {
+    fn from(value: &str) -> Self {
+        match value {
+            "success" => ServiceResult::Success,
+            "protocol" => ServiceResult::Protocol,
+            "timeout" => ServiceResult::Timeout,
+            "exit-code" => ServiceResult::ExitCode,
+            "signal" => ServiceResult::Signal,
+            "core-dump" => ServiceResult::CoreDump,
+            "watchdog" => ServiceResult::Watchdog,
+            "start-limit-hit" => ServiceResult::StartLimitHit,
+            "resources" => ServiceResult::Resources,
+            "oom-kill" => ServiceResult::OomKill,
+            other => ServiceResult::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ServiceResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How the main process of a service terminated, from `$EXIT_CODE` and `$EXIT_STATUS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExitStatus {
+    /// The process exited with the given exit code.
+    Exited(i32),
+    /// The process was killed by the given signal.
+    Killed(nix::sys::signal::Signal),
+    /// The process was killed by the given signal and dumped core.
+    Dumped(nix::sys::signal::Signal),
+}
+
+/// Outcome of a service run, see [`service_result`] and [`monitored_service_result`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceOutcome {
+    result: ServiceResult,
+    exit_status: Option<ExitStatus>,
+    unit: Option<String>,
+    invocation_id: Option<crate::id128::Id128>,
+}
+
+impl ServiceOutcome {
+    /// Return the overall result of the service run.
+    pub fn result(&self) -> &ServiceResult {
+        &self.result
+    }
+
+    /// Return how the main process terminated, if it was started at all.
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_status
+    }
+
+    /// Return the name of the monitored unit (only for [`monitored_service_result`]).
+    pub fn unit(&self) -> Option<&str> {
+        self.unit.as_deref()
+    }
+
+    /// Return the invocation ID of the monitored unit (only for [`monitored_service_result`]).
+    pub fn invocation_id(&self) -> Option<&crate::id128::Id128> {
+        self.invocation_id.as_ref()
+    }
+}
+
+/// Return the outcome of the service main process.
+///
+/// This is available to `ExecStop=` and `ExecStopPost=` commands, through
+/// `$SERVICE_RESULT`, `$EXIT_CODE` and `$EXIT_STATUS`. It returns `None`
+/// if not running in such context.
+pub fn service_result() -> Result<Option<ServiceOutcome>, SdError> {
+    parse_service_outcome("", |name| env::var(name).ok())
+}
+
+/// Return the outcome of the unit which triggered the current one.
+///
+/// This is available to units started through `OnFailure=` or `OnSuccess=`,
+/// through the `$MONITOR_*` variables. It returns `None` if not running in
+/// such context.
+pub fn monitored_service_result() -> Result<Option<ServiceOutcome>, SdError> {
+    parse_service_outcome("MONITOR_", |name| env::var(name).ok())
+}
+
+/// Parse a service outcome from environment variables with the given prefix.
+fn parse_service_outcome<F>(prefix: &str, getenv: F) -> Result<Option<ServiceOutcome>, SdError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let var = |name: &str| getenv(&format!("{}{}", prefix, name)).filter(|v| !v.is_empty());
+
+    let result = match var("SERVICE_RESULT") {
+        Some(r) => ServiceResult::from(r.as_str()),
+        None => return Ok(None),
+    };
+
+    let exit_status = match (var("EXIT_CODE"), var("EXIT_STATUS")) {
+        (Some(code), Some(status)) => Some(parse_exit_status(&code, &status)?),
+        _ => None,
+    };
+
+    let invocation_id = var("INVOCATION_ID")
+        .map(crate::id128::Id128::parse_str)
+        .transpose()?;
+
+    let outcome = ServiceOutcome {
+        result,
+        exit_status,
+        unit: var("UNIT"),
+        invocation_id,
+    };
+    Ok(Some(outcome))
+}
+
+/// Parse the exit status of a process from `$EXIT_CODE` and `$EXIT_STATUS` values.
+fn parse_exit_status(code: &str, status: &str) -> Result<ExitStatus, SdError> {
+    let signal = || {
+        format!("SIG{}", status)
+            .parse::<nix::sys::signal::Signal>()
+            .with_context(|| format!("invalid exit signal '{}'", status))
+    };
+
+    match code {
+        "exited" => status
+            .parse()
+            .map(ExitStatus::Exited)
+            .with_context(|| format!("invalid exit status '{}'", status)),
+        "killed" => signal().map(ExitStatus::Killed),
+        "dumped" => signal().map(ExitStatus::Dumped),
+        _ => Err(format!("unknown exit code '{}'", code).into()),
+    }
+}
+
+/// Service directories, as set up by the service manager.
+///
+/// Each kind of directory may list multiple paths, in the order of the
+/// corresponding `RuntimeDirectory=`, `StateDirectory=`, `CacheDirectory=`,
+/// `LogsDirectory=` and `ConfigurationDirectory=` settings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Directories {
+    runtime: Vec<PathBuf>,
+    state: Vec<PathBuf>,
+    cache: Vec<PathBuf>,
+    logs: Vec<PathBuf>,
+    configuration: Vec<PathBuf>,
+}
+
+impl Directories {
+    /// Return the runtime directories, from `$RUNTIME_DIRECTORY`.
+    pub fn runtime(&self) -> &[PathBuf] {
+        &self.runtime
+    }
+
+    /// Return the state directories, from `$STATE_DIRECTORY`.
+    pub fn state(&self) -> &[PathBuf] {
+        &self.state
+    }
+
+    /// Return the cache directories, from `$CACHE_DIRECTORY`.
+    pub fn cache(&self) -> &[PathBuf] {
+        &self.cache
+    }
+
+    /// Return the logs directories, from `$LOGS_DIRECTORY`.
+    pub fn logs(&self) -> &[PathBuf] {
+        &self.logs
+    }
+
+    /// Return the configuration directories, from `$CONFIGURATION_DIRECTORY`.
+    pub fn configuration(&self) -> &[PathBuf] {
+        &self.configuration
+    }
+}
+
+/// Return the service directories set up by the service manager.
+///
+/// Kinds of directories which are not configured for the service are empty.
+pub fn directories() -> Directories {
+    parse_directories(|name| env::var_os(name))
+}
+
+/// Parse service directories from environment variables.
+fn parse_directories<F>(getenv: F) -> Directories
+where
+    F: Fn(&str) -> Option<std::ffi::OsString>,
+{
+    let paths = |name: &str| -> Vec<PathBuf> {
+        getenv(name)
+            .map(|value| {
+                env::split_paths(&value)
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    Directories {
+        runtime: paths("RUNTIME_DIRECTORY"),
+        state: paths("STATE_DIRECTORY"),
+        cache: paths("CACHE_DIRECTORY"),
+        logs: paths("LOGS_DIRECTORY"),
+        configuration: paths("CONFIGURATION_DIRECTORY"),
+    }
+}
+
 /// Check for watchdog support at runtime.
 ///
-/// Return a timeout before which the watchdog expects a
-/// response from the process, or `None` if watchdog support is
-/// not enabled. If `unset_env` is true, environment will be cleared.
-pub fn watchdog_enabled(unset_env: bool) -> Option<time::Duration> {
-    let env_usec = env::var("WATCHDOG_USEC").ok();
-    let env_pid = env::var("WATCHDOG_PID").ok();
+/// Return a timeout before which the watchdog expects a
+/// response from the process, or `None` if watchdog support is
+/// not enabled. If `unset_env` is true, environment will be cleared.
+#[deprecated(since = "0.8.0", note = "use `watchdog_config` instead")]
+pub fn watchdog_enabled(unset_env: bool) -> Option<time::Duration> {
+    watchdog_config(unset_env)
+        .filter(|config| config.pid_matched())
+        .map(|config| config.timeout())
+}
+
+/// Watchdog configuration, as set by the service manager.
+///
+/// See [`watchdog_config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    timeout: time::Duration,
+    raw_usec: u64,
+    pid_matched: bool,
+}
+
+impl WatchdogConfig {
+    /// Return the timeout before which the watchdog expects a response from the process.
+    pub fn timeout(&self) -> time::Duration {
+        self.timeout
+    }
+
+    /// Return the raw `WATCHDOG_USEC` value, in microseconds.
+    pub fn raw_usec(&self) -> u64 {
+        self.raw_usec
+    }
+
+    /// Return whether the watchdog applies to this process.
+    ///
+    /// This is true if `WATCHDOG_PID` is unset or matches the current process ID.
+    pub fn pid_matched(&self) -> bool {
+        self.pid_matched
+    }
+}
+
+/// Check for watchdog support at runtime, returning its configuration.
+///
+/// Return the watchdog configuration from `WATCHDOG_USEC` and `WATCHDOG_PID`,
+/// or `None` if watchdog support is not enabled. The watchdog may be enabled
+/// for a different process, see [`WatchdogConfig::pid_matched`].
+/// If `unset_env` is true, environment will be cleared.
+pub fn watchdog_config(unset_env: bool) -> Option<WatchdogConfig> {
+    let env_usec = env::var("WATCHDOG_USEC").ok();
+    let env_pid = env::var("WATCHDOG_PID").ok();
+
+    if unset_env {
+        env::remove_var("WATCHDOG_USEC");
+        env::remove_var("WATCHDOG_PID");
+    };
+
+    parse_watchdog_config(env_usec.as_deref(), env_pid.as_deref(), unistd::getpid())
+}
+
+/// Parse watchdog settings from raw `WATCHDOG_USEC` and `WATCHDOG_PID` values.
+fn parse_watchdog_config(
+    env_usec: Option<&str>,
+    env_pid: Option<&str>,
+    own_pid: unistd::Pid,
+) -> Option<WatchdogConfig> {
+    let raw_usec = env_usec?.parse::<u64>().ok().filter(|usec| *usec > 0)?;
+
+    let pid_matched = match env_pid {
+        Some(pid_str) => {
+            let pid = pid_str.parse::<pid_t>().ok()?;
+            unistd::Pid::from_raw(pid) == own_pid
+        }
+        None => true,
+    };
+
+    let config = WatchdogConfig {
+        timeout: time::Duration::from_micros(raw_usec),
+        raw_usec,
+        pid_matched,
+    };
+    Some(config)
+}
+
+/// Background keep-alive for the service manager watchdog.
+///
+/// This spawns a thread which sends `WATCHDOG=1` at half of the interval
+/// configured by the service manager. Pings can be paused and resumed, and can
+/// be tied to an application-specific liveness check via [`Watchdog::start_with_feed`].
+/// The background thread is stopped when this handle is dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::daemon::Watchdog;
+///
+/// let watchdog = Watchdog::start()?;
+/// if let Some(ref w) = watchdog {
+///     println!("watchdog enabled, timeout {:?}", w.timeout());
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct Watchdog {
+    timeout: time::Duration,
+    shared: Arc<WatchdogShared>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+/// State shared between a `Watchdog` handle and its background thread.
+#[derive(Debug, Default)]
+struct WatchdogShared {
+    state: Mutex<WatchdogState>,
+    cond: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct WatchdogState {
+    paused: bool,
+    stopped: bool,
+}
+
+impl Watchdog {
+    /// Start pinging the watchdog in the background.
+    ///
+    /// Return `None` if watchdog support is not enabled for this process.
+    pub fn start() -> Result<Option<Self>, SdError> {
+        Self::start_with_feed(|| true)
+    }
+
+    /// Start pinging the watchdog in the background, subject to a liveness check.
+    ///
+    /// Before each ping, `feed` is invoked and the ping is only sent if it
+    /// returns true. This allows tying keep-alives to the actual health of
+    /// the application. Return `None` if watchdog support is not enabled.
+    pub fn start_with_feed<F>(feed: F) -> Result<Option<Self>, SdError>
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        match watchdog_config(false).filter(|config| config.pid_matched()) {
+            Some(config) => Self::spawn(config.timeout(), feed).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn spawn<F>(timeout: time::Duration, mut feed: F) -> Result<Self, SdError>
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let interval = timeout / 2;
+        let shared = Arc::new(WatchdogShared::default());
+        let thread_shared = Arc::clone(&shared);
+
+        let thread = thread::Builder::new()
+            .name("sd-watchdog".to_string())
+            .spawn(move || loop {
+                {
+                    let state = thread_shared
+                        .state
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner());
+                    let (state, _) = thread_shared
+                        .cond
+                        .wait_timeout(state, interval)
+                        .unwrap_or_else(|e| e.into_inner());
+                    if state.stopped {
+                        break;
+                    }
+                    if state.paused {
+                        continue;
+                    }
+                }
+
+                if feed() {
+                    if let Err(e) = notify(false, &[NotifyState::Watchdog]) {
+                        log::warn!("failed to ping watchdog: {}", e);
+                    }
+                }
+            })
+            .context("failed to spawn watchdog thread")?;
+
+        let watchdog = Self {
+            timeout,
+            shared,
+            thread: Some(thread),
+        };
+        Ok(watchdog)
+    }
+
+    /// Return the watchdog timeout configured by the service manager.
+    pub fn timeout(&self) -> time::Duration {
+        self.timeout
+    }
+
+    /// Temporarily stop pinging the watchdog.
+    pub fn pause(&self) {
+        self.update_state(|state| state.paused = true);
+    }
+
+    /// Resume pinging the watchdog, sending a ping right away.
+    pub fn resume(&self) {
+        self.update_state(|state| state.paused = false);
+    }
+
+    /// Stop the background thread and wait for it to terminate.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn update_state(&self, f: impl FnOnce(&mut WatchdogState)) {
+        let mut state = self.shared.state.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut state);
+        self.shared.cond.notify_all();
+    }
+
+    fn shutdown(&mut self) {
+        self.update_state(|state| state.stopped = true);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Handle to pause and resume an async watchdog task.
+///
+/// See [`watchdog_task`].
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct WatchdogTaskHandle {
+    timeout: time::Duration,
+    paused: tokio::sync::watch::Sender<bool>,
+}
+
+#[cfg(feature = "tokio")]
+impl WatchdogTaskHandle {
+    /// Return the watchdog timeout configured by the service manager.
+    pub fn timeout(&self) -> time::Duration {
+        self.timeout
+    }
+
+    /// Temporarily stop pinging the watchdog.
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Resume pinging the watchdog.
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+}
+
+/// Create an async task pinging the service manager watchdog.
+///
+/// Return `None` if watchdog support is not enabled for this process.
+/// Otherwise return a handle to pause and resume pings, together with a future
+/// which sends `WATCHDOG=1` at half of the watchdog timeout without blocking
+/// the executor. The future never completes: drop it (or abort the task it was
+/// spawned on) to stop pinging. It must be polled from within a Tokio runtime.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn doctest_watchdog() {
+/// use libsystemd::daemon;
+///
+/// if let Some((handle, task)) = daemon::watchdog_task() {
+///     println!("watchdog enabled, timeout {:?}", handle.timeout());
+///     tokio::spawn(task);
+/// }
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+pub fn watchdog_task() -> Option<(
+    WatchdogTaskHandle,
+    impl std::future::Future<Output = ()> + Send + 'static,
+)> {
+    let config = watchdog_config(false).filter(|config| config.pid_matched())?;
+    Some(watchdog_task_impl(config.timeout(), || {
+        notify_socket_from_env(false)
+    }))
+}
+
+/// Build the watchdog task, sending pings to the address returned by `notify_addr`.
+#[cfg(feature = "tokio")]
+fn watchdog_task_impl<F>(
+    timeout: time::Duration,
+    notify_addr: F,
+) -> (
+    WatchdogTaskHandle,
+    impl std::future::Future<Output = ()> + Send + 'static,
+)
+where
+    F: Fn() -> Result<Option<NotifySocketAddr>, SdError> + Send + 'static,
+{
+    let (paused, mut paused_rx) = tokio::sync::watch::channel(false);
+    let handle = WatchdogTaskHandle { timeout, paused };
+
+    let task = async move {
+        let mut interval = tokio::time::interval(timeout / 2);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            let is_paused = *paused_rx.borrow_and_update();
+            if is_paused {
+                if paused_rx.changed().await.is_err() {
+                    // The handle is gone while paused, nothing can resume us.
+                    std::future::pending::<()>().await;
+                }
+                continue;
+            }
+
+            interval.tick().await;
+            let is_paused = *paused_rx.borrow();
+            if is_paused {
+                continue;
+            }
+            let res = match notify_addr() {
+                Ok(addr) => send_notify_async_to(addr, &[NotifyState::Watchdog], &[]).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                log::warn!("failed to ping watchdog: {}", e);
+            }
+        }
+    };
+
+    (handle, task)
+}
+
+/// Notify service manager about status changes.
+///
+/// Send a notification to the manager about service status changes.
+/// The returned boolean show whether notifications are supported for
+/// this service. If `unset_env` is true, environment will be cleared
+/// and no further notifications are possible.
+/// Also see [`notify_with_fds`] which can send file descriptors to the
+/// service manager.
+///
+/// Besides Unix sockets, `$NOTIFY_SOCKET` may reference an `AF_VSOCK` socket
+/// as `vsock:CID:PORT`, e.g. for virtual machines managed by a host systemd.
+pub fn notify(unset_env: bool, state: &[NotifyState]) -> Result<bool, SdError> {
+    notify_with_fds(unset_env, state, &[])
+}
+
+/// Notify service manager about status changes and send file descriptors.
+///
+/// Use this together with [`NotifyState::Fdstore`]. Otherwise works like [`notify`].
+/// File descriptors cannot be sent over `AF_VSOCK` notification sockets.
+pub fn notify_with_fds(
+    unset_env: bool,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<bool, SdError> {
+    send_notify(None, unset_env, state, fds)
+}
+
+/// Helper for keeping file descriptors in the service manager file descriptor store.
+///
+/// This takes care of pairing `FDSTORE=1` with the matching `FDNAME=`, and
+/// optionally disables polling of stored descriptors (`FDPOLL=0`). The service
+/// manager receives its own copy of each descriptor, so the caller keeps
+/// ownership of the ones passed in. Stored descriptors are handed back on
+/// restart, see [`receive_descriptors_with_names`](crate::activation::receive_descriptors_with_names).
+/// The service needs a non-zero `FileDescriptorStoreMax=` for this to work.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::daemon::FdStore;
+/// use std::fs::File;
+///
+/// let state = File::open("/dev/null")?;
+/// let fdstore = FdStore::new();
+/// fdstore.store("state", &state)?;
+/// // ...and later, once it is not needed anymore.
+/// fdstore.remove("state")?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct FdStore {
+    poll: bool,
+}
+
+impl Default for FdStore {
+    fn default() -> Self {
+        Self { poll: true }
+    }
+}
+
+impl FdStore {
+    /// Create a new helper, with polling of stored descriptors enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the service manager should poll stored descriptors for errors.
+    ///
+    /// If disabled, the service manager keeps broken descriptors around,
+    /// until they are explicitly removed.
+    pub fn poll(mut self, poll: bool) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Store a file descriptor under the given name.
+    ///
+    /// The returned boolean show whether notifications are supported for this service.
+    pub fn store(&self, name: &str, fd: impl AsFd) -> Result<bool, SdError> {
+        self.store_many(name, &[fd.as_fd()])
+    }
+
+    /// Store multiple file descriptors, all under the same name.
+    ///
+    /// The returned boolean show whether notifications are supported for this service.
+    pub fn store_many(&self, name: &str, fds: &[BorrowedFd]) -> Result<bool, SdError> {
+        if fds.is_empty() {
+            return Err(format!("no file descriptors to store as '{}'", name).into());
+        }
+        let raw_fds: Vec<RawFd> = fds.iter().map(|fd| fd.as_raw_fd()).collect();
+        notify_with_fds(false, &self.store_state(name), &raw_fds)
+    }
+
+    /// Remove all file descriptors stored under the given name.
+    ///
+    /// The returned boolean show whether notifications are supported for this service.
+    pub fn remove(&self, name: &str) -> Result<bool, SdError> {
+        let state = [
+            NotifyState::FdstoreRemove,
+            NotifyState::Fdname(name.to_string()),
+        ];
+        notify(false, &state)
+    }
+
+    fn store_state(&self, name: &str) -> Vec<NotifyState> {
+        let mut state = vec![NotifyState::Fdstore, NotifyState::Fdname(name.to_string())];
+        if !self.poll {
+            state.push(NotifyState::FdpollDisable);
+        }
+        state
+    }
+}
+
+/// Destination of service manager notifications.
+///
+/// See [`notify_to`].
+#[derive(Clone, Copy, Debug)]
+pub enum NotifyTarget<'a> {
+    /// A socket bound to a path in the filesystem.
+    Path(&'a Path),
+    /// A socket in the Linux abstract namespace, named without the leading `@`.
+    Abstract(&'a [u8]),
+    /// An already-open datagram socket, connected to its destination.
+    Socket(&'a UnixDatagram),
+}
+
+/// Notify about status changes and send file descriptors to an explicit socket.
+///
+/// This works like [`notify_with_fds`], but sends notifications to the given
+/// `target` instead of the socket referenced by `$NOTIFY_SOCKET`. This is
+/// useful for testing, for proxying notifications, and for nested supervisors.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::daemon::{self, NotifyState, NotifyTarget};
+/// use std::path::Path;
+///
+/// let target = NotifyTarget::Path(Path::new("/run/my-supervisor/notify"));
+/// daemon::notify_to(target, &[NotifyState::Ready], &[])?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn notify_to(
+    target: NotifyTarget,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<(), SdError> {
+    sanity_check_state_entries(state)?;
+    let msg = notify_message(state);
+
+    let sent_len = match target {
+        NotifyTarget::Socket(socket) => {
+            send_notify_datagram(socket.as_raw_fd(), None, &msg, None, fds)
+        }
+        NotifyTarget::Path(path) => {
+            let socket_addr = socket::UnixAddr::new(path)
+                .with_context(|| format!("invalid Unix socket path address {}", path.display()))?;
+            let socket = UnixDatagram::unbound().context("failed to open Unix datagram socket")?;
+            send_notify_datagram(socket.as_raw_fd(), Some(&socket_addr), &msg, None, fds)
+        }
+        NotifyTarget::Abstract(name) => {
+            let socket_addr = socket::UnixAddr::new_abstract(name).with_context(|| {
+                format!(
+                    "invalid Unix socket abstract address @{}",
+                    String::from_utf8_lossy(name)
+                )
+            })?;
+            let socket = UnixDatagram::unbound().context("failed to open Unix datagram socket")?;
+            send_notify_datagram(socket.as_raw_fd(), Some(&socket_addr), &msg, None, fds)
+        }
+    }
+    .map_err(|e| io::Error::from_raw_os_error(e as i32))
+    .context("failed to send notify datagram")?;
+    ensure_fully_sent(sent_len, msg.len())
+}
+
+/// Maximum size of a notification datagram accepted by [`NotifyListener`].
+const NOTIFY_BUFFER_MAX: usize = 4096;
+
+/// Maximum number of file descriptors passed along a single notification (`SCM_MAX_FD`).
+const NOTIFY_FD_MAX: usize = 253;
+
+/// Open a socket for receiving service manager notifications.
+///
+/// This binds a datagram socket at the given `target` (or reuses the given
+/// socket), and enables `SO_PASSCRED` on it so that the credentials of each
+/// sender are available. It allows implementing supervisors which spawn
+/// processes with `$NOTIFY_SOCKET` pointing to the listener.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::daemon::{self, NotifyState, NotifyTarget};
+/// use std::path::Path;
+///
+/// let listener = daemon::notify_listener(NotifyTarget::Path(Path::new("/run/my-supervisor/notify")))?;
+/// let message = listener.recv()?;
+/// if message.state().contains(&NotifyState::Ready) {
+///     println!("process {:?} is ready", message.pid());
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn notify_listener(target: NotifyTarget) -> Result<NotifyListener, SdError> {
+    let socket = match target {
+        NotifyTarget::Path(path) => UnixDatagram::bind(path)
+            .with_context(|| format!("failed to bind Unix socket {}", path.display()))?,
+        NotifyTarget::Abstract(name) => {
+            let socket_addr = socket::UnixAddr::new_abstract(name).with_context(|| {
+                format!(
+                    "invalid Unix socket abstract address @{}",
+                    String::from_utf8_lossy(name)
+                )
+            })?;
+            let fd = socket::socket(
+                socket::AddressFamily::Unix,
+                socket::SockType::Datagram,
+                socket::SockFlag::SOCK_CLOEXEC,
+                None,
+            )
+            .map_err(|e| io::Error::from_raw_os_error(e as i32))
+            .context("failed to open Unix datagram socket")?;
+            socket::bind(fd.as_raw_fd(), &socket_addr)
+                .map_err(|e| io::Error::from_raw_os_error(e as i32))
+                .with_context(|| {
+                    format!(
+                        "failed to bind Unix socket @{}",
+                        String::from_utf8_lossy(name)
+                    )
+                })?;
+            UnixDatagram::from(fd)
+        }
+        NotifyTarget::Socket(socket) => socket
+            .try_clone()
+            .context("failed to duplicate Unix datagram socket")?,
+    };
+
+    socket::setsockopt(&socket, socket::sockopt::PassCred, &true)
+        .map_err(|e| io::Error::from_raw_os_error(e as i32))
+        .context("failed to enable SO_PASSCRED")?;
+    Ok(NotifyListener { socket })
+}
+
+/// Socket receiving service manager notifications, see [`notify_listener`].
+#[derive(Debug)]
+pub struct NotifyListener {
+    socket: UnixDatagram,
+}
+
+impl NotifyListener {
+    /// Wait for the next notification.
+    pub fn recv(&self) -> Result<NotifyMessage, SdError> {
+        let mut buf = vec![0u8; NOTIFY_BUFFER_MAX];
+        let mut cmsg_buf = nix::cmsg_space!(socket::UnixCredentials, [RawFd; NOTIFY_FD_MAX]);
+        let mut iov = [io::IoSliceMut::new(&mut buf)];
+
+        let msg = loop {
+            match socket::recvmsg::<()>(
+                self.socket.as_raw_fd(),
+                &mut iov,
+                Some(&mut cmsg_buf),
+                socket::MsgFlags::MSG_CMSG_CLOEXEC,
+            ) {
+                Err(Errno::EINTR) => continue,
+                res => break res,
+            }
+        }
+        .map_err(|e| io::Error::from_raw_os_error(e as i32))
+        .context("failed to receive notify datagram")?;
+
+        let mut fds = vec![];
+        let mut credentials = None;
+        for cmsg in msg.cmsgs() {
+            match cmsg {
+                socket::ControlMessageOwned::ScmRights(received) => {
+                    // SAFETY: the kernel just installed these FDs for us.
+                    let owned = received
+                        .into_iter()
+                        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+                    fds.extend(owned);
+                }
+                socket::ControlMessageOwned::ScmCredentials(creds) => {
+                    credentials = Some(creds);
+                }
+                _ => {}
+            }
+        }
+        let truncated = msg
+            .flags
+            .intersects(socket::MsgFlags::MSG_TRUNC | socket::MsgFlags::MSG_CTRUNC);
+        let len = msg.bytes;
+        if truncated {
+            return Err("truncated notify datagram".into());
+        }
+
+        let payload = std::str::from_utf8(&buf[..len]).context("invalid notify datagram")?;
+        let message = NotifyMessage {
+            state: parse_notify_payload(payload),
+            pid: credentials.map(|c| unistd::Pid::from_raw(c.pid())),
+            uid: credentials.map(|c| unistd::Uid::from_raw(c.uid())),
+            gid: credentials.map(|c| unistd::Gid::from_raw(c.gid())),
+            fds,
+        };
+        Ok(message)
+    }
+}
+
+/// Parse the state entries of a notification payload.
+///
+/// Like the service manager, this is lenient: assignments with unexpected
+/// values are kept as [`NotifyState::Other`], and lines which are not
+/// assignments are skipped.
+fn parse_notify_payload(payload: &str) -> Vec<NotifyState> {
+    payload
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| match line.parse() {
+            Ok(state) => Some(state),
+            Err(_)
+                if line
+                    .split_once('=')
+                    .map_or(false, |(key, _)| !key.is_empty()) =>
+            {
+                Some(NotifyState::Other(line.to_string()))
+            }
+            Err(_) => {
+                log::debug!("ignoring invalid notify state entry '{}'", line);
+                None
+            }
+        })
+        .collect()
+}
+
+impl AsFd for NotifyListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+impl AsRawFd for NotifyListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+/// Notification received by a [`NotifyListener`].
+#[derive(Debug)]
+pub struct NotifyMessage {
+    state: Vec<NotifyState>,
+    pid: Option<unistd::Pid>,
+    uid: Option<unistd::Uid>,
+    gid: Option<unistd::Gid>,
+    fds: Vec<OwnedFd>,
+}
+
+impl NotifyMessage {
+    /// Return the state entries carried by this notification.
+    pub fn state(&self) -> &[NotifyState] {
+        &self.state
+    }
+
+    /// Return the process ID of the sender.
+    pub fn pid(&self) -> Option<unistd::Pid> {
+        self.pid
+    }
+
+    /// Return the user ID of the sender.
+    pub fn uid(&self) -> Option<unistd::Uid> {
+        self.uid
+    }
+
+    /// Return the group ID of the sender.
+    pub fn gid(&self) -> Option<unistd::Gid> {
+        self.gid
+    }
+
+    /// Return the file descriptors passed along this notification.
+    pub fn fds(&self) -> &[OwnedFd] {
+        &self.fds
+    }
+
+    /// Take ownership of the file descriptors passed along this notification.
+    pub fn take_fds(&mut self) -> Vec<OwnedFd> {
+        std::mem::take(&mut self.fds)
+    }
+}
+
+/// Notify service manager about status changes, without blocking the executor.
+///
+/// This is the async counterpart of [`notify`], for use in services based on
+/// the Tokio runtime. It must be called from within a Tokio runtime.
+#[cfg(feature = "tokio")]
+pub async fn notify_async(unset_env: bool, state: &[NotifyState]) -> Result<bool, SdError> {
+    send_notify_async(unset_env, state, &[]).await
+}
+
+/// Notify service manager about status changes and send file descriptors,
+/// without blocking the executor.
+///
+/// This is the async counterpart of [`notify_with_fds`], for use in services based
+/// on the Tokio runtime. It must be called from within a Tokio runtime.
+#[cfg(feature = "tokio")]
+pub async fn notify_with_fds_async(
+    unset_env: bool,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<bool, SdError> {
+    send_notify_async(unset_env, state, fds).await
+}
+
+/// Outcome of a notification attempt.
+///
+/// See [`notify_detailed`].
+#[derive(Debug)]
+pub enum NotifyOutcome {
+    /// `$NOTIFY_SOCKET` is not set, notifications are not supported for this service.
+    NotSupported,
+    /// The notification was sent in full.
+    Sent,
+    /// Only part of the notification datagram was sent.
+    Partial {
+        /// Number of bytes actually sent.
+        sent: usize,
+        /// Size of the whole notification datagram.
+        expected: usize,
+    },
+    /// Sending the notification datagram failed.
+    Failed(io::Error),
+}
+
+impl NotifyOutcome {
+    /// Return whether the notification was sent in full.
+    pub fn is_sent(&self) -> bool {
+        matches!(self, NotifyOutcome::Sent)
+    }
+}
+
+/// Notify service manager about status changes, reporting the detailed outcome.
+///
+/// This works like [`notify_with_fds`], but distinguishes between notifications
+/// not being supported, being sent, and failing to be sent (along with the
+/// underlying OS error), so that callers can log actionable diagnostics.
+/// Invalid state entries and socket addresses are still reported as errors.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::daemon::{self, NotifyOutcome, NotifyState};
+///
+/// match daemon::notify_detailed(false, &[NotifyState::Ready], &[])? {
+///     NotifyOutcome::Sent => {}
+///     NotifyOutcome::NotSupported => println!("not running under a service manager"),
+///     NotifyOutcome::Partial { sent, expected } => {
+///         println!("notification truncated, {} out of {} bytes sent", sent, expected)
+///     }
+///     NotifyOutcome::Failed(e) => println!("notification failed: {}", e),
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn notify_detailed(
+    unset_env: bool,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<NotifyOutcome, SdError> {
+    send_notify_detailed(None, unset_env, state, fds)
+}
+
+/// Notify service manager about status changes on behalf of another process.
+///
+/// This attaches `SCM_CREDENTIALS` for `pid` to the notification, so that the
+/// service manager attributes it to that process (see `sd_pid_notify_with_fds(3)`).
+/// Sending credentials for another process requires privileges; if the kernel
+/// refuses them, the notification is sent with the credentials of the current
+/// process instead. Otherwise works like [`notify_with_fds`].
+pub fn notify_with_pid(
+    pid: unistd::Pid,
+    unset_env: bool,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<bool, SdError> {
+    send_notify(Some(pid), unset_env, state, fds)
+}
+
+/// Send a notification datagram to `$NOTIFY_SOCKET`, optionally on behalf of `pid`.
+fn send_notify(
+    pid: Option<unistd::Pid>,
+    unset_env: bool,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<bool, SdError> {
+    match send_notify_detailed(pid, unset_env, state, fds)? {
+        NotifyOutcome::NotSupported => Ok(false),
+        NotifyOutcome::Sent => Ok(true),
+        NotifyOutcome::Partial { sent, expected } => {
+            ensure_fully_sent(sent, expected).map(|_| true)
+        }
+        NotifyOutcome::Failed(e) => Err(e).context("failed to send notify datagram"),
+    }
+}
+
+/// Send a notification datagram to `$NOTIFY_SOCKET`, reporting the detailed outcome.
+fn send_notify_detailed(
+    pid: Option<unistd::Pid>,
+    unset_env: bool,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<NotifyOutcome, SdError> {
+    let socket_addr = match notify_socket_from_env(unset_env)? {
+        None => return Ok(NotifyOutcome::NotSupported),
+        Some(v) => v,
+    };
+    sanity_check_state_entries(state)?;
+
+    let msg = notify_message(state);
+    let result = match socket_addr {
+        NotifySocketAddr::Unix(socket_addr) => {
+            let socket = UnixDatagram::unbound().context("failed to open Unix datagram socket")?;
+            send_notify_datagram(socket.as_raw_fd(), Some(&socket_addr), &msg, pid, fds)
+        }
+        NotifySocketAddr::Vsock(socket_addr, sock_type) => {
+            ensure_no_vsock_fds(fds)?;
+            send_notify_vsock(&socket_addr, sock_type, &msg)
+        }
+    };
+    let outcome = match result {
+        Ok(sent) if sent == msg.len() => NotifyOutcome::Sent,
+        Ok(sent) => NotifyOutcome::Partial {
+            sent,
+            expected: msg.len(),
+        },
+        Err(e) => NotifyOutcome::Failed(io::Error::from_raw_os_error(e as i32)),
+    };
+
+    Ok(outcome)
+}
+
+/// Send a notification datagram to `$NOTIFY_SOCKET`, without blocking the executor.
+///
+/// This must be called from within a Tokio runtime.
+#[cfg(feature = "tokio")]
+async fn send_notify_async(
+    unset_env: bool,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<bool, SdError> {
+    send_notify_async_to(notify_socket_from_env(unset_env)?, state, fds).await
+}
+
+/// Send a notification datagram to `addr`, without blocking the executor.
+///
+/// Nothing is sent if `addr` is `None`.
+#[cfg(feature = "tokio")]
+async fn send_notify_async_to(
+    addr: Option<NotifySocketAddr>,
+    state: &[NotifyState],
+    fds: &[RawFd],
+) -> Result<bool, SdError> {
+    let socket_addr = match addr {
+        None => return Ok(false),
+        Some(NotifySocketAddr::Unix(v)) => v,
+        Some(NotifySocketAddr::Vsock(socket_addr, sock_type)) => {
+            // Notifications over AF_VSOCK are short-lived local sends, perform them inline.
+            sanity_check_state_entries(state)?;
+            ensure_no_vsock_fds(fds)?;
+            let msg = notify_message(state);
+            let sent_len = send_notify_vsock(&socket_addr, sock_type, &msg)
+                .map_err(|e| io::Error::from_raw_os_error(e as i32))
+                .context("failed to send notify datagram")?;
+            ensure_fully_sent(sent_len, msg.len())?;
+            return Ok(true);
+        }
+    };
+    sanity_check_state_entries(state)?;
+
+    let socket = UnixDatagram::unbound().context("failed to open Unix datagram socket")?;
+    socket
+        .set_nonblocking(true)
+        .context("failed to set notify socket as non-blocking")?;
+    let socket = tokio::net::UnixDatagram::from_std(socket)
+        .context("failed to register notify socket with the runtime")?;
+    let msg = notify_message(state);
+
+    let sent_len = loop {
+        socket
+            .writable()
+            .await
+            .context("failed to wait for notify socket")?;
+        let res = socket.try_io(tokio::io::Interest::WRITABLE, || {
+            send_notify_datagram(socket.as_raw_fd(), Some(&socket_addr), &msg, None, fds)
+                .map_err(|e| io::Error::from_raw_os_error(e as i32))
+        });
+        match res {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            res => break res,
+        }
+    }
+    .context("failed to send notify datagram")?;
+    ensure_fully_sent(sent_len, msg.len())?;
+
+    Ok(true)
+}
+
+/// Address of the notification socket, as referenced by `$NOTIFY_SOCKET`.
+#[derive(Debug, PartialEq, Eq)]
+enum NotifySocketAddr {
+    /// A Unix datagram socket, either in the filesystem or in the abstract namespace.
+    Unix(socket::UnixAddr),
+    /// An `AF_VSOCK` socket, optionally with an explicit socket type.
+    Vsock(socket::VsockAddr, Option<socket::SockType>),
+}
+
+/// Return the notification socket address from `$NOTIFY_SOCKET`, if any.
+///
+/// If `unset_env` is true, the variable is removed from the environment.
+fn notify_socket_from_env(unset_env: bool) -> Result<Option<NotifySocketAddr>, SdError> {
+    let env_sock = match env::var("NOTIFY_SOCKET").ok() {
+        None => return Ok(None),
+        Some(v) => v,
+    };
+
+    if unset_env {
+        env::remove_var("NOTIFY_SOCKET");
+    };
+
+    parse_notify_socket(&env_sock).map(Some)
+}
+
+/// Parse the notification socket address from a `$NOTIFY_SOCKET` value.
+fn parse_notify_socket(env_sock: &str) -> Result<NotifySocketAddr, SdError> {
+    // Sockets in VMs can be referenced as `vsock:CID:PORT`, optionally
+    // with an explicit socket type (e.g. `vsock-seqpacket:CID:PORT`).
+    if let Some((scheme, addr)) = env_sock.split_once(':') {
+        let sock_type = match scheme {
+            "vsock" => Some(None),
+            "vsock-dgram" => Some(Some(socket::SockType::Datagram)),
+            "vsock-seqpacket" => Some(Some(socket::SockType::SeqPacket)),
+            "vsock-stream" => Some(Some(socket::SockType::Stream)),
+            _ => None,
+        };
+        if let Some(sock_type) = sock_type {
+            let (cid, port) = addr
+                .split_once(':')
+                .and_then(|(cid, port)| Some((cid.parse().ok()?, port.parse().ok()?)))
+                .with_context(|| format!("invalid AF_VSOCK address {}", env_sock))?;
+            let socket_addr = socket::VsockAddr::new(cid, port);
+            return Ok(NotifySocketAddr::Vsock(socket_addr, sock_type));
+        }
+    }
+
+    // If the first character of `$NOTIFY_SOCKET` is '@', the string
+    // is understood as Linux abstract namespace socket.
+    let socket_addr = match env_sock.strip_prefix('@') {
+        Some(stripped_addr) => socket::UnixAddr::new_abstract(stripped_addr.as_bytes())
+            .with_context(|| format!("invalid Unix socket abstract address {}", env_sock))?,
+        None => socket::UnixAddr::new(env_sock)
+            .with_context(|| format!("invalid Unix socket path address {}", env_sock))?,
+    };
+
+    Ok(NotifySocketAddr::Unix(socket_addr))
+}
+
+/// Send a notification payload over an `AF_VSOCK` socket.
+///
+/// If no socket type is specified, a datagram socket is tried first,
+/// falling back to a sequential packet one if datagrams are not supported.
+fn send_notify_vsock(
+    socket_addr: &socket::VsockAddr,
+    sock_type: Option<socket::SockType>,
+    msg: &[u8],
+) -> nix::Result<usize> {
+    let send = |sock_type| -> nix::Result<usize> {
+        let fd = socket::socket(
+            socket::AddressFamily::Vsock,
+            sock_type,
+            socket::SockFlag::SOCK_CLOEXEC,
+            None,
+        )?;
+        if sock_type == socket::SockType::Datagram {
+            socket::sendto(fd.as_raw_fd(), msg, socket_addr, socket::MsgFlags::empty())
+        } else {
+            socket::connect(fd.as_raw_fd(), socket_addr)?;
+            socket::send(fd.as_raw_fd(), msg, socket::MsgFlags::MSG_NOSIGNAL)
+        }
+    };
+
+    match sock_type {
+        Some(sock_type) => send(sock_type),
+        None => match send(socket::SockType::Datagram) {
+            Err(
+                Errno::ENODEV | Errno::ESOCKTNOSUPPORT | Errno::EPROTONOSUPPORT | Errno::EOPNOTSUPP,
+            ) => send(socket::SockType::SeqPacket),
+            res => res,
+        },
+    }
+}
+
+/// Ensure that no file descriptors are passed to an `AF_VSOCK` socket.
+fn ensure_no_vsock_fds(fds: &[RawFd]) -> Result<(), SdError> {
+    if !fds.is_empty() {
+        return Err("file descriptors cannot be passed over AF_VSOCK".into());
+    }
+    Ok(())
+}
+
+/// Serialize state entries into the notification payload.
+fn notify_message(state: &[NotifyState]) -> Vec<u8> {
+    state
+        .iter()
+        .fold(String::new(), |res, s| res + &format!("{}\n", s))
+        .into_bytes()
+}
+
+/// Send a notification payload, along with file descriptors and credentials.
+///
+/// If `socket_addr` is `None`, the socket must already be connected.
+fn send_notify_datagram(
+    socket: RawFd,
+    socket_addr: Option<&socket::UnixAddr>,
+    msg: &[u8],
+    pid: Option<unistd::Pid>,
+    fds: &[RawFd],
+) -> nix::Result<usize> {
+    let msg_iov = IoSlice::new(msg);
+    let credentials = pid.filter(|p| *p != unistd::getpid()).map(|p| {
+        socket::UnixCredentials::from(libc::ucred {
+            pid: p.as_raw(),
+            uid: unistd::getuid().as_raw(),
+            gid: unistd::getgid().as_raw(),
+        })
+    });
+
+    let mut ancillary = vec![];
+    if !fds.is_empty() {
+        ancillary.push(socket::ControlMessage::ScmRights(fds));
+    }
+    if let Some(ref creds) = credentials {
+        ancillary.push(socket::ControlMessage::ScmCredentials(creds));
+    }
+
+    let send = |cmsgs: &[socket::ControlMessage]| {
+        socket::sendmsg(
+            socket,
+            &[msg_iov],
+            cmsgs,
+            socket::MsgFlags::empty(),
+            socket_addr,
+        )
+    };
+    match send(&ancillary) {
+        // Not privileged enough to send credentials for another process,
+        // retry with our own ones.
+        Err(Errno::EPERM) if credentials.is_some() => {
+            ancillary.pop();
+            send(&ancillary)
+        }
+        res => res,
+    }
+}
+
+/// Ensure that a notification datagram was sent in full.
+fn ensure_fully_sent(sent_len: usize, msg_len: usize) -> Result<(), SdError> {
+    if sent_len != msg_len {
+        return Err(format!(
+            "incomplete notify sendmsg, sent {} out of {}",
+            sent_len, msg_len
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Wait for the service manager to process all previously sent notifications.
+///
+/// This sends `BARRIER=1` together with the write end of a pipe, and then waits
+/// up to `timeout` for the service manager to close it (see `sd_notify_barrier(3)`).
+/// It is useful to avoid races when a process exits right after notifying.
+/// The returned boolean show whether notifications are supported for
+/// this service. If `unset_env` is true, environment will be cleared.
+pub fn notify_barrier(unset_env: bool, timeout: time::Duration) -> Result<bool, SdError> {
+    let (read_fd, write_fd) = unistd::pipe2(OFlag::O_CLOEXEC)
+        .map_err(|e| io::Error::from_raw_os_error(e as i32))
+        .context("failed to create barrier pipe")?;
+    // SAFETY: `pipe2` just returned these FDs, so we own them now.
+    let (read_end, write_end) = unsafe {
+        (
+            OwnedFd::from_raw_fd(read_fd),
+            OwnedFd::from_raw_fd(write_fd),
+        )
+    };
+
+    let state = [NotifyState::Other("BARRIER=1".to_string())];
+    let sent = notify_with_fds(unset_env, &state, &[write_end.as_raw_fd()])?;
+    // Close our side, so that the pipe hangs up once the manager closes its copy.
+    drop(write_end);
+    if !sent {
+        return Ok(false);
+    }
+
+    let timeout_ms = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    let mut fds = [PollFd::new(&read_end, PollFlags::empty())];
+    let ready = loop {
+        match poll(&mut fds, timeout_ms) {
+            Err(Errno::EINTR) => continue,
+            res => break res,
+        }
+    }
+    .map_err(|e| io::Error::from_raw_os_error(e as i32))
+    .context("failed to wait for notification barrier")?;
+    if ready == 0 {
+        return Err("timed out waiting for notification barrier".into());
+    }
+
+    Ok(true)
+}
+
+/// Hand over the role of main service process to another process.
+///
+/// This sends `MAINPID=<pid>`, to let double-forking daemons migrate to
+/// `Type=notify`: the original process forks the actual daemon, hands over
+/// the main PID and exits. If `barrier` is set, this additionally waits up to
+/// the given timeout for the service manager to process the notification
+/// (see [`notify_barrier`]), so that the caller can safely exit afterwards.
+///
+/// The service manager only accepts this from the main process, unless
+/// `NotifyAccess=` is set to `exec` or `all` in the unit. Unprivileged
+/// services can only hand over to processes in the control group of the
+/// service (or in one of its sub-groups), which is checked here before
+/// notifying whenever both control groups can be determined.
+///
+/// If `unset_env` is true, environment will be cleared.
+pub fn notify_mainpid(
+    unset_env: bool,
+    pid: unistd::Pid,
+    barrier: Option<time::Duration>,
+) -> Result<bool, SdError> {
+    if pid.as_raw() <= 0 {
+        return Err(format!("invalid main PID {}", pid).into());
+    }
+    if env::var_os("NOTIFY_SOCKET").is_none() {
+        return Ok(false);
+    }
+    if !unistd::geteuid().is_root() {
+        let own = fs::read_to_string("/proc/self/cgroup").ok();
+        let target = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok();
+        // The service manager enforces this anyway, so the check is skipped
+        // if either control group cannot be determined.
+        if let (Some(own), Some(target)) = (
+            own.as_deref().and_then(cgroup_path),
+            target.as_deref().and_then(cgroup_path),
+        ) {
+            if !cgroup_is_below(target, unit_cgroup(own)) {
+                return Err(format!(
+                    "PID {} is outside of the service control group, and the caller is unprivileged",
+                    pid
+                )
+                .into());
+            }
+        }
+    }
+
+    let state = [NotifyState::Mainpid(pid)];
+    let sent = notify(unset_env && barrier.is_none(), &state)?;
+    match barrier {
+        Some(timeout) if sent => notify_barrier(unset_env, timeout),
+        _ => Ok(sent),
+    }
+}
+
+/// Notify service manager that the service is reloading its configuration.
+///
+/// This sends `RELOADING=1` together with the current `CLOCK_MONOTONIC`
+/// timestamp, as required for `Type=notify-reload` services by systemd 253
+/// and later. Once reloading is complete, [`NotifyState::Ready`] must be sent.
+pub fn notify_reloading(unset_env: bool) -> Result<bool, SdError> {
+    let state = [
+        NotifyState::Reloading,
+        NotifyState::MonotonicUsec(monotonic_usec()?),
+    ];
+    notify(unset_env, &state)
+}
 
-    if unset_env {
-        env::remove_var("WATCHDOG_USEC");
-        env::remove_var("WATCHDOG_PID");
-    };
+/// Maximum length of a status message sent by [`notify_status`], in bytes.
+const STATUS_MAX_LEN: usize = 2048;
 
-    let timeout = {
-        if let Some(usec) = env_usec.and_then(|usec_str| usec_str.parse::<u64>().ok()) {
-            time::Duration::from_millis(usec / 1_000)
-        } else {
-            return None;
-        }
-    };
+/// Notify service manager about a free-form status, after sanitizing it.
+///
+/// Newlines and tabs are replaced by spaces, other control characters are
+/// removed, and overly long messages are truncated. This returns the status
+/// which was actually sent, or `None` if the notification was not sent.
+pub fn notify_status(unset_env: bool, status: &str) -> Result<Option<String>, SdError> {
+    let status = sanitize_status(status);
+    let sent = notify(unset_env, &[NotifyState::Status(status.clone())])?;
+    Ok(if sent { Some(status) } else { None })
+}
 
-    let pid = {
-        if let Some(pid_str) = env_pid {
-            if let Ok(p) = pid_str.parse::<pid_t>() {
-                unistd::Pid::from_raw(p)
-            } else {
-                return None;
+/// Make a status message safe to be sent as a single `STATUS=` entry.
+fn sanitize_status(status: &str) -> String {
+    const ELLIPSIS: &str = "...";
+
+    let mut output = String::with_capacity(status.len().min(STATUS_MAX_LEN));
+    for c in status.chars() {
+        let c = match c {
+            '\n' | '\r' | '\t' => ' ',
+            c if c.is_control() => continue,
+            c => c,
+        };
+        if output.len() + c.len_utf8() > STATUS_MAX_LEN {
+            while output.len() + ELLIPSIS.len() > STATUS_MAX_LEN {
+                output.pop();
             }
-        } else {
-            return Some(timeout);
+            output.push_str(ELLIPSIS);
+            break;
         }
-    };
-
-    if unistd::getpid() == pid {
-        Some(timeout)
-    } else {
-        None
+        output.push(c);
     }
+    output
 }
 
-/// Notify service manager about status changes.
+/// Ask the service manager to consider the service as hung.
 ///
-/// Send a notification to the manager about service status changes.
-/// The returned boolean show whether notifications are supported for
-/// this service. If `unset_env` is true, environment will be cleared
-/// and no further notifications are possible.
-/// Also see [`notify_with_fds`] which can send file descriptors to the
-/// service manager.
-pub fn notify(unset_env: bool, state: &[NotifyState]) -> Result<bool, SdError> {
-    notify_with_fds(unset_env, state, &[])
+/// This sends `WATCHDOG=trigger`, which makes the service manager act as if
+/// the watchdog timeout had elapsed, e.g. restarting the service according to
+/// its `Restart=` setting. It is meant for internal failure detectors, like
+/// deadlock checks. The watchdog logic has to be enabled for the service,
+/// see `WatchdogSec=` in `systemd.service(5)`.
+/// If `unset_env` is true, environment will be cleared.
+pub fn watchdog_trigger(unset_env: bool) -> Result<bool, SdError> {
+    notify(unset_env, &[NotifyState::WatchdogTrigger])
 }
 
-/// Notify service manager about status changes and send file descriptors.
+/// Guard for a configuration reload, completing the reload handshake when dropped.
 ///
-/// Use this together with [`NotifyState::Fdstore`]. Otherwise works like [`notify`].
-pub fn notify_with_fds(
-    unset_env: bool,
-    state: &[NotifyState],
-    fds: &[RawFd],
-) -> Result<bool, SdError> {
-    let env_sock = match env::var("NOTIFY_SOCKET").ok() {
-        None => return Ok(false),
-        Some(v) => v,
-    };
+/// [`ReloadGuard::begin`] sends `RELOADING=1` (see [`notify_reloading`]), and
+/// `READY=1` is sent once the guard is finished or dropped, so that early
+/// returns on reload errors cannot leave the service in the reloading state.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::daemon::ReloadGuard;
+///
+/// let reload = ReloadGuard::begin()?;
+/// // Reload configuration...
+/// reload.finish()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+#[must_use = "reload is completed as soon as the guard is dropped"]
+pub struct ReloadGuard {
+    finished: bool,
+}
 
-    if unset_env {
-        env::remove_var("NOTIFY_SOCKET");
-    };
+impl ReloadGuard {
+    /// Notify the service manager that a reload is starting.
+    pub fn begin() -> Result<Self, SdError> {
+        notify_reloading(false)?;
+        Ok(Self { finished: false })
+    }
 
-    sanity_check_state_entries(state)?;
+    /// Notify the service manager that the reload is complete.
+    ///
+    /// This sends `READY=1`, returning whether the notification was sent.
+    pub fn finish(mut self) -> Result<bool, SdError> {
+        self.finished = true;
+        notify(false, &[NotifyState::Ready])
+    }
+}
 
-    // If the first character of `$NOTIFY_SOCKET` is '@', the string
-    // is understood as Linux abstract namespace socket.
-    let socket_addr = match env_sock.strip_prefix('@') {
-        Some(stripped_addr) => socket::UnixAddr::new_abstract(stripped_addr.as_bytes())
-            .with_context(|| format!("invalid Unix socket abstract address {}", env_sock))?,
-        None => socket::UnixAddr::new(env_sock.as_str())
-            .with_context(|| format!("invalid Unix socket path address {}", env_sock))?,
-    };
+impl Drop for ReloadGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = notify(false, &[NotifyState::Ready]);
+        }
+    }
+}
 
-    let socket = UnixDatagram::unbound().context("failed to open Unix datagram socket")?;
-    let msg = state
-        .iter()
-        .fold(String::new(), |res, s| res + &format!("{}\n", s))
-        .into_bytes();
-    let msg_len = msg.len();
-    let msg_iov = IoSlice::new(&msg);
-
-    let ancillary = if !fds.is_empty() {
-        vec![socket::ControlMessage::ScmRights(fds)]
-    } else {
-        vec![]
-    };
-
-    let sent_len = socket::sendmsg(
-        socket.as_raw_fd(),
-        &[msg_iov],
-        &ancillary,
-        socket::MsgFlags::empty(),
-        Some(&socket_addr),
-    )
-    .map_err(|e| io::Error::from_raw_os_error(e as i32))
-    .context("failed to send notify datagram")?;
+/// Guard for a graceful shutdown, sending a final status message when dropped.
+///
+/// [`StoppingGuard::enter`] sends `STOPPING=1`. A final `STATUS=` message can
+/// be attached while shutting down, which is sent once the guard is finished
+/// or dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::daemon::StoppingGuard;
+///
+/// let mut stopping = StoppingGuard::enter()?;
+/// // Close connections, flush buffers...
+/// stopping.set_status("Shut down cleanly");
+/// stopping.finish()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+#[must_use = "the final status is sent as soon as the guard is dropped"]
+pub struct StoppingGuard {
+    status: Option<String>,
+}
 
-    if sent_len != msg_len {
-        return Err(format!(
-            "incomplete notify sendmsg, sent {} out of {}",
-            sent_len, msg_len
-        )
-        .into());
+impl StoppingGuard {
+    /// Notify the service manager that the service is beginning its shutdown.
+    pub fn enter() -> Result<Self, SdError> {
+        notify(false, &[NotifyState::Stopping])?;
+        Ok(Self { status: None })
     }
 
-    Ok(true)
+    /// Notify the service manager that the service is beginning its shutdown,
+    /// along with a status message.
+    pub fn enter_with_status(status: impl Into<String>) -> Result<Self, SdError> {
+        let status = NotifyState::Status(status.into());
+        notify(false, &[NotifyState::Stopping, status])?;
+        Ok(Self { status: None })
+    }
+
+    /// Set the final status message, replacing any previous one.
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = Some(status.into());
+    }
+
+    /// Send the final status message, if any.
+    ///
+    /// This returns whether the notification was sent.
+    pub fn finish(mut self) -> Result<bool, SdError> {
+        match self.status.take() {
+            Some(status) => notify(false, &[NotifyState::Status(status)]),
+            None => Ok(false),
+        }
+    }
+}
+
+impl Drop for StoppingGuard {
+    fn drop(&mut self) {
+        if let Some(status) = self.status.take() {
+            let _ = notify(false, &[NotifyState::Status(status)]);
+        }
+    }
+}
+
+/// Return the current `CLOCK_MONOTONIC` timestamp, in microseconds.
+fn monotonic_usec() -> Result<u64, SdError> {
+    let now = clock_gettime(ClockId::CLOCK_MONOTONIC)
+        .map_err(|e| io::Error::from_raw_os_error(e as i32))
+        .context("failed to read monotonic clock")?;
+    let usec = time::Duration::from(now).as_micros();
+    u64::try_from(usec).context("overlarge monotonic timestamp")
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -154,18 +1830,26 @@ pub enum NotifyState {
     FdpollDisable,
     /// The main process ID of the service, in case of forking applications.
     Mainpid(unistd::Pid),
-    /// Custom state change, as a `KEY=VALUE` string.
+    /// Current `CLOCK_MONOTONIC` timestamp in microseconds, sent along with
+    /// [`NotifyState::Reloading`]. See [`notify_reloading`].
+    MonotonicUsec(u64),
+    /// Custom state change, as a single-line `KEY=VALUE` string.
     Other(String),
     /// Service startup is finished.
     Ready,
     /// Service is reloading.
     Reloading,
-    /// Custom status change.
+    /// Custom status change, which may not contain newlines.
+    ///
+    /// See [`notify_status`] for sending arbitrary text.
     Status(String),
     /// Service is beginning to shutdown.
     Stopping,
     /// Tell the service manager to update the watchdog timestamp.
     Watchdog,
+    /// Tell the service manager to trigger a watchdog failure, as if the
+    /// watchdog timeout had elapsed. See [`watchdog_trigger`].
+    WatchdogTrigger,
     /// Reset watchdog timeout value during runtime.
     WatchdogUsec(u64),
 }
@@ -180,22 +1864,65 @@ impl fmt::Display for NotifyState {
             NotifyState::FdstoreRemove => write!(f, "FDSTOREREMOVE=1"),
             NotifyState::FdpollDisable => write!(f, "FDPOLL=0"),
             NotifyState::Mainpid(ref p) => write!(f, "MAINPID={}", p),
+            NotifyState::MonotonicUsec(u) => write!(f, "MONOTONIC_USEC={}", u),
             NotifyState::Other(ref s) => write!(f, "{}", s),
             NotifyState::Ready => write!(f, "READY=1"),
             NotifyState::Reloading => write!(f, "RELOADING=1"),
             NotifyState::Status(ref s) => write!(f, "STATUS={}", s),
             NotifyState::Stopping => write!(f, "STOPPING=1"),
             NotifyState::Watchdog => write!(f, "WATCHDOG=1"),
+            NotifyState::WatchdogTrigger => write!(f, "WATCHDOG=trigger"),
             NotifyState::WatchdogUsec(u) => write!(f, "WATCHDOG_USEC={}", u),
         }
     }
 }
 
+/// Parse a single state entry, as found in notification datagrams.
+///
+/// Well-known entries are parsed into their own variants, while unknown
+/// ones are returned as [`NotifyState::Other`].
+impl FromStr for NotifyState {
+    type Err = SdError;
+
+    fn from_str(entry: &str) -> Result<Self, Self::Err> {
+        let (key, value) = entry
+            .split_once('=')
+            .filter(|(key, _)| !key.is_empty())
+            .with_context(|| format!("invalid notify state entry '{}'", entry))?;
+
+        let state = match (key, value) {
+            ("BUSERROR", _) => Some(NotifyState::Buserror(value.to_string())),
+            ("ERRNO", _) => value.parse().ok().map(NotifyState::Errno),
+            ("FDNAME", _) => Some(NotifyState::Fdname(value.to_string())),
+            ("FDSTORE", "1") => Some(NotifyState::Fdstore),
+            ("FDSTOREREMOVE", "1") => Some(NotifyState::FdstoreRemove),
+            ("FDPOLL", "0") => Some(NotifyState::FdpollDisable),
+            ("MAINPID", _) => value
+                .parse()
+                .ok()
+                .map(|pid| NotifyState::Mainpid(unistd::Pid::from_raw(pid))),
+            ("MONOTONIC_USEC", _) => value.parse().ok().map(NotifyState::MonotonicUsec),
+            ("READY", "1") => Some(NotifyState::Ready),
+            ("RELOADING", "1") => Some(NotifyState::Reloading),
+            ("STATUS", _) => Some(NotifyState::Status(value.to_string())),
+            ("STOPPING", "1") => Some(NotifyState::Stopping),
+            ("WATCHDOG", "1") => Some(NotifyState::Watchdog),
+            ("WATCHDOG", "trigger") => Some(NotifyState::WatchdogTrigger),
+            ("WATCHDOG_USEC", _) => value.parse().ok().map(NotifyState::WatchdogUsec),
+            _ => Some(NotifyState::Other(entry.to_string())),
+        };
+        state.with_context(|| format!("invalid value in notify state entry '{}'", entry))
+    }
+}
+
 /// Perform some basic sanity checks against state entries.
 fn sanity_check_state_entries(state: &[NotifyState]) -> Result<(), SdError> {
     for (index, entry) in state.iter().enumerate() {
         match entry {
+            NotifyState::Buserror(ref value) => validate_single_line("BUSERROR", value),
             NotifyState::Fdname(ref name) => validate_fdname(name),
+            NotifyState::Other(ref assignment) => validate_other(assignment),
+            NotifyState::Status(ref value) => validate_single_line("STATUS", value),
             _ => Ok(()),
         }
         .with_context(|| format!("invalid notify state entry #{}", index))?;
@@ -204,11 +1931,52 @@ fn sanity_check_state_entries(state: &[NotifyState]) -> Result<(), SdError> {
     Ok(())
 }
 
+/// Ensure that a value does not contain newlines, which would inject additional assignments.
+fn validate_single_line(key: &str, value: &str) -> Result<(), SdError> {
+    if value.contains('\n') {
+        return Err(format!(
+            "invalid newline in {} value '{}'",
+            key,
+            value.escape_debug()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Validate a custom state change, which must be a single `KEY=VALUE` assignment.
+fn validate_other(assignment: &str) -> Result<(), SdError> {
+    let key = match assignment.split_once('=') {
+        Some((key, _)) => key,
+        None => {
+            let msg = format!(
+                "custom state '{}' is not a KEY=VALUE assignment",
+                assignment
+            );
+            return Err(msg.into());
+        }
+    };
+    if key.is_empty() {
+        return Err(format!("empty key in custom state '{}'", assignment).into());
+    }
+    if let Some(c) = key.chars().find(|c| c.is_whitespace() || c.is_control()) {
+        let msg = format!(
+            "invalid character '{}' in key of custom state '{}'",
+            c.escape_debug(),
+            assignment.escape_debug()
+        );
+        return Err(msg.into());
+    }
+
+    validate_single_line(key, assignment)
+}
+
 /// Validate an `FDNAME` according to systemd rules.
 ///
 /// The name may consist of arbitrary ASCII characters except control
 /// characters or ":". It may not be longer than 255 characters.
-fn validate_fdname(fdname: &str) -> Result<(), SdError> {
+pub(crate) fn validate_fdname(fdname: &str) -> Result<(), SdError> {
     if fdname.len() > 255 {
         return Err(format!("fdname '{}' longer than 255 characters", fdname).into());
     }
@@ -221,3 +1989,710 @@ fn validate_fdname(fdname: &str) -> Result<(), SdError> {
 
     Ok(())
 }
+
+/// Watcher for memory pressure events, following the systemd memory pressure protocol.
+///
+/// The service manager points services to a memory pressure source through
+/// `$MEMORY_PRESSURE_WATCH` (usually the PSI `memory.pressure` file of the
+/// service cgroup), together with a threshold to configure in
+/// `$MEMORY_PRESSURE_WRITE`. Services should react to events by releasing
+/// memory, e.g. shrinking caches.
+///
+/// Events can be waited for with [`MemoryPressureMonitor::wait`], or the
+/// monitor can be polled as part of an existing event loop, see
+/// [`MemoryPressureMonitor::poll_flags`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use libsystemd::daemon::MemoryPressureMonitor;
+///
+/// if let Some(monitor) = MemoryPressureMonitor::from_env()? {
+///     monitor.run(|| {
+///         println!("memory pressure, flushing caches");
+///         true
+///     })?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct MemoryPressureMonitor {
+    fd: OwnedFd,
+    events: PollFlags,
+}
+
+impl MemoryPressureMonitor {
+    /// Set up memory pressure monitoring from the environment.
+    ///
+    /// This returns `None` if `$MEMORY_PRESSURE_WATCH` is unset,
+    /// or if memory pressure monitoring is disabled for the service.
+    pub fn from_env() -> Result<Option<Self>, SdError> {
+        let watch = match env::var_os("MEMORY_PRESSURE_WATCH") {
+            Some(w) if !w.is_empty() && w != "/dev/null" => w,
+            _ => return Ok(None),
+        };
+        let trigger = match env::var("MEMORY_PRESSURE_WRITE") {
+            Ok(encoded) => Some(decode_memory_pressure_write(&encoded)?),
+            Err(_) => None,
+        };
+
+        Self::open(watch, trigger.as_deref()).map(Some)
+    }
+
+    /// Set up memory pressure monitoring on the given source.
+    ///
+    /// The source can be a PSI file (e.g. `/proc/pressure/memory`), a FIFO or a
+    /// Unix stream socket. If `trigger` is set, it is written to the source in
+    /// order to configure the pressure threshold.
+    pub fn open(path: impl AsRef<Path>, trigger: Option<&[u8]>) -> Result<Self, SdError> {
+        use std::io::Write;
+        use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+        use std::os::unix::net::UnixStream;
+
+        let path = path.as_ref();
+        let file_type = fs::metadata(path)
+            .with_context(|| format!("failed to inspect '{}'", path.display()))?
+            .file_type();
+
+        let (mut file, events) = if file_type.is_socket() {
+            let stream = UnixStream::connect(path)
+                .with_context(|| format!("failed to connect to '{}'", path.display()))?;
+            stream
+                .set_nonblocking(true)
+                .context("failed to set memory pressure socket as non-blocking")?;
+            (fs::File::from(OwnedFd::from(stream)), PollFlags::POLLIN)
+        } else {
+            let file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path)
+                .with_context(|| format!("failed to open '{}'", path.display()))?;
+            // PSI triggers signal events as exceptional conditions.
+            let events = if file_type.is_fifo() {
+                PollFlags::POLLIN
+            } else {
+                PollFlags::POLLPRI
+            };
+            (file, events)
+        };
+
+        if let Some(trigger) = trigger {
+            // PSI triggers must be configured through a single write.
+            let written = file
+                .write(trigger)
+                .with_context(|| format!("failed to configure trigger on '{}'", path.display()))?;
+            if written != trigger.len() {
+                return Err(format!("incomplete trigger write on '{}'", path.display()).into());
+            }
+        }
+
+        let monitor = Self {
+            fd: OwnedFd::from(file),
+            events,
+        };
+        Ok(monitor)
+    }
+
+    /// Return the events to poll the monitor file descriptor for.
+    pub fn poll_flags(&self) -> PollFlags {
+        self.events
+    }
+
+    /// Wait up to `timeout` for a memory pressure event.
+    ///
+    /// This returns true if memory pressure was signaled, or false on timeout.
+    /// Without a timeout, this blocks until the next event.
+    pub fn wait(&self, timeout: Option<time::Duration>) -> Result<bool, SdError> {
+        let timeout_ms = timeout
+            .map(|t| i32::try_from(t.as_millis()).unwrap_or(i32::MAX))
+            .unwrap_or(-1);
+        let mut fds = [PollFd::new(&self.fd, self.events)];
+        let ready = loop {
+            match poll(&mut fds, timeout_ms) {
+                Err(Errno::EINTR) => continue,
+                res => break res,
+            }
+        }
+        .map_err(|e| io::Error::from_raw_os_error(e as i32))
+        .context("failed to wait for memory pressure events")?;
+        if ready == 0 {
+            return Ok(false);
+        }
+
+        // A hung up source will never signal events again, and would otherwise
+        // be reported as ready forever.
+        let revents = fds[0].revents().unwrap_or_else(PollFlags::empty);
+        if revents.intersects(PollFlags::POLLERR | PollFlags::POLLHUP | PollFlags::POLLNVAL) {
+            return Err("memory pressure source is no longer available".into());
+        }
+        if revents.contains(PollFlags::POLLIN) {
+            self.drain()?;
+        }
+        Ok(true)
+    }
+
+    /// Call `callback` on each memory pressure event, until it returns false.
+    pub fn run<F>(&self, mut callback: F) -> Result<(), SdError>
+    where
+        F: FnMut() -> bool,
+    {
+        loop {
+            if self.wait(None)? && !callback() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Consume any pending data from FIFO and socket sources.
+    ///
+    /// Reaching the end of file means that the source was closed.
+    fn drain(&self) -> Result<(), SdError> {
+        let mut buf = [0u8; 64];
+        loop {
+            match unistd::read(self.fd.as_raw_fd(), &mut buf) {
+                Ok(0) => return Err("memory pressure source was closed".into()),
+                Err(Errno::EAGAIN) => return Ok(()),
+                Ok(_) | Err(Errno::EINTR) => continue,
+                Err(e) => {
+                    return Err(io::Error::from_raw_os_error(e as i32))
+                        .context("failed to read memory pressure event")
+                }
+            }
+        }
+    }
+}
+
+impl AsFd for MemoryPressureMonitor {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for MemoryPressureMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Decode the base64-encoded `$MEMORY_PRESSURE_WRITE` value.
+fn decode_memory_pressure_write(encoded: &str) -> Result<Vec<u8>, SdError> {
+    use base64::Engine;
+
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .context("invalid MEMORY_PRESSURE_WRITE value")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scope_from_cgroup() {
+        let cases = [
+            ("0::/system.slice/foo.service\n", ManagerScope::System),
+            (
+                "0::/user.slice/user-1000.slice/session-2.scope\n",
+                ManagerScope::User,
+            ),
+            ("0::/system.slice/app.scope\n", ManagerScope::System),
+            (
+                "0::/user.slice/user-1000.slice/user@1000.service/app.slice/foo.service\n",
+                ManagerScope::User,
+            ),
+            ("0::/\n", ManagerScope::None),
+            (
+                "1:name=systemd:/system.slice/bar.service\n",
+                ManagerScope::System,
+            ),
+            ("4:memory:/docker/abcdef\n", ManagerScope::None),
+            ("", ManagerScope::None),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(scope_from_cgroup(content), expected, "{}", content);
+        }
+    }
+
+    #[test]
+    fn test_unit_cgroup() {
+        let service = "/system.slice/foo.service";
+        assert_eq!(unit_cgroup(service), service);
+        assert_eq!(unit_cgroup("/system.slice/foo.service/worker"), service);
+        assert_eq!(
+            unit_cgroup("/user.slice/user-1000.slice/session-2.scope"),
+            "/user.slice/user-1000.slice/session-2.scope"
+        );
+        assert_eq!(unit_cgroup("/"), "/");
+
+        assert!(cgroup_is_below(service, service));
+        assert!(cgroup_is_below("/system.slice/foo.service/worker", service));
+        assert!(!cgroup_is_below("/system.slice/foo.service2", service));
+        assert!(!cgroup_is_below("/system.slice/bar.service", service));
+        assert!(cgroup_is_below("/system.slice/bar.service", "/"));
+
+        assert_eq!(
+            cgroup_path("12:pids:/x\n1:name=systemd:/system.slice/foo.service\n"),
+            Some(service)
+        );
+        assert_eq!(cgroup_path("0::/system.slice/foo.service\n"), Some(service));
+        assert_eq!(cgroup_path("4:memory:/docker\n"), None);
+    }
+
+    #[test]
+    fn test_write_reboot_parameter() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("reboot-param");
+
+        write_reboot_parameter(&path, "recovery").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "recovery");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+
+        write_reboot_parameter(&path, "").unwrap_err();
+        write_reboot_parameter(&path, "two\nlines").unwrap_err();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "recovery");
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_sanitize_status() {
+        assert_eq!(sanitize_status("serving"), "serving");
+        assert_eq!(sanitize_status("a\nb\r\tc\x07d\u{9b}"), "a b  cd");
+
+        let long = "é".repeat(STATUS_MAX_LEN);
+        let status = sanitize_status(&long);
+        assert!(status.len() <= STATUS_MAX_LEN);
+        assert!(status.ends_with("é..."));
+        sanity_check_state_entries(&[NotifyState::Status(status)]).unwrap();
+    }
+
+    #[test]
+    fn test_reloading_state() {
+        let usec = monotonic_usec().unwrap();
+        assert!(usec > 0);
+        assert!(monotonic_usec().unwrap() >= usec);
+
+        let state = NotifyState::MonotonicUsec(usec);
+        assert_eq!(state.to_string(), format!("MONOTONIC_USEC={}", usec));
+        assert_eq!(NotifyState::WatchdogTrigger.to_string(), "WATCHDOG=trigger");
+    }
+
+    #[test]
+    fn test_notify_to() {
+        let (sender, receiver) = UnixDatagram::pair().unwrap();
+        let state = [
+            NotifyState::Ready,
+            NotifyState::Status("serving".to_string()),
+        ];
+        notify_to(NotifyTarget::Socket(&sender), &state, &[]).unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=serving\n");
+
+        let invalid = [NotifyState::Status("multi\nline".to_string())];
+        notify_to(NotifyTarget::Socket(&sender), &invalid, &[]).unwrap_err();
+
+        let name = format!("libsystemd-rs-test-{}", std::process::id());
+        let bound = socket::UnixAddr::new_abstract(name.as_bytes()).unwrap();
+        let listener = socket::socket(
+            socket::AddressFamily::Unix,
+            socket::SockType::Datagram,
+            socket::SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .unwrap();
+        socket::bind(listener.as_raw_fd(), &bound).unwrap();
+        let listener = UnixDatagram::from(listener);
+        notify_to(
+            NotifyTarget::Abstract(name.as_bytes()),
+            &[NotifyState::Stopping],
+            &[],
+        )
+        .unwrap();
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"STOPPING=1\n");
+    }
+
+    #[test]
+    fn test_notify_state_parse() {
+        let states = [
+            NotifyState::Buserror("org.example.Error".to_string()),
+            NotifyState::Errno(2),
+            NotifyState::Fdname("listener".to_string()),
+            NotifyState::Fdstore,
+            NotifyState::FdstoreRemove,
+            NotifyState::FdpollDisable,
+            NotifyState::Mainpid(unistd::Pid::from_raw(42)),
+            NotifyState::MonotonicUsec(123),
+            NotifyState::Other("X_CUSTOM=1".to_string()),
+            NotifyState::Ready,
+            NotifyState::Reloading,
+            NotifyState::Status("a = b".to_string()),
+            NotifyState::Stopping,
+            NotifyState::Watchdog,
+            NotifyState::WatchdogTrigger,
+            NotifyState::WatchdogUsec(5_000_000),
+        ];
+        for state in states {
+            assert_eq!(state.to_string().parse::<NotifyState>().unwrap(), state);
+        }
+
+        assert_eq!(
+            "READY=0".parse::<NotifyState>().unwrap(),
+            NotifyState::Other("READY=0".to_string())
+        );
+        "READY".parse::<NotifyState>().unwrap_err();
+        "=1".parse::<NotifyState>().unwrap_err();
+        "ERRNO=-1".parse::<NotifyState>().unwrap_err();
+    }
+
+    #[test]
+    fn test_notify_listener() {
+        let name = format!("libsystemd-rs-listener-{}", std::process::id());
+        let listener = notify_listener(NotifyTarget::Abstract(name.as_bytes())).unwrap();
+
+        let (passed, _) = UnixDatagram::pair().unwrap();
+        let state = [
+            NotifyState::Fdstore,
+            NotifyState::Fdname("passed".to_string()),
+        ];
+        let target = NotifyTarget::Abstract(name.as_bytes());
+        notify_to(target, &state, &[passed.as_raw_fd()]).unwrap();
+
+        let mut message = listener.recv().unwrap();
+        assert_eq!(message.state(), &state);
+        assert_eq!(message.pid(), Some(unistd::getpid()));
+        assert_eq!(message.uid(), Some(unistd::getuid()));
+        assert_eq!(message.gid(), Some(unistd::getgid()));
+        assert_eq!(message.fds().len(), 1);
+        assert_eq!(message.take_fds().len(), 1);
+        assert!(message.fds().is_empty());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_send_notify_async() {
+        let name = format!("libsystemd-rs-async-{}", std::process::id());
+        let listener = notify_listener(NotifyTarget::Abstract(name.as_bytes())).unwrap();
+        let addr = socket::UnixAddr::new_abstract(name.as_bytes()).unwrap();
+
+        let state = [
+            NotifyState::Ready,
+            NotifyState::Status("serving".to_string()),
+        ];
+        let sent = send_notify_async_to(Some(NotifySocketAddr::Unix(addr)), &state, &[]);
+        assert!(sent.await.unwrap());
+        let message = listener.recv().unwrap();
+        assert_eq!(message.state(), &state);
+        assert_eq!(message.pid(), Some(unistd::getpid()));
+
+        let (passed, _) = UnixDatagram::pair().unwrap();
+        let state = [NotifyState::Fdstore];
+        let fds = [passed.as_raw_fd()];
+        let sent = send_notify_async_to(Some(NotifySocketAddr::Unix(addr)), &state, &fds);
+        assert!(sent.await.unwrap());
+        assert_eq!(listener.recv().unwrap().fds().len(), 1);
+
+        let invalid = [NotifyState::Status("multi\nline".to_string())];
+        let sent = send_notify_async_to(Some(NotifySocketAddr::Unix(addr)), &invalid, &[]);
+        sent.await.unwrap_err();
+        assert!(!send_notify_async_to(None, &state, &[]).await.unwrap());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_watchdog_task() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("notify");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        receiver.set_nonblocking(true).unwrap();
+        let addr = socket::UnixAddr::new(&path).unwrap();
+        let pings = || {
+            let mut buf = [0u8; 64];
+            let mut count = 0;
+            while let Ok(len) = receiver.recv(&mut buf) {
+                assert_eq!(&buf[..len], b"WATCHDOG=1\n");
+                count += 1;
+            }
+            count
+        };
+        let sleep = |ms| tokio::time::sleep(time::Duration::from_millis(ms));
+
+        let timeout = time::Duration::from_millis(40);
+        let (handle, task) =
+            watchdog_task_impl(timeout, move || Ok(Some(NotifySocketAddr::Unix(addr))));
+        assert_eq!(handle.timeout(), timeout);
+        let task = tokio::spawn(task);
+
+        sleep(200).await;
+        assert!(pings() >= 2);
+
+        handle.pause();
+        // Let a possibly in-flight ping complete.
+        sleep(30).await;
+        pings();
+        sleep(200).await;
+        assert_eq!(pings(), 0);
+
+        handle.resume();
+        sleep(200).await;
+        assert!(pings() >= 2);
+
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        pings();
+        sleep(100).await;
+        assert_eq!(pings(), 0);
+    }
+
+    #[test]
+    fn test_parse_notify_payload() {
+        let state =
+            parse_notify_payload("READY=1\nERRNO=abc\nX_CUSTOM=1\ngarbage\n=1\n\nSTATUS=ok");
+        assert_eq!(
+            state,
+            [
+                NotifyState::Ready,
+                NotifyState::Other("ERRNO=abc".to_string()),
+                NotifyState::Other("X_CUSTOM=1".to_string()),
+                NotifyState::Status("ok".to_string()),
+            ]
+        );
+        assert!(parse_notify_payload("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_notify_socket() {
+        let unix = parse_notify_socket("/run/systemd/notify").unwrap();
+        let expected = socket::UnixAddr::new("/run/systemd/notify").unwrap();
+        assert_eq!(unix, NotifySocketAddr::Unix(expected));
+
+        let abstract_addr = parse_notify_socket("@notify").unwrap();
+        let expected = socket::UnixAddr::new_abstract(b"notify").unwrap();
+        assert_eq!(abstract_addr, NotifySocketAddr::Unix(expected));
+
+        let vsock = parse_notify_socket("vsock:2:1234").unwrap();
+        let expected = socket::VsockAddr::new(2, 1234);
+        assert_eq!(vsock, NotifySocketAddr::Vsock(expected, None));
+
+        let vsock = parse_notify_socket("vsock-seqpacket:2:1234").unwrap();
+        let expected = socket::VsockAddr::new(2, 1234);
+        assert_eq!(
+            vsock,
+            NotifySocketAddr::Vsock(expected, Some(socket::SockType::SeqPacket))
+        );
+
+        parse_notify_socket("vsock:host:1234").unwrap_err();
+        parse_notify_socket("vsock:2").unwrap_err();
+    }
+
+    #[test]
+    fn test_memory_pressure_monitor() {
+        let trigger = decode_memory_pressure_write("c29tZSAyMDAwMDAgMjAwMDAwMAA=").unwrap();
+        assert_eq!(trigger, b"some 200000 2000000\0");
+        decode_memory_pressure_write("not base64!").unwrap_err();
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let fifo = dir.join("pressure");
+        unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        let monitor = MemoryPressureMonitor::open(&fifo, None).unwrap();
+        assert_eq!(monitor.poll_flags(), PollFlags::POLLIN);
+        let timeout = Some(time::Duration::from_millis(10));
+        assert!(!monitor.wait(timeout).unwrap());
+
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&fifo)
+            .and_then(|mut f| std::io::Write::write_all(&mut f, b"event"))
+            .unwrap();
+        assert!(monitor.wait(timeout).unwrap());
+        assert!(!monitor.wait(timeout).unwrap());
+
+        let socket = dir.join("pressure.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let monitor = MemoryPressureMonitor::open(&socket, None).unwrap();
+        let (peer, _) = listener.accept().unwrap();
+        assert!(!monitor.wait(timeout).unwrap());
+        drop(peer);
+        monitor.wait(timeout).unwrap_err();
+        monitor.run(|| true).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_service_outcome() {
+        use nix::sys::signal::Signal;
+        use std::collections::HashMap;
+
+        let env: HashMap<&str, &str> = [
+            ("SERVICE_RESULT", "exit-code"),
+            ("EXIT_CODE", "exited"),
+            ("EXIT_STATUS", "3"),
+            ("MONITOR_SERVICE_RESULT", "core-dump"),
+            ("MONITOR_EXIT_CODE", "dumped"),
+            ("MONITOR_EXIT_STATUS", "SEGV"),
+            ("MONITOR_UNIT", "foo.service"),
+            ("MONITOR_INVOCATION_ID", "8c8a38d4b4b44e3c9c8c1a2f0e3a4b5c"),
+        ]
+        .into_iter()
+        .collect();
+        let getenv = |name: &str| env.get(name).map(|v| v.to_string());
+
+        let outcome = parse_service_outcome("", getenv).unwrap().unwrap();
+        assert_eq!(outcome.result(), &ServiceResult::ExitCode);
+        assert_eq!(outcome.exit_status(), Some(ExitStatus::Exited(3)));
+        assert_eq!(outcome.unit(), None);
+
+        let monitored = parse_service_outcome("MONITOR_", getenv).unwrap().unwrap();
+        assert_eq!(monitored.result(), &ServiceResult::CoreDump);
+        assert_eq!(
+            monitored.exit_status(),
+            Some(ExitStatus::Dumped(Signal::SIGSEGV))
+        );
+        assert_eq!(monitored.unit(), Some("foo.service"));
+        assert!(monitored.invocation_id().is_some());
+
+        assert_eq!(parse_service_outcome("NONE_", getenv).unwrap(), None);
+        assert_eq!(
+            ServiceResult::from("future-result"),
+            ServiceResult::Other("future-result".to_string())
+        );
+        assert_eq!(
+            parse_exit_status("killed", "TERM").unwrap(),
+            ExitStatus::Killed(Signal::SIGTERM)
+        );
+        parse_exit_status("killed", "NOPE").unwrap_err();
+        parse_exit_status("exited", "TERM").unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_directories() {
+        let getenv = |name: &str| match name {
+            "RUNTIME_DIRECTORY" => Some("/run/foo".into()),
+            "STATE_DIRECTORY" => Some("/var/lib/foo:/var/lib/bar".into()),
+            "LOGS_DIRECTORY" => Some("".into()),
+            _ => None,
+        };
+
+        let dirs = parse_directories(getenv);
+        assert_eq!(dirs.runtime(), &[PathBuf::from("/run/foo")]);
+        assert_eq!(
+            dirs.state(),
+            &[PathBuf::from("/var/lib/foo"), PathBuf::from("/var/lib/bar")]
+        );
+        assert!(dirs.cache().is_empty());
+        assert!(dirs.logs().is_empty());
+        assert!(dirs.configuration().is_empty());
+    }
+
+    #[test]
+    fn test_fdstore_state() {
+        let state = FdStore::new().store_state("cache");
+        assert_eq!(
+            notify_message(&state),
+            b"FDSTORE=1\nFDNAME=cache\n".to_vec()
+        );
+
+        let state = FdStore::new().poll(false).store_state("cache");
+        assert_eq!(
+            notify_message(&state),
+            b"FDSTORE=1\nFDNAME=cache\nFDPOLL=0\n".to_vec()
+        );
+
+        FdStore::new().store_many("cache", &[]).unwrap_err();
+    }
+
+    #[test]
+    fn test_sanity_check_state_entries() {
+        let ok_cases = vec![
+            NotifyState::Status("all good".to_string()),
+            NotifyState::Buserror("org.freedesktop.DBus.Error.TimedOut".to_string()),
+            NotifyState::Other("X_CUSTOM=some value".to_string()),
+            NotifyState::Other("EMPTY=".to_string()),
+        ];
+        for entry in ok_cases {
+            sanity_check_state_entries(&[entry]).unwrap();
+        }
+
+        let err_cases = vec![
+            NotifyState::Status("line\nREADY=1".to_string()),
+            NotifyState::Buserror("foo\n".to_string()),
+            NotifyState::Other("READY".to_string()),
+            NotifyState::Other("=1".to_string()),
+            NotifyState::Other("X CUSTOM=1".to_string()),
+            NotifyState::Other("X_CUSTOM=1\nREADY=1".to_string()),
+            NotifyState::Fdname("foo:bar".to_string()),
+        ];
+        for entry in err_cases {
+            sanity_check_state_entries(&[NotifyState::Ready, entry]).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_parse_watchdog_config() {
+        let own_pid = unistd::Pid::from_raw(42);
+
+        let config = parse_watchdog_config(Some("1500"), None, own_pid).unwrap();
+        assert_eq!(config.timeout(), time::Duration::from_micros(1500));
+        assert_eq!(config.raw_usec(), 1500);
+        assert!(config.pid_matched());
+
+        let config = parse_watchdog_config(Some("30000000"), Some("42"), own_pid).unwrap();
+        assert_eq!(config.timeout(), time::Duration::from_secs(30));
+        assert!(config.pid_matched());
+
+        let config = parse_watchdog_config(Some("30000000"), Some("7"), own_pid).unwrap();
+        assert!(!config.pid_matched());
+
+        assert!(parse_watchdog_config(None, None, own_pid).is_none());
+        assert!(parse_watchdog_config(Some("0"), None, own_pid).is_none());
+        assert!(parse_watchdog_config(Some("foo"), None, own_pid).is_none());
+        assert!(parse_watchdog_config(Some("1500"), Some("bar"), own_pid).is_none());
+    }
+
+    #[test]
+    fn test_watchdog_feed_and_pause() {
+        use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+
+        let (ping_tx, ping_rx) = mpsc::channel();
+        let (ack_tx, ack_rx) = mpsc::channel::<()>();
+        let timeout = time::Duration::from_millis(40);
+        let watchdog = Watchdog::spawn(timeout, move || {
+            ping_tx.send(()).unwrap();
+            // Block until acknowledged, or until acknowledgements are dropped.
+            let _ = ack_rx.recv();
+            false
+        })
+        .unwrap();
+        assert_eq!(watchdog.timeout(), timeout);
+        let bound = time::Duration::from_secs(10);
+
+        ping_rx.recv_timeout(bound).unwrap();
+        ack_tx.send(()).unwrap();
+        ping_rx.recv_timeout(bound).unwrap();
+
+        // The thread is blocked in `feed`, so no ping is in flight.
+        watchdog.pause();
+        ack_tx.send(()).unwrap();
+        assert_eq!(
+            ping_rx.recv_timeout(timeout * 5),
+            Err(RecvTimeoutError::Timeout)
+        );
+
+        watchdog.resume();
+        ping_rx.recv_timeout(bound).unwrap();
+
+        // The feed closure, and its sender, are dropped with the thread.
+        drop(ack_tx);
+        watchdog.stop();
+        ping_rx.try_iter().for_each(drop);
+        assert_eq!(ping_rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
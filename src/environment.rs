@@ -0,0 +1,488 @@
+//! Environment files, as used by `EnvironmentFile=` and `environment.d`.
+//!
+//! For the complete documentation see
+//! <https://www.freedesktop.org/software/systemd/man/systemd.exec.html#EnvironmentFile=>
+//! and <https://www.freedesktop.org/software/systemd/man/environment.d.html>.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # fn doctest_parse() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::environment::Environment;
+//!
+//! let content = r#"
+//! # Comment
+//! NAME=value
+//! QUOTED="with spaces and \"quotes\""
+//! LONG=first \
+//! second
+//! "#;
+//!
+//! let env = Environment::parse(content)?;
+//! assert_eq!(env.get("NAME"), Some("value"));
+//! assert_eq!(env.get("QUOTED"), Some(r#"with spaces and "quotes""#));
+//! assert_eq!(env.get("LONG"), Some("first second"));
+//! # Ok(())
+//! # }
+//! # doctest_parse().unwrap();
+//! ```
+
+use crate::conffiles::{conf_dirs, conf_files};
+use crate::errors::{Context, SdError};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Characters which need escaping within double quotes.
+const SHELL_NEED_ESCAPE: &str = "\"\\`$";
+
+/// Ordered set of environment variable assignments.
+///
+/// Variables keep the position of their first assignment; assigning an
+/// existing variable again replaces its value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Environment {
+    vars: Vec<(String, String)>,
+}
+
+impl Environment {
+    /// Create an empty environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the content of an environment file, like `EnvironmentFile=`.
+    ///
+    /// Values may be single- or double-quoted, and lines can be continued
+    /// with a trailing backslash. Lines starting with `#` or `;` are
+    /// comments. Assignments with invalid variable names are skipped, with
+    /// a warning.
+    pub fn parse(input: &str) -> Result<Self, SdError> {
+        let mut env = Self::new();
+        for (key, value) in parse_assignments(input) {
+            if !is_valid_name(&key) {
+                log::warn!(
+                    "ignoring invalid environment assignment '{}={}'",
+                    key,
+                    value
+                );
+                continue;
+            }
+            env.set(key, value);
+        }
+        Ok(env)
+    }
+
+    /// Load an environment file from disk.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SdError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        Self::parse(&content)
+    }
+
+    /// Return the value of a variable, if set.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set a variable, replacing any previous value.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), SdError> {
+        let name = name.into();
+        if !is_valid_name(&name) {
+            return Err(format!("invalid environment variable name '{}'", name).into());
+        }
+        self.set(name, value.into());
+        Ok(())
+    }
+
+    /// Unset a variable, returning its previous value.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let index = self.vars.iter().position(|(key, _)| key == name)?;
+        Some(self.vars.remove(index).1)
+    }
+
+    /// Return an iterator over all variables, in assignment order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Return the number of variables.
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    /// Return whether no variables are set.
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// Apply the assignments of an `environment.d` file on top of this
+    /// environment.
+    ///
+    /// Each value is expanded (see [`expand`]) against the variables set so
+    /// far, including the ones assigned earlier in the same file.
+    pub fn merge_expanded(&mut self, input: &str) -> Result<(), SdError> {
+        for (key, value) in parse_assignments(input) {
+            if !is_valid_name(&key) {
+                log::warn!(
+                    "ignoring invalid environment assignment '{}={}'",
+                    key,
+                    value
+                );
+                continue;
+            }
+            let value = expand(&value, self);
+            self.set(key, value);
+        }
+        Ok(())
+    }
+
+    /// Format this environment as the content of an environment file.
+    ///
+    /// Values are quoted when needed, so that parsing the output yields the
+    /// same environment.
+    pub fn to_file_content(&self) -> String {
+        self.to_string()
+    }
+
+    fn set(&mut self, name: String, value: String) {
+        match self.vars.iter_mut().find(|(key, _)| *key == name) {
+            Some(entry) => entry.1 = value,
+            None => self.vars.push((name, value)),
+        }
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.vars {
+            writeln!(f, "{}={}", key, quote_value(value))?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Environment {
+    /// Collect assignments into an environment, skipping invalid names.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut env = Self::new();
+        for (key, value) in iter {
+            let _ = env.insert(key, value);
+        }
+        env
+    }
+}
+
+/// Load the `environment.d` configuration below the given roots.
+///
+/// Roots are given in precedence order (use `["/"]` for the running
+/// system). For each of them, `etc/environment.d`, `run/environment.d`,
+/// `usr/local/lib/environment.d` and `usr/lib/environment.d` are scanned,
+/// with file-name overriding and masking. Files are then applied in
+/// file-name order on top of `base` (e.g. the current environment), with
+/// variable expansion.
+pub fn load_environment_d<P: AsRef<Path>>(
+    roots: &[P],
+    base: Environment,
+) -> Result<Environment, SdError> {
+    let mut env = base;
+    for path in conf_files(&conf_dirs(roots, "environment.d"), ".conf")? {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        env.merge_expanded(&content)?;
+    }
+    Ok(env)
+}
+
+/// Expand variable references in a value, like `environment.d` does.
+///
+/// Supported forms are `$VAR`, `${VAR}`, `${VAR:-default}` (default if
+/// unset or empty) and `${VAR:+alternate}` (alternate if set and not
+/// empty). Unset variables expand to an empty string.
+pub fn expand(value: &str, env: &Environment) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        if let Some(braced) = rest.strip_prefix('{') {
+            let end = match braced.find('}') {
+                Some(end) => end,
+                None => {
+                    // Unterminated reference, kept as is.
+                    output.push('$');
+                    continue;
+                }
+            };
+            let reference = &braced[..end];
+            rest = &braced[end + 1..];
+            let lookup = |name: &str| env.get(name).filter(|v| !v.is_empty());
+            if let Some((name, default)) = reference.split_once(":-") {
+                output.push_str(lookup(name).unwrap_or(default));
+            } else if let Some((name, alternate)) = reference.split_once(":+") {
+                if lookup(name).is_some() {
+                    output.push_str(alternate);
+                }
+            } else {
+                output.push_str(env.get(reference).unwrap_or_default());
+            }
+            continue;
+        }
+
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            output.push('$');
+            continue;
+        }
+        output.push_str(env.get(&rest[..len]).unwrap_or_default());
+        rest = &rest[len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Return whether `name` is a valid environment variable name.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Quote a value if needed, so that it parses back unchanged.
+fn quote_value(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c) || !c.is_ascii());
+    if plain || value.is_empty() {
+        return value.to_string();
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if SHELL_NEED_ESCAPE.contains(c) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parser states, mirroring systemd's `parse_env_file_internal()`.
+enum State {
+    PreKey,
+    Key,
+    PreValue,
+    Value,
+    ValueEscape,
+    SingleQuote,
+    DoubleQuote,
+    DoubleQuoteEscape,
+    Comment,
+    CommentEscape,
+}
+
+/// Split the content of an environment file into raw assignments.
+fn parse_assignments(input: &str) -> Vec<(String, String)> {
+    let is_newline = |c: char| c == '\n' || c == '\r';
+    let is_whitespace = |c: char| c == ' ' || c == '\t' || is_newline(c);
+
+    let mut assignments = vec![];
+    let mut state = State::PreKey;
+    let (mut key, mut value) = (String::new(), String::new());
+    // Length of the key and value without trailing whitespace.
+    let (mut key_len, mut value_len) = (0, 0);
+
+    for c in input.chars() {
+        state = match state {
+            State::PreKey if c == '#' || c == ';' => State::Comment,
+            State::PreKey if is_whitespace(c) => State::PreKey,
+            State::PreKey => {
+                key.clear();
+                key.push(c);
+                key_len = key.len();
+                State::Key
+            }
+            State::Key if is_newline(c) => State::PreKey,
+            State::Key if c == '=' => {
+                key.truncate(key_len);
+                value.clear();
+                value_len = 0;
+                State::PreValue
+            }
+            State::Key => {
+                key.push(c);
+                if !is_whitespace(c) {
+                    key_len = key.len();
+                }
+                State::Key
+            }
+            State::PreValue if is_newline(c) => {
+                assignments.push((key.clone(), value.clone()));
+                State::PreKey
+            }
+            State::PreValue if c == '\'' => State::SingleQuote,
+            State::PreValue if c == '"' => State::DoubleQuote,
+            State::PreValue if c == '\\' => State::ValueEscape,
+            State::PreValue if is_whitespace(c) => State::PreValue,
+            State::PreValue => {
+                value.push(c);
+                value_len = value.len();
+                State::Value
+            }
+            State::Value if is_newline(c) => {
+                value.truncate(value_len);
+                assignments.push((key.clone(), value.clone()));
+                State::PreKey
+            }
+            State::Value if c == '\\' => {
+                // Whitespace before an escape is preserved.
+                value_len = value.len();
+                State::ValueEscape
+            }
+            State::Value => {
+                value.push(c);
+                if !is_whitespace(c) {
+                    value_len = value.len();
+                }
+                State::Value
+            }
+            State::ValueEscape => {
+                // Escaped newlines are dropped entirely.
+                if !is_newline(c) {
+                    value.push(c);
+                    value_len = value.len();
+                }
+                State::Value
+            }
+            State::SingleQuote if c == '\'' => {
+                value_len = value.len();
+                State::PreValue
+            }
+            State::SingleQuote => {
+                value.push(c);
+                State::SingleQuote
+            }
+            State::DoubleQuote if c == '"' => {
+                value_len = value.len();
+                State::PreValue
+            }
+            State::DoubleQuote if c == '\\' => State::DoubleQuoteEscape,
+            State::DoubleQuote => {
+                value.push(c);
+                State::DoubleQuote
+            }
+            State::DoubleQuoteEscape => {
+                if SHELL_NEED_ESCAPE.contains(c) {
+                    value.push(c);
+                } else if !is_newline(c) {
+                    value.push('\\');
+                    value.push(c);
+                }
+                State::DoubleQuote
+            }
+            State::Comment if c == '\\' => State::CommentEscape,
+            State::Comment if is_newline(c) => State::PreKey,
+            State::Comment | State::CommentEscape => State::Comment,
+        };
+    }
+
+    match state {
+        State::PreValue | State::Value | State::ValueEscape => {
+            value.truncate(value_len);
+            assignments.push((key, value));
+        }
+        // Unterminated quotes keep the value as is.
+        State::SingleQuote | State::DoubleQuote | State::DoubleQuoteEscape => {
+            assignments.push((key, value));
+        }
+        _ => {}
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let input = "\
+# comment \\
+continued comment\n\
+A=plain value   \n\
+ ; another comment\n\
+B = 'single $quoted \\n'\n\
+C=\"double \\\"quoted\\\" \\$HOME \\n\"\n\
+D=line \\\n  continued\n\
+E=\n\
+not an assignment\n\
+1INVALID=x\n\
+F=\"multi\nline\"\n\
+A=replaced\n\
+G=a\"b c\"'d'\n\
+H=\"unterminated";
+        let env = Environment::parse(input).unwrap();
+        let expected = vec![
+            ("A", "replaced"),
+            ("B", "single $quoted \\n"),
+            ("C", "double \"quoted\" $HOME \\n"),
+            ("D", "line   continued"),
+            ("E", ""),
+            ("F", "multi\nline"),
+            ("G", "a\"b c\"'d'"),
+            ("H", "unterminated"),
+        ];
+        assert_eq!(env.iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let env: Environment = [
+            ("PLAIN", "/usr/bin:/bin"),
+            ("EMPTY", ""),
+            ("SPACES", " a  b "),
+            ("SPECIAL", "\"q\" \\ `cmd` $VAR 'x' #c"),
+            ("NEWLINE", "a\nb"),
+        ]
+        .into_iter()
+        .collect();
+        let content = env.to_file_content();
+        assert!(content.starts_with("PLAIN=/usr/bin:/bin\nEMPTY=\n"));
+        assert_eq!(Environment::parse(&content).unwrap(), env);
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut env = Environment::new();
+        env.insert("HOME", "/home/user").unwrap();
+        env.insert("EMPTY", "").unwrap();
+        env.insert("bad-name", "x").unwrap_err();
+
+        env.merge_expanded(
+            "PATH=$HOME/bin:${PATH:-/usr/bin}\n\
+             XDG=${HOME}/.local\n\
+             A=${EMPTY:-default} ${HOME:+set} ${EMPTY:+unset}.\n\
+             B=$ $1 ${unterminated\n\
+             PATH=${PATH}:/opt/bin\n",
+        )
+        .unwrap();
+        assert_eq!(env.get("PATH"), Some("/home/user/bin:/usr/bin:/opt/bin"));
+        assert_eq!(env.get("XDG"), Some("/home/user/.local"));
+        assert_eq!(env.get("A"), Some("default set ."));
+        assert_eq!(env.get("B"), Some("$  ${unterminated"));
+    }
+}
@@ -31,6 +31,7 @@ impl From<String> for SdError {
 pub(crate) enum ErrorKind {
     Generic,
     SysusersUnknownType,
+    TmpfilesUnknownType,
 }
 
 /// Context is similar to anyhow::Context, in that it provides a mechanism internally to adapt
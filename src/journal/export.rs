@@ -0,0 +1,398 @@
+//! Journal Export Format serialization.
+//!
+//! This is the stream format produced by `journalctl -o export` and consumed by
+//! `systemd-journal-remote`. Each entry is a sequence of fields terminated by an
+//! empty line. Text fields are written as `NAME=value`, while fields carrying
+//! binary data or newlines use an explicit little-endian 64 bit length prefix.
+//!
+//! See <https://systemd.io/JOURNAL_EXPORT_FORMATS/> for details.
+//!
+//! ```rust
+//! # fn doctest_export() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::journal::export::{Entry, Reader, Writer};
+//!
+//! let mut entry = Entry::new();
+//! entry.push("MESSAGE", "Hello\nWorld")?;
+//! entry.push("PRIORITY", "6")?;
+//!
+//! let mut writer = Writer::new(Vec::new());
+//! writer.write_entry(&entry)?;
+//! let stream = writer.into_inner();
+//!
+//! let entries: Vec<Entry> = Reader::new(stream.as_slice()).collect::<Result<_, _>>()?;
+//! assert_eq!(entries, vec![entry]);
+//! # Ok(())
+//! # }
+//! # doctest_export().unwrap();
+//! ```
+
+use crate::errors::{Context, SdError};
+use std::io::{BufRead, Read, Write};
+
+/// Maximum length of a journal field name.
+const FIELD_NAME_MAX_LEN: usize = 64;
+
+/// Maximum size of a binary field payload, like `DATA_SIZE_MAX` in
+/// `systemd-journal-remote`.
+const DATA_SIZE_MAX: u64 = 768 * 1024 * 1024;
+
+/// A journal entry, as an ordered sequence of fields.
+///
+/// Field values are kept as raw bytes, as journal fields may carry arbitrary
+/// binary data. The same field name may appear multiple times.
+///
+/// Entries can be (de)serialized with serde, following the journal JSON
+/// format used by `journalctl -o json`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Entry {
+    fields: Vec<(String, Vec<u8>)>,
+}
+
+impl Entry {
+    /// Create a new empty entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a field to this entry.
+    ///
+    /// This fails if `name` is not a valid journal field name.
+    pub fn push(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+    ) -> Result<(), SdError> {
+        let name = name.into();
+        validate_field_name(&name)?;
+        self.fields.push((name, value.into()));
+        Ok(())
+    }
+
+    /// Return the value of the first field named `name`, if any.
+    pub fn field(&self, name: &str) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Return an iterator over all `(name, value)` fields, in order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_slice()))
+    }
+
+    /// Return the number of fields in this entry.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Return whether this entry has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// Streaming reader for the Journal Export Format.
+///
+/// This yields one [`Entry`] per record found in the underlying stream.
+#[derive(Debug)]
+pub struct Reader<R: BufRead> {
+    inner: R,
+    done: bool,
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Create a new reader on top of a buffered stream.
+    pub fn new(inner: R) -> Self {
+        Self { inner, done: false }
+    }
+
+    /// Consume this reader, returning the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read the next entry, returning `None` at the end of the stream.
+    fn read_entry(&mut self) -> Result<Option<Entry>, SdError> {
+        let mut entry = Entry::new();
+        let mut line = Vec::new();
+
+        loop {
+            line.clear();
+            let len = self
+                .inner
+                .read_until(b'\n', &mut line)
+                .context("failed to read export stream")?;
+            if len == 0 {
+                self.done = true;
+                break;
+            }
+            if line.last() != Some(&b'\n') {
+                return Err("truncated export stream, missing trailing newline".into());
+            }
+            line.pop();
+
+            // An empty line terminates the current entry.
+            if line.is_empty() {
+                if entry.is_empty() {
+                    continue;
+                }
+                break;
+            }
+
+            match line.iter().position(|b| *b == b'=') {
+                Some(pos) => {
+                    let name = field_name_from_bytes(&line[..pos])?;
+                    entry.push(name, &line[pos + 1..])?;
+                }
+                None => {
+                    let name = field_name_from_bytes(&line)?;
+                    let value = self.read_binary_payload(&name)?;
+                    entry.push(name, value)?;
+                }
+            }
+        }
+
+        if entry.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(entry))
+    }
+
+    /// Read a length-prefixed binary field payload, including its trailing newline.
+    fn read_binary_payload(&mut self, name: &str) -> Result<Vec<u8>, SdError> {
+        let mut size_buf = [0u8; 8];
+        self.inner
+            .read_exact(&mut size_buf)
+            .with_context(|| format!("failed to read size of binary field '{}'", name))?;
+        let size = u64::from_le_bytes(size_buf);
+        if size > DATA_SIZE_MAX {
+            return Err(format!("overlarge binary field '{}': {} bytes", name, size).into());
+        }
+
+        // The size is untrusted, only allocate for data actually read.
+        let mut value = Vec::new();
+        let len = (&mut self.inner)
+            .take(size)
+            .read_to_end(&mut value)
+            .with_context(|| format!("failed to read binary field '{}'", name))?;
+        if len as u64 != size {
+            return Err(format!("truncated binary field '{}'", name).into());
+        }
+
+        let mut newline = [0u8; 1];
+        self.inner
+            .read_exact(&mut newline)
+            .with_context(|| format!("failed to read end of binary field '{}'", name))?;
+        if newline[0] != b'\n' {
+            return Err(format!("missing newline after binary field '{}'", name).into());
+        }
+
+        Ok(value)
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Entry, SdError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.read_entry().transpose();
+        if matches!(res, Some(Err(_))) {
+            self.done = true;
+        }
+        res
+    }
+}
+
+/// Streaming writer for the Journal Export Format.
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    /// Create a new writer on top of an output stream.
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Consume this writer, returning the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Serialize a single entry, followed by the entry separator.
+    pub fn write_entry(&mut self, entry: &Entry) -> Result<(), SdError> {
+        let mut data = Vec::new();
+        for (name, value) in entry.fields() {
+            add_export_field(&mut data, name, value);
+        }
+        data.push(b'\n');
+
+        self.inner
+            .write_all(&data)
+            .context("failed to write export entry")
+    }
+
+    /// Flush the underlying stream.
+    pub fn flush(&mut self) -> Result<(), SdError> {
+        self.inner.flush().context("failed to flush export stream")
+    }
+}
+
+/// Append a field to `data`, picking the text or binary encoding as journalctl does.
+fn add_export_field(data: &mut Vec<u8>, name: &str, value: &[u8]) {
+    data.extend(name.as_bytes());
+    if is_printable(value) {
+        data.push(b'=');
+        data.extend(value);
+    } else {
+        data.push(b'\n');
+        data.extend((value.len() as u64).to_le_bytes());
+        data.extend(value);
+    }
+    data.push(b'\n');
+}
+
+/// Return whether a value can be serialized with the simple `NAME=value` encoding.
+///
+/// This matches systemd's `utf8_is_printable()`: the value must be valid UTF-8
+/// and may not contain control characters other than tabs.
+pub(crate) fn is_printable(value: &[u8]) -> bool {
+    match std::str::from_utf8(value) {
+        Ok(s) => !s
+            .chars()
+            .any(|c| (c < ' ' && c != '\t') || ('\u{7f}'..='\u{9f}').contains(&c)),
+        Err(_) => false,
+    }
+}
+
+/// Decode and validate a field name read from an export stream.
+fn field_name_from_bytes(input: &[u8]) -> Result<String, SdError> {
+    let name = std::str::from_utf8(input).context("non UTF-8 field name in export stream")?;
+    validate_field_name(name)?;
+    Ok(name.to_string())
+}
+
+/// Validate a field name as found in exported entries.
+///
+/// Unlike fields submitted by clients, exported entries may carry trusted
+/// fields (starting with `_`) and address fields (starting with `__`).
+fn validate_field_name(name: &str) -> Result<(), SdError> {
+    if name.is_empty() || name.len() > FIELD_NAME_MAX_LEN {
+        return Err(format!("invalid field name length for '{}'", name).into());
+    }
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("field name '{}' starts with a digit", name).into());
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_uppercase() || c.is_ascii_digit() || *c == '_'))
+    {
+        return Err(format!("invalid character '{}' in field name '{}'", c, name).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_text_entries() {
+        let input = "__CURSOR=s=739ad463348b4ceca5a9e69c95a3c93f;i=4ece7\n\
+                     __REALTIME_TIMESTAMP=1342540861416351\n\
+                     _BOOT_ID=dcbc6f5a3b8f4f9a9fcb3fc18e5b4dc7\n\
+                     MESSAGE=Hello\n\
+                     \n\
+                     MESSAGE=World\n\
+                     PRIORITY=6\n\
+                     \n";
+        let entries: Vec<Entry> = Reader::new(input.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].len(), 4);
+        assert_eq!(
+            entries[0].field("__REALTIME_TIMESTAMP"),
+            Some("1342540861416351".as_bytes())
+        );
+        assert_eq!(entries[0].field("MESSAGE"), Some("Hello".as_bytes()));
+        assert_eq!(entries[1].field("MESSAGE"), Some("World".as_bytes()));
+        assert_eq!(entries[1].field("_BOOT_ID"), None);
+    }
+
+    #[test]
+    fn test_read_binary_entry() {
+        let mut input = b"MESSAGE\n".to_vec();
+        input.extend(4u64.to_le_bytes());
+        input.extend(b"A\nB\0\n");
+        // The last entry may lack the separator.
+        input.extend(b"FOO=bar\n");
+
+        let entries: Vec<Entry> = Reader::new(input.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].field("MESSAGE"), Some("A\nB\0".as_bytes()));
+        assert_eq!(entries[0].field("FOO"), Some("bar".as_bytes()));
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        let cases: Vec<&[u8]> = vec![
+            b"MESSAGE=truncated",
+            b"lowercase=value\n\n",
+            b"MESSAGE\n\x10\0\0\0\0\0\0\0short\n",
+            b"FOO\n\xff\xff\xff\xff\xff\xff\xff\xff",
+            b"FOO\n\0\0\0\x30\0\0\0\0",
+        ];
+        for input in cases {
+            let mut reader = Reader::new(input);
+            reader.next().unwrap().unwrap_err();
+            assert!(reader.next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_write_entry() {
+        let mut entry = Entry::new();
+        entry.push("MESSAGE", "tab\tseparated").unwrap();
+        entry.push("BINARY", b"A\nB".to_vec()).unwrap();
+        entry.push("foo", "bar").unwrap_err();
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_entry(&entry).unwrap();
+        let mut expected = b"MESSAGE=tab\tseparated\nBINARY\n".to_vec();
+        expected.extend(3u64.to_le_bytes());
+        expected.extend(b"A\nB\n\n");
+        assert_eq!(writer.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut first = Entry::new();
+        first.push("_PID", "42").unwrap();
+        first.push("MESSAGE", vec![0xff, 0xfe, b'\n']).unwrap();
+        first.push("MESSAGE", "repeated").unwrap();
+        let mut second = Entry::new();
+        second.push("__MONOTONIC_TIMESTAMP", "1234").unwrap();
+        second.push("EMPTY", "").unwrap();
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_entry(&first).unwrap();
+        writer.write_entry(&second).unwrap();
+        let stream = writer.into_inner();
+
+        let entries: Vec<Entry> = Reader::new(stream.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(entries, vec![first, second]);
+    }
+}
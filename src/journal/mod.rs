@@ -0,0 +1,82 @@
+//! Helpers for working with journal files on disk.
+
+use crate::errors::{Context, SdError};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+pub mod export;
+mod serialization;
+
+/// Compute the disk space used by journal files under a directory.
+///
+/// This sums the on-disk size of all active (`*.journal`) and archived
+/// (`*.journal~`) journal files found under `path`, descending into
+/// subdirectories (e.g. the per-machine `/var/log/journal/<machine-id>`).
+/// Like `sd_journal_get_usage` and `journalctl --disk-usage`, sizes are
+/// computed from allocated blocks rather than apparent file length.
+pub fn disk_usage(path: impl AsRef<Path>) -> Result<u64, SdError> {
+    let path = path.as_ref();
+    let entries = fs::read_dir(path)
+        .with_context(|| format!("failed to read journal directory '{}'", path.display()))?;
+
+    let mut usage: u64 = 0;
+    for item in entries {
+        let entry =
+            item.with_context(|| format!("failed to read entry in '{}'", path.display()))?;
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("failed to stat '{}'", entry.path().display()))?;
+
+        if file_type.is_dir() {
+            usage = usage.saturating_add(disk_usage(entry.path())?);
+        } else if file_type.is_file() && is_journal_file(&entry.file_name().to_string_lossy()) {
+            let metadata = entry
+                .metadata()
+                .with_context(|| format!("failed to stat '{}'", entry.path().display()))?;
+            usage = usage.saturating_add(metadata.blocks().saturating_mul(512));
+        }
+    }
+
+    Ok(usage)
+}
+
+/// Return whether a file name belongs to an active or archived journal file.
+fn is_journal_file(name: &str) -> bool {
+    name.ends_with(".journal") || name.ends_with(".journal~")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_journal_file() {
+        assert!(is_journal_file("system.journal"));
+        assert!(is_journal_file(
+            "user-1000@0005f7c2d7e6c3a1-2f1b6e1e4cbe8d0f.journal~"
+        ));
+        assert!(!is_journal_file("system.journal.tmp"));
+        assert!(!is_journal_file("README"));
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let machine_dir = root.join("2e074e9b299c41a59923c51ae16f279b");
+        fs::create_dir_all(&machine_dir).unwrap();
+        fs::write(machine_dir.join("system.journal"), vec![0u8; 8192]).unwrap();
+        fs::write(machine_dir.join("system@foo.journal~"), vec![0u8; 4096]).unwrap();
+        fs::write(machine_dir.join("unrelated.txt"), vec![0u8; 4096]).unwrap();
+
+        let expected: u64 = ["system.journal", "system@foo.journal~"]
+            .iter()
+            .map(|name| fs::metadata(machine_dir.join(name)).unwrap().blocks() * 512)
+            .sum();
+        let usage = disk_usage(root).unwrap();
+
+        assert_eq!(usage, expected);
+        disk_usage(root.join("missing")).unwrap_err();
+    }
+}
@@ -0,0 +1,163 @@
+use super::export::Entry;
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Single field value in the JSON representation of a journal entry.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonValue {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// Field value(s) in the JSON representation of a journal entry.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonField {
+    Single(JsonValue),
+    Multiple(Vec<JsonValue>),
+    Missing(()),
+}
+
+/// Serialize a single field value, as text if printable or as an array of bytes.
+struct FieldValue<'a>(&'a [u8]);
+
+impl Serialize for FieldValue<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match std::str::from_utf8(self.0) {
+            Ok(s) if super::export::is_printable(self.0) => serializer.serialize_str(s),
+            _ => self.0.serialize(serializer),
+        }
+    }
+}
+
+/// Serialize entries following the journal JSON format (`journalctl -o json`).
+///
+/// Printable fields are encoded as strings, binary ones as arrays of bytes.
+/// Fields appearing multiple times are collected into an array of values.
+impl Serialize for Entry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut names: Vec<&str> = vec![];
+        for (name, _) in self.fields() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        let mut state = serializer.serialize_map(Some(names.len()))?;
+        for name in names {
+            let values: Vec<FieldValue> = self
+                .fields()
+                .filter(|(field, _)| *field == name)
+                .map(|(_, value)| FieldValue(value))
+                .collect();
+            if values.len() == 1 {
+                state.serialize_entry(name, &values[0])?;
+            } else {
+                state.serialize_entry(name, &values)?;
+            }
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(EntryVisitor)
+    }
+}
+
+struct EntryVisitor;
+
+impl<'de> Visitor<'de> for EntryVisitor {
+    type Value = Entry;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a journal entry in JSON format")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entry = Entry::new();
+        while let Some((name, field)) = map.next_entry::<String, JsonField>()? {
+            let values = match field {
+                JsonField::Single(v) => vec![v],
+                JsonField::Multiple(vs) => vs,
+                // Fields exceeding the data threshold are exported as `null`.
+                JsonField::Missing(()) => vec![],
+            };
+            for value in values {
+                let bytes = match value {
+                    JsonValue::Text(s) => s.into_bytes(),
+                    JsonValue::Binary(b) => b,
+                };
+                entry
+                    .push(name.as_str(), bytes)
+                    .map_err(|e| de::Error::custom(e.msg))?;
+            }
+        }
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_serialization() {
+        let mut entry = Entry::new();
+        entry.push("MESSAGE", "Hello").unwrap();
+        entry.push("BINARY", vec![0x00, 0xff]).unwrap();
+        entry.push("MULTI", "first").unwrap();
+        entry.push("MULTI", vec![0x0a]).unwrap();
+
+        let expected = r#"{"MESSAGE":"Hello","BINARY":[0,255],"MULTI":["first",[10]]}"#;
+        let output = serde_json::to_string(&entry).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_deserialization() {
+        let input = r#"{"__CURSOR":"s=0;i=1","MESSAGE":[72,105],"MULTI":["a","b"],"HUGE":null}"#;
+        let entry: Entry = serde_json::from_str(input).unwrap();
+        assert_eq!(entry.len(), 4);
+        assert_eq!(entry.field("__CURSOR"), Some("s=0;i=1".as_bytes()));
+        assert_eq!(entry.field("MESSAGE"), Some("Hi".as_bytes()));
+        let multi: Vec<_> = entry.fields().filter(|(n, _)| *n == "MULTI").collect();
+        assert_eq!(
+            multi,
+            vec![("MULTI", "a".as_bytes()), ("MULTI", "b".as_bytes())]
+        );
+        assert_eq!(entry.field("HUGE"), None);
+
+        serde_json::from_str::<Entry>(r#"{"lowercase":"value"}"#).unwrap_err();
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut input = Entry::new();
+        input.push("_PID", "42").unwrap();
+        input.push("MESSAGE", "multi\nline").unwrap();
+        input
+            .push("COREDUMP", vec![0x7f, b'E', b'L', b'F'])
+            .unwrap();
+
+        let json = serde_json::to_string(&input).unwrap();
+        let output: Entry = serde_json::from_str(&json).unwrap();
+        assert_eq!(output, input);
+    }
+}
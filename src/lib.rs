@@ -23,16 +23,35 @@
 
 /// Interfaces for socket-activated services.
 pub mod activation;
+/// Helpers for working with `binfmt.d` configuration.
+pub mod binfmt;
+mod conffiles;
+/// Helpers for working with daemon configuration files.
+pub mod config;
 /// Helpers for securely passing potentially sensitive data to services.
 pub mod credentials;
 /// Interfaces for systemd-aware daemons.
 pub mod daemon;
+/// Helpers for working with environment files.
+pub mod environment;
 /// Error handling.
 pub mod errors;
 /// APIs for processing 128-bits IDs.
 pub mod id128;
+/// Helpers for working with journal files on disk.
+pub mod journal;
 /// Helpers for logging to `systemd-journald`.
 pub mod logging;
+/// Helpers for working with `modules-load.d` configuration.
+pub mod modules_load;
+/// Helpers for working with `sysctl.d` configuration.
+pub mod sysctl;
+/// Validation of system and configuration extensions.
+pub mod sysext;
 pub mod sysusers;
+/// Helpers for working with `tmpfiles.d` configuration.
+pub mod tmpfiles;
 /// Helpers for working with systemd units.
 pub mod unit;
+/// Detection of virtual machines and containers.
+pub mod virt;
@@ -0,0 +1,128 @@
+//! Helpers for working with `modules-load.d` configuration files.
+//!
+//! For the complete documentation see
+//! <https://www.freedesktop.org/software/systemd/man/modules-load.d.html>.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # fn doctest_parse() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::modules_load;
+//!
+//! let config_fragment = r#"
+//! ## Load the virtio network driver at boot
+//! virtio-net
+//! "#;
+//!
+//! let modules = modules_load::parse_lines(config_fragment)?;
+//! assert_eq!(modules, vec!["virtio-net"]);
+//! assert_eq!(modules_load::to_config(&modules), "virtio-net\n");
+//! # Ok(())
+//! # }
+//! # doctest_parse().unwrap();
+//! ```
+
+use crate::conffiles::{conf_dirs, conf_files};
+use crate::errors::{Context, SdError};
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+
+/// Parse the module names listed in the content of a `modules-load.d` file.
+pub fn parse_lines(input: &str) -> Result<Vec<String>, SdError> {
+    from_reader(input.as_bytes())
+}
+
+/// Parse the module names listed in a `modules-load.d` file from a buffered
+/// reader.
+pub fn from_reader(reader: impl BufRead) -> Result<Vec<String>, SdError> {
+    let mut output = vec![];
+    for (index, item) in reader.lines().enumerate() {
+        let linenumber = index.saturating_add(1);
+        let line = item.map_err(|e| format!("failed to read line {}: {}", linenumber, e))?;
+
+        let data = line.trim();
+        // Skip empty lines and comments.
+        if data.is_empty() || data.starts_with('#') || data.starts_with(';') {
+            continue;
+        }
+        validate_module_name(data)
+            .map_err(|e| format!("invalid entry at line {}: {}", linenumber, e.msg))?;
+        output.push(data.to_string());
+    }
+    Ok(output)
+}
+
+/// Validate a kernel module name.
+pub fn validate_module_name(name: &str) -> Result<(), SdError> {
+    if name.is_empty() {
+        return Err("empty module name".into());
+    }
+    if name.contains(|c: char| c.is_whitespace() || c == '/') {
+        return Err(format!("invalid module name '{}'", name).into());
+    }
+    Ok(())
+}
+
+/// Format a list of module names as the content of a `modules-load.d` file.
+pub fn to_config<S: AsRef<str>>(modules: &[S]) -> String {
+    modules
+        .iter()
+        .map(|m| format!("{}\n", m.as_ref()))
+        .collect()
+}
+
+/// Load the effective list of modules to load at boot below the given roots.
+///
+/// Roots are given in precedence order (use `["/"]` for the running
+/// system). For each of them, `etc/modules-load.d`, `run/modules-load.d`,
+/// `usr/local/lib/modules-load.d` and `usr/lib/modules-load.d` are scanned,
+/// with file-name overriding and masking. Modules are returned in file-name
+/// order, without duplicates.
+pub fn load_config<P: AsRef<Path>>(roots: &[P]) -> Result<Vec<String>, SdError> {
+    let mut output: Vec<String> = vec![];
+    for path in conf_files(&conf_dirs(roots, "modules-load.d"), ".conf")? {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        let modules = parse_lines(&content)
+            .with_context(|| format!("failed to parse '{}'", path.display()))?;
+        for module in modules {
+            if !output.contains(&module) {
+                output.push(module);
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_parse() {
+        let modules = parse_lines("; comment\n  # comment\n\n loop \nvfio_pci\n").unwrap();
+        assert_eq!(modules, vec!["loop", "vfio_pci"]);
+        assert_eq!(to_config(&modules), "loop\nvfio_pci\n");
+        parse_lines("loop\nmax_loop 8\n").unwrap_err();
+    }
+
+    #[test]
+    fn test_load_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let (etc, usr) = (
+            root.join("etc/modules-load.d"),
+            root.join("usr/lib/modules-load.d"),
+        );
+        fs::create_dir_all(&etc).unwrap();
+        fs::create_dir_all(&usr).unwrap();
+        fs::write(usr.join("10-a.conf"), "loop\nfuse\n").unwrap();
+        fs::write(usr.join("20-masked.conf"), "dummy\n").unwrap();
+        symlink("/dev/null", etc.join("20-masked.conf")).unwrap();
+        fs::write(etc.join("30-b.conf"), "fuse\ntun\n").unwrap();
+
+        assert_eq!(load_config(&[&root]).unwrap(), vec!["loop", "fuse", "tun"]);
+    }
+}
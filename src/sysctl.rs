@@ -0,0 +1,230 @@
+//! Helpers for working with `sysctl.d` configuration files.
+//!
+//! For the complete documentation see
+//! <https://www.freedesktop.org/software/systemd/man/sysctl.d.html>.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # fn doctest_parse() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::sysctl::SysctlEntry;
+//!
+//! let config_fragment = r#"
+//! ## Comment
+//! kernel.domainname = example.com
+//! -net.ipv4.conf.all.rp_filter=1
+//! "#;
+//!
+//! let entries = SysctlEntry::parse_lines(config_fragment)?;
+//! assert_eq!(entries[0].key(), "kernel.domainname");
+//! assert_eq!(entries[0].path().to_str(), Some("/proc/sys/kernel/domainname"));
+//! assert!(entries[1].ignore_failure());
+//! # Ok(())
+//! # }
+//! # doctest_parse().unwrap();
+//! ```
+
+use crate::conffiles::{conf_dirs, conf_files};
+use crate::errors::{Context, SdError};
+use std::fmt;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Single assignment in `sysctl.d` configuration format.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SysctlEntry {
+    key: String,
+    value: String,
+    ignore_failure: bool,
+}
+
+impl SysctlEntry {
+    /// Create a new assignment.
+    ///
+    /// A leading `-` on `key` marks failures to set it as ignored.
+    pub fn new(key: &str, value: impl Into<String>) -> Result<Self, SdError> {
+        let (ignore_failure, key) = match key.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, key),
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("invalid sysctl key '{}'", key).into());
+        }
+        Ok(Self {
+            key: key.to_string(),
+            value: value.into(),
+            ignore_failure,
+        })
+    }
+
+    /// Parse all entries from the content of a `sysctl.d` file.
+    pub fn parse_lines(input: &str) -> Result<Vec<SysctlEntry>, SdError> {
+        Self::from_reader(input.as_bytes())
+    }
+
+    /// Parse all entries of a `sysctl.d` file from a buffered reader.
+    pub fn from_reader(reader: impl BufRead) -> Result<Vec<SysctlEntry>, SdError> {
+        let mut output = vec![];
+        for (index, item) in reader.lines().enumerate() {
+            let linenumber = index.saturating_add(1);
+            let line = item.map_err(|e| format!("failed to read line {}: {}", linenumber, e))?;
+
+            let data = line.trim();
+            // Skip empty lines and comments.
+            if data.is_empty() || data.starts_with('#') || data.starts_with(';') {
+                continue;
+            }
+
+            let entry = data.parse().map_err(|e: SdError| {
+                format!(
+                    "failed to parse sysctl entry at line {}: {}",
+                    linenumber, e.msg
+                )
+            })?;
+            output.push(entry);
+        }
+        Ok(output)
+    }
+
+    /// Return the key, as written (e.g. `net.ipv4.ip_forward`).
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Return the value to assign.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Return whether failures to set this key are ignored (`-` prefix).
+    pub fn ignore_failure(&self) -> bool {
+        self.ignore_failure
+    }
+
+    /// Return whether the key contains glob patterns, matching multiple keys.
+    pub fn is_glob(&self) -> bool {
+        self.key.contains(['*', '?', '['])
+    }
+
+    /// Return the key in normalized form, using `/` as separator.
+    ///
+    /// When the first separator in the key is a dot, dots and slashes are
+    /// swapped (so that `net.ipv4.conf.eth0/1.rp_filter` refers to the
+    /// `eth0.1` interface), like `systemd-sysctl` does.
+    pub fn normalized_key(&self) -> String {
+        let key = self.key.trim_start_matches('/');
+        match key.find(['.', '/']) {
+            Some(index) if key[index..].starts_with('.') => key
+                .chars()
+                .map(|c| match c {
+                    '.' => '/',
+                    '/' => '.',
+                    c => c,
+                })
+                .collect(),
+            _ => key.to_string(),
+        }
+    }
+
+    /// Return the path of this key below `/proc/sys`.
+    pub fn path(&self) -> PathBuf {
+        Path::new("/proc/sys").join(self.normalized_key())
+    }
+
+    /// Format this entry as a `sysctl.d` configuration line.
+    pub fn to_config_line(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl FromStr for SysctlEntry {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("line is not an assignment: '{}'", s))?;
+        Self::new(key.trim(), value.trim())
+    }
+}
+
+impl fmt::Display for SysctlEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.ignore_failure { "-" } else { "" };
+        write!(f, "{}{} = {}", prefix, self.key, self.value)
+    }
+}
+
+/// Load the effective `sysctl.d` configuration below the given roots.
+///
+/// Roots are given in precedence order (use `["/"]` for the running
+/// system). For each of them, `etc/sysctl.d`, `run/sysctl.d`,
+/// `usr/local/lib/sysctl.d` and `usr/lib/sysctl.d` are scanned, with
+/// file-name overriding and masking. Files are read in file-name order, and
+/// a later assignment to the same (normalized) key overrides the earlier one.
+pub fn load_config<P: AsRef<Path>>(roots: &[P]) -> Result<Vec<SysctlEntry>, SdError> {
+    let mut output: Vec<SysctlEntry> = vec![];
+    for path in conf_files(&conf_dirs(roots, "sysctl.d"), ".conf")? {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        let entries = SysctlEntry::parse_lines(&content)
+            .with_context(|| format!("failed to parse '{}'", path.display()))?;
+        for entry in entries {
+            let key = entry.normalized_key();
+            match output.iter_mut().find(|e| e.normalized_key() == key) {
+                Some(existing) => *existing = entry,
+                None => output.push(entry),
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_parse() {
+        let entry: SysctlEntry = "  net.ipv4.conf.eth0/1.rp_filter =  2 ".parse().unwrap();
+        assert_eq!(entry.key(), "net.ipv4.conf.eth0/1.rp_filter");
+        assert_eq!(entry.value(), "2");
+        assert_eq!(entry.normalized_key(), "net/ipv4/conf/eth0.1/rp_filter");
+        assert_eq!(entry.to_config_line(), "net.ipv4.conf.eth0/1.rp_filter = 2");
+
+        let entry: SysctlEntry = "-net/ipv4/conf/*/rp_filter=".parse().unwrap();
+        assert!(entry.ignore_failure() && entry.is_glob());
+        assert_eq!(entry.value(), "");
+        assert_eq!(
+            entry.path(),
+            Path::new("/proc/sys/net/ipv4/conf/*/rp_filter")
+        );
+        assert_eq!(entry.to_config_line(), "-net/ipv4/conf/*/rp_filter = ");
+
+        for input in ["no assignment", " = 1", "a b = 1"] {
+            input.parse::<SysctlEntry>().unwrap_err();
+        }
+        SysctlEntry::parse_lines("; comment\n# comment\n\nkernel.foo=1\nbroken\n").unwrap_err();
+    }
+
+    #[test]
+    fn test_load_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let (etc, usr) = (root.join("etc/sysctl.d"), root.join("usr/lib/sysctl.d"));
+        fs::create_dir_all(&etc).unwrap();
+        fs::create_dir_all(&usr).unwrap();
+        fs::write(usr.join("10-default.conf"), "kernel.a = 1\nkernel.b = 1\n").unwrap();
+        fs::write(usr.join("20-masked.conf"), "kernel.c = 1\n").unwrap();
+        symlink("/dev/null", etc.join("20-masked.conf")).unwrap();
+        fs::write(etc.join("30-local.conf"), "kernel/b = 2\n").unwrap();
+
+        let entries = load_config(&[&root]).unwrap();
+        let lines: Vec<_> = entries.iter().map(|e| e.to_config_line()).collect();
+        assert_eq!(lines, vec!["kernel.a = 1", "kernel/b = 2"]);
+    }
+}
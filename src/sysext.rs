@@ -0,0 +1,347 @@
+//! Validation of system and configuration extensions (`systemd-sysext` and
+//! `systemd-confext`).
+//!
+//! For the complete documentation see
+//! <https://www.freedesktop.org/software/systemd/man/systemd-sysext.html>.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # fn doctest_check() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::sysext::{ExtensionClass, ExtensionRelease, ExtensionScope};
+//!
+//! let release = ExtensionRelease::load("/var/lib/extensions/debug", ExtensionClass::Sysext)?;
+//! let verdict = release.check_host(ExtensionScope::System)?;
+//! if !verdict.is_compatible() {
+//!     println!("extension '{}' not usable: {}", release.name(), verdict);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::environment::Environment;
+use crate::errors::{Context, SdError};
+use crate::unit::native_architecture;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Kind of extension image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExtensionClass {
+    /// System extension, extending `/usr` and `/opt` (`systemd-sysext`).
+    Sysext,
+    /// Configuration extension, extending `/etc` (`systemd-confext`).
+    Confext,
+}
+
+impl ExtensionClass {
+    /// Return the directory holding extension-release files, relative to
+    /// the image root.
+    pub fn release_dir(self) -> &'static str {
+        match self {
+            ExtensionClass::Sysext => "usr/lib/extension-release.d",
+            ExtensionClass::Confext => "etc/extension-release.d",
+        }
+    }
+
+    fn level_key(self) -> &'static str {
+        match self {
+            ExtensionClass::Sysext => "SYSEXT_LEVEL",
+            ExtensionClass::Confext => "CONFEXT_LEVEL",
+        }
+    }
+
+    fn scope_key(self) -> &'static str {
+        match self {
+            ExtensionClass::Sysext => "SYSEXT_SCOPE",
+            ExtensionClass::Confext => "CONFEXT_SCOPE",
+        }
+    }
+}
+
+/// Environment an extension is meant to be merged into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExtensionScope {
+    /// Regular booted system.
+    System,
+    /// Initial RAM disk.
+    Initrd,
+    /// Portable service images.
+    Portable,
+}
+
+impl ExtensionScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            ExtensionScope::System => "system",
+            ExtensionScope::Initrd => "initrd",
+            ExtensionScope::Portable => "portable",
+        }
+    }
+}
+
+/// Result of checking an extension against a host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Compatibility {
+    /// The extension can be merged.
+    Compatible,
+    /// The extension cannot be merged, with the reason.
+    Incompatible(String),
+}
+
+impl Compatibility {
+    /// Return whether the extension can be merged.
+    pub fn is_compatible(&self) -> bool {
+        *self == Compatibility::Compatible
+    }
+}
+
+impl fmt::Display for Compatibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Compatibility::Compatible => write!(f, "compatible"),
+            Compatibility::Incompatible(reason) => write!(f, "incompatible: {}", reason),
+        }
+    }
+}
+
+/// Release metadata of an extension, from its `extension-release.<name>`
+/// file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionRelease {
+    class: ExtensionClass,
+    name: String,
+    path: PathBuf,
+    fields: Environment,
+}
+
+impl ExtensionRelease {
+    /// Load the release metadata of the extension directory `image`.
+    ///
+    /// The extension name is the directory name, without a `.raw` suffix.
+    /// Raw disk images have to be mounted first, and their mount point
+    /// passed with [`load_named`](Self::load_named).
+    pub fn load(image: impl AsRef<Path>, class: ExtensionClass) -> Result<Self, SdError> {
+        let image = image.as_ref();
+        let name = image
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.strip_suffix(".raw").unwrap_or(n))
+            .ok_or_else(|| format!("invalid extension path '{}'", image.display()))?;
+        Self::load_named(image, name, class)
+    }
+
+    /// Load the release metadata of the extension `name`, rooted at `image`.
+    pub fn load_named(
+        image: impl AsRef<Path>,
+        name: &str,
+        class: ExtensionClass,
+    ) -> Result<Self, SdError> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(format!("invalid extension name '{}'", name).into());
+        }
+        let path = image
+            .as_ref()
+            .join(class.release_dir())
+            .join(format!("extension-release.{}", name));
+        let fields = Environment::load(&path)?;
+        Ok(Self {
+            class,
+            name: name.to_string(),
+            path,
+            fields,
+        })
+    }
+
+    /// Return the kind of this extension.
+    pub fn class(&self) -> ExtensionClass {
+        self.class
+    }
+
+    /// Return the name of this extension.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the path of the extension-release file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return all the fields of the extension-release file.
+    pub fn fields(&self) -> &Environment {
+        &self.fields
+    }
+
+    /// Return the value of a field, if set and not empty.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).filter(|v| !v.is_empty())
+    }
+
+    /// Return the OS the extension was built for (`ID`).
+    pub fn id(&self) -> Option<&str> {
+        self.get("ID")
+    }
+
+    /// Return the extension API level (`SYSEXT_LEVEL` or `CONFEXT_LEVEL`).
+    pub fn level(&self) -> Option<&str> {
+        self.get(self.class.level_key())
+    }
+
+    /// Return whether the extension may be merged in the given scope.
+    ///
+    /// Without an explicit `SYSEXT_SCOPE` (or `CONFEXT_SCOPE`), extensions
+    /// apply to the `system` and `portable` scopes.
+    pub fn supports_scope(&self, scope: ExtensionScope) -> bool {
+        let scopes = self
+            .get(self.class.scope_key())
+            .unwrap_or("system portable");
+        scopes.split_whitespace().any(|s| s == scope.as_str())
+    }
+
+    /// Check this extension against the os-release fields of a host.
+    ///
+    /// Like `systemd-sysext`, this checks the architecture, the OS `ID`
+    /// (also matching the host `ID_LIKE`), and then either the extension
+    /// level or `VERSION_ID`, depending on what the host defines. `ID=_any`
+    /// skips the OS checks, and a host defining neither a level nor
+    /// `VERSION_ID` accepts any version.
+    pub fn check(&self, host: &Environment, scope: ExtensionScope) -> Compatibility {
+        let incompatible = |reason: String| Compatibility::Incompatible(reason);
+        let host_get = |key: &str| host.get(key).filter(|v| !v.is_empty());
+
+        if !self.supports_scope(scope) {
+            return incompatible(format!("not enabled for scope '{}'", scope.as_str()));
+        }
+        if let Some(arch) = self.get("ARCHITECTURE").filter(|a| *a != "_any") {
+            if arch != native_architecture() {
+                return incompatible(format!(
+                    "built for architecture '{}', host is '{}'",
+                    arch,
+                    native_architecture()
+                ));
+            }
+        }
+
+        let id = match self.id() {
+            Some(id) => id,
+            None => return incompatible("no ID set".to_string()),
+        };
+        if id == "_any" {
+            return Compatibility::Compatible;
+        }
+        let host_id = match host_get("ID") {
+            Some(host_id) => host_id,
+            None => return incompatible("host has no ID set".to_string()),
+        };
+        let id_like = host_get("ID_LIKE").unwrap_or_default();
+        if id != host_id && !id_like.split_whitespace().any(|like| like == id) {
+            return incompatible(format!("built for '{}', host is '{}'", id, host_id));
+        }
+
+        let level_key = self.class.level_key();
+        let (key, host_value) = match (host_get(level_key), host_get("VERSION_ID")) {
+            (Some(level), _) => (level_key, level),
+            (None, Some(version)) => ("VERSION_ID", version),
+            (None, None) => return Compatibility::Compatible,
+        };
+        match self.get(key) {
+            Some(value) if value == host_value => Compatibility::Compatible,
+            Some(value) => {
+                incompatible(format!("{} is '{}', host has '{}'", key, value, host_value))
+            }
+            None => incompatible(format!("no {} set, host has '{}'", key, host_value)),
+        }
+    }
+
+    /// Check this extension against the running system.
+    pub fn check_host(&self, scope: ExtensionScope) -> Result<Compatibility, SdError> {
+        let host = load_os_release("/")?;
+        Ok(self.check(&host, scope))
+    }
+}
+
+/// Load the os-release fields of the OS tree at `root`.
+///
+/// `etc/os-release` is used if present, otherwise `usr/lib/os-release`.
+pub fn load_os_release(root: impl AsRef<Path>) -> Result<Environment, SdError> {
+    let root = root.as_ref();
+    let path = ["etc/os-release", "usr/lib/os-release"]
+        .iter()
+        .map(|p| root.join(p))
+        .find(|p| p.exists())
+        .ok_or_else(|| format!("no os-release file found in '{}'", root.display()))?;
+    Environment::load(&path).with_context(|| format!("failed to load '{}'", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_check() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let image = root.join("debug.raw");
+        let dir = image.join("usr/lib/extension-release.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("extension-release.debug"),
+            "ID=debian\nSYSEXT_LEVEL=2\nVERSION_ID=12\n",
+        )
+        .unwrap();
+
+        let release = ExtensionRelease::load(&image, ExtensionClass::Sysext).unwrap();
+        assert_eq!(release.name(), "debug");
+        assert_eq!(release.level(), Some("2"));
+        assert!(ExtensionRelease::load(&image, ExtensionClass::Confext).is_err());
+
+        let system = ExtensionScope::System;
+        let host = |content: &str| Environment::parse(content).unwrap();
+        let cases = [
+            ("ID=debian\nSYSEXT_LEVEL=2\n", true),
+            (
+                "ID=ubuntu\nID_LIKE=debian\nSYSEXT_LEVEL=2\nVERSION_ID=1\n",
+                true,
+            ),
+            ("ID=debian\nSYSEXT_LEVEL=3\nVERSION_ID=12\n", false),
+            ("ID=debian\nVERSION_ID=12\n", true),
+            ("ID=debian\nVERSION_ID=13\n", false),
+            ("ID=debian\n", true),
+            ("ID=fedora\nSYSEXT_LEVEL=2\n", false),
+            ("VERSION_ID=12\n", false),
+        ];
+        for (content, expected) in cases {
+            let verdict = release.check(&host(content), system);
+            assert_eq!(
+                verdict.is_compatible(),
+                expected,
+                "{}: {}",
+                content,
+                verdict
+            );
+        }
+        assert!(!release
+            .check(&host("ID=debian\n"), ExtensionScope::Initrd)
+            .is_compatible());
+
+        fs::write(
+            dir.join("extension-release.debug"),
+            "ID=_any\nARCHITECTURE=_any\nSYSEXT_SCOPE=initrd\n",
+        )
+        .unwrap();
+        let release = ExtensionRelease::load(&image, ExtensionClass::Sysext).unwrap();
+        assert!(release
+            .check(&host("ID=fedora\n"), ExtensionScope::Initrd)
+            .is_compatible());
+        assert!(!release.check(&host("ID=fedora\n"), system).is_compatible());
+
+        let os = root.join("os/usr/lib");
+        fs::create_dir_all(&os).unwrap();
+        fs::write(os.join("os-release"), "ID=arch\n").unwrap();
+        let host = load_os_release(root.join("os")).unwrap();
+        assert_eq!(host.get("ID"), Some("arch"));
+        load_os_release(&image).unwrap_err();
+    }
+}
@@ -0,0 +1,271 @@
+use super::SysusersEntry;
+use crate::errors::{Context, SdError};
+use std::fmt;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// A `sysusers.d` file, preserving comments and ordering.
+///
+/// Unlike [`SysusersEntry::parse_lines`], this keeps every line of the
+/// original file, so that tools can edit entries and write the file back
+/// without losing human annotations. Lines which are not modified are
+/// written back exactly as they were read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Document {
+    source: Option<PathBuf>,
+    items: Vec<DocumentItem>,
+}
+
+/// Single line of a [`Document`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum DocumentItem {
+    /// A blank line, a comment or an entry of unknown type, kept verbatim.
+    Verbatim(String),
+    /// A configuration entry.
+    Entry(DocumentEntry),
+}
+
+/// Configuration entry of a [`Document`], with its provenance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentEntry {
+    entry: SysusersEntry,
+    line: Option<usize>,
+    raw: Option<String>,
+}
+
+impl Document {
+    /// Create an empty document, not backed by any file.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a document from the content of a `sysusers.d` file.
+    pub fn parse(input: &str) -> Result<Self, SdError> {
+        Self::from_reader(input.as_bytes())
+    }
+
+    /// Parse a document from a buffered reader.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, SdError> {
+        use crate::errors::ErrorKind;
+
+        let mut items = vec![];
+        for (index, item) in reader.lines().enumerate() {
+            let linenumber = index.saturating_add(1);
+            let line = item.map_err(|e| format!("failed to read line {}: {}", linenumber, e))?;
+
+            let data = line.trim();
+            if data.is_empty() || data.starts_with('#') {
+                items.push(DocumentItem::Verbatim(line));
+                continue;
+            }
+
+            match data.parse() {
+                Ok(entry) => items.push(DocumentItem::Entry(DocumentEntry {
+                    entry,
+                    line: Some(linenumber),
+                    raw: Some(line),
+                })),
+                Err(SdError {
+                    kind: ErrorKind::SysusersUnknownType,
+                    ..
+                }) => items.push(DocumentItem::Verbatim(line)),
+                Err(e) => {
+                    let msg = format!(
+                        "failed to parse sysusers entry at line {}: {}",
+                        linenumber, e.msg
+                    );
+                    return Err(msg.into());
+                }
+            }
+        }
+
+        Ok(Self {
+            source: None,
+            items,
+        })
+    }
+
+    /// Load a document from a `sysusers.d` file, recording its path.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SdError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        let mut doc = Self::parse(&content)
+            .with_context(|| format!("failed to parse '{}'", path.display()))?;
+        doc.source = Some(path.to_path_buf());
+        Ok(doc)
+    }
+
+    /// Write this document back to the file it was loaded from.
+    pub fn save(&self) -> Result<(), SdError> {
+        let path = self
+            .source
+            .as_ref()
+            .ok_or_else(|| SdError::from("document has no source path"))?;
+        fs::write(path, self.to_string())
+            .with_context(|| format!("failed to write '{}'", path.display()))
+    }
+
+    /// Return the path of the file this document was loaded from, if any.
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    /// Set the path of the file backing this document.
+    pub fn set_source(&mut self, path: impl Into<PathBuf>) {
+        self.source = Some(path.into());
+    }
+
+    /// Return all lines of this document, in order.
+    pub fn items(&self) -> &[DocumentItem] {
+        &self.items
+    }
+
+    /// Return all lines of this document, for editing.
+    pub fn items_mut(&mut self) -> &mut Vec<DocumentItem> {
+        &mut self.items
+    }
+
+    /// Return an iterator over the configuration entries of this document.
+    pub fn entries(&self) -> impl Iterator<Item = &DocumentEntry> {
+        self.items.iter().filter_map(|item| match item {
+            DocumentItem::Entry(entry) => Some(entry),
+            DocumentItem::Verbatim(_) => None,
+        })
+    }
+
+    /// Return an iterator over the configuration entries of this document, for editing.
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut DocumentEntry> {
+        self.items.iter_mut().filter_map(|item| match item {
+            DocumentItem::Entry(entry) => Some(entry),
+            DocumentItem::Verbatim(_) => None,
+        })
+    }
+
+    /// Append a new entry at the end of this document.
+    pub fn push_entry(&mut self, entry: SysusersEntry) {
+        self.items
+            .push(DocumentItem::Entry(DocumentEntry::new(entry)));
+    }
+
+    /// Append a comment line at the end of this document.
+    ///
+    /// The `# ` prefix is added to each line of `text`.
+    pub fn push_comment(&mut self, text: &str) {
+        for line in text.lines() {
+            let comment = if line.is_empty() {
+                "#".to_string()
+            } else {
+                format!("# {}", line)
+            };
+            self.items.push(DocumentItem::Verbatim(comment));
+        }
+    }
+
+    /// Remove all entries for which `f` returns `false`, keeping comments.
+    pub fn retain_entries(&mut self, mut f: impl FnMut(&SysusersEntry) -> bool) {
+        self.items.retain(|item| match item {
+            DocumentItem::Entry(entry) => f(&entry.entry),
+            DocumentItem::Verbatim(_) => true,
+        })
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in &self.items {
+            match item {
+                DocumentItem::Verbatim(line) => writeln!(f, "{}", line)?,
+                DocumentItem::Entry(entry) => writeln!(f, "{}", entry)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DocumentEntry {
+    /// Create a new entry, not originating from any file.
+    pub fn new(entry: SysusersEntry) -> Self {
+        Self {
+            entry,
+            line: None,
+            raw: None,
+        }
+    }
+
+    /// Return the configuration entry.
+    pub fn entry(&self) -> &SysusersEntry {
+        &self.entry
+    }
+
+    /// Replace the configuration entry.
+    ///
+    /// The entry is then written back in normalized form.
+    pub fn set_entry(&mut self, entry: SysusersEntry) {
+        self.entry = entry;
+        self.raw = None;
+    }
+
+    /// Return the line number (starting from 1) of this entry in its source
+    /// document, if it was parsed from one.
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+}
+
+impl fmt::Display for DocumentEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.raw {
+            Some(raw) => write!(f, "{}", raw),
+            None => write!(f, "{}", self.entry.to_config_line()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sysusers::{AddRange, NameValidation};
+
+    #[test]
+    fn test_document_roundtrip() {
+        let input = r#"# Example sysusers.d file
+
+u   httpd  404  "HTTP User"
+g   input  -
+X   future-type  -
+m   httpd  input
+"#;
+        let mut doc = Document::parse(input).unwrap();
+        assert_eq!(doc.to_string(), input);
+        assert_eq!(doc.entries().count(), 3);
+        let lines: Vec<_> = doc.entries().map(|e| e.line()).collect();
+        assert_eq!(lines, vec![Some(3), Some(4), Some(6)]);
+
+        doc.retain_entries(|e| e.type_signature() != "m");
+        let group = crate::sysusers::CreateGroup::new_with_gid(
+            "input".to_string(),
+            97,
+            NameValidation::Strict,
+        )
+        .unwrap();
+        doc.entries_mut()
+            .find(|e| e.entry().name() == "input")
+            .unwrap()
+            .set_entry(group.into_sysusers_entry());
+        doc.push_comment("Local range");
+        doc.push_entry(AddRange::new(500, 900).unwrap().into_sysusers_entry());
+
+        let expected = r#"# Example sysusers.d file
+
+u   httpd  404  "HTTP User"
+g input 97 - - -
+X   future-type  -
+# Local range
+r - 500-900 - - -
+"#;
+        assert_eq!(doc.to_string(), expected);
+        Document::parse(expected).unwrap();
+    }
+}
@@ -1,5 +1,87 @@
 use super::*;
 use std::fmt::{self, Display};
+use std::path::Path;
+
+impl SysusersEntry {
+    /// Format this entry as a `sysusers.d` configuration line.
+    pub fn to_config_line(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl AddRange {
+    /// Format this entry as a `sysusers.d` configuration line.
+    pub fn to_config_line(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl AddUserToGroup {
+    /// Format this entry as a `sysusers.d` configuration line.
+    pub fn to_config_line(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl CreateGroup {
+    /// Format this entry as a `sysusers.d` configuration line.
+    pub fn to_config_line(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl CreateUserAndGroup {
+    /// Format this entry as a `sysusers.d` configuration line.
+    pub fn to_config_line(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Quote a field value if needed, escaping quotes and backslashes.
+///
+/// Empty values are written as the `-` placeholder.
+pub(crate) fn quote_field(value: &str) -> Cow<'_, str> {
+    if value.is_empty() {
+        return Cow::Borrowed("-");
+    }
+    let needs_quoting = value
+        .chars()
+        .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\');
+    if !needs_quoting {
+        return Cow::Borrowed(value);
+    }
+    Cow::Owned(quote(value))
+}
+
+/// Quote a value with double quotes, escaping special characters.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Format an optional path field, using `-` when unset.
+fn path_field(value: Option<&Path>) -> Cow<'_, str> {
+    match value {
+        Some(path) => match path.to_string_lossy() {
+            Cow::Borrowed(path) => quote_field(path),
+            Cow::Owned(path) => Cow::Owned(quote_field(&path).into_owned()),
+        },
+        None => Cow::Borrowed("-"),
+    }
+}
 
 impl Display for SysusersEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -32,20 +114,21 @@ impl Display for CreateGroup {
 
 impl Display for CreateUserAndGroup {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // GECOS is conventionally quoted, even when not strictly needed.
+        let gecos = if self.gecos.is_empty() {
+            Cow::Borrowed("-")
+        } else {
+            Cow::Owned(quote(&self.gecos))
+        };
         write!(
             f,
-            "u {} {} \"{}\" {} {}",
+            "{} {} {} {} {} {}",
+            if self.locked { "u!" } else { "u" },
             self.name,
             self.id,
-            self.gecos,
-            self.home_dir
-                .as_deref()
-                .map(|p| p.to_string_lossy())
-                .unwrap_or(Cow::Borrowed("-")),
-            self.shell
-                .as_deref()
-                .map(|p| p.to_string_lossy())
-                .unwrap_or(Cow::Borrowed("-")),
+            gecos,
+            path_field(self.home_dir.as_deref()),
+            path_field(self.shell.as_deref()),
         )
     }
 }
@@ -56,7 +139,7 @@ impl Display for IdOrPath {
             IdOrPath::Id(i) => write!(f, "{}", i),
             IdOrPath::UidGid((u, g)) => write!(f, "{}:{}", u, g),
             IdOrPath::UidGroupname((u, g)) => write!(f, "{}:{}", u, g),
-            IdOrPath::Path(p) => write!(f, "{}", p.display()),
+            IdOrPath::Path(p) => write!(f, "{}", path_field(Some(p))),
             IdOrPath::Automatic => write!(f, "-",),
         }
     }
@@ -66,7 +149,7 @@ impl Display for GidOrPath {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             GidOrPath::Gid(g) => write!(f, "{}", g),
-            GidOrPath::Path(p) => write!(f, "{}", p.display()),
+            GidOrPath::Path(p) => write!(f, "{}", path_field(Some(p))),
             GidOrPath::Automatic => write!(f, "-",),
         }
     }
@@ -79,14 +162,19 @@ mod test {
     #[test]
     fn test_formatters() {
         {
-            let type_u =
-                CreateUserAndGroup::new("foo0".to_string(), "test".to_string(), None, None)
-                    .unwrap();
+            let type_u = CreateUserAndGroup::new(
+                "foo0".to_string(),
+                "test".to_string(),
+                None,
+                None,
+                NameValidation::Strict,
+            )
+            .unwrap();
             let expected = r#"u foo0 - "test" - -"#;
             assert_eq!(type_u.to_string(), expected);
         }
         {
-            let type_g = CreateGroup::new("foo1".to_string()).unwrap();
+            let type_g = CreateGroup::new("foo1".to_string(), NameValidation::Strict).unwrap();
             let expected = r#"g foo1 - - - -"#;
             assert_eq!(type_g.to_string(), expected);
         }
@@ -96,9 +184,66 @@ mod test {
             assert_eq!(type_r.to_string(), expected);
         }
         {
-            let type_m = AddUserToGroup::new("foo3".to_string(), "bar".to_string()).unwrap();
+            let type_m = AddUserToGroup::new(
+                "foo3".to_string(),
+                "bar".to_string(),
+                NameValidation::Strict,
+            )
+            .unwrap();
             let expected = r#"m foo3 bar - - -"#;
             assert_eq!(type_m.to_string(), expected);
         }
+        {
+            let type_u = CreateUserAndGroup::new(
+                "foo4".to_string(),
+                String::new(),
+                Some("/var/lib/with space".into()),
+                None,
+                NameValidation::Strict,
+            )
+            .unwrap();
+            let expected = r#"u foo4 - - "/var/lib/with space" -"#;
+            assert_eq!(type_u.to_config_line(), expected);
+        }
+    }
+
+    #[test]
+    fn test_config_line_roundtrip() {
+        let entries = vec![
+            CreateUserAndGroup::new_with_path(
+                "foo5".to_string(),
+                "/usr/bin/with space".into(),
+                "Quoted \"name\" with \\ backslash\tand tab".to_string(),
+                Some("/home/foo5".into()),
+                Some("/bin/sh".into()),
+                NameValidation::Strict,
+            )
+            .unwrap()
+            .into_sysusers_entry(),
+            CreateUserAndGroup::new_with_uid_groupname(
+                "foo6".to_string(),
+                1000,
+                "users".to_string(),
+                String::new(),
+                None,
+                None,
+                NameValidation::Strict,
+            )
+            .unwrap()
+            .into_sysusers_entry(),
+            CreateGroup::new_with_path(
+                "foo7".to_string(),
+                "/dev/kvm".into(),
+                NameValidation::Strict,
+            )
+            .unwrap()
+            .into_sysusers_entry(),
+            AddRange::new(1000, 1000).unwrap().into_sysusers_entry(),
+        ];
+        for entry in entries {
+            let line = entry.to_config_line();
+            let parsed: SysusersEntry = line.parse().unwrap();
+            assert_eq!(parsed, entry, "{}", line);
+        }
     }
 }
@@ -0,0 +1,99 @@
+use super::SysusersEntry;
+use crate::conffiles::{conf_dirs, conf_files};
+use crate::errors::{Context, SdError};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Load the effective `sysusers.d` configuration below the given roots.
+///
+/// Roots are given in precedence order (use `["/"]` for the running
+/// system). For each of them, `etc/sysusers.d`, `run/sysusers.d`,
+/// `usr/local/lib/sysusers.d` and `usr/lib/sysusers.d` are scanned: files
+/// override same-named ones in lower-precedence directories, and files
+/// masked with a symlink to `/dev/null` are ignored.
+///
+/// Entries are merged in file-name order like `systemd-sysusers` does: only
+/// the first definition of each user and group is kept (a user also defines
+/// its group), and duplicate memberships are dropped.
+pub fn load_config<P: AsRef<Path>>(roots: &[P]) -> Result<Vec<SysusersEntry>, SdError> {
+    let files = conf_files(&conf_dirs(roots, "sysusers.d"), ".conf")?;
+
+    let mut users = HashSet::new();
+    let mut groups = HashSet::new();
+    let mut members = HashSet::new();
+    let mut output = vec![];
+    for path in files {
+        let file =
+            File::open(&path).with_context(|| format!("failed to open '{}'", path.display()))?;
+        let entries = SysusersEntry::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse '{}'", path.display()))?;
+        for entry in entries {
+            let keep = match &entry {
+                SysusersEntry::AddRange(_) => true,
+                SysusersEntry::AddUserToGroup(v) => {
+                    members.insert((v.username.clone(), v.groupname.clone()))
+                }
+                SysusersEntry::CreateGroup(v) => groups.insert(v.groupname.clone()),
+                SysusersEntry::CreateUserAndGroup(v) => {
+                    groups.insert(v.name.clone());
+                    users.insert(v.name.clone())
+                }
+            };
+            if keep {
+                output.push(entry);
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_load_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let (etc, run, usr) = (
+            root.join("etc/sysusers.d"),
+            root.join("run/sysusers.d"),
+            root.join("usr/lib/sysusers.d"),
+        );
+        for dir in [&etc, &run, &usr] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(
+            usr.join("10-base.conf"),
+            "g wheel -\nu root 0\nm root wheel\n",
+        )
+        .unwrap();
+        fs::write(usr.join("20-app.conf"), "u app -\n").unwrap();
+        fs::write(
+            run.join("20-app.conf"),
+            "u app 500\ng root -\nm root wheel\n",
+        )
+        .unwrap();
+        fs::write(usr.join("30-masked.conf"), "u masked -\n").unwrap();
+        symlink("/dev/null", etc.join("30-masked.conf")).unwrap();
+        fs::write(etc.join("40-local.conf"), "u app 600\nr - 1000-2000\n").unwrap();
+
+        let entries = load_config(&[&root]).unwrap();
+        let lines: Vec<_> = entries.iter().map(|e| e.to_config_line()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "g wheel - - - -",
+                "u root 0 - - -",
+                "m root wheel - - -",
+                "u app 500 - - -",
+                "r - 1000-2000 - - -",
+            ]
+        );
+    }
+}
@@ -39,8 +39,11 @@
 //! # doctest_parse().unwrap();
 //! ```
 
+pub(crate) use self::format::quote_field;
 pub(crate) use self::serialization::SysusersData;
 use crate::errors::{Context, SdError};
+pub use document::{Document, DocumentEntry, DocumentItem};
+pub use load::load_config;
 pub use parse::parse_from_reader;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
@@ -48,7 +51,9 @@ use std::io::BufRead;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+mod document;
 mod format;
+mod load;
 mod parse;
 mod serialization;
 
@@ -128,9 +133,13 @@ pub struct AddUserToGroup {
 
 impl AddUserToGroup {
     /// Create a new `AddUserToGroup` entry.
-    pub fn new(username: String, groupname: String) -> Result<Self, SdError> {
-        validate_name_strict(&username)?;
-        validate_name_strict(&groupname)?;
+    pub fn new(
+        username: String,
+        groupname: String,
+        policy: NameValidation,
+    ) -> Result<Self, SdError> {
+        policy.validate(&username)?;
+        policy.validate(&groupname)?;
         Ok(Self {
             username,
             groupname,
@@ -167,22 +176,34 @@ pub struct CreateGroup {
 
 impl CreateGroup {
     /// Create a new `CreateGroup` entry.
-    pub fn new(groupname: String) -> Result<Self, SdError> {
-        Self::impl_new(groupname, GidOrPath::Automatic)
+    pub fn new(groupname: String, policy: NameValidation) -> Result<Self, SdError> {
+        Self::impl_new(groupname, GidOrPath::Automatic, policy)
     }
 
     /// Create a new `CreateGroup` entry, using a numeric ID.
-    pub fn new_with_gid(groupname: String, gid: u32) -> Result<Self, SdError> {
-        Self::impl_new(groupname, GidOrPath::Gid(gid))
+    pub fn new_with_gid(
+        groupname: String,
+        gid: u32,
+        policy: NameValidation,
+    ) -> Result<Self, SdError> {
+        Self::impl_new(groupname, GidOrPath::Gid(gid), policy)
     }
 
     /// Create a new `CreateGroup` entry, using a filepath reference.
-    pub fn new_with_path(groupname: String, path: PathBuf) -> Result<Self, SdError> {
-        Self::impl_new(groupname, GidOrPath::Path(path))
+    pub fn new_with_path(
+        groupname: String,
+        path: PathBuf,
+        policy: NameValidation,
+    ) -> Result<Self, SdError> {
+        Self::impl_new(groupname, GidOrPath::Path(path), policy)
     }
 
-    pub(crate) fn impl_new(groupname: String, gid: GidOrPath) -> Result<Self, SdError> {
-        validate_name_strict(&groupname)?;
+    pub(crate) fn impl_new(
+        groupname: String,
+        gid: GidOrPath,
+        policy: NameValidation,
+    ) -> Result<Self, SdError> {
+        policy.validate(&groupname)?;
         Ok(Self { groupname, gid })
     }
 
@@ -223,6 +244,7 @@ pub struct CreateUserAndGroup {
     pub(crate) gecos: String,
     pub(crate) home_dir: Option<PathBuf>,
     pub(crate) shell: Option<PathBuf>,
+    pub(crate) locked: bool,
 }
 
 impl CreateUserAndGroup {
@@ -232,8 +254,9 @@ impl CreateUserAndGroup {
         gecos: String,
         home_dir: Option<PathBuf>,
         shell: Option<PathBuf>,
+        policy: NameValidation,
     ) -> Result<Self, SdError> {
-        Self::impl_new(name, gecos, home_dir, shell, IdOrPath::Automatic)
+        Self::impl_new(name, gecos, home_dir, shell, IdOrPath::Automatic, policy)
     }
 
     /// Create a new `CreateUserAndrGroup` entry, using a numeric ID.
@@ -243,8 +266,9 @@ impl CreateUserAndGroup {
         gecos: String,
         home_dir: Option<PathBuf>,
         shell: Option<PathBuf>,
+        policy: NameValidation,
     ) -> Result<Self, SdError> {
-        Self::impl_new(name, gecos, home_dir, shell, IdOrPath::Id(id))
+        Self::impl_new(name, gecos, home_dir, shell, IdOrPath::Id(id), policy)
     }
 
     /// Create a new `CreateUserAndGroup` entry, using a UID and a GID.
@@ -255,8 +279,16 @@ impl CreateUserAndGroup {
         gecos: String,
         home_dir: Option<PathBuf>,
         shell: Option<PathBuf>,
+        policy: NameValidation,
     ) -> Result<Self, SdError> {
-        Self::impl_new(name, gecos, home_dir, shell, IdOrPath::UidGid((uid, gid)))
+        Self::impl_new(
+            name,
+            gecos,
+            home_dir,
+            shell,
+            IdOrPath::UidGid((uid, gid)),
+            policy,
+        )
     }
 
     /// Create a new `CreateUserAndGroup` entry, using a UID and a groupname.
@@ -267,14 +299,16 @@ impl CreateUserAndGroup {
         gecos: String,
         home_dir: Option<PathBuf>,
         shell: Option<PathBuf>,
+        policy: NameValidation,
     ) -> Result<Self, SdError> {
-        validate_name_strict(&groupname)?;
+        policy.validate(&groupname)?;
         Self::impl_new(
             name,
             gecos,
             home_dir,
             shell,
             IdOrPath::UidGroupname((uid, groupname)),
+            policy,
         )
     }
 
@@ -285,8 +319,9 @@ impl CreateUserAndGroup {
         gecos: String,
         home_dir: Option<PathBuf>,
         shell: Option<PathBuf>,
+        policy: NameValidation,
     ) -> Result<Self, SdError> {
-        Self::impl_new(name, gecos, home_dir, shell, IdOrPath::Path(path))
+        Self::impl_new(name, gecos, home_dir, shell, IdOrPath::Path(path), policy)
     }
 
     pub(crate) fn impl_new(
@@ -295,22 +330,38 @@ impl CreateUserAndGroup {
         home_dir: Option<PathBuf>,
         shell: Option<PathBuf>,
         id: IdOrPath,
+        policy: NameValidation,
     ) -> Result<Self, SdError> {
-        validate_name_strict(&name)?;
+        policy.validate(&name)?;
         Ok(Self {
             name,
             id,
             gecos,
             home_dir,
             shell,
+            locked: false,
         })
     }
 
+    /// Set whether the account is locked (`u!` type), i.e. created without
+    /// any valid password or login ability.
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+
     /// Return the single-character signature for the "Type" field of this entry.
+    ///
+    /// Locked users share the `u` signature, see [`is_locked`](Self::is_locked).
     pub fn type_signature(&self) -> &str {
         "u"
     }
 
+    /// Return whether the account is locked (`u!` type).
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
     /// Return the user and group name ("Name" field) of this entry.
     pub fn name(&self) -> &str {
         &self.name
@@ -412,6 +463,26 @@ impl FromStr for GidOrPath {
     }
 }
 
+/// Policy for validating user and group names.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum NameValidation {
+    /// Strict mode, see [`validate_name_strict`].
+    #[default]
+    Strict,
+    /// Relaxed (compatibility) mode, see [`validate_name_relaxed`].
+    Relaxed,
+}
+
+impl NameValidation {
+    /// Validate a sysusers name according to this policy.
+    pub fn validate(self, input: &str) -> Result<(), SdError> {
+        match self {
+            NameValidation::Strict => validate_name_strict(input),
+            NameValidation::Relaxed => validate_name_relaxed(input),
+        }
+    }
+}
+
 /// Validate a sysusers name in strict mode.
 pub fn validate_name_strict(input: &str) -> Result<(), SdError> {
     if input.is_empty() {
@@ -444,6 +515,58 @@ pub fn validate_name_strict(input: &str) -> Result<(), SdError> {
     Ok(())
 }
 
+/// Validate a sysusers name in relaxed mode.
+///
+/// This matches the compatibility rules of systemd for names coming from
+/// existing user databases: names up to 255 characters are allowed, including
+/// dots and a trailing `$`, as long as they are not fully numeric, do not start
+/// with a dash, and contain no control characters, colons or slashes.
+pub fn validate_name_relaxed(input: &str) -> Result<(), SdError> {
+    if input.is_empty() {
+        return Err(SdError::from("empty name"));
+    }
+
+    if input.len() > 255 {
+        let err_msg = format!(
+            "overlong sysusers name '{}' (more than 255 characters)",
+            input
+        );
+        return Err(SdError::from(err_msg));
+    }
+
+    if input == "." || input == ".." {
+        return Err(format!("invalid sysusers name '{}'", input).into());
+    }
+
+    if input.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("fully numeric sysusers name '{}'", input).into());
+    }
+
+    if input.starts_with('-') || input.starts_with(char::is_whitespace) {
+        let err_msg = format!("invalid starting character in sysusers name '{}'", input);
+        return Err(SdError::from(err_msg));
+    }
+
+    if input.ends_with(char::is_whitespace) {
+        let err_msg = format!("invalid trailing character in sysusers name '{}'", input);
+        return Err(SdError::from(err_msg));
+    }
+
+    if let Some(ch) = input
+        .chars()
+        .find(|&c| c.is_control() || c == ':' || c == '/')
+    {
+        let err_msg = format!(
+            "invalid character '{}' in sysusers name '{}'",
+            ch.escape_default(),
+            input
+        );
+        return Err(SdError::from(err_msg));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -460,4 +583,29 @@ mod test {
             validate_name_strict(entry).unwrap();
         }
     }
+
+    #[test]
+    fn test_validate_name_relaxed() {
+        let err_cases = vec!["", "-foo", "42", ".", "..", "a:b", "a/b", " foo", "foo\n"];
+        for entry in err_cases {
+            validate_name_relaxed(entry).unwrap_err();
+        }
+
+        let long_name = "x".repeat(64);
+        let ok_cases = vec![
+            "_authd",
+            "10bar",
+            "john.doe",
+            "machine$",
+            long_name.as_str(),
+        ];
+        for entry in ok_cases {
+            validate_name_relaxed(entry).unwrap();
+            NameValidation::Relaxed.validate(entry).unwrap();
+        }
+
+        NameValidation::default().validate("john.doe").unwrap_err();
+        CreateGroup::new("john.doe".to_string(), NameValidation::Strict).unwrap_err();
+        CreateGroup::new("john.doe".to_string(), NameValidation::Relaxed).unwrap();
+    }
 }
@@ -1,6 +1,9 @@
 use super::*;
-use nom::bytes::complete::{tag, take_while1};
-use nom::character::complete::{anychar, multispace0, multispace1};
+use crate::unit::file::split_words;
+use nom::bytes::complete::take_while1;
+use nom::character::complete::{anychar, char, multispace0, multispace1};
+use nom::combinator::opt;
+use nom::error::{Error, ErrorKind as NomErrorKind};
 use nom::{Finish, IResult};
 use std::convert::TryInto;
 use std::str::FromStr;
@@ -22,7 +25,10 @@ pub fn parse_from_reader(bufrd: &mut impl BufRead) -> Result<Vec<SysusersEntry>,
 
         match data.parse() {
             Ok(entry) => output.push(entry),
-            Err(SdError { kind, msg }) if kind == ErrorKind::SysusersUnknownType => {
+            Err(SdError {
+                kind: ErrorKind::SysusersUnknownType,
+                msg,
+            }) => {
                 log::warn!("skipped line {}: {}", linenumber, msg);
             }
             Err(e) => {
@@ -38,6 +44,21 @@ pub fn parse_from_reader(bufrd: &mut impl BufRead) -> Result<Vec<SysusersEntry>,
     Ok(output)
 }
 
+impl SysusersEntry {
+    /// Parse all entries from the content of a `sysusers.d` file.
+    ///
+    /// Blank lines and comments are skipped, as well as lines with unknown
+    /// entry types (with a warning), like `systemd-sysusers` does.
+    pub fn parse_lines(input: &str) -> Result<Vec<SysusersEntry>, SdError> {
+        parse_from_reader(&mut input.as_bytes())
+    }
+
+    /// Parse all entries of a `sysusers.d` file from a buffered reader.
+    pub fn from_reader(mut reader: impl BufRead) -> Result<Vec<SysusersEntry>, SdError> {
+        parse_from_reader(&mut reader)
+    }
+}
+
 impl FromStr for SysusersEntry {
     type Err = SdError;
 
@@ -116,34 +137,38 @@ fn parse_to_sysusers_data(line: &str) -> Result<SysusersData, SdError> {
 fn parse_line(input: &str) -> IResult<&str, SysusersData> {
     let rest = input;
     let (rest, kind) = {
+        // A type character, optionally followed by a `!` modifier.
         let (rest, kind) = anychar(rest)?;
+        let (rest, modifier) = opt(char('!'))(rest)?;
         let (rest, _) = multispace1(rest)?;
-        (rest, kind.to_string())
+        let mut kind = kind.to_string();
+        kind.extend(modifier);
+        (rest, kind)
     };
     let (rest, name) = {
-        let (rest, name) = take_while1(|c: char| !c.is_ascii_whitespace())(rest)?;
+        let (rest, name) = parse_field(rest)?;
         let (rest, _) = multispace1(rest)?;
-        (rest, name.to_string())
+        (rest, name)
     };
     let (rest, id) = {
-        let (rest, id) = take_while1(|c: char| !c.is_ascii_whitespace())(rest)?;
+        let (rest, id) = parse_field(rest)?;
         let (rest, _) = multispace0(rest)?;
-        (rest, id.to_string())
+        (rest, id)
     };
     let (rest, gecos) = {
         let (rest, gecos) = parse_opt_string(rest)?;
         let (rest, _) = multispace0(rest)?;
-        (rest, gecos.map(|s| s.to_string()))
+        (rest, gecos)
     };
     let (rest, home_dir) = {
         let (rest, home_dir) = parse_opt_string(rest)?;
         let (rest, _) = multispace0(rest)?;
-        (rest, home_dir.map(|s| s.to_string()))
+        (rest, home_dir)
     };
     let (rest, shell) = {
         let (rest, shell) = parse_opt_string(rest)?;
         let (rest, _) = multispace0(rest)?;
-        (rest, shell.map(|s| s.to_string()))
+        (rest, shell)
     };
 
     let data = SysusersData {
@@ -157,27 +182,50 @@ fn parse_line(input: &str) -> IResult<&str, SysusersData> {
     Ok((rest, data))
 }
 
-fn parse_opt_string(input: &str) -> IResult<&str, Option<&str>> {
+/// Parse a mandatory field, possibly quoted.
+fn parse_field(input: &str) -> IResult<&str, String> {
+    let (rest, field) = match input.chars().next() {
+        Some('"') | Some('\'') => parse_quoted_string(input)?,
+        _ => parse_plain_string(input)?,
+    };
+    Ok((rest, field.unwrap_or_default()))
+}
+
+fn parse_opt_string(input: &str) -> IResult<&str, Option<String>> {
     match input.chars().next() {
         None => Ok((input, None)),
-        Some('"') => parse_quoted_string(input),
+        Some('"') | Some('\'') => parse_quoted_string(input),
         _ => parse_plain_string(input),
     }
 }
 
-// XXX(lucab): should this account for inner escaped quotes?
-fn parse_quoted_string(input: &str) -> IResult<&str, Option<&str>> {
-    let rest = input;
-    let (rest, _) = tag("\"")(rest)?;
-    let (rest, txt) = take_while1(|c: char| c != '"')(rest)?;
-    let (rest, _) = tag("\"")(rest)?;
-    Ok((rest, Some(txt)))
+/// Parse a single- or double-quoted string, with C-style escapes.
+fn parse_quoted_string(input: &str) -> IResult<&str, Option<String>> {
+    let mut chars = input.char_indices();
+    let quote = match chars.next() {
+        Some((_, quote)) => quote,
+        None => return Err(nom::Err::Error(Error::new(input, NomErrorKind::Char))),
+    };
+    let mut escaped = false;
+    for (index, c) in chars {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            let end = index + c.len_utf8();
+            let words = split_words(&input[..end])
+                .map_err(|_| nom::Err::Failure(Error::new(input, NomErrorKind::Escaped)))?;
+            return Ok((&input[end..], Some(words.concat())));
+        }
+    }
+    Err(nom::Err::Error(Error::new(input, NomErrorKind::Char)))
 }
 
-fn parse_plain_string(input: &str) -> IResult<&str, Option<&str>> {
+fn parse_plain_string(input: &str) -> IResult<&str, Option<String>> {
     let rest = input;
     let (rest, txt) = take_while1(|c: char| !c.is_ascii_whitespace())(rest)?;
-    Ok((rest, Some(txt)))
+    Ok((rest, Some(txt.to_string())))
 }
 
 #[cfg(test)]
@@ -189,7 +237,7 @@ mod test {
     fn test_type_g() {
         {
             let input = r#"g input - - - -"#;
-            let expected = CreateGroup::new("input".to_string()).unwrap();
+            let expected = CreateGroup::new("input".to_string(), NameValidation::Strict).unwrap();
             let output: CreateGroup = input.parse().unwrap();
             assert_eq!(output, expected);
             let line = output.to_string();
@@ -201,7 +249,12 @@ mod test {
     fn test_type_m() {
         {
             let input = r#"m _authd input - - -"#;
-            let expected = AddUserToGroup::new("_authd".to_string(), "input".to_string()).unwrap();
+            let expected = AddUserToGroup::new(
+                "_authd".to_string(),
+                "input".to_string(),
+                NameValidation::Strict,
+            )
+            .unwrap();
             let output: AddUserToGroup = input.parse().unwrap();
             assert_eq!(output, expected);
             let line = output.to_string();
@@ -231,6 +284,7 @@ mod test {
                 "Postgresql Database".to_string(),
                 Some("/var/lib/pgsql".to_string().into()),
                 Some("/usr/libexec/postgresdb".to_string().into()),
+                NameValidation::Strict,
             )
             .unwrap();
             let output: CreateUserAndGroup = input.parse().unwrap();
@@ -238,6 +292,66 @@ mod test {
             let line = output.to_string();
             assert_eq!(line, input);
         }
+        {
+            let input = r#"u! locked 1000 "Locked User" /home/locked -"#;
+            let output: CreateUserAndGroup = input.parse().unwrap();
+            assert!(output.is_locked());
+            assert_eq!(output.type_signature(), "u");
+            assert_eq!(output.static_uid(), Some(1000));
+            assert_eq!(output.to_string(), input);
+
+            let entry: SysusersEntry = input.parse().unwrap();
+            assert_eq!(entry, output.into_sysusers_entry());
+        }
+        {
+            let input = r#"g! locked -"#;
+            input.parse::<SysusersEntry>().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let config_fragment = r#"
+# Comment, followed by a blank line
+
+g     wheel    -
+g     kvm      /dev/kvm
+u     nobody   65534:65534    "Kernel Overflow User" -   -
+u     quoted   -              'Single "quoted"'
+u     escaped  -              "Escaped \"quote\" and tab\t" "/var/lib/with space"
+u     empty    -              ""
+m     nobody   wheel
+r     -        1000
+X     unknown  -
+"#;
+        let entries = SysusersEntry::parse_lines(config_fragment).unwrap();
+        let signatures: Vec<_> = entries.iter().map(|e| e.type_signature()).collect();
+        assert_eq!(signatures, vec!["g", "g", "u", "u", "u", "u", "m", "r"]);
+
+        let users: Vec<_> = entries
+            .iter()
+            .filter_map(|e| match e {
+                SysusersEntry::CreateUserAndGroup(u) => Some(u),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(users[0].gecos, "Kernel Overflow User");
+        assert_eq!(users[0].home_dir, None);
+        assert_eq!(users[0].shell, None);
+        assert_eq!(users[0].static_uid(), Some(65534));
+        assert_eq!(users[1].gecos, r#"Single "quoted""#);
+        assert_eq!(users[2].gecos, "Escaped \"quote\" and tab\t");
+        assert_eq!(
+            users[2].home_dir,
+            Some(PathBuf::from("/var/lib/with space"))
+        );
+        assert_eq!(users[3].gecos, "");
+
+        let from_reader = SysusersEntry::from_reader(config_fragment.as_bytes()).unwrap();
+        assert_eq!(from_reader, entries);
+
+        SysusersEntry::parse_lines("u foo - \"unterminated").unwrap_err();
+        SysusersEntry::parse_lines("g 0invalid -").unwrap_err();
     }
 
     #[test]
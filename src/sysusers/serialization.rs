@@ -57,7 +57,7 @@ impl TryFrom<SysusersData> for AddUserToGroup {
         ensure_field_none_or_automatic("Home directory", &value.home_dir)?;
         ensure_field_none_or_automatic("Shell", &value.shell)?;
 
-        Self::new(value.name, value.id)
+        Self::new(value.name, value.id, NameValidation::Strict)
     }
 }
 
@@ -73,7 +73,7 @@ impl TryFrom<SysusersData> for CreateGroup {
         ensure_field_none_or_automatic("Shell", &value.shell)?;
 
         let gid: GidOrPath = value.id.parse()?;
-        Self::impl_new(value.name, gid)
+        Self::impl_new(value.name, gid, NameValidation::Strict)
     }
 }
 
@@ -81,18 +81,24 @@ impl TryFrom<SysusersData> for CreateUserAndGroup {
     type Error = SdError;
 
     fn try_from(value: SysusersData) -> Result<Self, Self::Error> {
-        if value.kind != "u" {
-            return Err(format!("unexpected sysuser entry of type '{}'", value.kind).into());
-        }
+        let locked = match value.kind.as_str() {
+            "u" => false,
+            "u!" => true,
+            _ => return Err(format!("unexpected sysuser entry of type '{}'", value.kind).into()),
+        };
 
         let id: IdOrPath = value.id.parse()?;
+        // A single dash marks a field as unset.
+        let field = |value: Option<String>| value.filter(|v| v != "-");
         Self::impl_new(
             value.name,
-            value.gecos.unwrap_or_default(),
-            value.home_dir.map(Into::into),
-            value.shell.map(Into::into),
+            field(value.gecos).unwrap_or_default(),
+            field(value.home_dir).map(Into::into),
+            field(value.shell).map(Into::into),
             id,
+            NameValidation::Strict,
         )
+        .map(|entry| entry.locked(locked))
     }
 }
 
@@ -150,7 +156,8 @@ impl Serialize for CreateUserAndGroup {
         S: Serializer,
     {
         let mut state = serializer.serialize_struct("CreateUserAndGroup", SYSUSERS_FIELDS)?;
-        state.serialize_field("Type", self.type_signature())?;
+        let kind = if self.locked { "u!" } else { "u" };
+        state.serialize_field("Type", kind)?;
         state.serialize_field("Name", &self.name)?;
         state.serialize_field("ID", &self.id)?;
         state.serialize_field("GECOS", &self.gecos)?;
@@ -208,7 +215,12 @@ mod test {
             assert_eq!(output, expected);
         }
         {
-            let input = AddUserToGroup::new("foo3".to_string(), "bar".to_string()).unwrap();
+            let input = AddUserToGroup::new(
+                "foo3".to_string(),
+                "bar".to_string(),
+                NameValidation::Strict,
+            )
+            .unwrap();
             let expected = r#"{"Type":"m","Name":"foo3","ID":"bar","GECOS":null,"Home directory":null,"Shell":null}"#;
 
             let output = serde_json::to_string(&input).unwrap();
@@ -218,7 +230,7 @@ mod test {
             assert_eq!(output, expected);
         }
         {
-            let input = CreateGroup::new("foo1".to_string()).unwrap();
+            let input = CreateGroup::new("foo1".to_string(), NameValidation::Strict).unwrap();
             let expected = r#"{"Type":"g","Name":"foo1","ID":"-","GECOS":null,"Home directory":null,"Shell":null}"#;
 
             let output = serde_json::to_string(&input).unwrap();
@@ -228,8 +240,14 @@ mod test {
             assert_eq!(output, expected);
         }
         {
-            let input = CreateUserAndGroup::new("foo0".to_string(), "test".to_string(), None, None)
-                .unwrap();
+            let input = CreateUserAndGroup::new(
+                "foo0".to_string(),
+                "test".to_string(),
+                None,
+                None,
+                NameValidation::Strict,
+            )
+            .unwrap();
             let expected = r#"{"Type":"u","Name":"foo0","ID":"-","GECOS":"test","Home directory":null,"Shell":null}"#;
 
             let output = serde_json::to_string(&input).unwrap();
@@ -253,7 +271,12 @@ mod test {
         }
         {
             let input = r#"{"Type":"m","Name":"foo3","ID":"bar","GECOS":null,"Home directory":null,"Shell":null}"#;
-            let expected = AddUserToGroup::new("foo3".to_string(), "bar".to_string()).unwrap();
+            let expected = AddUserToGroup::new(
+                "foo3".to_string(),
+                "bar".to_string(),
+                NameValidation::Strict,
+            )
+            .unwrap();
 
             let output: AddUserToGroup = serde_json::from_str(input).unwrap();
             assert_eq!(output, expected);
@@ -262,7 +285,7 @@ mod test {
         }
         {
             let input = r#"{"Type":"g","Name":"foo1","ID":"-","GECOS":null,"Home directory":null,"Shell":null}"#;
-            let expected = CreateGroup::new("foo1".to_string()).unwrap();
+            let expected = CreateGroup::new("foo1".to_string(), NameValidation::Strict).unwrap();
 
             let output: CreateGroup = serde_json::from_str(input).unwrap();
             assert_eq!(output, expected);
@@ -271,9 +294,14 @@ mod test {
         }
         {
             let input = r#"{"Type":"u","Name":"foo0","ID":"-","GECOS":"test","Home directory":null,"Shell":null}"#;
-            let expected =
-                CreateUserAndGroup::new("foo0".to_string(), "test".to_string(), None, None)
-                    .unwrap();
+            let expected = CreateUserAndGroup::new(
+                "foo0".to_string(),
+                "test".to_string(),
+                None,
+                None,
+                NameValidation::Strict,
+            )
+            .unwrap();
 
             let output: CreateUserAndGroup = serde_json::from_str(input).unwrap();
             assert_eq!(output, expected);
@@ -294,7 +322,12 @@ mod test {
             assert_eq!(output, SysusersEntry::AddRange(input));
         }
         {
-            let input = AddUserToGroup::new("foo3".to_string(), "bar".to_string()).unwrap();
+            let input = AddUserToGroup::new(
+                "foo3".to_string(),
+                "bar".to_string(),
+                NameValidation::Strict,
+            )
+            .unwrap();
 
             let json = serde_json::to_string(&input).unwrap();
             let output: AddUserToGroup = serde_json::from_str(&json).unwrap();
@@ -303,7 +336,7 @@ mod test {
             assert_eq!(output, SysusersEntry::AddUserToGroup(input));
         }
         {
-            let input = CreateGroup::new("foo1".to_string()).unwrap();
+            let input = CreateGroup::new("foo1".to_string(), NameValidation::Strict).unwrap();
 
             let json = serde_json::to_string(&input).unwrap();
             let output: CreateGroup = serde_json::from_str(&json).unwrap();
@@ -312,8 +345,14 @@ mod test {
             assert_eq!(output, SysusersEntry::CreateGroup(input));
         }
         {
-            let input = CreateUserAndGroup::new("foo0".to_string(), "test".to_string(), None, None)
-                .unwrap();
+            let input = CreateUserAndGroup::new(
+                "foo0".to_string(),
+                "test".to_string(),
+                None,
+                None,
+                NameValidation::Strict,
+            )
+            .unwrap();
 
             let json = serde_json::to_string(&input).unwrap();
             let output: CreateUserAndGroup = serde_json::from_str(&json).unwrap();
@@ -0,0 +1,1128 @@
+use super::*;
+use crate::credentials::CredentialsLoader;
+use crate::errors::Context;
+use nix::sys::stat::{makedev, mknod, Mode as NixMode, SFlag};
+use nix::unistd::{fchownat, mkfifo, FchownatFlags, Gid, Group, Uid, User};
+use std::fs::{self, Metadata, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::time::SystemTime;
+
+/// Directory holding factory defaults for `L` and `C` entries without argument.
+const FACTORY_DIR: &str = "/usr/share/factory";
+
+/// Options for [`apply`], mirroring the `systemd-tmpfiles` command line.
+#[derive(Clone, Debug, Default)]
+pub struct ApplyOptions {
+    create: bool,
+    clean: bool,
+    remove: bool,
+    boot: bool,
+    root: Option<PathBuf>,
+    prefixes: Vec<PathBuf>,
+    exclude_prefixes: Vec<PathBuf>,
+}
+
+impl ApplyOptions {
+    /// Create a new set of options, with all actions disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create, write and adjust paths (`--create`).
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Clean up files older than the configured age (`--clean`).
+    pub fn clean(mut self, clean: bool) -> Self {
+        self.clean = clean;
+        self
+    }
+
+    /// Remove paths from `r`/`R` entries and contents of `D` entries (`--remove`).
+    pub fn remove(mut self, remove: bool) -> Self {
+        self.remove = remove;
+        self
+    }
+
+    /// Also apply entries marked with the `!` modifier (`--boot`).
+    pub fn boot(mut self, boot: bool) -> Self {
+        self.boot = boot;
+        self
+    }
+
+    /// Operate on paths below an alternate root directory (`--root`).
+    ///
+    /// Symlinks in parent directories are resolved relative to the root, so
+    /// paths from configuration stay inside of it. This is not a security
+    /// boundary against concurrent changes to the tree: a root writable by
+    /// untrusted users can still redirect operations while they happen.
+    ///
+    /// User and group names are still resolved against the host databases.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Only apply entries for paths below `prefix` (`--prefix`).
+    ///
+    /// This can be repeated to allow multiple prefixes.
+    pub fn prefix(mut self, prefix: impl Into<PathBuf>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Skip entries for paths below `prefix` (`--exclude-prefix`).
+    ///
+    /// This can be repeated to exclude multiple prefixes.
+    pub fn exclude_prefix(mut self, prefix: impl Into<PathBuf>) -> Self {
+        self.exclude_prefixes.push(prefix.into());
+        self
+    }
+
+    fn selects(&self, entry: &TmpfilesEntry) -> bool {
+        if entry.modifiers.boot_only && !self.boot {
+            return false;
+        }
+        let path = entry.path();
+        if !self.prefixes.is_empty() && !self.prefixes.iter().any(|p| path.starts_with(p)) {
+            return false;
+        }
+        !self.exclude_prefixes.iter().any(|p| path.starts_with(p))
+    }
+
+    /// Map an absolute path from configuration to the filesystem.
+    ///
+    /// With an alternate root, symlinks in parent directories are resolved
+    /// relative to the root, so that they cannot point outside of it. The
+    /// last component is not followed.
+    fn resolve(&self, path: &Path) -> Result<PathBuf, SdError> {
+        match &self.root {
+            Some(root) => resolve_in_root(root, path),
+            None => Ok(path.to_path_buf()),
+        }
+    }
+}
+
+/// Maximum number of symlinks followed when resolving a path.
+const MAX_SYMLINKS: usize = 40;
+
+/// Resolve `path` below `root`, like `chase()` with `CHASE_PREFIX_ROOT`:
+/// absolute symlinks and `..` components never leave the root directory.
+fn resolve_in_root(root: &Path, path: &Path) -> Result<PathBuf, SdError> {
+    use std::path::Component;
+
+    let mut resolved = PathBuf::new();
+    let mut pending: Vec<PathBuf> = path.iter().rev().map(PathBuf::from).collect();
+    let mut symlinks = 0;
+    while let Some(component) = pending.pop() {
+        match component.components().next() {
+            Some(Component::Normal(name)) => {
+                let candidate = root.join(&resolved).join(name);
+                let is_last = pending.is_empty();
+                let target = match fs::symlink_metadata(&candidate) {
+                    Ok(meta) if !is_last && meta.file_type().is_symlink() => {
+                        fs::read_link(&candidate).map_err(|e| io_error("read", &candidate, e))?
+                    }
+                    _ => {
+                        resolved.push(name);
+                        continue;
+                    }
+                };
+                symlinks += 1;
+                if symlinks > MAX_SYMLINKS {
+                    return Err(
+                        format!("too many levels of symlinks in '{}'", path.display()).into(),
+                    );
+                }
+                if target.is_absolute() {
+                    resolved.clear();
+                }
+                pending.extend(target.iter().rev().map(PathBuf::from));
+            }
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            _ => {}
+        }
+    }
+    Ok(root.join(resolved))
+}
+
+/// Apply `tmpfiles.d` entries to the filesystem, like `systemd-tmpfiles`.
+///
+/// Removal and cleanup are performed first, then paths are created and
+/// adjusted, according to the enabled actions in `options`. Failures on
+/// single entries do not stop processing: they are logged, and an error is
+/// returned at the end. Failures to create paths are ignored for entries
+/// with the `-` modifier.
+///
+/// Paths containing specifiers are skipped, as well as entry types which
+/// are not supported yet (extended attributes, file attributes and ACLs).
+pub fn apply(entries: &[TmpfilesEntry], options: &ApplyOptions) -> Result<(), SdError> {
+    apply_at(entries, options, SystemTime::now())
+}
+
+fn apply_at(
+    entries: &[TmpfilesEntry],
+    options: &ApplyOptions,
+    now: SystemTime,
+) -> Result<(), SdError> {
+    let selected: Vec<_> = entries
+        .iter()
+        .filter(|entry| options.selects(entry))
+        .filter(|entry| {
+            let has_specifiers = entry.path.to_string_lossy().contains('%');
+            if has_specifiers {
+                log::warn!(
+                    "skipped '{}': specifiers are not supported",
+                    entry.path.display()
+                );
+            }
+            !has_specifiers
+        })
+        .collect();
+
+    let mut failures = vec![];
+    let mut record = |entry: &TmpfilesEntry, result: Result<(), SdError>| {
+        if let Err(e) = result {
+            log::warn!("failed to apply '{}': {}", entry, e.msg);
+            failures.push(e.msg);
+        }
+    };
+
+    if options.remove {
+        for entry in &selected {
+            record(entry, remove_entry(entry, options));
+        }
+    }
+    if options.clean {
+        let ignores: Vec<_> = selected
+            .iter()
+            .filter(|e| matches!(e.entry_type, EntryType::Ignore | EntryType::IgnorePath))
+            .map(|e| (e.path.as_path(), e.entry_type == EntryType::Ignore))
+            .collect();
+        for entry in &selected {
+            record(entry, clean_entry(entry, options, &ignores, now));
+        }
+    }
+    if options.create {
+        for entry in &selected {
+            let result = create_entry(entry, options);
+            if entry.modifiers.ignore_errors {
+                if let Err(e) = result {
+                    log::debug!("ignored failure on '{}': {}", entry, e.msg);
+                }
+                continue;
+            }
+            record(entry, result);
+        }
+    }
+
+    match failures.first() {
+        None => Ok(()),
+        Some(first) => Err(format!(
+            "failed to apply {} tmpfiles entries, first error: {}",
+            failures.len(),
+            first
+        )
+        .into()),
+    }
+}
+
+fn remove_entry(entry: &TmpfilesEntry, options: &ApplyOptions) -> Result<(), SdError> {
+    match entry.entry_type {
+        EntryType::Remove => {
+            for path in expand_glob(&entry.path, options) {
+                let real = options.resolve(&path)?;
+                let result = match fs::symlink_metadata(&real) {
+                    Ok(meta) if meta.is_dir() => fs::remove_dir(&real),
+                    Ok(_) => fs::remove_file(&real),
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) if e.raw_os_error() == Some(libc::ENOTEMPTY) => {}
+                    Err(e) => return Err(io_error("remove", &real, e)),
+                }
+            }
+        }
+        EntryType::RemoveRecursive => {
+            for path in expand_glob(&entry.path, options) {
+                let real = options.resolve(&path)?;
+                remove_recursive(&real).map_err(|e| io_error("remove", &real, e))?;
+            }
+        }
+        EntryType::TruncateDirectory => {
+            let real = options.resolve(&entry.path)?;
+            let dir = match fs::read_dir(&real) {
+                Ok(dir) => dir,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(io_error("read directory", &real, e)),
+            };
+            for child in dir {
+                let child = child.map_err(|e| io_error("read directory", &real, e))?;
+                let path = child.path();
+                remove_recursive(&path).map_err(|e| io_error("remove", &path, e))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn remove_recursive(path: &Path) -> std::io::Result<()> {
+    let result = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match result {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn clean_entry(
+    entry: &TmpfilesEntry,
+    options: &ApplyOptions,
+    ignores: &[(&Path, bool)],
+    now: SystemTime,
+) -> Result<(), SdError> {
+    let age = match &entry.age {
+        Some(age) => age,
+        None => return Ok(()),
+    };
+    if !matches!(
+        entry.entry_type,
+        EntryType::CreateDirectory
+            | EntryType::TruncateDirectory
+            | EntryType::EmptyDirectory
+            | EntryType::CreateSubvolume
+            | EntryType::CreateSubvolumeInheritQuota
+            | EntryType::CreateSubvolumeNewQuota
+            | EntryType::Copy
+    ) {
+        return Ok(());
+    }
+    let cutoff = match now.checked_sub(age.span) {
+        Some(cutoff) => cutoff,
+        None => return Ok(()),
+    };
+    let cleaner = Cleaner::new(age, cutoff, ignores, options);
+
+    for path in expand_glob(&entry.path, options) {
+        let real = options.resolve(&path)?;
+        let meta = match fs::symlink_metadata(&real) {
+            Ok(meta) if meta.is_dir() => meta,
+            Ok(_) => continue,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(io_error("inspect", &real, e)),
+        };
+        cleaner.clean_dir(&path, meta.dev(), 0)?;
+    }
+    Ok(())
+}
+
+/// Age-based cleanup of a directory tree.
+struct Cleaner<'a> {
+    cutoff: SystemTime,
+    keep_first_level: bool,
+    age_by_file: String,
+    age_by_dir: String,
+    ignores: &'a [(&'a Path, bool)],
+    options: &'a ApplyOptions,
+}
+
+impl<'a> Cleaner<'a> {
+    fn new(
+        age: &Age,
+        cutoff: SystemTime,
+        ignores: &'a [(&'a Path, bool)],
+        options: &'a ApplyOptions,
+    ) -> Self {
+        // By default all timestamps are considered, except change time on directories.
+        let age_by = age.age_by.as_deref().unwrap_or("abcmABM");
+        Self {
+            cutoff,
+            keep_first_level: age.keep_first_level,
+            age_by_file: age_by.chars().filter(char::is_ascii_lowercase).collect(),
+            age_by_dir: age_by
+                .chars()
+                .filter(char::is_ascii_uppercase)
+                .map(|c| c.to_ascii_lowercase())
+                .collect(),
+            ignores,
+            options,
+        }
+    }
+
+    /// Remove old entries in `dir` (a path from configuration), without
+    /// crossing filesystem boundaries.
+    fn clean_dir(&self, dir: &Path, dev: u64, depth: usize) -> Result<(), SdError> {
+        let real_dir = self.options.resolve(dir)?;
+        let children = match fs::read_dir(&real_dir) {
+            Ok(children) => children,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(io_error("read directory", &real_dir, e)),
+        };
+        for child in children {
+            let child = child.map_err(|e| io_error("read directory", &real_dir, e))?;
+            let path = dir.join(child.file_name());
+            let real = child.path();
+            let meta = match fs::symlink_metadata(&real) {
+                Ok(meta) => meta,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(io_error("inspect", &real, e)),
+            };
+            if meta.dev() != dev {
+                continue;
+            }
+            let ignored = self.ignores.iter().find(|(p, _)| path_matches(p, &path));
+            if let Some((_, true)) = ignored {
+                continue;
+            }
+
+            if meta.is_dir() {
+                self.clean_dir(&path, dev, depth + 1)?;
+            }
+            if ignored.is_some() || (depth == 0 && self.keep_first_level) {
+                continue;
+            }
+            let age_by = if meta.is_dir() {
+                &self.age_by_dir
+            } else {
+                &self.age_by_file
+            };
+            if self.is_young(&meta, age_by) {
+                continue;
+            }
+
+            let result = if meta.is_dir() {
+                fs::remove_dir(&real)
+            } else {
+                fs::remove_file(&real)
+            };
+            match result {
+                Ok(()) => log::debug!("removed old '{}'", path.display()),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) if e.raw_os_error() == Some(libc::ENOTEMPTY) => {}
+                Err(e) => return Err(io_error("remove", &real, e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn is_young(&self, meta: &Metadata, age_by: &str) -> bool {
+        let timestamp = |secs: i64, nsecs: i64| {
+            let secs = u64::try_from(secs).unwrap_or_default();
+            SystemTime::UNIX_EPOCH + Duration::new(secs, nsecs as u32)
+        };
+        age_by.chars().any(|c| {
+            let time = match c {
+                'a' => Some(timestamp(meta.atime(), meta.atime_nsec())),
+                'b' => meta.created().ok(),
+                'c' => Some(timestamp(meta.ctime(), meta.ctime_nsec())),
+                'm' => Some(timestamp(meta.mtime(), meta.mtime_nsec())),
+                _ => None,
+            };
+            time.map_or(false, |t| t > self.cutoff)
+        })
+    }
+}
+
+fn create_entry(entry: &TmpfilesEntry, options: &ApplyOptions) -> Result<(), SdError> {
+    let real = options.resolve(&entry.path)?;
+    match entry.entry_type {
+        EntryType::CreateFile | EntryType::TruncateFile => {
+            let truncate = entry.entry_type == EntryType::TruncateFile || entry.modifiers.force;
+            let created = create_file(entry, &real, truncate)?;
+            fix_permissions(entry, &real, created)?;
+        }
+        EntryType::WriteFile => {
+            let data = argument_data(entry)?;
+            for path in expand_glob(&entry.path, options) {
+                let real = options.resolve(&path)?;
+                let mut file = match OpenOptions::new()
+                    .write(true)
+                    .append(entry.modifiers.force)
+                    .truncate(!entry.modifiers.force)
+                    .open(&real)
+                {
+                    Ok(file) => file,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(io_error("open", &real, e)),
+                };
+                file.write_all(&data)
+                    .map_err(|e| io_error("write", &real, e))?;
+            }
+        }
+        EntryType::CreateDirectory
+        | EntryType::TruncateDirectory
+        | EntryType::CreateSubvolume
+        | EntryType::CreateSubvolumeInheritQuota
+        | EntryType::CreateSubvolumeNewQuota => {
+            let created = create_node(entry, &real, Metadata::is_dir, |path| {
+                fs::DirBuilder::new()
+                    .mode(entry.mode.map_or(0o755, |m| m.bits))
+                    .create(path)
+            })?;
+            fix_permissions(entry, &real, created)?;
+        }
+        EntryType::EmptyDirectory | EntryType::AdjustMode => {
+            for path in expand_glob(&entry.path, options) {
+                fix_permissions(entry, &options.resolve(&path)?, false)?;
+            }
+        }
+        EntryType::AdjustModeRecursive => {
+            for path in expand_glob(&entry.path, options) {
+                fix_permissions_recursive(entry, &options.resolve(&path)?)?;
+            }
+        }
+        EntryType::CreateFifo => {
+            let created = create_node(
+                entry,
+                &real,
+                |m| m.file_type().is_fifo(),
+                |path| {
+                    let mode = NixMode::from_bits_truncate(entry.mode.map_or(0o644, |m| m.bits));
+                    mkfifo(path, mode).map_err(std::io::Error::from)
+                },
+            )?;
+            fix_permissions(entry, &real, created)?;
+        }
+        EntryType::CreateCharDevice | EntryType::CreateBlockDevice => {
+            let (major, minor) = parse_device_numbers(entry.argument().unwrap_or_default())?;
+            let (kind, is_kind): (_, fn(&Metadata) -> bool) =
+                if entry.entry_type == EntryType::CreateCharDevice {
+                    (SFlag::S_IFCHR, |m| m.file_type().is_char_device())
+                } else {
+                    (SFlag::S_IFBLK, |m| m.file_type().is_block_device())
+                };
+            let created = create_node(entry, &real, is_kind, |path| {
+                let mode = NixMode::from_bits_truncate(entry.mode.map_or(0o644, |m| m.bits));
+                let dev = makedev(major.into(), minor.into());
+                mknod(path, kind, mode, dev).map_err(std::io::Error::from)
+            })?;
+            fix_permissions(entry, &real, created)?;
+        }
+        EntryType::CreateSymlink => {
+            let target = match &entry.argument {
+                Some(target) => PathBuf::from(target),
+                None => factory_path(&entry.path),
+            };
+            let is_same = |m: &Metadata| {
+                m.file_type().is_symlink()
+                    && fs::read_link(&real).map_or(false, |current| current == target)
+            };
+            create_node(entry, &real, is_same, |path| {
+                std::os::unix::fs::symlink(&target, path)
+            })?;
+            fix_ownership(entry, &real, true)?;
+        }
+        EntryType::Copy => {
+            // Existing directories are only copied into if empty, unless
+            // `+` is set to merge missing entries into them.
+            let existing = match fs::symlink_metadata(&real) {
+                Ok(meta) if meta.is_dir() => {
+                    let mut children =
+                        fs::read_dir(&real).map_err(|e| io_error("read directory", &real, e))?;
+                    if !entry.modifiers.force && children.next().is_some() {
+                        return Ok(());
+                    }
+                    true
+                }
+                Ok(_) => return Ok(()),
+                Err(_) => false,
+            };
+            let source = match &entry.argument {
+                Some(source) => PathBuf::from(source),
+                None => factory_path(&entry.path),
+            };
+            let source = options.resolve(&source)?;
+            create_parents(&real)?;
+            copy_recursive(&source, &real)?;
+            fix_permissions(entry, &real, !existing)?;
+        }
+        EntryType::SetXattr
+        | EntryType::SetXattrRecursive
+        | EntryType::SetAttributes
+        | EntryType::SetAttributesRecursive
+        | EntryType::SetAcl
+        | EntryType::SetAclRecursive => {
+            log::warn!(
+                "skipped '{}': entry type '{}' is not supported",
+                entry.path.display(),
+                entry.entry_type.as_char()
+            );
+        }
+        EntryType::Ignore
+        | EntryType::IgnorePath
+        | EntryType::Remove
+        | EntryType::RemoveRecursive => {}
+    }
+    Ok(())
+}
+
+/// Create a regular file if missing (or truncate it), writing the argument
+/// into it. Return whether the file has been created.
+fn create_file(entry: &TmpfilesEntry, real: &Path, truncate: bool) -> Result<bool, SdError> {
+    let exists = match fs::symlink_metadata(real) {
+        Ok(meta) if meta.is_file() => true,
+        Ok(_) if entry.modifiers.remove_invalid => {
+            remove_recursive(real).map_err(|e| io_error("remove", real, e))?;
+            false
+        }
+        Ok(_) => return Err(format!("'{}' exists and is not a file", real.display()).into()),
+        Err(_) => false,
+    };
+    if exists && !truncate {
+        return Ok(false);
+    }
+
+    create_parents(real)?;
+    let data = match entry.argument {
+        Some(_) => argument_data(entry)?,
+        None => vec![],
+    };
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(entry.mode.map_or(0o644, |m| m.bits))
+        .open(real)
+        .map_err(|e| io_error("create", real, e))?;
+    file.write_all(&data)
+        .map_err(|e| io_error("write", real, e))?;
+    Ok(!exists)
+}
+
+/// Create a filesystem node if missing, replacing existing nodes of the
+/// wrong kind when allowed. Return whether the node has been created.
+fn create_node(
+    entry: &TmpfilesEntry,
+    real: &Path,
+    is_expected: impl Fn(&Metadata) -> bool,
+    create: impl Fn(&Path) -> std::io::Result<()>,
+) -> Result<bool, SdError> {
+    if let Ok(meta) = fs::symlink_metadata(real) {
+        if is_expected(&meta) {
+            return Ok(false);
+        }
+        let replace = entry.modifiers.remove_invalid
+            || (entry.modifiers.force && entry.entry_type != EntryType::CreateDirectory);
+        if !replace {
+            let msg = format!(
+                "'{}' exists with a different type than '{}'",
+                real.display(),
+                entry.entry_type.as_char()
+            );
+            return Err(msg.into());
+        }
+        remove_recursive(real).map_err(|e| io_error("remove", real, e))?;
+    }
+    create_parents(real)?;
+    create(real).map_err(|e| io_error("create", real, e))?;
+    Ok(true)
+}
+
+fn create_parents(real: &Path) -> Result<(), SdError> {
+    match real.parent() {
+        Some(parent) => fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(parent)
+            .map_err(|e| io_error("create", parent, e)),
+        None => Ok(()),
+    }
+}
+
+/// Return the content to write for `f` and `w` entries.
+fn argument_data(entry: &TmpfilesEntry) -> Result<Vec<u8>, SdError> {
+    use base64::Engine;
+
+    let argument = entry.argument().unwrap_or_default();
+    if entry.modifiers.credential {
+        let mut data = vec![];
+        CredentialsLoader::open()?
+            .get(argument)?
+            .read_to_end(&mut data)
+            .with_context(|| format!("failed to read credential '{}'", argument))?;
+        return Ok(data);
+    }
+    if entry.modifiers.base64 {
+        return base64::engine::general_purpose::STANDARD
+            .decode(argument)
+            .with_context(|| format!("invalid base64 argument for '{}'", entry.path.display()));
+    }
+    cunescape(argument).with_context(|| format!("invalid argument for '{}'", entry.path.display()))
+}
+
+/// Resolve C-style escapes (e.g. `\n`, `\x20` or `\040`), like systemd's
+/// `cunescape()`.
+fn cunescape(input: &str) -> Result<Vec<u8>, SdError> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(index) = rest.find('\\') {
+        output.extend_from_slice(&rest.as_bytes()[..index]);
+        let escaped = &rest[index + 1..];
+        let c = escaped.chars().next().ok_or("trailing backslash")?;
+        let digits = |range: std::ops::Range<usize>, radix: u32| {
+            escaped
+                .get(range)
+                .filter(|digits| digits.chars().all(|d| d.is_digit(radix)))
+                .and_then(|digits| u8::from_str_radix(digits, radix).ok())
+        };
+        let (len, byte) = match c {
+            'a' => (1, Some(0x07)),
+            'b' => (1, Some(0x08)),
+            'f' => (1, Some(0x0c)),
+            'n' => (1, Some(b'\n')),
+            'r' => (1, Some(b'\r')),
+            't' => (1, Some(b'\t')),
+            'v' => (1, Some(0x0b)),
+            's' => (1, Some(b' ')),
+            '\\' | '"' | '\'' => (1, Some(c as u8)),
+            'x' => (3, digits(1..3, 16)),
+            '0'..='3' => (3, digits(0..3, 8)),
+            _ => (1, None),
+        };
+        // NUL bytes are refused, like in systemd.
+        match byte {
+            Some(byte) if byte != 0 => output.push(byte),
+            _ => return Err(format!("invalid escape sequence in '{}'", input).into()),
+        }
+        rest = &escaped[len..];
+    }
+    output.extend_from_slice(rest.as_bytes());
+    Ok(output)
+}
+
+fn factory_path(path: &Path) -> PathBuf {
+    Path::new(FACTORY_DIR).join(path.strip_prefix("/").unwrap_or(path))
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> Result<(), SdError> {
+    let meta = fs::symlink_metadata(source).map_err(|e| io_error("inspect", source, e))?;
+    let existing = fs::symlink_metadata(target).ok();
+    if meta.is_dir() {
+        match existing {
+            // Merge into an existing directory, without replacing anything.
+            Some(existing) if existing.is_dir() => {}
+            Some(_) => return Ok(()),
+            None => fs::DirBuilder::new()
+                .mode(meta.permissions().mode() & 0o7777)
+                .create(target)
+                .map_err(|e| io_error("create", target, e))?,
+        }
+        for child in fs::read_dir(source).map_err(|e| io_error("read directory", source, e))? {
+            let child = child.map_err(|e| io_error("read directory", source, e))?;
+            copy_recursive(&child.path(), &target.join(child.file_name()))?;
+        }
+    } else if existing.is_some() {
+        return Ok(());
+    } else if meta.file_type().is_symlink() {
+        let link = fs::read_link(source).map_err(|e| io_error("read link", source, e))?;
+        std::os::unix::fs::symlink(link, target).map_err(|e| io_error("create", target, e))?;
+    } else {
+        fs::copy(source, target).map_err(|e| io_error("copy", source, e))?;
+    }
+    Ok(())
+}
+
+fn fix_permissions_recursive(entry: &TmpfilesEntry, real: &Path) -> Result<(), SdError> {
+    fix_permissions(entry, real, false)?;
+    let meta = match fs::symlink_metadata(real) {
+        Ok(meta) => meta,
+        Err(_) => return Ok(()),
+    };
+    if meta.is_dir() {
+        for child in fs::read_dir(real).map_err(|e| io_error("read directory", real, e))? {
+            let child = child.map_err(|e| io_error("read directory", real, e))?;
+            fix_permissions_recursive(entry, &child.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Adjust mode and ownership of an existing path.
+fn fix_permissions(entry: &TmpfilesEntry, real: &Path, created: bool) -> Result<(), SdError> {
+    let meta = match fs::symlink_metadata(real) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(io_error("inspect", real, e)),
+    };
+    if meta.file_type().is_symlink() {
+        return fix_ownership(entry, real, created);
+    }
+
+    if let Some(mode) = entry.mode {
+        if created || !mode.only_on_create {
+            let current = meta.permissions().mode() & 0o7777;
+            let mut bits = mode.bits;
+            if mode.masked {
+                // Drop read, write and execute bits not set on the existing file.
+                for class in [0o444, 0o222, 0o111] {
+                    if current & class == 0 {
+                        bits &= !class;
+                    }
+                }
+            }
+            if bits != current {
+                fs::set_permissions(real, fs::Permissions::from_mode(bits))
+                    .map_err(|e| io_error("change mode of", real, e))?;
+            }
+        }
+    }
+    fix_ownership(entry, real, created)
+}
+
+fn fix_ownership(entry: &TmpfilesEntry, real: &Path, created: bool) -> Result<(), SdError> {
+    let owner = |value: Option<&str>| match value {
+        Some(v) => match v.strip_prefix(':') {
+            Some(_) if !created => None,
+            Some(v) => Some(v.to_string()),
+            None => Some(v.to_string()),
+        },
+        None => None,
+    };
+    let uid = owner(entry.user()).map(|u| resolve_user(&u)).transpose()?;
+    let gid = owner(entry.group())
+        .map(|g| resolve_group(&g))
+        .transpose()?;
+    if uid.is_none() && gid.is_none() {
+        return Ok(());
+    }
+
+    let meta = fs::symlink_metadata(real).map_err(|e| io_error("inspect", real, e))?;
+    let uid = uid.filter(|u| u.as_raw() != meta.uid());
+    let gid = gid.filter(|g| g.as_raw() != meta.gid());
+    if uid.is_some() || gid.is_some() {
+        fchownat(None, real, uid, gid, FchownatFlags::NoFollowSymlink)
+            .with_context(|| format!("failed to change owner of '{}'", real.display()))?;
+    }
+    Ok(())
+}
+
+fn resolve_user(name: &str) -> Result<Uid, SdError> {
+    if let Ok(uid) = name.parse() {
+        return Ok(Uid::from_raw(uid));
+    }
+    User::from_name(name)
+        .with_context(|| format!("failed to look up user '{}'", name))?
+        .map(|user| user.uid)
+        .ok_or_else(|| format!("unknown user '{}'", name).into())
+}
+
+fn resolve_group(name: &str) -> Result<Gid, SdError> {
+    if let Ok(gid) = name.parse() {
+        return Ok(Gid::from_raw(gid));
+    }
+    Group::from_name(name)
+        .with_context(|| format!("failed to look up group '{}'", name))?
+        .map(|group| group.gid)
+        .ok_or_else(|| format!("unknown group '{}'", name).into())
+}
+
+fn io_error(action: &str, path: &Path, err: std::io::Error) -> SdError {
+    format!("failed to {} '{}': {}", action, path.display(), err).into()
+}
+
+/// Expand glob patterns in a path from configuration, returning existing
+/// matching paths (or the path itself, if it has no patterns).
+fn expand_glob(pattern: &Path, options: &ApplyOptions) -> Vec<PathBuf> {
+    let has_glob = |s: &str| s.contains(['*', '?', '[']);
+    if !has_glob(&pattern.to_string_lossy()) {
+        return vec![pattern.to_path_buf()];
+    }
+
+    let mut matches = vec![PathBuf::from("/")];
+    for component in pattern.iter().skip(1) {
+        let component = component.to_string_lossy();
+        if !has_glob(&component) {
+            matches.iter_mut().for_each(|m| m.push(&*component));
+            continue;
+        }
+        let mut next = vec![];
+        for dir in matches {
+            let children = match options.resolve(&dir).map(fs::read_dir) {
+                Ok(Ok(children)) => children,
+                _ => continue,
+            };
+            let mut names: Vec<_> = children
+                .filter_map(|c| c.ok()?.file_name().into_string().ok())
+                .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                .filter(|name| wildcard_match(&component, name))
+                .collect();
+            names.sort();
+            next.extend(names.into_iter().map(|name| dir.join(name)));
+        }
+        matches = next;
+    }
+    matches.retain(|m| {
+        options
+            .resolve(m)
+            .map_or(false, |real| fs::symlink_metadata(real).is_ok())
+    });
+    matches
+}
+
+/// Return whether `path` matches a path pattern, component by component.
+fn path_matches(pattern: &Path, path: &Path) -> bool {
+    let (mut pattern, mut path) = (pattern.iter(), path.iter());
+    loop {
+        match (pattern.next(), path.next()) {
+            (None, None) => return true,
+            (Some(p), Some(c)) if wildcard_match(&p.to_string_lossy(), &c.to_string_lossy()) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Match a file name against a shell wildcard pattern (`*`, `?` and `[...]`).
+pub(crate) fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_from(&pattern, &name)
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| match_from(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && match_from(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let c = match name.first() {
+                Some(c) => *c,
+                None => return false,
+            };
+            match match_class(&pattern[1..], c) {
+                Some((true, rest)) => match_from(rest, &name[1..]),
+                Some((false, _)) => false,
+                // An unterminated class matches a literal bracket.
+                None => c == '[' && match_from(&pattern[1..], &name[1..]),
+            }
+        }
+        Some(p) => name.first() == Some(p) && match_from(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Match a character against a bracket expression (after the opening `[`),
+/// returning the result and the rest of the pattern.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut index) = match pattern.first() {
+        Some('!') | Some('^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    let mut first = true;
+    while index < pattern.len() {
+        let start = pattern[index];
+        if start == ']' && !first {
+            return Some((matched != negated, &pattern[index + 1..]));
+        }
+        first = false;
+        if pattern.get(index + 1) == Some(&'-')
+            && pattern.get(index + 2).map_or(false, |e| *e != ']')
+        {
+            matched |= (start..=pattern[index + 2]).contains(&c);
+            index += 3;
+        } else {
+            matched |= start == c;
+            index += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wildcard_match() {
+        let cases = [
+            ("*.old", "foo.old", true),
+            ("*.old", "foo.new", false),
+            ("foo?", "foo1", true),
+            ("foo?", "foo", false),
+            ("[a-c]x", "bx", true),
+            ("[!a-c]x", "bx", false),
+            ("[]]", "]", true),
+            ("*", "", true),
+        ];
+        for (pattern, name, expected) in cases {
+            assert_eq!(
+                wildcard_match(pattern, name),
+                expected,
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+        assert!(path_matches(
+            Path::new("/tmp/systemd-*"),
+            Path::new("/tmp/systemd-foo")
+        ));
+        assert!(!path_matches(Path::new("/tmp/*"), Path::new("/tmp/a/b")));
+    }
+
+    #[test]
+    fn test_resolve_in_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("usr/lib")).unwrap();
+        symlink("/usr/lib", root.join("lib")).unwrap();
+        symlink("../../..", root.join("usr/lib/up")).unwrap();
+        symlink("loop", root.join("loop")).unwrap();
+
+        let resolve = |path: &str| resolve_in_root(root, Path::new(path));
+        assert_eq!(resolve("/lib/file").unwrap(), root.join("usr/lib/file"));
+        assert_eq!(resolve("/lib/up/etc").unwrap(), root.join("etc"));
+        assert_eq!(resolve("/../../etc").unwrap(), root.join("etc"));
+        assert_eq!(resolve("/lib").unwrap(), root.join("lib"));
+        resolve("/loop/file").unwrap_err();
+    }
+
+    #[test]
+    fn test_cunescape() {
+        assert_eq!(cunescape("plain").unwrap(), b"plain");
+        assert_eq!(cunescape("a\\x20b\\tc\\040d\\\\").unwrap(), b"a b\tc d\\");
+        assert_eq!(cunescape("\\xff\\n").unwrap(), [0xff, b'\n']);
+        for input in ["\\", "\\q", "\\x+1", "\\x4", "\\x00", "\\8", "\\000"] {
+            cunescape(input).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_apply_create() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let config = r#"
+d /run/app 0750 - - -
+f /run/app/file 0600 - - - hello
+f /run/app/empty
+w+ /run/app/file - - - - \x20world
+L /run/app/link - - - - /dev/null
+p /run/app/fifo 0640
+C /run/app/copy - - - - /usr/share/app
+z /run/app/*.txt 0444
+R /run/old
+"#;
+        let entries = TmpfilesEntry::parse_lines(config).unwrap();
+        fs::create_dir_all(root.join("usr/share/app/sub")).unwrap();
+        fs::write(root.join("usr/share/app/sub/data"), "data").unwrap();
+        fs::create_dir_all(root.join("run/app")).unwrap();
+        fs::write(root.join("run/app/a.txt"), "").unwrap();
+        fs::create_dir_all(root.join("run/old")).unwrap();
+
+        let options = ApplyOptions::new().create(true).root(root);
+        apply(&entries, &options).unwrap();
+
+        let mode = |p: &str| fs::symlink_metadata(root.join(p)).unwrap().mode() & 0o7777;
+        assert_eq!(mode("run/app"), 0o750);
+        assert_eq!(mode("run/app/file"), 0o600);
+        assert_eq!(mode("run/app/empty"), 0o644);
+        assert_eq!(mode("run/app/a.txt"), 0o444);
+        assert_eq!(mode("run/app/fifo"), 0o640);
+        assert_eq!(
+            fs::read_to_string(root.join("run/app/file")).unwrap(),
+            "hello world"
+        );
+        assert_eq!(
+            fs::read_link(root.join("run/app/link")).unwrap(),
+            Path::new("/dev/null")
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("run/app/copy/sub/data")).unwrap(),
+            "data"
+        );
+        assert!(root.join("run/old").exists());
+
+        // `--remove` handles `R` entries.
+        let options = options.remove(true);
+        apply(&entries, &options).unwrap();
+        assert!(!root.join("run/old").exists());
+
+        // Conflicting nodes are reported, without stopping other entries.
+        let entries = TmpfilesEntry::parse_lines(
+            "L /run/app/file - - - - /dev/null\nd /run/app/new\nd- /run/app/file",
+        )
+        .unwrap();
+        apply(&entries, &options).unwrap_err();
+        assert!(root.join("run/app/new").is_dir());
+    }
+
+    #[test]
+    fn test_apply_copy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("usr/share/app/sub")).unwrap();
+        fs::write(root.join("usr/share/app/top"), "top").unwrap();
+        fs::write(root.join("usr/share/app/sub/data"), "data").unwrap();
+        for dir in ["empty", "full", "merged"] {
+            fs::create_dir_all(root.join("run").join(dir)).unwrap();
+        }
+        fs::write(root.join("run/full/top"), "local").unwrap();
+        fs::write(root.join("run/merged/top"), "local").unwrap();
+
+        let entries = TmpfilesEntry::parse_lines(
+            "C /run/empty - - - - /usr/share/app\n\
+             C /run/full - - - - /usr/share/app\n\
+             C+ /run/merged - - - - /usr/share/app",
+        )
+        .unwrap();
+        let options = ApplyOptions::new().create(true).root(root);
+        apply(&entries, &options).unwrap();
+
+        let read = |p: &str| fs::read_to_string(root.join(p)).unwrap();
+        assert_eq!(read("run/empty/top"), "top");
+        assert_eq!(read("run/empty/sub/data"), "data");
+        assert_eq!(read("run/full/top"), "local");
+        assert!(!root.join("run/full/sub").exists());
+        assert_eq!(read("run/merged/top"), "local");
+        assert_eq!(read("run/merged/sub/data"), "data");
+    }
+
+    #[test]
+    fn test_apply_clean() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let entries = TmpfilesEntry::parse_lines(
+            "d /tmp 1777 - - 10d\nx /tmp/keep-*\nX /tmp/dir\nd! /tmp/boot 0700",
+        )
+        .unwrap();
+        for dir in ["tmp/dir/sub", "tmp/keep-me", "tmp/empty"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["tmp/file", "tmp/dir/sub/file", "tmp/keep-me/file"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        // Nothing is old enough yet.
+        let options = ApplyOptions::new().clean(true).root(root);
+        apply(&entries, &options).unwrap();
+        assert!(root.join("tmp/file").exists());
+
+        let later = SystemTime::now() + Duration::from_secs(11 * 86400);
+        apply_at(&entries, &options, later).unwrap();
+        assert!(!root.join("tmp/file").exists());
+        assert!(!root.join("tmp/empty").exists());
+        assert!(!root.join("tmp/dir/sub").exists());
+        assert!(root.join("tmp/dir").exists());
+        assert!(root.join("tmp/keep-me/file").exists());
+
+        // Boot-only entries and filtered prefixes are skipped.
+        let options = ApplyOptions::new().create(true).root(root);
+        apply(&entries, &options.clone().exclude_prefix("/tmp")).unwrap();
+        apply(&entries, &options).unwrap();
+        assert!(!root.join("tmp/boot").exists());
+        apply(&entries, &options.boot(true).prefix("/tmp/boot")).unwrap();
+        assert!(root.join("tmp/boot").exists());
+    }
+}
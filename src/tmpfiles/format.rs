@@ -0,0 +1,108 @@
+use super::*;
+use crate::sysusers::quote_field;
+use crate::unit::format_timespan;
+use std::fmt::{self, Display};
+
+impl TmpfilesEntry {
+    /// Format this entry as a `tmpfiles.d` configuration line.
+    pub fn to_config_line(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for TmpfilesEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dash = |value: Option<&str>| quote_field(value.unwrap_or_default()).into_owned();
+        write!(
+            f,
+            "{}{} {} {} {} {} {}",
+            self.entry_type.as_char(),
+            self.modifiers,
+            quote_field(&self.path.to_string_lossy()),
+            self.mode.map_or_else(|| "-".to_string(), |m| m.to_string()),
+            dash(self.user()),
+            dash(self.group()),
+            self.age
+                .as_ref()
+                .map_or_else(|| "-".to_string(), |a| a.to_string()),
+        )?;
+        if let Some(argument) = &self.argument {
+            write!(f, " {}", argument)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (self.force, '+'),
+            (self.boot_only, '!'),
+            (self.ignore_errors, '-'),
+            (self.remove_invalid, '='),
+            (self.base64, '~'),
+            (self.credential, '^'),
+        ];
+        for (set, c) in flags {
+            if set {
+                write!(f, "{}", c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.only_on_create {
+            write!(f, ":")?;
+        }
+        if self.masked {
+            write!(f, "~")?;
+        }
+        write!(f, "{:04o}", self.bits)
+    }
+}
+
+impl Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.keep_first_level {
+            write!(f, "~")?;
+        }
+        if let Some(age_by) = &self.age_by {
+            write!(f, "{}:", age_by)?;
+        }
+        // Multi-unit spans are written without spaces, to keep a single field.
+        write!(f, "{}", format_timespan(self.span).replace(' ', ""))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_line_roundtrip() {
+        let cases = [
+            "d /run/user 0755 root root 1w3d",
+            "f+! \"/var/lib/with space\" :~0600 - - ~cm:1h foo  bar",
+            "L /etc/mtab - - - - ../proc/self/mounts",
+            "c /dev/null 0666 - - - 1:3",
+            "R- /var/tmp/*.old - - - -",
+            "w^ /proc/sys/kernel/hostname - - - - hostname",
+        ];
+        for input in cases {
+            let entry: TmpfilesEntry = input.parse().unwrap();
+            assert_eq!(entry.to_config_line(), input);
+        }
+
+        let mut entry = TmpfilesEntry::new(EntryType::CreateDirectory, "/run/foo").unwrap();
+        entry.set_mode(Some(Mode::new(0o1777).unwrap()));
+        entry.set_age(Some(Age::new(Duration::from_secs(10 * 86400))));
+        assert_eq!(entry.to_config_line(), "d /run/foo 1777 - - 1w3d");
+        assert_eq!(
+            entry.to_config_line().parse::<TmpfilesEntry>().unwrap(),
+            entry
+        );
+    }
+}
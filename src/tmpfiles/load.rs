@@ -0,0 +1,106 @@
+use super::*;
+use crate::conffiles::{conf_dirs, conf_files};
+use crate::errors::Context;
+use std::fs::File;
+use std::io::BufReader;
+
+/// Load the effective `tmpfiles.d` configuration below the given roots.
+///
+/// Roots are given in precedence order (use `["/"]` for the running
+/// system). For each of them, `etc/tmpfiles.d`, `run/tmpfiles.d`,
+/// `usr/local/lib/tmpfiles.d` and `usr/lib/tmpfiles.d` are scanned: files
+/// override same-named ones in lower-precedence directories, and files
+/// masked with a symlink to `/dev/null` are ignored.
+///
+/// Entries are merged in file-name order like `systemd-tmpfiles` does: a
+/// later entry which would take ownership of a path already claimed by a
+/// different entry is dropped with a warning, while entries adjusting
+/// existing paths (e.g. `z` or `a`) are always kept.
+pub fn load_config<P: AsRef<Path>>(roots: &[P]) -> Result<Vec<TmpfilesEntry>, SdError> {
+    let files = conf_files(&conf_dirs(roots, "tmpfiles.d"), ".conf")?;
+
+    let mut output: Vec<TmpfilesEntry> = vec![];
+    for path in files {
+        let file =
+            File::open(&path).with_context(|| format!("failed to open '{}'", path.display()))?;
+        let entries = TmpfilesEntry::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse '{}'", path.display()))?;
+        for entry in entries {
+            let conflict = output.iter().find(|other| {
+                other.path == entry.path
+                    && takes_ownership(other.entry_type)
+                    && takes_ownership(entry.entry_type)
+            });
+            match conflict {
+                Some(other) if *other == entry => {}
+                Some(_) => log::warn!(
+                    "{}: duplicate line for path '{}', ignoring",
+                    path.display(),
+                    entry.path.display()
+                ),
+                None => output.push(entry),
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Return whether entries of this type claim their path, so that only one of
+/// them may exist for each path.
+fn takes_ownership(entry_type: EntryType) -> bool {
+    !matches!(
+        entry_type,
+        EntryType::AdjustMode
+            | EntryType::AdjustModeRecursive
+            | EntryType::SetXattr
+            | EntryType::SetXattrRecursive
+            | EntryType::SetAttributes
+            | EntryType::SetAttributesRecursive
+            | EntryType::SetAcl
+            | EntryType::SetAclRecursive
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_load_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let (etc, usr) = (root.join("etc/tmpfiles.d"), root.join("usr/lib/tmpfiles.d"));
+        for dir in [&etc, &usr] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(
+            usr.join("10-base.conf"),
+            "d /run/base 0755\nz /run/base 0700\n",
+        )
+        .unwrap();
+        fs::write(usr.join("20-app.conf"), "d /run/app 0755\n").unwrap();
+        fs::write(etc.join("20-app.conf"), "d /run/app 0700\n").unwrap();
+        fs::write(usr.join("30-masked.conf"), "d /run/masked\n").unwrap();
+        symlink("/dev/null", etc.join("30-masked.conf")).unwrap();
+        fs::write(
+            etc.join("40-local.conf"),
+            "d /run/base 0777\na /run/base - - - - u:foo:r\n",
+        )
+        .unwrap();
+
+        let entries = load_config(&[&root]).unwrap();
+        let lines: Vec<_> = entries.iter().map(|e| e.to_config_line()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "d /run/base 0755 - - -",
+                "z /run/base 0700 - - -",
+                "d /run/app 0700 - - -",
+                "a /run/base - - - - u:foo:r",
+            ]
+        );
+    }
+}
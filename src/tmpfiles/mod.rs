@@ -0,0 +1,531 @@
+//! Helpers for working with `tmpfiles.d` configuration files.
+//!
+//! For the complete documentation see
+//! <https://www.freedesktop.org/software/systemd/man/tmpfiles.d.html>.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # fn doctest_parse() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::tmpfiles::{EntryType, TmpfilesEntry};
+//!
+//! let config_fragment = r#"
+//! #Type Path              Mode User Group Age Argument
+//! d     /run/user         0755 root root  10d -
+//! L     /tmp/foobar       -    -    -     -   /dev/null
+//! w     /proc/sys/vm/swappiness - - -     -   10
+//! R!    /var/tmp/*.old
+//! "#;
+//!
+//! let entries = TmpfilesEntry::parse_lines(config_fragment)?;
+//! assert_eq!(entries.len(), 4);
+//! assert_eq!(entries[0].entry_type(), EntryType::CreateDirectory);
+//! assert_eq!(entries[0].mode().map(|m| m.bits()), Some(0o755));
+//! assert_eq!(entries[1].argument(), Some("/dev/null"));
+//! assert!(entries[3].modifiers().boot_only());
+//! # Ok(())
+//! # }
+//! # doctest_parse().unwrap();
+//! ```
+
+use crate::errors::SdError;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+mod apply;
+mod format;
+mod load;
+mod parse;
+
+pub(crate) use apply::wildcard_match;
+pub use apply::{apply, ApplyOptions};
+pub use load::load_config;
+
+/// Type of a `tmpfiles.d` entry ("Type" field).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EntryType {
+    /// `f`: create a file, optionally writing the argument to it.
+    CreateFile,
+    /// `F`: create or truncate a file (deprecated, `f+` is preferred).
+    TruncateFile,
+    /// `w`: write the argument to an existing file.
+    WriteFile,
+    /// `d`: create a directory.
+    CreateDirectory,
+    /// `D`: create a directory, removing its contents at boot.
+    TruncateDirectory,
+    /// `e`: adjust an existing directory and clean it up.
+    EmptyDirectory,
+    /// `v`: create a btrfs subvolume, or a directory.
+    CreateSubvolume,
+    /// `q`: create a btrfs subvolume, inheriting the parent quota group.
+    CreateSubvolumeInheritQuota,
+    /// `Q`: create a btrfs subvolume with a new leaf quota group.
+    CreateSubvolumeNewQuota,
+    /// `p`: create a named pipe (FIFO).
+    CreateFifo,
+    /// `L`: create a symlink.
+    CreateSymlink,
+    /// `c`: create a character device node.
+    CreateCharDevice,
+    /// `b`: create a block device node.
+    CreateBlockDevice,
+    /// `C`: recursively copy a file or directory, unless the destination
+    /// exists and is not an empty directory (`C+` merges into it instead).
+    Copy,
+    /// `x`: ignore a path and its contents during cleanup.
+    Ignore,
+    /// `X`: ignore a path, but not its contents, during cleanup.
+    IgnorePath,
+    /// `r`: remove a file or an empty directory.
+    Remove,
+    /// `R`: recursively remove a path.
+    RemoveRecursive,
+    /// `z`: adjust mode and ownership.
+    AdjustMode,
+    /// `Z`: recursively adjust mode and ownership.
+    AdjustModeRecursive,
+    /// `t`: set extended attributes.
+    SetXattr,
+    /// `T`: recursively set extended attributes.
+    SetXattrRecursive,
+    /// `h`: set file attributes.
+    SetAttributes,
+    /// `H`: recursively set file attributes.
+    SetAttributesRecursive,
+    /// `a`: set POSIX ACLs.
+    SetAcl,
+    /// `A`: recursively set POSIX ACLs.
+    SetAclRecursive,
+}
+
+impl EntryType {
+    /// Return the type for the given "Type" character.
+    pub fn from_char(c: char) -> Option<Self> {
+        let kind = match c {
+            'f' => EntryType::CreateFile,
+            'F' => EntryType::TruncateFile,
+            'w' => EntryType::WriteFile,
+            'd' => EntryType::CreateDirectory,
+            'D' => EntryType::TruncateDirectory,
+            'e' => EntryType::EmptyDirectory,
+            'v' => EntryType::CreateSubvolume,
+            'q' => EntryType::CreateSubvolumeInheritQuota,
+            'Q' => EntryType::CreateSubvolumeNewQuota,
+            'p' => EntryType::CreateFifo,
+            'L' => EntryType::CreateSymlink,
+            'c' => EntryType::CreateCharDevice,
+            'b' => EntryType::CreateBlockDevice,
+            'C' => EntryType::Copy,
+            'x' => EntryType::Ignore,
+            'X' => EntryType::IgnorePath,
+            'r' => EntryType::Remove,
+            'R' => EntryType::RemoveRecursive,
+            'z' => EntryType::AdjustMode,
+            'Z' => EntryType::AdjustModeRecursive,
+            't' => EntryType::SetXattr,
+            'T' => EntryType::SetXattrRecursive,
+            'h' => EntryType::SetAttributes,
+            'H' => EntryType::SetAttributesRecursive,
+            'a' => EntryType::SetAcl,
+            'A' => EntryType::SetAclRecursive,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// Return the character for the "Type" field of this entry type.
+    pub fn as_char(&self) -> char {
+        match self {
+            EntryType::CreateFile => 'f',
+            EntryType::TruncateFile => 'F',
+            EntryType::WriteFile => 'w',
+            EntryType::CreateDirectory => 'd',
+            EntryType::TruncateDirectory => 'D',
+            EntryType::EmptyDirectory => 'e',
+            EntryType::CreateSubvolume => 'v',
+            EntryType::CreateSubvolumeInheritQuota => 'q',
+            EntryType::CreateSubvolumeNewQuota => 'Q',
+            EntryType::CreateFifo => 'p',
+            EntryType::CreateSymlink => 'L',
+            EntryType::CreateCharDevice => 'c',
+            EntryType::CreateBlockDevice => 'b',
+            EntryType::Copy => 'C',
+            EntryType::Ignore => 'x',
+            EntryType::IgnorePath => 'X',
+            EntryType::Remove => 'r',
+            EntryType::RemoveRecursive => 'R',
+            EntryType::AdjustMode => 'z',
+            EntryType::AdjustModeRecursive => 'Z',
+            EntryType::SetXattr => 't',
+            EntryType::SetXattrRecursive => 'T',
+            EntryType::SetAttributes => 'h',
+            EntryType::SetAttributesRecursive => 'H',
+            EntryType::SetAcl => 'a',
+            EntryType::SetAclRecursive => 'A',
+        }
+    }
+
+    /// Return whether entries of this type require an argument.
+    pub fn requires_argument(&self) -> bool {
+        matches!(
+            self,
+            EntryType::WriteFile
+                | EntryType::CreateCharDevice
+                | EntryType::CreateBlockDevice
+                | EntryType::SetXattr
+                | EntryType::SetXattrRecursive
+                | EntryType::SetAttributes
+                | EntryType::SetAttributesRecursive
+                | EntryType::SetAcl
+                | EntryType::SetAclRecursive
+        )
+    }
+}
+
+/// Modifiers following the type character of an entry.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Modifiers {
+    pub(crate) force: bool,
+    pub(crate) boot_only: bool,
+    pub(crate) ignore_errors: bool,
+    pub(crate) remove_invalid: bool,
+    pub(crate) base64: bool,
+    pub(crate) credential: bool,
+}
+
+impl Modifiers {
+    /// Return whether the `+` modifier is set (append, force or truncate,
+    /// depending on the entry type).
+    pub fn force(&self) -> bool {
+        self.force
+    }
+
+    /// Return whether the `!` modifier is set (only apply at boot).
+    pub fn boot_only(&self) -> bool {
+        self.boot_only
+    }
+
+    /// Return whether the `-` modifier is set (ignore creation errors).
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Return whether the `=` modifier is set (remove mismatching objects).
+    pub fn remove_invalid(&self) -> bool {
+        self.remove_invalid
+    }
+
+    /// Return whether the `~` modifier is set (base64-encoded argument).
+    pub fn base64(&self) -> bool {
+        self.base64
+    }
+
+    /// Return whether the `^` modifier is set (argument names a credential).
+    pub fn credential(&self) -> bool {
+        self.credential
+    }
+
+    /// Set the `+` modifier.
+    pub fn set_force(&mut self, value: bool) {
+        self.force = value;
+    }
+
+    /// Set the `!` modifier.
+    pub fn set_boot_only(&mut self, value: bool) {
+        self.boot_only = value;
+    }
+
+    /// Set the `-` modifier.
+    pub fn set_ignore_errors(&mut self, value: bool) {
+        self.ignore_errors = value;
+    }
+
+    /// Set the `=` modifier.
+    pub fn set_remove_invalid(&mut self, value: bool) {
+        self.remove_invalid = value;
+    }
+
+    /// Set the `~` modifier.
+    pub fn set_base64(&mut self, value: bool) {
+        self.base64 = value;
+    }
+
+    /// Set the `^` modifier.
+    pub fn set_credential(&mut self, value: bool) {
+        self.credential = value;
+    }
+}
+
+/// Access mode of an entry ("Mode" field).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Mode {
+    pub(crate) bits: u32,
+    pub(crate) masked: bool,
+    pub(crate) only_on_create: bool,
+}
+
+impl Mode {
+    /// Create a new mode from permission bits (up to `0o7777`).
+    pub fn new(bits: u32) -> Result<Self, SdError> {
+        if bits > 0o7777 {
+            return Err(format!("invalid mode '{:o}'", bits).into());
+        }
+        Ok(Self {
+            bits,
+            masked: false,
+            only_on_create: false,
+        })
+    }
+
+    /// Return the permission bits.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Return whether the mode is masked by the existing one (`~` prefix).
+    pub fn masked(&self) -> bool {
+        self.masked
+    }
+
+    /// Return whether the mode is only applied on creation (`:` prefix).
+    pub fn only_on_create(&self) -> bool {
+        self.only_on_create
+    }
+
+    /// Set whether the mode is masked by the existing one (`~` prefix).
+    pub fn set_masked(&mut self, value: bool) {
+        self.masked = value;
+    }
+
+    /// Set whether the mode is only applied on creation (`:` prefix).
+    pub fn set_only_on_create(&mut self, value: bool) {
+        self.only_on_create = value;
+    }
+}
+
+/// Cleanup age of an entry ("Age" field).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Age {
+    pub(crate) span: Duration,
+    pub(crate) keep_first_level: bool,
+    pub(crate) age_by: Option<String>,
+}
+
+impl Age {
+    /// Create a new age from a time span.
+    pub fn new(span: Duration) -> Self {
+        Self {
+            span,
+            keep_first_level: false,
+            age_by: None,
+        }
+    }
+
+    /// Return the time span after which files are cleaned up.
+    pub fn span(&self) -> Duration {
+        self.span
+    }
+
+    /// Return whether immediate children of the path are spared (`~` prefix).
+    pub fn keep_first_level(&self) -> bool {
+        self.keep_first_level
+    }
+
+    /// Return the timestamps considered for aging (e.g. `"cm"`), if set.
+    ///
+    /// Each character is one of `a` (access), `b` (birth), `c` (change) or
+    /// `m` (modification); uppercase variants apply to directories.
+    pub fn age_by(&self) -> Option<&str> {
+        self.age_by.as_deref()
+    }
+
+    /// Set whether immediate children of the path are spared (`~` prefix).
+    pub fn set_keep_first_level(&mut self, value: bool) {
+        self.keep_first_level = value;
+    }
+
+    /// Set the timestamps considered for aging.
+    pub fn set_age_by(&mut self, age_by: Option<String>) -> Result<(), SdError> {
+        if let Some(value) = &age_by {
+            validate_age_by(value)?;
+        }
+        self.age_by = age_by;
+        Ok(())
+    }
+}
+
+/// Single entry in `tmpfiles.d` configuration format.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TmpfilesEntry {
+    pub(crate) entry_type: EntryType,
+    pub(crate) modifiers: Modifiers,
+    pub(crate) path: PathBuf,
+    pub(crate) mode: Option<Mode>,
+    pub(crate) user: Option<String>,
+    pub(crate) group: Option<String>,
+    pub(crate) age: Option<Age>,
+    pub(crate) argument: Option<String>,
+}
+
+impl TmpfilesEntry {
+    /// Create a new entry for an absolute path, with all other fields unset.
+    ///
+    /// The path may also start with a specifier (e.g. `%h/.cache`).
+    pub fn new(entry_type: EntryType, path: impl Into<PathBuf>) -> Result<Self, SdError> {
+        let path = path.into();
+        validate_path(&path)?;
+        Ok(Self {
+            entry_type,
+            modifiers: Modifiers::default(),
+            path,
+            mode: None,
+            user: None,
+            group: None,
+            age: None,
+            argument: None,
+        })
+    }
+
+    /// Return the type of this entry.
+    pub fn entry_type(&self) -> EntryType {
+        self.entry_type
+    }
+
+    /// Return the modifiers of this entry.
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// Return the path ("Path" field) of this entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the mode ("Mode" field) of this entry, if set.
+    pub fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
+
+    /// Return the user ("User" field) of this entry, if set.
+    ///
+    /// This is either a user name or a numeric UID, possibly prefixed by `:`
+    /// when only applied on creation.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Return the group ("Group" field) of this entry, if set.
+    ///
+    /// This is either a group name or a numeric GID, possibly prefixed by `:`
+    /// when only applied on creation.
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Return the cleanup age ("Age" field) of this entry, if set.
+    pub fn age(&self) -> Option<&Age> {
+        self.age.as_ref()
+    }
+
+    /// Return the argument ("Argument" field) of this entry, if set.
+    ///
+    /// For `f`, `F` and `w` entries, C-style escapes (e.g. `\x20`) are kept
+    /// as-is here and resolved when writing the content, unless the argument
+    /// is base64-encoded.
+    pub fn argument(&self) -> Option<&str> {
+        self.argument.as_deref()
+    }
+
+    /// Set the modifiers of this entry.
+    pub fn set_modifiers(&mut self, modifiers: Modifiers) {
+        self.modifiers = modifiers;
+    }
+
+    /// Set the mode of this entry.
+    pub fn set_mode(&mut self, mode: Option<Mode>) {
+        self.mode = mode;
+    }
+
+    /// Set the user of this entry.
+    pub fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
+    /// Set the group of this entry.
+    pub fn set_group(&mut self, group: Option<String>) {
+        self.group = group;
+    }
+
+    /// Set the cleanup age of this entry.
+    pub fn set_age(&mut self, age: Option<Age>) {
+        self.age = age;
+    }
+
+    /// Set the argument of this entry.
+    pub fn set_argument(&mut self, argument: Option<String>) {
+        self.argument = argument;
+    }
+
+    /// Check the consistency of this entry, like `systemd-tmpfiles` does
+    /// when loading it.
+    pub fn validate(&self) -> Result<(), SdError> {
+        validate_path(&self.path)?;
+        let type_char = self.entry_type.as_char();
+        if self.entry_type.requires_argument() && self.argument.is_none() {
+            return Err(format!("entry of type '{}' requires an argument", type_char).into());
+        }
+        if matches!(
+            self.entry_type,
+            EntryType::CreateCharDevice | EntryType::CreateBlockDevice
+        ) {
+            let argument = self.argument.as_deref().unwrap_or_default();
+            parse_device_numbers(argument)?;
+        }
+        if self.modifiers.force
+            && !matches!(
+                self.entry_type,
+                EntryType::CreateFile
+                    | EntryType::TruncateFile
+                    | EntryType::WriteFile
+                    | EntryType::CreateFifo
+                    | EntryType::CreateSymlink
+                    | EntryType::CreateCharDevice
+                    | EntryType::CreateBlockDevice
+                    | EntryType::Copy
+                    | EntryType::SetAcl
+                    | EntryType::SetAclRecursive
+            )
+        {
+            return Err(format!("'+' modifier not supported for type '{}'", type_char).into());
+        }
+        Ok(())
+    }
+}
+
+/// Parse the `major:minor` argument of a device node entry.
+pub(crate) fn parse_device_numbers(argument: &str) -> Result<(u32, u32), SdError> {
+    let invalid = || SdError::from(format!("invalid device numbers '{}'", argument));
+    let (major, minor) = argument.split_once(':').ok_or_else(invalid)?;
+    let major = major.parse().map_err(|_| invalid())?;
+    let minor = minor.parse().map_err(|_| invalid())?;
+    Ok((major, minor))
+}
+
+fn validate_path(path: &Path) -> Result<(), SdError> {
+    let valid = match path.to_str() {
+        Some(p) => p.starts_with('/') || p.starts_with('%'),
+        None => path.is_absolute(),
+    };
+    if !valid {
+        return Err(format!("path '{}' is not absolute", path.display()).into());
+    }
+    Ok(())
+}
+
+fn validate_age_by(value: &str) -> Result<(), SdError> {
+    if value.is_empty() || !value.chars().all(|c| "abcmABCM".contains(c)) {
+        return Err(format!("invalid age-by specifier '{}'", value).into());
+    }
+    Ok(())
+}
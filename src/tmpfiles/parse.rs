@@ -0,0 +1,257 @@
+use super::*;
+use crate::errors::ErrorKind;
+use crate::unit::file::split_words;
+use crate::unit::parse_timespan;
+use std::io::BufRead;
+use std::str::FromStr;
+
+impl TmpfilesEntry {
+    /// Parse all entries from the content of a `tmpfiles.d` file.
+    ///
+    /// Blank lines and comments are skipped, as well as lines with unknown
+    /// entry types (with a warning).
+    pub fn parse_lines(input: &str) -> Result<Vec<TmpfilesEntry>, SdError> {
+        Self::from_reader(input.as_bytes())
+    }
+
+    /// Parse all entries of a `tmpfiles.d` file from a buffered reader.
+    pub fn from_reader(reader: impl BufRead) -> Result<Vec<TmpfilesEntry>, SdError> {
+        let mut output = vec![];
+        for (index, item) in reader.lines().enumerate() {
+            let linenumber = index.saturating_add(1);
+            let line = item.map_err(|e| format!("failed to read line {}: {}", linenumber, e))?;
+
+            let data = line.trim();
+            // Skip empty lines and comments.
+            if data.is_empty() || data.starts_with('#') {
+                continue;
+            }
+
+            match data.parse() {
+                Ok(entry) => output.push(entry),
+                Err(SdError {
+                    kind: ErrorKind::TmpfilesUnknownType,
+                    msg,
+                }) => {
+                    log::warn!("skipped line {}: {}", linenumber, msg);
+                }
+                Err(e) => {
+                    let msg = format!(
+                        "failed to parse tmpfiles entry at line {}: {}",
+                        linenumber, e.msg
+                    );
+                    return Err(msg.into());
+                }
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+impl FromStr for TmpfilesEntry {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s.trim();
+        let (type_field, rest) = next_field(rest)?.ok_or("missing tmpfiles type")?;
+        let (path, rest) = next_field(rest)?.ok_or("missing tmpfiles path")?;
+        let (mode, rest) = next_field(rest)?.unwrap_or_default();
+        let (user, rest) = next_field(rest)?.unwrap_or_default();
+        let (group, rest) = next_field(rest)?.unwrap_or_default();
+        let (age, rest) = next_field(rest)?.unwrap_or_default();
+        let argument = rest.trim();
+
+        let mut chars = type_field.chars();
+        let type_char = chars.next().ok_or("missing tmpfiles type")?;
+        let entry_type = EntryType::from_char(type_char).ok_or_else(|| SdError {
+            kind: ErrorKind::TmpfilesUnknownType,
+            msg: format!("unknown tmpfiles type '{}'", type_char),
+        })?;
+
+        let mut entry = TmpfilesEntry::new(entry_type, path)?;
+        entry.modifiers = chars.as_str().parse()?;
+        entry.mode = parse_dash(&mode).map(str::parse).transpose()?;
+        entry.user = parse_dash(&user).map(String::from);
+        entry.group = parse_dash(&group).map(String::from);
+        entry.age = parse_dash(&age).map(str::parse).transpose()?;
+        entry.argument = parse_dash(argument).map(String::from);
+        entry.validate()?;
+
+        Ok(entry)
+    }
+}
+
+impl FromStr for Modifiers {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::default();
+        for c in s.chars() {
+            let flag = match c {
+                '+' => &mut modifiers.force,
+                '!' => &mut modifiers.boot_only,
+                '-' => &mut modifiers.ignore_errors,
+                '=' => &mut modifiers.remove_invalid,
+                '~' => &mut modifiers.base64,
+                '^' => &mut modifiers.credential,
+                _ => return Err(format!("unknown tmpfiles modifier '{}'", c).into()),
+            };
+            if *flag {
+                return Err(format!("duplicate tmpfiles modifier '{}'", c).into());
+            }
+            *flag = true;
+        }
+        Ok(modifiers)
+    }
+}
+
+impl FromStr for Mode {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (only_on_create, rest) = match s.strip_prefix(':') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (masked, rest) = match rest.strip_prefix('~') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let bits = u32::from_str_radix(rest, 8).map_err(|_| format!("invalid mode '{}'", s))?;
+        let mut mode = Mode::new(bits)?;
+        mode.masked = masked;
+        mode.only_on_create = only_on_create;
+        Ok(mode)
+    }
+}
+
+impl FromStr for Age {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (keep_first_level, rest) = match s.strip_prefix('~') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (age_by, span) = match rest.split_once(':') {
+            Some((age_by, span)) => {
+                validate_age_by(age_by)?;
+                (Some(age_by.to_string()), span)
+            }
+            None => (None, rest),
+        };
+        let span = parse_timespan(span).map_err(|e| format!("invalid age '{}': {}", s, e.msg))?;
+        Ok(Age {
+            span,
+            keep_first_level,
+            age_by,
+        })
+    }
+}
+
+/// Return `None` for empty values and the `-` placeholder.
+fn parse_dash(value: &str) -> Option<&str> {
+    match value {
+        "" | "-" => None,
+        value => Some(value),
+    }
+}
+
+/// Extract the next whitespace-separated field, which may be quoted.
+fn next_field(input: &str) -> Result<Option<(String, &str)>, SdError> {
+    let input = input.trim_start();
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut end = input.len();
+    for (index, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+        } else if c.is_whitespace() {
+            end = index;
+            break;
+        }
+    }
+
+    let field = split_words(&input[..end])?.concat();
+    Ok(Some((field, &input[end..])))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry() {
+        let entry: TmpfilesEntry = "d /run/user 0755 root root 10d -".parse().unwrap();
+        assert_eq!(entry.entry_type(), EntryType::CreateDirectory);
+        assert_eq!(entry.path(), Path::new("/run/user"));
+        assert_eq!(entry.mode(), Some(Mode::new(0o755).unwrap()));
+        assert_eq!(entry.user(), Some("root"));
+        assert_eq!(entry.group(), Some("root"));
+        assert_eq!(entry.age(), Some(&Age::new(Duration::from_secs(864_000))));
+        assert_eq!(entry.argument(), None);
+
+        let entry: TmpfilesEntry = "f+! \"/var/lib/with space\" :~0600 - - ~cm:1h foo  bar"
+            .parse()
+            .unwrap();
+        assert!(entry.modifiers().force());
+        assert!(entry.modifiers().boot_only());
+        assert_eq!(entry.path(), Path::new("/var/lib/with space"));
+        let mode = entry.mode().unwrap();
+        assert!(mode.masked() && mode.only_on_create());
+        assert_eq!(entry.user(), None);
+        let age = entry.age().unwrap();
+        assert!(age.keep_first_level());
+        assert_eq!(age.age_by(), Some("cm"));
+        assert_eq!(age.span(), Duration::from_secs(3600));
+        assert_eq!(entry.argument(), Some("foo  bar"));
+
+        let entry: TmpfilesEntry = "c /dev/null 0666 - - - 1:3".parse().unwrap();
+        assert_eq!(entry.entry_type(), EntryType::CreateCharDevice);
+
+        let err_cases = [
+            "d relative/path",
+            "d /foo 9999",
+            "d /foo - - - 1parsec",
+            "d /foo - - - zz:1h",
+            "d+ /foo",
+            "f? /foo",
+            "w /foo",
+            "c /dev/null 0666 - - - 1",
+        ];
+        for input in err_cases {
+            input.parse::<TmpfilesEntry>().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let config_fragment = r#"
+# Comment, followed by a blank line
+
+d /run/lock 0755 root root -
+L+ /etc/mtab - - - - ../proc/self/mounts
+Y /unknown/type
+x /tmp/systemd-private-*
+"#;
+        let entries = TmpfilesEntry::parse_lines(config_fragment).unwrap();
+        let types: Vec<_> = entries.iter().map(|e| e.entry_type().as_char()).collect();
+        assert_eq!(types, vec!['d', 'L', 'x']);
+        assert_eq!(entries[1].argument(), Some("../proc/self/mounts"));
+
+        TmpfilesEntry::parse_lines("d /foo 0755\nd bar").unwrap_err();
+    }
+}
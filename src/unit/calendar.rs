@@ -0,0 +1,782 @@
+use super::datetime::{days_in_month, DateTime, USEC_PER_SEC, WEEKDAYS};
+use crate::errors::SdError;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Range of years supported by calendar specifications.
+const MIN_YEAR: i64 = 1970;
+const MAX_YEAR: i64 = 2199;
+
+const ALL_WEEKDAYS: u8 = 0b111_1111;
+
+/// Calendar event expression, as used by `OnCalendar=` in timer units.
+///
+/// This follows the syntax described in `systemd.time(7)`, and its
+/// `Display` implementation produces the same normalized form as
+/// `systemd-analyze calendar`:
+///
+/// ```
+/// # use libsystemd::unit::CalendarSpec;
+/// let spec = CalendarSpec::parse("Fri,Mon..Wed 10:00").unwrap();
+/// assert_eq!(spec.to_string(), "Mon..Wed,Fri *-*-* 10:00:00");
+/// ```
+///
+/// Timezones other than `UTC` are not supported, events without an explicit
+/// timezone are evaluated in local time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalendarSpec {
+    /// Bitmask of weekdays, with Monday as the lowest bit.
+    weekdays: u8,
+    year: Vec<Component>,
+    month: Vec<Component>,
+    day: Vec<Component>,
+    hour: Vec<Component>,
+    minute: Vec<Component>,
+    /// Seconds, in microseconds.
+    second: Vec<Component>,
+    end_of_month: bool,
+    utc: bool,
+}
+
+/// Single item of a comma-separated list: `start[..stop][/repeat]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Component {
+    start: i64,
+    stop: Option<i64>,
+    repeat: i64,
+}
+
+impl Component {
+    fn matches(&self, value: i64) -> bool {
+        if value < self.start || self.stop.map_or(false, |stop| value > stop) {
+            return false;
+        }
+        if self.repeat > 0 {
+            (value - self.start) % self.repeat == 0
+        } else {
+            self.stop.is_some() || value == self.start
+        }
+    }
+
+    /// Smallest matching value which is not lower than `value`.
+    fn next(&self, value: i64) -> Option<i64> {
+        if value <= self.start {
+            return Some(self.start);
+        }
+        let next = if self.repeat > 0 {
+            let steps = (value - self.start)
+                .checked_add(self.repeat - 1)?
+                .checked_div(self.repeat)?;
+            steps
+                .checked_mul(self.repeat)
+                .and_then(|offset| offset.checked_add(self.start))?
+        } else if self.stop.is_some() {
+            value
+        } else {
+            return None;
+        };
+        match self.stop {
+            Some(stop) if next > stop => None,
+            _ => Some(next),
+        }
+    }
+
+    /// Map a day relative to the end of the month (`~`) to a day of the month.
+    fn relative_to_end(&self, days: i64) -> Self {
+        let start = days + 1 - self.start;
+        match self.stop {
+            Some(stop) => Self {
+                start: days + 1 - stop,
+                stop: Some(start),
+                repeat: self.repeat,
+            },
+            None => Self {
+                start,
+                stop: None,
+                repeat: self.repeat,
+            },
+        }
+    }
+}
+
+fn chain_matches(chain: &[Component], value: i64) -> bool {
+    chain.is_empty() || chain.iter().any(|c| c.matches(value))
+}
+
+fn chain_next(chain: &[Component], value: i64) -> Option<i64> {
+    if chain.is_empty() {
+        return Some(value);
+    }
+    chain.iter().filter_map(|c| c.next(value)).min()
+}
+
+impl CalendarSpec {
+    /// Parse a calendar event expression, e.g. `Mon..Fri *-*-* 10:00:00`.
+    ///
+    /// Shorthands like `daily` or `weekly`, and `@` followed by a UNIX
+    /// timestamp, are also accepted.
+    pub fn parse(input: &str) -> Result<Self, SdError> {
+        Self::parse_inner(input)
+            .map_err(|e| format!("invalid calendar specification '{}': {}", input, e.msg).into())
+    }
+
+    fn parse_inner(input: &str) -> Result<Self, SdError> {
+        let mut words: Vec<&str> = input.split_whitespace().collect();
+        let utc = match words.last() {
+            Some(last) if last.eq_ignore_ascii_case("UTC") => {
+                words.pop();
+                true
+            }
+            _ => false,
+        };
+
+        let expanded;
+        match words.as_slice() {
+            [] => return Err("empty expression".into()),
+            [word] if word.starts_with('@') => return Self::from_timestamp(&word[1..]),
+            [word] => {
+                if let Some(shorthand) = expand_shorthand(word) {
+                    expanded = shorthand;
+                    words = expanded.split(' ').collect();
+                }
+            }
+            _ => {}
+        }
+
+        let mut spec = Self {
+            weekdays: ALL_WEEKDAYS,
+            year: vec![],
+            month: vec![],
+            day: vec![],
+            hour: vec![],
+            minute: vec![],
+            second: vec![Component {
+                start: 0,
+                stop: None,
+                repeat: 0,
+            }],
+            end_of_month: false,
+            utc,
+        };
+        let mut words = words.into_iter().peekable();
+
+        if let Some(word) = words.next_if(|w| w.starts_with(|c: char| c.is_ascii_alphabetic())) {
+            spec.weekdays = parse_weekdays(word)?;
+        }
+        if let Some(word) = words.next_if(|w| !w.contains(':')) {
+            spec.parse_date(word)?;
+        }
+        match words.next() {
+            Some(word) => spec.parse_time(word)?,
+            None => {
+                let zero = vec![Component {
+                    start: 0,
+                    stop: None,
+                    repeat: 0,
+                }];
+                spec.hour = zero.clone();
+                spec.minute = zero;
+            }
+        }
+        if let Some(word) = words.next() {
+            return Err(format!("unexpected '{}'", word).into());
+        }
+
+        spec.normalize();
+        Ok(spec)
+    }
+
+    /// Build a spec matching a single point in time, from `@<seconds>`.
+    fn from_timestamp(value: &str) -> Result<Self, SdError> {
+        let secs: i64 = value
+            .parse()
+            .map_err(|_| format!("invalid timestamp '{}'", value))?;
+        let dt = secs
+            .checked_mul(USEC_PER_SEC)
+            .and_then(|usec| DateTime::from_unix(usec, true))
+            .filter(|dt| (MIN_YEAR..=MAX_YEAR).contains(&dt.year))
+            .ok_or_else(|| format!("timestamp '{}' out of range", value))?;
+        let single = |start| {
+            vec![Component {
+                start,
+                stop: None,
+                repeat: 0,
+            }]
+        };
+        Ok(Self {
+            weekdays: ALL_WEEKDAYS,
+            year: single(dt.year),
+            month: single(dt.month),
+            day: single(dt.day),
+            hour: single(dt.hour),
+            minute: single(dt.minute),
+            second: single(dt.second * USEC_PER_SEC),
+            end_of_month: false,
+            utc: true,
+        })
+    }
+
+    /// Parse `[year-]month-day`, with `~` as an alternative day separator.
+    fn parse_date(&mut self, word: &str) -> Result<(), SdError> {
+        let (date, day) = match word.rsplit_once('~') {
+            Some((date, day)) => {
+                self.end_of_month = true;
+                (date, day)
+            }
+            None => word
+                .rsplit_once('-')
+                .ok_or_else(|| format!("invalid date '{}'", word))?,
+        };
+        let (year, month) = match date.split_once('-') {
+            Some((year, month)) => (Some(year), month),
+            None => (None, date),
+        };
+        if let Some(year) = year {
+            self.year = parse_chain(year, MIN_YEAR, MAX_YEAR, ValueKind::Year)?;
+        }
+        self.month = parse_chain(month, 1, 12, ValueKind::Integer)?;
+        self.day = parse_chain(day, 1, 31, ValueKind::Integer)?;
+        Ok(())
+    }
+
+    /// Parse `hour:minute[:second]`.
+    fn parse_time(&mut self, word: &str) -> Result<(), SdError> {
+        let mut fields = word.split(':');
+        let (hour, minute, second) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(h), Some(m), s) if fields.next().is_none() => (h, m, s),
+            _ => return Err(format!("invalid time '{}'", word).into()),
+        };
+        self.hour = parse_chain(hour, 0, 23, ValueKind::Integer)?;
+        self.minute = parse_chain(minute, 0, 59, ValueKind::Integer)?;
+        if let Some(second) = second {
+            self.second = parse_chain(second, 0, 60 * USEC_PER_SEC - 1, ValueKind::Seconds)?;
+        }
+        Ok(())
+    }
+
+    /// Sort and deduplicate components, like systemd does.
+    fn normalize(&mut self) {
+        for chain in [
+            &mut self.year,
+            &mut self.month,
+            &mut self.day,
+            &mut self.hour,
+            &mut self.minute,
+            &mut self.second,
+        ] {
+            chain.sort();
+            chain.dedup();
+        }
+    }
+
+    /// Whether the expression is evaluated in UTC, rather than local time.
+    pub fn is_utc(&self) -> bool {
+        self.utc
+    }
+
+    /// Compute the first time after `after` which matches this expression.
+    ///
+    /// Returns `None` if the expression never elapses again. This can be
+    /// called repeatedly with the previous result to iterate over elapses.
+    pub fn next_elapse(&self, after: SystemTime) -> Option<SystemTime> {
+        let start = match after.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => i64::try_from(elapsed.as_micros()).ok()?.checked_add(1)?,
+            Err(_) => 0,
+        };
+        let mut dt = DateTime::from_unix(start, self.utc)?;
+        let usec = self.find_next(&mut dt, start)?;
+        let usec = u64::try_from(usec).ok()?;
+        Some(UNIX_EPOCH + Duration::from_micros(usec))
+    }
+
+    fn find_next(&self, dt: &mut DateTime, start: i64) -> Option<i64> {
+        // Seconds are matched together with microseconds.
+        let mut usec = dt.second * USEC_PER_SEC + dt.usec;
+        loop {
+            if dt.year > MAX_YEAR {
+                return None;
+            }
+            if !chain_matches(&self.year, dt.year) {
+                let year = chain_next(&self.year, dt.year)?;
+                *dt = DateTime::date(year, 1, 1);
+                usec = 0;
+                continue;
+            }
+
+            if dt.month > 12 {
+                *dt = DateTime::date(dt.year + 1, 1, 1);
+                usec = 0;
+                continue;
+            }
+            if !chain_matches(&self.month, dt.month) {
+                *dt = match chain_next(&self.month, dt.month) {
+                    Some(month) if month <= 12 => DateTime::date(dt.year, month, 1),
+                    _ => DateTime::date(dt.year + 1, 1, 1),
+                };
+                usec = 0;
+                continue;
+            }
+
+            let days = days_in_month(dt.year, dt.month);
+            if dt.day > days {
+                *dt = DateTime::date(dt.year, dt.month + 1, 1);
+                usec = 0;
+                continue;
+            }
+            if !self.day_matches(dt, days) {
+                *dt = DateTime::date(dt.year, dt.month, dt.day + 1);
+                usec = 0;
+                continue;
+            }
+
+            if dt.hour > 23 {
+                *dt = DateTime::date(dt.year, dt.month, dt.day + 1);
+                usec = 0;
+                continue;
+            }
+            if !chain_matches(&self.hour, dt.hour) {
+                match chain_next(&self.hour, dt.hour) {
+                    Some(hour) if hour <= 23 => dt.hour = hour,
+                    _ => dt.hour = 24,
+                }
+                dt.minute = 0;
+                usec = 0;
+                continue;
+            }
+
+            if dt.minute > 59 {
+                dt.hour += 1;
+                dt.minute = 0;
+                usec = 0;
+                continue;
+            }
+            if !chain_matches(&self.minute, dt.minute) {
+                match chain_next(&self.minute, dt.minute) {
+                    Some(minute) if minute <= 59 => dt.minute = minute,
+                    _ => dt.minute = 60,
+                }
+                usec = 0;
+                continue;
+            }
+
+            // A wildcard matches at the start of each second.
+            let next = if self.second.is_empty() {
+                Some((usec + USEC_PER_SEC - 1) / USEC_PER_SEC * USEC_PER_SEC)
+            } else {
+                chain_next(&self.second, usec)
+            };
+            match next {
+                Some(next) if next < 60 * USEC_PER_SEC => usec = next,
+                _ => {
+                    dt.minute += 1;
+                    usec = 0;
+                    continue;
+                }
+            }
+
+            dt.second = usec / USEC_PER_SEC;
+            dt.usec = usec % USEC_PER_SEC;
+            match dt.to_unix(self.utc) {
+                Some(result) if result >= start => return Some(result),
+                _ => {
+                    dt.minute += 1;
+                    usec = 0;
+                }
+            }
+        }
+    }
+
+    fn day_matches(&self, dt: &DateTime, days: i64) -> bool {
+        if self.weekdays & (1 << dt.weekday()) == 0 {
+            return false;
+        }
+        if self.end_of_month {
+            self.day
+                .iter()
+                .any(|c| c.relative_to_end(days).matches(dt.day))
+        } else {
+            chain_matches(&self.day, dt.day)
+        }
+    }
+}
+
+impl FromStr for CalendarSpec {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for CalendarSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.weekdays != ALL_WEEKDAYS {
+            format_weekdays(f, self.weekdays)?;
+            f.write_str(" ")?;
+        }
+        format_chain(f, &self.year, 4, false)?;
+        f.write_str("-")?;
+        format_chain(f, &self.month, 2, false)?;
+        f.write_str(if self.end_of_month { "~" } else { "-" })?;
+        format_chain(f, &self.day, 2, false)?;
+        f.write_str(" ")?;
+        format_chain(f, &self.hour, 2, false)?;
+        f.write_str(":")?;
+        format_chain(f, &self.minute, 2, false)?;
+        f.write_str(":")?;
+        format_chain(f, &self.second, 2, true)?;
+        if self.utc {
+            f.write_str(" UTC")?;
+        }
+        Ok(())
+    }
+}
+
+/// Expand shorthand expressions, like `daily`.
+fn expand_shorthand(word: &str) -> Option<&'static str> {
+    let expanded = match word.to_ascii_lowercase().as_str() {
+        "minutely" => "*-*-* *:*:00",
+        "hourly" => "*-*-* *:00:00",
+        "daily" => "*-*-* 00:00:00",
+        "weekly" => "Mon *-*-* 00:00:00",
+        "monthly" => "*-*-01 00:00:00",
+        "quarterly" => "*-01,04,07,10-01 00:00:00",
+        "semiannually" => "*-01,07-01 00:00:00",
+        "yearly" | "annually" => "*-01-01 00:00:00",
+        _ => return None,
+    };
+    Some(expanded)
+}
+
+fn parse_weekday(name: &str) -> Result<u32, SdError> {
+    let lower = name.to_ascii_lowercase();
+    WEEKDAYS
+        .iter()
+        .position(|(short, long)| lower == short.to_ascii_lowercase() || lower == *long)
+        .map(|index| index as u32)
+        .ok_or_else(|| format!("invalid weekday '{}'", name).into())
+}
+
+/// Parse a list of weekdays and ranges, e.g. `Mon..Wed,Fri`.
+fn parse_weekdays(word: &str) -> Result<u8, SdError> {
+    let mut mask = 0u8;
+    for item in word.split(',') {
+        // Single dashes are an older syntax for ranges.
+        let range = item.split_once("..").or_else(|| item.split_once('-'));
+        let (start, stop) = match range {
+            Some((start, stop)) => (parse_weekday(start)?, parse_weekday(stop)?),
+            None => {
+                let day = parse_weekday(item)?;
+                (day, day)
+            }
+        };
+        if start > stop {
+            return Err(format!("invalid weekday range '{}'", item).into());
+        }
+        for day in start..=stop {
+            mask |= 1 << day;
+        }
+    }
+    Ok(mask)
+}
+
+fn format_weekdays(f: &mut fmt::Formatter, mask: u8) -> fmt::Result {
+    let mut first = true;
+    let mut day = 0;
+    while day < 7 {
+        if mask & (1 << day) == 0 {
+            day += 1;
+            continue;
+        }
+        let mut end = day;
+        while end + 1 < 7 && mask & (1 << (end + 1)) != 0 {
+            end += 1;
+        }
+        if !first {
+            f.write_str(",")?;
+        }
+        first = false;
+        if end - day >= 2 {
+            write!(f, "{}..{}", WEEKDAYS[day].0, WEEKDAYS[end].0)?;
+            day = end + 1;
+        } else {
+            f.write_str(WEEKDAYS[day].0)?;
+            day += 1;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Integer,
+    /// Integer, with two-digit years mapped to 1970-2069.
+    Year,
+    /// Decimal seconds, stored as microseconds.
+    Seconds,
+}
+
+/// Parse a comma-separated list of components, or `*`.
+fn parse_chain(
+    input: &str,
+    min: i64,
+    max: i64,
+    kind: ValueKind,
+) -> Result<Vec<Component>, SdError> {
+    if input == "*" {
+        return Ok(vec![]);
+    }
+    input
+        .split(',')
+        .map(|item| parse_component(item, min, max, kind))
+        .collect()
+}
+
+fn parse_component(item: &str, min: i64, max: i64, kind: ValueKind) -> Result<Component, SdError> {
+    let invalid = || SdError::from(format!("invalid value '{}'", item));
+    let (range, repeat) = match item.split_once('/') {
+        Some((range, repeat)) => {
+            let repeat = parse_value(repeat, kind).ok_or_else(invalid)?;
+            if repeat <= 0 {
+                return Err(invalid());
+            }
+            if repeat > max - min {
+                return Err(format!("repeat in '{}' out of range", item).into());
+            }
+            (range, repeat)
+        }
+        None => (item, 0),
+    };
+    let (start, stop) = match range.split_once("..") {
+        Some((start, stop)) => (start, Some(stop)),
+        None => (range, None),
+    };
+    let start = parse_value(start, kind).ok_or_else(invalid)?;
+    let stop = stop
+        .map(|stop| parse_value(stop, kind).ok_or_else(invalid))
+        .transpose()?;
+    let in_range = |value: i64| (min..=max).contains(&value);
+    if !in_range(start) || !stop.map_or(true, in_range) || stop.map_or(false, |stop| stop < start) {
+        return Err(format!("value '{}' out of range", item).into());
+    }
+    Ok(Component {
+        start,
+        stop,
+        repeat,
+    })
+}
+
+fn parse_value(input: &str, kind: ValueKind) -> Option<i64> {
+    if input.is_empty() || input.starts_with(|c: char| !c.is_ascii_digit()) {
+        return None;
+    }
+    match kind {
+        ValueKind::Integer => input.parse().ok(),
+        ValueKind::Year => {
+            let year: i64 = input.parse().ok()?;
+            match (input.len(), year) {
+                (1..=2, 0..=69) => Some(year + 2000),
+                (1..=2, _) => Some(year + 1900),
+                _ => Some(year),
+            }
+        }
+        ValueKind::Seconds => {
+            let (int, frac) = match input.split_once('.') {
+                Some((int, frac)) => (int, frac),
+                None => (input, ""),
+            };
+            if frac.len() > 6 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let int: i64 = int.parse().ok()?;
+            let frac: i64 = if frac.is_empty() {
+                0
+            } else {
+                format!("{:0<6}", frac).parse().ok()?
+            };
+            int.checked_mul(USEC_PER_SEC)?.checked_add(frac)
+        }
+    }
+}
+
+fn format_chain(
+    f: &mut fmt::Formatter,
+    chain: &[Component],
+    width: usize,
+    usec: bool,
+) -> fmt::Result {
+    if chain.is_empty() {
+        return f.write_str("*");
+    }
+    let value = |f: &mut fmt::Formatter, value: i64, width: usize| {
+        if usec {
+            write!(f, "{:0width$}", value / USEC_PER_SEC, width = width)?;
+            if value % USEC_PER_SEC != 0 {
+                write!(f, ".{:06}", value % USEC_PER_SEC)?;
+            }
+            Ok(())
+        } else {
+            write!(f, "{:0width$}", value, width = width)
+        }
+    };
+    for (index, component) in chain.iter().enumerate() {
+        if index > 0 {
+            f.write_str(",")?;
+        }
+        value(f, component.start, width)?;
+        if let Some(stop) = component.stop {
+            f.write_str("..")?;
+            value(f, stop, width)?;
+        }
+        if component.repeat > 0 {
+            f.write_str("/")?;
+            value(f, component.repeat, 0)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("Mon..Fri *-*-* 10:00:00", "Mon..Fri *-*-* 10:00:00"),
+            ("weekly", "Mon *-*-* 00:00:00"),
+            ("quarterly", "*-01,04,07,10-01 00:00:00"),
+            ("minutely", "*-*-* *:*:00"),
+            (
+                "Sat,Mon..Wed,Fri 2024-*-1..5 *:0/15",
+                "Mon..Wed,Fri,Sat 2024-*-01..05 *:00/15:00",
+            ),
+            ("*-02~03", "*-02~03 00:00:00"),
+            ("*-*-* 1,3,2,1:00:00.5", "*-*-* 01,02,03:00:00.500000"),
+            ("12:00 UTC", "*-*-* 12:00:00 UTC"),
+            ("mon *-*-* 00:00 utc", "Mon *-*-* 00:00:00 UTC"),
+            ("24-3-1 01:02", "2024-03-01 01:02:00"),
+            ("Mon-Wed", "Mon..Wed *-*-* 00:00:00"),
+            ("Mon..Sun", "*-*-* 00:00:00"),
+            ("Tue,Wed", "Tue,Wed *-*-* 00:00:00"),
+            ("*:*:*", "*-*-* *:*:*"),
+            ("*:00:1.25/0.5", "*-*-* *:00:01.250000/0.500000"),
+            ("*-1..3,2-1", "*-01..03,02-01 00:00:00"),
+            ("@1700", "1970-01-01 00:28:20 UTC"),
+        ];
+        for (input, expected) in cases {
+            let spec = CalendarSpec::parse(input).unwrap();
+            assert_eq!(spec.to_string(), expected, "input: {}", input);
+            let reparsed: CalendarSpec = expected.parse().unwrap();
+            assert_eq!(reparsed.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        for input in [
+            "",
+            "*:*/5",
+            "*-*-*/2",
+            "Fri..Mon",
+            "*-*-* 5..3:00",
+            "1969-01-01",
+            "2200-01-01",
+            "10",
+            "*-*~05..02",
+            "Funday",
+            "*-*-* 00:00:00 extra",
+            "*:0/60",
+            "*:0/9223372036854775807 UTC",
+        ] {
+            CalendarSpec::parse(input).unwrap_err();
+        }
+
+        let huge = Component {
+            start: 0,
+            stop: None,
+            repeat: i64::MAX,
+        };
+        assert_eq!(huge.next(5), None);
+    }
+
+    fn elapses(spec: &str, after: &str, count: usize) -> Vec<String> {
+        let spec = CalendarSpec::parse(spec).unwrap();
+        let after = CalendarSpec::parse(after).unwrap();
+        let mut time = after.next_elapse(UNIX_EPOCH).unwrap();
+        let mut result = vec![];
+        for _ in 0..count {
+            time = match spec.next_elapse(time) {
+                Some(time) => time,
+                None => break,
+            };
+            let usec = time.duration_since(UNIX_EPOCH).unwrap().as_micros() as i64;
+            let dt = DateTime::from_unix(usec, true).unwrap();
+            result.push(format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+                dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second, dt.usec
+            ));
+        }
+        result
+    }
+
+    #[test]
+    fn test_next_elapse() {
+        let now = "2026-10-16 12:08:05.5 UTC";
+        assert_eq!(
+            elapses("Mon..Fri *-*-* 10:00:00 UTC", now, 2),
+            ["2026-10-19 10:00:00.000000", "2026-10-20 10:00:00.000000"]
+        );
+        assert_eq!(
+            elapses("*:*:* UTC", now, 2),
+            ["2026-10-16 12:08:06.000000", "2026-10-16 12:08:07.000000"]
+        );
+        assert_eq!(
+            elapses("*:00:1.25/0.5 UTC", now, 3),
+            [
+                "2026-10-16 13:00:01.250000",
+                "2026-10-16 13:00:01.750000",
+                "2026-10-16 13:00:02.250000"
+            ]
+        );
+        assert_eq!(
+            elapses("*-02~03 UTC", now, 2),
+            ["2027-02-26 00:00:00.000000", "2028-02-27 00:00:00.000000"]
+        );
+        assert_eq!(
+            elapses("*-*~01..03 UTC", now, 3),
+            [
+                "2026-10-29 00:00:00.000000",
+                "2026-10-30 00:00:00.000000",
+                "2026-10-31 00:00:00.000000"
+            ]
+        );
+        assert_eq!(
+            elapses("*-*~07/1 UTC", now, 2),
+            ["2026-10-25 00:00:00.000000", "2026-10-26 00:00:00.000000"]
+        );
+        assert_eq!(
+            elapses("*-*-1..10/3 UTC", now, 3),
+            [
+                "2026-11-01 00:00:00.000000",
+                "2026-11-04 00:00:00.000000",
+                "2026-11-07 00:00:00.000000"
+            ]
+        );
+        assert_eq!(
+            elapses("*-*-31 UTC", now, 3),
+            [
+                "2026-10-31 00:00:00.000000",
+                "2026-12-31 00:00:00.000000",
+                "2027-01-31 00:00:00.000000"
+            ]
+        );
+        assert_eq!(
+            elapses("2199-01-01 UTC", now, 2),
+            ["2199-01-01 00:00:00.000000"]
+        );
+        assert!(elapses("*-02-30 UTC", now, 1).is_empty());
+        assert!(elapses("Sat 2024-*-1..5 UTC", now, 1).is_empty());
+    }
+}
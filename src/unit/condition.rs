@@ -0,0 +1,678 @@
+use super::file::{parse_boolean, split_words, Section};
+use crate::errors::{Context, SdError};
+use crate::id128::{self, Id128};
+use crate::tmpfiles::wildcard_match;
+use crate::virt::{self, Virtualization};
+use nix::sys::statvfs::{statvfs, FsFlags};
+use nix::unistd::{self, Gid, Group, Uid, User};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Highest UID of system users, as configured by default in systemd.
+const SYSTEM_UID_MAX: u32 = 999;
+
+/// Kind of a `Condition*=`/`Assert*=` check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConditionKind {
+    Architecture,
+    DirectoryNotEmpty,
+    Environment,
+    FileIsExecutable,
+    FileNotEmpty,
+    FirstBoot,
+    Group,
+    Host,
+    KernelCommandLine,
+    PathExists,
+    PathIsDirectory,
+    PathIsMountPoint,
+    PathIsReadWrite,
+    PathIsSymbolicLink,
+    User,
+    Virtualization,
+}
+
+impl ConditionKind {
+    const ALL: [ConditionKind; 16] = [
+        ConditionKind::Architecture,
+        ConditionKind::DirectoryNotEmpty,
+        ConditionKind::Environment,
+        ConditionKind::FileIsExecutable,
+        ConditionKind::FileNotEmpty,
+        ConditionKind::FirstBoot,
+        ConditionKind::Group,
+        ConditionKind::Host,
+        ConditionKind::KernelCommandLine,
+        ConditionKind::PathExists,
+        ConditionKind::PathIsDirectory,
+        ConditionKind::PathIsMountPoint,
+        ConditionKind::PathIsReadWrite,
+        ConditionKind::PathIsSymbolicLink,
+        ConditionKind::User,
+        ConditionKind::Virtualization,
+    ];
+
+    /// Return the directive name, without the `Condition`/`Assert` prefix.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConditionKind::Architecture => "Architecture",
+            ConditionKind::DirectoryNotEmpty => "DirectoryNotEmpty",
+            ConditionKind::Environment => "Environment",
+            ConditionKind::FileIsExecutable => "FileIsExecutable",
+            ConditionKind::FileNotEmpty => "FileNotEmpty",
+            ConditionKind::FirstBoot => "FirstBoot",
+            ConditionKind::Group => "Group",
+            ConditionKind::Host => "Host",
+            ConditionKind::KernelCommandLine => "KernelCommandLine",
+            ConditionKind::PathExists => "PathExists",
+            ConditionKind::PathIsDirectory => "PathIsDirectory",
+            ConditionKind::PathIsMountPoint => "PathIsMountPoint",
+            ConditionKind::PathIsReadWrite => "PathIsReadWrite",
+            ConditionKind::PathIsSymbolicLink => "PathIsSymbolicLink",
+            ConditionKind::User => "User",
+            ConditionKind::Virtualization => "Virtualization",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|k| k.as_str() == name).copied()
+    }
+
+    fn takes_path(&self) -> bool {
+        matches!(
+            self,
+            ConditionKind::DirectoryNotEmpty
+                | ConditionKind::FileIsExecutable
+                | ConditionKind::FileNotEmpty
+                | ConditionKind::PathExists
+                | ConditionKind::PathIsDirectory
+                | ConditionKind::PathIsMountPoint
+                | ConditionKind::PathIsReadWrite
+                | ConditionKind::PathIsSymbolicLink
+        )
+    }
+}
+
+/// Single `Condition*=` or `Assert*=` directive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    kind: ConditionKind,
+    parameter: String,
+    assert: bool,
+    trigger: bool,
+    negate: bool,
+}
+
+impl Condition {
+    /// Parse a directive, e.g. `ConditionPathExists` with value `!/etc/foo`.
+    ///
+    /// Values may be prefixed with `|` (triggering condition) and/or `!`
+    /// (negated condition).
+    pub fn parse(key: &str, value: &str) -> Result<Self, SdError> {
+        let (assert, name) = match (key.strip_prefix("Condition"), key.strip_prefix("Assert")) {
+            (Some(name), _) => (false, name),
+            (_, Some(name)) => (true, name),
+            _ => return Err(format!("'{}' is not a condition or assertion", key).into()),
+        };
+        let kind = ConditionKind::from_name(name)
+            .ok_or_else(|| format!("unsupported condition '{}'", key))?;
+
+        let value = value.trim();
+        let (trigger, value) = match value.strip_prefix('|') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, value),
+        };
+        let (negate, value) = match value.strip_prefix('!') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, value),
+        };
+        if value.is_empty() {
+            return Err(format!("empty parameter for '{}'", key).into());
+        }
+        if kind.takes_path() && !value.starts_with('/') {
+            return Err(format!("path '{}' for '{}' is not absolute", value, key).into());
+        }
+
+        Ok(Self {
+            kind,
+            parameter: value.to_string(),
+            assert,
+            trigger,
+            negate,
+        })
+    }
+
+    /// Return the kind of check.
+    pub fn kind(&self) -> ConditionKind {
+        self.kind
+    }
+
+    /// Return the parameter of the check, without prefixes.
+    pub fn parameter(&self) -> &str {
+        &self.parameter
+    }
+
+    /// Whether this is an `Assert*=` directive.
+    pub fn is_assert(&self) -> bool {
+        self.assert
+    }
+
+    /// Whether this is a triggering condition (prefixed with `|`).
+    pub fn is_trigger(&self) -> bool {
+        self.trigger
+    }
+
+    /// Whether the result is negated (prefixed with `!`).
+    pub fn is_negated(&self) -> bool {
+        self.negate
+    }
+
+    /// Evaluate this check against the local system.
+    pub fn evaluate(&self) -> Result<bool, SdError> {
+        let result = self
+            .check()
+            .with_context(|| format!("failed to evaluate {}", self))?;
+        Ok(result != self.negate)
+    }
+
+    fn check(&self) -> Result<bool, SdError> {
+        let param = self.parameter.as_str();
+        let path = Path::new(param);
+        let result = match self.kind {
+            ConditionKind::Architecture => check_architecture(param),
+            ConditionKind::DirectoryNotEmpty => fs::read_dir(path)
+                .map(|mut entries| entries.next().is_some())
+                .unwrap_or(false),
+            ConditionKind::Environment => check_environment(param),
+            ConditionKind::FileIsExecutable => fs::metadata(path)
+                .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false),
+            ConditionKind::FileNotEmpty => fs::metadata(path)
+                .map(|m| m.is_file() && m.len() > 0)
+                .unwrap_or(false),
+            ConditionKind::FirstBoot => {
+                parse_boolean(param)? == Path::new("/run/systemd/first-boot").exists()
+            }
+            ConditionKind::Group => check_group(param)?,
+            ConditionKind::Host => check_host(param)?,
+            ConditionKind::KernelCommandLine => check_kernel_cmdline(param)?,
+            ConditionKind::PathExists => path.exists(),
+            ConditionKind::PathIsDirectory => path.is_dir(),
+            ConditionKind::PathIsMountPoint => check_mount_point(path)?,
+            ConditionKind::PathIsReadWrite => statvfs(path)
+                .map(|stat| !stat.flags().contains(FsFlags::ST_RDONLY))
+                .unwrap_or(false),
+            ConditionKind::PathIsSymbolicLink => path
+                .symlink_metadata()
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(false),
+            ConditionKind::User => check_user(param)?,
+            ConditionKind::Virtualization => check_virtualization(param)?,
+        };
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}={}{}{}",
+            if self.assert { "Assert" } else { "Condition" },
+            self.kind.as_str(),
+            if self.trigger { "|" } else { "" },
+            if self.negate { "!" } else { "" },
+            self.parameter
+        )
+    }
+}
+
+/// Outcome of checking the conditions and assertions of a unit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// All checks passed, the unit would be started.
+    Start,
+    /// Some conditions are not met, the unit would be skipped.
+    Skip(Vec<Condition>),
+    /// Some assertions are not met, the unit would fail to start.
+    Fail(Vec<Condition>),
+    /// The outcome depends on checks which are not supported, given as
+    /// `key=value` directives.
+    Unknown(Vec<String>),
+}
+
+/// Conditions and assertions of a unit, from its `[Unit]` section.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Conditions {
+    conditions: Vec<Condition>,
+    asserts: Vec<Condition>,
+    unsupported_conditions: Vec<(String, String)>,
+    unsupported_asserts: Vec<(String, String)>,
+}
+
+impl Conditions {
+    /// Collect all `Condition*=` and `Assert*=` directives from a section.
+    ///
+    /// Like in systemd, an empty assignment to any condition resets all
+    /// previous conditions (and similarly for assertions). Directives for
+    /// checks which are not supported (e.g. `ConditionACPower=`) are kept
+    /// aside, see [`unsupported`](Self::unsupported).
+    pub fn from_section(section: &Section) -> Result<Self, SdError> {
+        let mut conditions = Conditions::default();
+        for entry in section.entries() {
+            let key = entry.key();
+            let (name, list, unsupported) = if let Some(name) = key.strip_prefix("Condition") {
+                (
+                    name,
+                    &mut conditions.conditions,
+                    &mut conditions.unsupported_conditions,
+                )
+            } else if let Some(name) = key.strip_prefix("Assert") {
+                (
+                    name,
+                    &mut conditions.asserts,
+                    &mut conditions.unsupported_asserts,
+                )
+            } else {
+                continue;
+            };
+            if entry.value().is_empty() {
+                list.clear();
+                unsupported.clear();
+                continue;
+            }
+            if ConditionKind::from_name(name).is_none() {
+                unsupported.push((key.to_string(), entry.value().to_string()));
+                continue;
+            }
+            list.push(Condition::parse(key, entry.value())?);
+        }
+        Ok(conditions)
+    }
+
+    /// Return all `Condition*=` and `Assert*=` directives for checks which
+    /// are not supported, as `(key, value)` pairs.
+    pub fn unsupported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.unsupported_conditions
+            .iter()
+            .chain(&self.unsupported_asserts)
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Return all `Condition*=` directives.
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    /// Return all `Assert*=` directives.
+    pub fn asserts(&self) -> &[Condition] {
+        &self.asserts
+    }
+
+    /// Evaluate all checks against the local system.
+    ///
+    /// Conditions are checked before assertions. A group of checks passes if
+    /// all non-triggering checks pass and, if there are triggering checks,
+    /// at least one of them passes. If the outcome of a group depends on
+    /// unsupported checks, [`Verdict::Unknown`] is returned.
+    pub fn evaluate(&self) -> Result<Verdict, SdError> {
+        match evaluate_group(&self.conditions, &self.unsupported_conditions)? {
+            GroupResult::Pass => {}
+            GroupResult::Failed(failed) => return Ok(Verdict::Skip(failed)),
+            GroupResult::Unknown(unknown) => return Ok(Verdict::Unknown(unknown)),
+        }
+        match evaluate_group(&self.asserts, &self.unsupported_asserts)? {
+            GroupResult::Pass => Ok(Verdict::Start),
+            GroupResult::Failed(failed) => Ok(Verdict::Fail(failed)),
+            GroupResult::Unknown(unknown) => Ok(Verdict::Unknown(unknown)),
+        }
+    }
+}
+
+/// Outcome of a group of checks.
+enum GroupResult {
+    Pass,
+    Failed(Vec<Condition>),
+    Unknown(Vec<String>),
+}
+
+/// Evaluate a group of checks, along with the unsupported ones.
+fn evaluate_group(
+    checks: &[Condition],
+    unsupported: &[(String, String)],
+) -> Result<GroupResult, SdError> {
+    let mut failed = vec![];
+    let mut failed_triggers = vec![];
+    let mut triggered = false;
+    for check in checks {
+        let result = check.evaluate()?;
+        match (check.trigger, result) {
+            (true, true) => triggered = true,
+            (true, false) => failed_triggers.push(check.clone()),
+            (false, false) => failed.push(check.clone()),
+            (false, true) => {}
+        }
+    }
+    // A failed regular check is conclusive, and so is a passed triggering
+    // check for unsupported triggering ones.
+    if !failed.is_empty() {
+        if !triggered {
+            failed.extend(failed_triggers);
+        }
+        return Ok(GroupResult::Failed(failed));
+    }
+    let unknown: Vec<String> = unsupported
+        .iter()
+        .filter(|(_, value)| !(triggered && value.trim_start().starts_with('|')))
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    if !unknown.is_empty() {
+        return Ok(GroupResult::Unknown(unknown));
+    }
+    if !triggered && !failed_triggers.is_empty() {
+        return Ok(GroupResult::Failed(failed_triggers));
+    }
+    Ok(GroupResult::Pass)
+}
+
+/// Return the systemd name of the architecture this was built for.
+pub(crate) fn native_architecture() -> &'static str {
+    match env::consts::ARCH {
+        "x86_64" => "x86-64",
+        "x86" => "x86",
+        "aarch64" if cfg!(target_endian = "big") => "arm64-be",
+        "aarch64" => "arm64",
+        "arm" if cfg!(target_endian = "big") => "arm-be",
+        "arm" => "arm",
+        "powerpc64" if cfg!(target_endian = "little") => "ppc64-le",
+        "powerpc64" => "ppc64",
+        "powerpc" => "ppc",
+        "s390x" => "s390x",
+        "riscv64" => "riscv64",
+        "riscv32" => "riscv32",
+        "mips64" if cfg!(target_endian = "little") => "mips64-le",
+        "mips64" => "mips64",
+        "mips" if cfg!(target_endian = "little") => "mips-le",
+        "mips" => "mips",
+        "loongarch64" => "loongarch64",
+        "sparc64" => "sparc64",
+        other => other,
+    }
+}
+
+fn check_architecture(param: &str) -> bool {
+    param == "native" || param == native_architecture()
+}
+
+fn check_environment(param: &str) -> bool {
+    match param.split_once('=') {
+        Some((key, value)) => env::var_os(key).map_or(false, |v| v == value),
+        None => env::var_os(param).is_some(),
+    }
+}
+
+fn check_user(param: &str) -> Result<bool, SdError> {
+    let uid = unistd::getuid();
+    if param == "@system" {
+        return Ok(uid.as_raw() <= SYSTEM_UID_MAX);
+    }
+    if let Ok(id) = param.parse::<u32>() {
+        return Ok(uid == Uid::from_raw(id));
+    }
+    let user = User::from_uid(uid).context("failed to look up current user")?;
+    Ok(user.map_or(false, |u| u.name == param))
+}
+
+fn check_group(param: &str) -> Result<bool, SdError> {
+    let mut gids = unistd::getgroups().context("failed to get supplementary groups")?;
+    gids.push(unistd::getgid());
+    let target = match param.parse::<u32>() {
+        Ok(id) => Gid::from_raw(id),
+        Err(_) => match Group::from_name(param).context("failed to look up group")? {
+            Some(group) => group.gid,
+            None => return Ok(false),
+        },
+    };
+    Ok(gids.contains(&target))
+}
+
+fn check_host(param: &str) -> Result<bool, SdError> {
+    if let Ok(id) = Id128::parse_str(param) {
+        return Ok(id128::get_machine().map_or(false, |machine| machine == id));
+    }
+    let hostname =
+        fs::read_to_string("/proc/sys/kernel/hostname").context("failed to read hostname")?;
+    Ok(hostname_matches(hostname.trim(), param))
+}
+
+/// Match a hostname against a case-insensitive shell wildcard pattern.
+fn hostname_matches(hostname: &str, pattern: &str) -> bool {
+    wildcard_match(&pattern.to_lowercase(), &hostname.to_lowercase())
+}
+
+fn check_kernel_cmdline(param: &str) -> Result<bool, SdError> {
+    let cmdline = fs::read_to_string("/proc/cmdline").context("failed to read /proc/cmdline")?;
+    Ok(cmdline_matches(&cmdline, param))
+}
+
+/// Match a kernel command line word: either exactly, or by key for
+/// parameters without a value.
+fn cmdline_matches(cmdline: &str, param: &str) -> bool {
+    let words = split_words(cmdline)
+        .unwrap_or_else(|_| cmdline.split_whitespace().map(String::from).collect());
+    words.iter().any(|word| {
+        word == param
+            || (!param.contains('=') && word.split_once('=').map_or(false, |(key, _)| key == param))
+    })
+}
+
+fn check_mount_point(path: &Path) -> Result<bool, SdError> {
+    let path = match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return Ok(false),
+    };
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")
+        .context("failed to read /proc/self/mountinfo")?;
+    let found = mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .any(|mount_point| Path::new(&unescape_octal(mount_point)) == path);
+    Ok(found)
+}
+
+/// Decode octal escapes (e.g. `\040` for spaces) used in `/proc` mount tables.
+fn unescape_octal(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(index) = rest.find('\\') {
+        output.push_str(&rest[..index]);
+        let escaped = rest.get(index + 1..index + 4);
+        match escaped.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) => {
+                output.push(char::from(byte));
+                rest = &rest[index + 4..];
+            }
+            None => {
+                output.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn check_virtualization(param: &str) -> Result<bool, SdError> {
+    if let Ok(enabled) = parse_boolean(param) {
+        return Ok(virt::detect().is_none() != enabled);
+    }
+    let result = match param {
+        "vm" => virt::detect_vm().is_vm(),
+        "container" => virt::detect_container().is_container(),
+        "private-users" => fs::read_to_string("/proc/self/uid_map")
+            .map(|map| map.split_whitespace().collect::<Vec<_>>() != ["0", "0", "4294967295"])
+            .unwrap_or(false),
+        name => {
+            let expected: Virtualization = name.parse()?;
+            virt::detect_container() == expected || virt::detect_vm() == expected
+        }
+    };
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::unit::file::UnitFile;
+
+    #[test]
+    fn test_parse_condition() {
+        let cond = Condition::parse("ConditionPathExists", "|!/etc/foo").unwrap();
+        assert_eq!(cond.kind(), ConditionKind::PathExists);
+        assert_eq!(cond.parameter(), "/etc/foo");
+        assert!(cond.is_trigger());
+        assert!(cond.is_negated());
+        assert!(!cond.is_assert());
+        assert_eq!(cond.to_string(), "ConditionPathExists=|!/etc/foo");
+
+        let assert = Condition::parse("AssertUser", "root").unwrap();
+        assert!(assert.is_assert());
+        assert_eq!(assert.kind(), ConditionKind::User);
+
+        Condition::parse("ConditionPathExists", "relative").unwrap_err();
+        Condition::parse("ConditionPathExists", "!").unwrap_err();
+        Condition::parse("ConditionUnknown", "x").unwrap_err();
+        Condition::parse("Description", "x").unwrap_err();
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let dir = env::temp_dir();
+        let dir = dir.to_str().unwrap();
+        let check =
+            |key: &str, value: &str| Condition::parse(key, value).unwrap().evaluate().unwrap();
+
+        assert!(check("ConditionPathExists", dir));
+        assert!(check("ConditionPathIsDirectory", dir));
+        assert!(!check("ConditionPathExists", "/nonexistent/path"));
+        assert!(check("ConditionPathExists", "!/nonexistent/path"));
+        assert!(check("ConditionPathIsMountPoint", "/"));
+        assert!(!check("ConditionFileNotEmpty", dir));
+        assert!(check("ConditionFileIsExecutable", "/bin/sh"));
+        assert!(check("ConditionArchitecture", "native"));
+        assert!(check("ConditionArchitecture", native_architecture()));
+        assert!(!check("ConditionArchitecture", "unknown-arch"));
+        assert!(check("ConditionEnvironment", "PATH"));
+        assert!(!check("ConditionEnvironment", "PATH=/nonexistent"));
+        let uid = unistd::getuid().to_string();
+        assert!(check("ConditionUser", &uid));
+        let gid = unistd::getgid().to_string();
+        assert!(check("ConditionGroup", &gid));
+    }
+
+    #[test]
+    fn test_cmdline_matches() {
+        let cmdline = "BOOT_IMAGE=/vmlinuz root=/dev/sda1 quiet \"opt=with space\"\n";
+        assert!(cmdline_matches(cmdline, "quiet"));
+        assert!(cmdline_matches(cmdline, "root"));
+        assert!(cmdline_matches(cmdline, "root=/dev/sda1"));
+        assert!(cmdline_matches(cmdline, "opt=with space"));
+        assert!(!cmdline_matches(cmdline, "root=/dev/sda2"));
+        assert!(!cmdline_matches(cmdline, "BOOT"));
+    }
+
+    #[test]
+    fn test_unescape_octal() {
+        assert_eq!(unescape_octal("/mnt/with\\040space"), "/mnt/with space");
+        assert_eq!(unescape_octal("/plain"), "/plain");
+        assert_eq!(unescape_octal("/bad\\9"), "/bad\\9");
+    }
+
+    #[test]
+    fn test_conditions() {
+        let unit = UnitFile::parse(
+            "[Unit]
+ConditionPathExists=/nonexistent/reset
+ConditionPathExists=
+ConditionPathExists=|/nonexistent/a
+ConditionPathExists=|/
+ConditionPathIsDirectory=/
+AssertPathExists=/nonexistent/assert
+",
+        )
+        .unwrap();
+        let conditions = Conditions::from_section(unit.section("Unit").unwrap()).unwrap();
+        assert_eq!(conditions.conditions().len(), 3);
+        assert_eq!(conditions.asserts().len(), 1);
+        match conditions.evaluate().unwrap() {
+            Verdict::Fail(failed) => assert_eq!(failed, conditions.asserts()),
+            other => panic!("unexpected verdict {:?}", other),
+        }
+
+        let unit = UnitFile::parse(
+            "[Unit]
+ConditionPathExists=|/nonexistent/a
+ConditionPathExists=|/nonexistent/b
+AssertPathExists=/nonexistent/assert
+",
+        )
+        .unwrap();
+        let conditions = Conditions::from_section(unit.section("Unit").unwrap()).unwrap();
+        match conditions.evaluate().unwrap() {
+            Verdict::Skip(failed) => assert_eq!(failed.len(), 2),
+            other => panic!("unexpected verdict {:?}", other),
+        }
+
+        let conditions = Conditions::default();
+        assert_eq!(conditions.evaluate().unwrap(), Verdict::Start);
+
+        let unit = UnitFile::parse(
+            "[Unit]
+ConditionACPower=true
+ConditionPathExists=/
+AssertKernelVersion=>=4.0
+",
+        )
+        .unwrap();
+        let conditions = Conditions::from_section(unit.section("Unit").unwrap()).unwrap();
+        assert_eq!(
+            conditions.unsupported().collect::<Vec<_>>(),
+            [
+                ("ConditionACPower", "true"),
+                ("AssertKernelVersion", ">=4.0")
+            ]
+        );
+        assert_eq!(
+            conditions.evaluate().unwrap(),
+            Verdict::Unknown(vec!["ConditionACPower=true".to_string()])
+        );
+
+        let unit = UnitFile::parse(
+            "[Unit]
+ConditionACPower=true
+ConditionPathExists=/nonexistent/path
+ConditionSecurity=|selinux
+ConditionPathExists=|/
+",
+        )
+        .unwrap();
+        let conditions = Conditions::from_section(unit.section("Unit").unwrap()).unwrap();
+        match conditions.evaluate().unwrap() {
+            Verdict::Skip(failed) => assert_eq!(failed.len(), 1),
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_hostname_matches() {
+        assert!(hostname_matches("web-01", "web-01"));
+        assert!(hostname_matches("Web-01", "web-*"));
+        assert!(hostname_matches("web-01", "WEB-0[0-9]"));
+        assert!(hostname_matches("web-01", "web-0?"));
+        assert!(!hostname_matches("db-01", "web-*"));
+        assert!(!hostname_matches("web-010", "web-0?"));
+    }
+}
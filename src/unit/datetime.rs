@@ -0,0 +1,220 @@
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+
+pub(crate) const USEC_PER_SEC: i64 = 1_000_000;
+const SEC_PER_DAY: i64 = 86_400;
+
+/// Abbreviated and full weekday names, from Monday.
+pub(crate) const WEEKDAYS: [(&str, &str); 7] = [
+    ("Mon", "monday"),
+    ("Tue", "tuesday"),
+    ("Wed", "wednesday"),
+    ("Thu", "thursday"),
+    ("Fri", "friday"),
+    ("Sat", "saturday"),
+    ("Sun", "sunday"),
+];
+
+/// Broken-down time, with microsecond precision.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct DateTime {
+    pub(crate) year: i64,
+    pub(crate) month: i64,
+    pub(crate) day: i64,
+    pub(crate) hour: i64,
+    pub(crate) minute: i64,
+    pub(crate) second: i64,
+    pub(crate) usec: i64,
+}
+
+impl DateTime {
+    /// Midnight of the given day.
+    pub(crate) fn date(year: i64, month: i64, day: i64) -> Self {
+        Self {
+            year,
+            month,
+            day,
+            ..Default::default()
+        }
+    }
+
+    /// Split a timestamp (microseconds since the epoch).
+    pub(crate) fn from_unix(usec: i64, utc: bool) -> Option<Self> {
+        let secs = usec.div_euclid(USEC_PER_SEC);
+        let usec = usec.rem_euclid(USEC_PER_SEC);
+        let mut dt = if utc {
+            let (year, month, day) = civil_from_days(secs.div_euclid(SEC_PER_DAY));
+            let rem = secs.rem_euclid(SEC_PER_DAY);
+            Self {
+                year,
+                month,
+                day,
+                hour: rem / 3600,
+                minute: rem / 60 % 60,
+                second: rem % 60,
+                usec: 0,
+            }
+        } else {
+            let time = libc::time_t::try_from(secs).ok()?;
+            let mut tm = MaybeUninit::<libc::tm>::uninit();
+            // SAFETY: `localtime_r` fully initializes `tm` when it succeeds.
+            let tm = unsafe {
+                if libc::localtime_r(&time, tm.as_mut_ptr()).is_null() {
+                    return None;
+                }
+                tm.assume_init()
+            };
+            Self {
+                year: i64::from(tm.tm_year) + 1900,
+                month: i64::from(tm.tm_mon) + 1,
+                day: i64::from(tm.tm_mday),
+                hour: i64::from(tm.tm_hour),
+                minute: i64::from(tm.tm_min),
+                second: i64::from(tm.tm_sec),
+                usec: 0,
+            }
+        };
+        dt.usec = usec;
+        Some(dt)
+    }
+
+    /// Convert to a timestamp (microseconds since the epoch).
+    ///
+    /// Local times which do not exist (e.g. in a DST gap) are rejected.
+    pub(crate) fn to_unix(self, utc: bool) -> Option<i64> {
+        let secs = if utc {
+            days_from_civil(self.year, self.month, self.day) * SEC_PER_DAY
+                + self.hour * 3600
+                + self.minute * 60
+                + self.second
+        } else {
+            // SAFETY: all-zero is a valid `libc::tm`.
+            let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+            tm.tm_year = i32::try_from(self.year - 1900).ok()?;
+            tm.tm_mon = (self.month - 1) as i32;
+            tm.tm_mday = self.day as i32;
+            tm.tm_hour = self.hour as i32;
+            tm.tm_min = self.minute as i32;
+            tm.tm_sec = self.second as i32;
+            tm.tm_isdst = -1;
+            // SAFETY: `tm` is a valid, initialized struct.
+            let time = unsafe { libc::mktime(&mut tm) };
+            // `time_t` is not 64 bits wide on all targets.
+            #[allow(clippy::useless_conversion)]
+            let secs = i64::from(time);
+            let expected = Self { usec: 0, ..self };
+            if Self::from_unix(secs * USEC_PER_SEC, false)? != expected {
+                return None;
+            }
+            secs
+        };
+        secs.checked_mul(USEC_PER_SEC)?.checked_add(self.usec)
+    }
+
+    /// Midnight of the day `days` days away from this one.
+    pub(crate) fn add_days(&self, days: i64) -> Self {
+        let (year, month, day) =
+            civil_from_days(days_from_civil(self.year, self.month, self.day) + days);
+        Self::date(year, month, day)
+    }
+
+    /// Day of the week, from 0 (Monday) to 6 (Sunday).
+    pub(crate) fn weekday(&self) -> i64 {
+        // 1970-01-01 was a Thursday.
+        (days_from_civil(self.year, self.month, self.day) + 3).rem_euclid(7)
+    }
+}
+
+/// Abbreviated name of the local timezone at a given time, e.g. `CET`.
+pub(crate) fn local_zone_name(usec: i64) -> Option<String> {
+    let time = libc::time_t::try_from(usec.div_euclid(USEC_PER_SEC)).ok()?;
+    let mut tm = MaybeUninit::<libc::tm>::uninit();
+    // SAFETY: `localtime_r` fully initializes `tm` when it succeeds, and
+    // `tm_zone` then points to a static string (or is null).
+    unsafe {
+        if libc::localtime_r(&time, tm.as_mut_ptr()).is_null() {
+            return None;
+        }
+        let zone = tm.assume_init().tm_zone;
+        if zone.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr(zone).to_string_lossy().into_owned())
+    }
+}
+
+/// Whether a year is a leap year, in the proleptic Gregorian calendar.
+pub(crate) fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Number of days in a month.
+pub(crate) fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since the epoch for a civil date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Civil date for a number of days since the epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_civil_roundtrip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+        for days in (-800_000..800_000).step_by(997) {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+
+    #[test]
+    fn test_utc() {
+        let dt = DateTime::from_unix(1_709_289_000_123_456, true).unwrap();
+        let expected = DateTime {
+            year: 2024,
+            month: 3,
+            day: 1,
+            hour: 10,
+            minute: 30,
+            second: 0,
+            usec: 123_456,
+        };
+        assert_eq!(dt, expected);
+        assert_eq!(dt.weekday(), 4);
+        assert_eq!(dt.to_unix(true), Some(1_709_289_000_123_456));
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2100, 2), 28);
+    }
+}
@@ -0,0 +1,676 @@
+//! Parsing of unit files, following systemd's INI dialect.
+//!
+//! This handles the syntax described in `systemd.syntax(7)`:
+//!
+//!  * lines starting with `#` or `;` are comments, blank lines are ignored;
+//!  * lines ending with `\` are continued on the next one (comment lines
+//!    within a continuation are skipped);
+//!  * section names and keys are case-sensitive;
+//!  * keys may be repeated: list settings accumulate all values, and an
+//!    empty assignment resets the list.
+//!
+//! Values are kept verbatim, as their interpretation depends on the setting.
+//! Settings taking a list of (possibly quoted) words can be split through
+//! [`split_words`].
+//!
+//! ## Example
+//!
+//! ```rust
+//! # fn doctest_parse() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::unit::file::UnitFile;
+//!
+//! let unit = UnitFile::parse(
+//!     r#"
+//! [Unit]
+//! Description=Example
+//! After=network.target
+//! After=time-sync.target
+//!
+//! [Service]
+//! ExecStart=/usr/bin/example \
+//!     --verbose
+//! Environment="A=1" B=2
+//! "#,
+//! )?;
+//! assert_eq!(unit.value("Unit", "Description"), Some("Example"));
+//! assert_eq!(
+//!     unit.values("Unit", "After"),
+//!     vec!["network.target", "time-sync.target"]
+//! );
+//! assert_eq!(
+//!     unit.value("Service", "ExecStart"),
+//!     Some("/usr/bin/example  --verbose")
+//! );
+//! # Ok(())
+//! # }
+//! # doctest_parse().unwrap();
+//! ```
+
+use super::UnitName;
+use crate::errors::{Context, SdError};
+use std::collections::HashSet;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// Parsed unit file (or drop-in), as a sequence of sections.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnitFile {
+    sections: Vec<Section>,
+}
+
+/// Section of a unit file, e.g. `[Service]`.
+///
+/// Sections appearing multiple times in a file are merged together.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    name: String,
+    entries: Vec<Entry>,
+}
+
+/// Single `Key=Value` assignment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    key: String,
+    value: String,
+    line: usize,
+    source: Option<PathBuf>,
+}
+
+impl UnitFile {
+    /// Parse a unit file from a string.
+    pub fn parse(input: &str) -> Result<Self, SdError> {
+        Self::parse_with_source(input, None)
+    }
+
+    /// Parse a unit file from a buffered reader.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, SdError> {
+        let mut input = String::new();
+        for line in reader.lines() {
+            input.push_str(&line.context("failed to read unit file")?);
+            input.push('\n');
+        }
+        Self::parse(&input)
+    }
+
+    /// Parse the unit file at `path`, recording it as the source of all entries.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, SdError> {
+        let path = path.as_ref();
+        let input = fs::read_to_string(path)
+            .with_context(|| format!("failed to read unit file {}", path.display()))?;
+        Self::parse_with_source(&input, Some(path))
+            .with_context(|| format!("failed to parse unit file {}", path.display()))
+    }
+
+    fn parse_with_source(input: &str, source: Option<&Path>) -> Result<Self, SdError> {
+        let mut unit = UnitFile::default();
+        let mut current: Option<usize> = None;
+        let mut continuation: Option<(usize, String)> = None;
+
+        let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+        for (index, raw) in input.lines().enumerate() {
+            let line_number = index + 1;
+            let line = raw.trim();
+            if line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let (start, mut line) = match continuation.take() {
+                Some((start, mut previous)) => {
+                    previous.push_str(line);
+                    (start, previous)
+                }
+                None => {
+                    if line.is_empty() {
+                        continue;
+                    }
+                    (line_number, line.to_string())
+                }
+            };
+            if line.ends_with('\\') {
+                line.pop();
+                line.push(' ');
+                continuation = Some((start, line));
+                continue;
+            }
+
+            unit.parse_line(&line, start, source, &mut current)?;
+        }
+        if let Some((start, line)) = continuation {
+            unit.parse_line(&line, start, source, &mut current)?;
+        }
+
+        Ok(unit)
+    }
+
+    fn parse_line(
+        &mut self,
+        line: &str,
+        line_number: usize,
+        source: Option<&Path>,
+        current: &mut Option<usize>,
+    ) -> Result<(), SdError> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .filter(|name| !name.is_empty() && !name.contains(['[', ']']))
+                .ok_or_else(|| format!("invalid section header at line {}", line_number))?;
+            *current = Some(self.section_index(name));
+            return Ok(());
+        }
+
+        let index = match *current {
+            Some(index) => index,
+            None => {
+                log::warn!(
+                    "assignment outside of section at line {}, ignoring",
+                    line_number
+                );
+                return Ok(());
+            }
+        };
+        let (key, value) = match line.split_once('=') {
+            Some(kv) => kv,
+            None => {
+                log::warn!("missing '=' at line {}, ignoring", line_number);
+                return Ok(());
+            }
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("empty key at line {}", line_number).into());
+        }
+
+        self.sections[index].entries.push(Entry {
+            key: key.to_string(),
+            value: value.trim().to_string(),
+            line: line_number,
+            source: source.map(Path::to_path_buf),
+        });
+        Ok(())
+    }
+
+    /// Return the index of the section `name`, appending it if missing.
+    fn section_index(&mut self, name: &str) -> usize {
+        match self.sections.iter().position(|s| s.name == name) {
+            Some(index) => index,
+            None => {
+                self.sections.push(Section {
+                    name: name.to_string(),
+                    entries: vec![],
+                });
+                self.sections.len() - 1
+            }
+        }
+    }
+
+    /// Load the unit `name` from `search_paths`, merging all its drop-ins.
+    ///
+    /// Search paths are given in order of precedence (highest first), e.g.
+    /// `/etc/systemd/system`, `/run/systemd/system`, `/usr/lib/systemd/system`.
+    /// The unit file is taken from the first search path containing it,
+    /// falling back to the template unit file for instances. Drop-ins are
+    /// merged as described in [`find_dropins`]. The source of each entry is
+    /// recorded, see [`Entry::source`].
+    pub fn load<P: AsRef<Path>>(name: &UnitName, search_paths: &[P]) -> Result<Self, SdError> {
+        let mut candidates = vec![name.clone()];
+        candidates.extend(name.template().filter(|t| t != name));
+        let path = candidates
+            .iter()
+            .find_map(|candidate| {
+                search_paths
+                    .iter()
+                    .map(|dir| dir.as_ref().join(candidate.as_str()))
+                    .find(|path| path.symlink_metadata().is_ok())
+            })
+            .ok_or_else(|| format!("unit file for '{}' not found", name))?;
+        if is_masked(&path) {
+            return Err(format!("unit '{}' is masked ({})", name, path.display()).into());
+        }
+
+        let mut unit = Self::from_path(&path)?;
+        for dropin in find_dropins(name, search_paths) {
+            unit.merge(Self::from_path(&dropin)?);
+        }
+        Ok(unit)
+    }
+
+    /// Merge the content of `other` (e.g. a drop-in) into this unit file.
+    ///
+    /// Entries are appended to the matching sections, so that they take
+    /// precedence over existing ones, or reset them with empty assignments.
+    pub fn merge(&mut self, other: UnitFile) {
+        for section in other.sections {
+            let index = self.section_index(&section.name);
+            self.sections[index].entries.extend(section.entries);
+        }
+    }
+
+    /// Return all sections, in order of first appearance.
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Return the section `name`, if present.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Return the effective value of a single-valued setting.
+    ///
+    /// See [`Section::value`].
+    pub fn value(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)?.value(key)
+    }
+
+    /// Return the effective values of a list setting.
+    ///
+    /// See [`Section::values`].
+    pub fn values(&self, section: &str, key: &str) -> Vec<&str> {
+        self.section(section)
+            .map(|s| s.values(key))
+            .unwrap_or_default()
+    }
+}
+
+impl Section {
+    /// Return the section name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return all assignments in this section, in order.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Return all assignments to `key`, in order (including resets).
+    pub fn assignments<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a Entry> + 'a {
+        self.entries.iter().filter(move |e| e.key == key)
+    }
+
+    /// Return the effective value of a single-valued setting.
+    ///
+    /// This is the last assigned value. An empty assignment resets the
+    /// setting to its default, in which case `None` is returned.
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.entry(key).map(|e| e.value.as_str())
+    }
+
+    /// Return the assignment providing the effective value of a
+    /// single-valued setting, e.g. to report the file it comes from.
+    pub fn entry(&self, key: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .rfind(|e| e.key == key)
+            .filter(|e| !e.value.is_empty())
+    }
+
+    /// Return the effective values of a list setting.
+    ///
+    /// Values of all assignments are accumulated, and an empty assignment
+    /// resets the list.
+    pub fn values(&self, key: &str) -> Vec<&str> {
+        let mut values = vec![];
+        for entry in self.entries.iter().filter(|e| e.key == key) {
+            if entry.value.is_empty() {
+                values.clear();
+            } else {
+                values.push(entry.value.as_str());
+            }
+        }
+        values
+    }
+}
+
+impl Entry {
+    /// Return the setting name.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Return the assigned value, verbatim.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Return the line number (1-based) where this assignment starts.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Return the path of the file this assignment comes from, if known.
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+/// Find the drop-in files of the unit `name`, in order of application.
+///
+/// Search paths are given in order of precedence (highest first). For each
+/// of them, drop-ins are looked up in these directories:
+///
+///  * `foo-bar@baz.service.d/`, named after the unit itself;
+///  * `foo-bar@.service.d/`, named after its template, for instances;
+///  * `foo-bar-.service.d/` and `foo-.service.d/`, named after the prefixes
+///    of dashed unit names;
+///  * `service.d/`, the top-level drop-in directory for the unit type.
+///
+/// Only `*.conf` files are considered. A file shadows all files with the
+/// same name in lower-precedence directories, and a file linked to
+/// `/dev/null` (or empty) masks them. The result is sorted by file name,
+/// which is the order in which drop-ins are applied.
+pub fn find_dropins<P: AsRef<Path>>(name: &UnitName, search_paths: &[P]) -> Vec<PathBuf> {
+    let mut dir_names = vec![name.to_string()];
+    if let Some(template) = name.template().filter(|t| t != name) {
+        dir_names.push(template.to_string());
+    }
+    let prefix = name.prefix();
+    for (index, _) in prefix.rmatch_indices('-') {
+        dir_names.push(format!("{}.{}", &prefix[..=index], name.suffix()));
+    }
+
+    let mut dirs = vec![];
+    for search_path in search_paths {
+        for dir_name in &dir_names {
+            dirs.push(search_path.as_ref().join(format!("{}.d", dir_name)));
+        }
+    }
+    // Top-level drop-ins are the most generic ones, so they come last.
+    for search_path in search_paths {
+        dirs.push(search_path.as_ref().join(format!("{}.d", name.suffix())));
+    }
+
+    let mut seen = HashSet::new();
+    let mut dropins = vec![];
+    for dir in dirs {
+        let mut files: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == "conf"))
+                .collect(),
+            Err(_) => continue,
+        };
+        files.sort();
+        for path in files {
+            let file_name = match path.file_name() {
+                Some(file_name) => file_name.to_os_string(),
+                None => continue,
+            };
+            if seen.insert(file_name.clone()) {
+                dropins.push((file_name, path));
+            }
+        }
+    }
+    dropins.sort_by(|a, b| a.0.cmp(&b.0));
+
+    dropins
+        .into_iter()
+        .map(|(_, path)| path)
+        .filter(|path| !is_masked(path))
+        .collect()
+}
+
+/// Whether a file is masked, i.e. linked to `/dev/null` or empty.
+pub(crate) fn is_masked(path: &Path) -> bool {
+    if fs::read_link(path).map_or(false, |target| target == Path::new("/dev/null")) {
+        return true;
+    }
+    fs::metadata(path).map_or(true, |meta| meta.is_file() && meta.len() == 0)
+}
+
+/// Parse a boolean setting value, like systemd does.
+///
+/// Accepted values are `1`, `yes`, `y`, `true`, `t` and `on` (and their
+/// negative counterparts `0`, `no`, `n`, `false`, `f` and `off`), ignoring case.
+pub fn parse_boolean(value: &str) -> Result<bool, SdError> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "yes" | "y" | "true" | "t" | "on" => Ok(true),
+        "0" | "no" | "n" | "false" | "f" | "off" => Ok(false),
+        _ => Err(format!("invalid boolean value '{}'", value).into()),
+    }
+}
+
+/// Split a value into words, following systemd quoting rules.
+///
+/// Words are separated by whitespace. Single or double quotes group words
+/// containing whitespace, and C-style escapes (e.g. `\n`, `\"`, `\x41`) are
+/// resolved both inside and outside quotes.
+pub fn split_words(value: &str) -> Result<Vec<String>, SdError> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => {
+                let escaped = unescape_char(&mut chars)?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, Some(_)) => word.get_or_insert_with(String::new).push(c),
+            ('"', None) | ('\'', None) => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (c, None) if c.is_whitespace() => words.extend(word.take()),
+            (c, None) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("unterminated quote in '{}'", value).into());
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// Resolve a C-style escape sequence, after the backslash.
+fn unescape_char(chars: &mut std::str::Chars) -> Result<char, SdError> {
+    let c = chars
+        .next()
+        .ok_or_else(|| SdError::from("trailing backslash"))?;
+    let unescaped = match c {
+        'a' => '\u{7}',
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'v' => '\u{b}',
+        's' => ' ',
+        '\\' | '"' | '\'' | ' ' => c,
+        'x' => {
+            let hex: String = chars.take(2).collect();
+            let byte = u8::from_str_radix(&hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 2)
+                .ok_or_else(|| format!("invalid escape sequence '\\x{}'", hex))?;
+            char::from(byte)
+        }
+        _ => return Err(format!("invalid escape sequence '\\{}'", c).into()),
+    };
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let input = "\
+# Comment
+; Another comment
+[Unit]
+Description = Some service  
+After=a.target
+After=b.target
+
+[Service]
+ExecStart=/bin/echo \\
+# comment within continuation
+  foo \\
+  bar
+Environment=A=1
+Environment=
+Environment=B=2
+Type=simple
+Type=
+
+[service]
+Type=oneshot
+
+[Unit]
+After=c.target
+";
+        let unit = UnitFile::parse(input).unwrap();
+        let names: Vec<&str> = unit.sections().iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["Unit", "Service", "service"]);
+
+        assert_eq!(unit.value("Unit", "Description"), Some("Some service"));
+        assert_eq!(
+            unit.values("Unit", "After"),
+            vec!["a.target", "b.target", "c.target"]
+        );
+        assert_eq!(
+            unit.value("Service", "ExecStart"),
+            Some("/bin/echo  foo  bar")
+        );
+        assert_eq!(unit.values("Service", "Environment"), vec!["B=2"]);
+        assert_eq!(unit.value("Service", "Type"), None);
+        assert_eq!(unit.value("service", "Type"), Some("oneshot"));
+        assert_eq!(unit.value("Unit", "description"), None);
+
+        let service = unit.section("Service").unwrap();
+        let exec = service.assignments("ExecStart").next().unwrap();
+        assert_eq!(exec.line(), 9);
+        assert_eq!(exec.source(), None);
+        assert_eq!(service.assignments("Environment").count(), 3);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        UnitFile::parse("[Unit\nA=b\n").unwrap_err();
+        UnitFile::parse("[]\n").unwrap_err();
+        UnitFile::parse("[Unit]\n=value\n").unwrap_err();
+
+        // Ignored like systemd does.
+        let unit = UnitFile::parse("Orphan=1\n[Unit]\nNoAssignment\nA=b\n").unwrap();
+        assert_eq!(unit.sections().len(), 1);
+        assert_eq!(unit.section("Unit").unwrap().entries().len(), 1);
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let input = "\u{feff}[Unit]\r\nDescription=CRLF\r\n";
+        let unit = UnitFile::from_reader(input.as_bytes()).unwrap();
+        assert_eq!(unit.value("Unit", "Description"), Some("CRLF"));
+    }
+
+    #[test]
+    fn test_dropins() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let etc = root.join("etc");
+        let usr = root.join("usr");
+        let write = |path: PathBuf, content: &str| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write(
+            usr.join("foo-bar@.service"),
+            "[Unit]\nDescription=Template\n[Service]\nExecStart=/bin/true\nEnvironment=A=1\n",
+        );
+        write(
+            usr.join("foo-bar@.service.d/10-env.conf"),
+            "[Service]\nEnvironment=B=2\n",
+        );
+        write(
+            usr.join("foo-bar@.service.d/20-masked.conf"),
+            "[Service]\nUser=nobody\n",
+        );
+        write(etc.join("foo-bar@.service.d/20-masked.conf"), "");
+        write(
+            usr.join("foo-.service.d/30-prefix.conf"),
+            "[Unit]\nDescription=Prefix\n",
+        );
+        write(
+            etc.join("foo-bar@x.service.d/40-instance.conf"),
+            "[Service]\nEnvironment=\n",
+        );
+        write(usr.join("service.d/50-all.conf"), "[Service]\nNice=5\n");
+        write(
+            usr.join("foo-bar@x.service.d/40-instance.conf"),
+            "[Service]\nNice=1\n",
+        );
+        write(usr.join("foo-bar@x.service.d/README"), "not a drop-in");
+
+        let name = UnitName::new("foo-bar@x.service").unwrap();
+        let search_paths = [&etc, &usr];
+        let dropins = find_dropins(&name, &search_paths);
+        let expected = vec![
+            usr.join("foo-bar@.service.d/10-env.conf"),
+            usr.join("foo-.service.d/30-prefix.conf"),
+            etc.join("foo-bar@x.service.d/40-instance.conf"),
+            usr.join("service.d/50-all.conf"),
+        ];
+        assert_eq!(dropins, expected);
+
+        let unit = UnitFile::load(&name, &search_paths).unwrap();
+        assert_eq!(unit.value("Unit", "Description"), Some("Prefix"));
+        assert!(unit.values("Service", "Environment").is_empty());
+        assert_eq!(unit.value("Service", "User"), None);
+        let service = unit.section("Service").unwrap();
+        assert_eq!(
+            service.entry("Nice").unwrap().source(),
+            Some(usr.join("service.d/50-all.conf").as_path())
+        );
+        assert_eq!(
+            service.entry("ExecStart").unwrap().source(),
+            Some(usr.join("foo-bar@.service").as_path())
+        );
+
+        std::os::unix::fs::symlink("/dev/null", etc.join("foo-bar@.service")).unwrap();
+        UnitFile::load(&name, &search_paths).unwrap_err();
+        let missing = UnitName::new("missing.service").unwrap();
+        UnitFile::load(&missing, &search_paths).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_boolean() {
+        for value in &["1", "yes", "Y", "true", "t", "ON"] {
+            assert!(parse_boolean(value).unwrap());
+        }
+        for value in &["0", "no", "N", "false", "F", "off"] {
+            assert!(!parse_boolean(value).unwrap());
+        }
+        parse_boolean("").unwrap_err();
+        parse_boolean("maybe").unwrap_err();
+    }
+
+    #[test]
+    fn test_split_words() {
+        let cases = vec![
+            ("", vec![]),
+            ("a b  c", vec!["a", "b", "c"]),
+            (r#""A=1 2" B='3 4'"#, vec!["A=1 2", "B=3 4"]),
+            (r#"a\ b "c\"d" e\x41"#, vec!["a b", "c\"d", "eA"]),
+            (r#""" ''"#, vec!["", ""]),
+            (r#"tab\tnl\n"#, vec!["tab\tnl\n"]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(split_words(input).unwrap(), expected, "{}", input);
+        }
+
+        for invalid in &[r#""unterminated"#, r#"trailing\"#, r#"\q"#, r#"\x4"#] {
+            split_words(invalid).unwrap_err();
+        }
+    }
+}
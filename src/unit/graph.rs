@@ -0,0 +1,521 @@
+//! Dependency graph of units, built from unit files.
+//!
+//! This models the dependencies declared in `[Unit]` sections and through
+//! `.wants/` and `.requires/` directories, for offline analysis: e.g.
+//! checking for ordering cycles, or computing a start order without a
+//! running service manager.
+//!
+//! ```rust
+//! # fn doctest_graph() -> Result<(), libsystemd::errors::SdError> {
+//! use libsystemd::unit::file::UnitFile;
+//! use libsystemd::unit::graph::UnitGraph;
+//! use libsystemd::unit::UnitName;
+//!
+//! let mut graph = UnitGraph::new();
+//! let unit = UnitFile::parse("[Unit]\nRequires=b.service\nAfter=b.service\n")?;
+//! graph.add_unit(&UnitName::new("a.service")?, &unit)?;
+//! let order: Vec<&str> = graph
+//!     .topological_order()?
+//!     .iter()
+//!     .map(|name| name.as_str())
+//!     .collect();
+//! assert_eq!(order, ["b.service", "a.service"]);
+//! # Ok(())
+//! # }
+//! # doctest_graph().unwrap();
+//! ```
+
+use super::file::{split_words, UnitFile};
+use super::install::expand;
+use super::lookup::list_unit_files_in;
+use super::name::UnitName;
+use super::UnitFileState;
+use crate::errors::{Context, SdError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Kind of dependency between two units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DependencyKind {
+    /// `Requires=`, or a link in a `.requires/` directory.
+    Requires,
+    /// `Wants=`, or a link in a `.wants/` directory.
+    Wants,
+    /// `After=`: the unit starts after the other one.
+    After,
+    /// `Before=`: the unit starts before the other one.
+    Before,
+    /// `Conflicts=`.
+    Conflicts,
+}
+
+impl DependencyKind {
+    /// All dependency kinds.
+    pub const ALL: [DependencyKind; 5] = [
+        DependencyKind::Requires,
+        DependencyKind::Wants,
+        DependencyKind::After,
+        DependencyKind::Before,
+        DependencyKind::Conflicts,
+    ];
+
+    /// Return the name of the corresponding `[Unit]` setting.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DependencyKind::Requires => "Requires",
+            DependencyKind::Wants => "Wants",
+            DependencyKind::After => "After",
+            DependencyKind::Before => "Before",
+            DependencyKind::Conflicts => "Conflicts",
+        }
+    }
+}
+
+impl fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Graph of units and the dependencies between them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnitGraph {
+    units: BTreeSet<UnitName>,
+    edges: BTreeSet<(UnitName, DependencyKind, UnitName)>,
+    unresolved: BTreeSet<(UnitName, DependencyKind, String)>,
+}
+
+impl UnitGraph {
+    /// Create an empty graph.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the graph of all units found in `search_paths`.
+    ///
+    /// Search paths are given in order of precedence, as for
+    /// [`UnitFile::load`]. Units linked from `.wants/` and `.requires/`
+    /// directories are included as well, while masked and template units
+    /// are skipped.
+    pub fn from_search_paths<P: AsRef<Path>>(search_paths: &[P]) -> Result<Self, SdError> {
+        let mut graph = Self::new();
+        graph.add_dependency_dirs(search_paths)?;
+
+        let mut names: BTreeSet<UnitName> = graph.units.clone();
+        for (name, _, state) in list_unit_files_in(search_paths)? {
+            if !matches!(
+                state,
+                UnitFileState::Masked | UnitFileState::MaskedRuntime | UnitFileState::Bad
+            ) {
+                names.insert(name);
+            }
+        }
+        for name in names.into_iter().filter(|name| !name.is_template()) {
+            match UnitFile::load(&name, search_paths) {
+                Ok(unit) => graph.add_unit(&name, &unit)?,
+                Err(_) => {
+                    graph.units.insert(name);
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    /// Add a unit and the dependencies declared in its `[Unit]` section.
+    ///
+    /// The `%n`, `%N`, `%p` and `%i` specifiers are expanded for `name`.
+    /// Dependencies which are not valid unit names after expansion (e.g.
+    /// using other specifiers) are skipped, and reported by
+    /// [`unresolved`](Self::unresolved).
+    pub fn add_unit(&mut self, name: &UnitName, unit: &UnitFile) -> Result<(), SdError> {
+        self.units.insert(name.clone());
+        let section = match unit.section("Unit") {
+            Some(section) => section,
+            None => return Ok(()),
+        };
+        for kind in DependencyKind::ALL {
+            for value in section.values(kind.as_str()) {
+                for word in split_words(value)? {
+                    match expand(&word, name).and_then(|other| UnitName::new(&other)) {
+                        Ok(other) => self.add_dependency(name, kind, &other),
+                        Err(e) => {
+                            log::debug!("skipping dependency '{}' of '{}': {}", word, name, e);
+                            self.unresolved.insert((name.clone(), kind, word));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the `Wants=`/`Requires=` dependencies from `.wants/` and
+    /// `.requires/` directories found in `search_paths`.
+    pub fn add_dependency_dirs<P: AsRef<Path>>(
+        &mut self,
+        search_paths: &[P],
+    ) -> Result<(), SdError> {
+        for dir in search_paths {
+            let dir = dir.as_ref();
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let entry = entry.with_context(|| format!("failed to read '{}'", dir.display()))?;
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                let (unit, kind) = match (
+                    file_name.strip_suffix(".wants"),
+                    file_name.strip_suffix(".requires"),
+                ) {
+                    (Some(unit), _) => (unit, DependencyKind::Wants),
+                    (_, Some(unit)) => (unit, DependencyKind::Requires),
+                    _ => continue,
+                };
+                let unit = match UnitName::new(unit) {
+                    Ok(unit) => unit,
+                    Err(_) => continue,
+                };
+                let links = match fs::read_dir(entry.path()) {
+                    Ok(links) => links,
+                    Err(_) => continue,
+                };
+                for link in links {
+                    let link = link.with_context(|| format!("failed to read '{}'", file_name))?;
+                    if let Ok(other) = UnitName::new(&link.file_name().to_string_lossy()) {
+                        self.add_dependency(&unit, kind, &other);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a single dependency of `from` on `to`.
+    pub fn add_dependency(&mut self, from: &UnitName, kind: DependencyKind, to: &UnitName) {
+        self.units.insert(from.clone());
+        self.units.insert(to.clone());
+        self.edges.insert((from.clone(), kind, to.clone()));
+    }
+
+    /// Return all units, sorted by name.
+    pub fn units(&self) -> impl Iterator<Item = &UnitName> {
+        self.units.iter()
+    }
+
+    /// Return the dependencies which were skipped as not being valid unit
+    /// names, as `(unit, kind, value)`.
+    pub fn unresolved(&self) -> impl Iterator<Item = &(UnitName, DependencyKind, String)> {
+        self.unresolved.iter()
+    }
+
+    /// Return the units `unit` depends on with the given kind of dependency.
+    pub fn dependencies<'a>(
+        &'a self,
+        unit: &'a UnitName,
+        kind: DependencyKind,
+    ) -> impl Iterator<Item = &'a UnitName> + 'a {
+        self.edges
+            .iter()
+            .filter(move |(from, k, _)| from == unit && *k == kind)
+            .map(|(_, _, to)| to)
+    }
+
+    /// Return the units depending on `unit` with the given kind of dependency.
+    pub fn dependents<'a>(
+        &'a self,
+        unit: &'a UnitName,
+        kind: DependencyKind,
+    ) -> impl Iterator<Item = &'a UnitName> + 'a {
+        self.edges
+            .iter()
+            .filter(move |(_, k, to)| to == unit && *k == kind)
+            .map(|(from, _, _)| from)
+    }
+
+    /// Return the ordering constraints as `(first, then)` pairs, combining
+    /// `After=` and `Before=`.
+    fn ordering(&self) -> BTreeMap<&UnitName, BTreeSet<&UnitName>> {
+        let mut ordering: BTreeMap<&UnitName, BTreeSet<&UnitName>> = self
+            .units
+            .iter()
+            .map(|unit| (unit, BTreeSet::new()))
+            .collect();
+        for (from, kind, to) in &self.edges {
+            let (first, then) = match kind {
+                DependencyKind::After => (to, from),
+                DependencyKind::Before => (from, to),
+                _ => continue,
+            };
+            ordering.entry(first).or_default().insert(then);
+        }
+        ordering
+    }
+
+    /// Find an ordering cycle, if any.
+    ///
+    /// The cycle is returned as a list of units, each one ordered before
+    /// the next one, with the first unit repeated at the end.
+    pub fn find_cycle(&self) -> Option<Vec<UnitName>> {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Mark {
+            Visiting,
+            Done,
+        }
+
+        let ordering = self.ordering();
+        let mut marks: BTreeMap<&UnitName, Mark> = BTreeMap::new();
+        for root in ordering.keys() {
+            if marks.contains_key(root) {
+                continue;
+            }
+            // Depth-first search, with an explicit stack of iterators.
+            let mut path = vec![*root];
+            let mut stack = vec![ordering[root].iter()];
+            marks.insert(root, Mark::Visiting);
+            while let Some(children) = stack.last_mut() {
+                match children.next() {
+                    Some(child) => match marks.get(child) {
+                        Some(Mark::Visiting) => {
+                            let start = path.iter().position(|unit| unit == child)?;
+                            let mut cycle: Vec<UnitName> =
+                                path[start..].iter().map(|unit| (*unit).clone()).collect();
+                            cycle.push((*child).clone());
+                            return Some(cycle);
+                        }
+                        Some(Mark::Done) => {}
+                        None => {
+                            marks.insert(child, Mark::Visiting);
+                            path.push(child);
+                            stack.push(ordering[child].iter());
+                        }
+                    },
+                    None => {
+                        stack.pop();
+                        if let Some(unit) = path.pop() {
+                            marks.insert(unit, Mark::Done);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Return all units sorted so that each one comes after the units it is
+    /// ordered after.
+    ///
+    /// Units without ordering constraints between them are sorted by name.
+    /// This fails if there is an ordering cycle.
+    pub fn topological_order(&self) -> Result<Vec<&UnitName>, SdError> {
+        let ordering = self.ordering();
+        let mut pending: BTreeMap<&UnitName, usize> =
+            ordering.keys().map(|unit| (*unit, 0)).collect();
+        for then in ordering.values().flatten() {
+            *pending.entry(then).or_default() += 1;
+        }
+
+        let mut ready: BTreeSet<&UnitName> = pending
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(unit, _)| *unit)
+            .collect();
+        let mut order = Vec::with_capacity(pending.len());
+        while let Some(unit) = ready.iter().next().copied() {
+            ready.remove(unit);
+            order.push(unit);
+            for then in &ordering[unit] {
+                let count = pending.get_mut(then).expect("unit missing from graph");
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(then);
+                }
+            }
+        }
+
+        if order.len() < pending.len() {
+            let cycle = self
+                .find_cycle()
+                .map(|cycle| {
+                    let names: Vec<&str> = cycle.iter().map(|unit| unit.as_str()).collect();
+                    names.join(" -> ")
+                })
+                .unwrap_or_default();
+            return Err(format!("ordering cycle detected: {}", cycle).into());
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn name(name: &str) -> UnitName {
+        UnitName::new(name).unwrap()
+    }
+
+    fn names<'a>(units: impl IntoIterator<Item = &'a UnitName>) -> Vec<&'a str> {
+        units.into_iter().map(|unit| unit.as_str()).collect()
+    }
+
+    #[test]
+    fn test_add_unit() {
+        let mut graph = UnitGraph::new();
+        let unit = UnitFile::parse(
+            "[Unit]
+Requires=a.service b.service
+Wants=c.socket
+After=a.service
+Before=multi-user.target
+Conflicts=shutdown.target
+",
+        )
+        .unwrap();
+        let web = name("web.service");
+        graph.add_unit(&web, &unit).unwrap();
+
+        assert_eq!(
+            names(graph.dependencies(&web, DependencyKind::Requires)),
+            ["a.service", "b.service"]
+        );
+        assert_eq!(
+            names(graph.dependencies(&web, DependencyKind::Wants)),
+            ["c.socket"]
+        );
+        assert_eq!(
+            names(graph.dependencies(&web, DependencyKind::Conflicts)),
+            ["shutdown.target"]
+        );
+        let a = name("a.service");
+        assert_eq!(
+            names(graph.dependents(&a, DependencyKind::After)),
+            ["web.service"]
+        );
+        assert_eq!(graph.units().count(), 6);
+
+        let order = graph.topological_order().unwrap();
+        let order = names(order);
+        let position = |unit: &str| order.iter().position(|u| *u == unit).unwrap();
+        assert!(position("a.service") < position("web.service"));
+        assert!(position("web.service") < position("multi-user.target"));
+        assert!(graph.find_cycle().is_none());
+
+        let invalid = UnitFile::parse("[Unit]\nAfter=not-a-unit %H.service\n").unwrap();
+        graph.add_unit(&web, &invalid).unwrap();
+        let unresolved: Vec<_> = graph
+            .unresolved()
+            .map(|(unit, kind, value)| (unit.as_str(), *kind, value.as_str()))
+            .collect();
+        assert_eq!(
+            unresolved,
+            [
+                ("web.service", DependencyKind::After, "%H.service"),
+                ("web.service", DependencyKind::After, "not-a-unit")
+            ]
+        );
+        assert_eq!(graph.units().count(), 6);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut graph = UnitGraph::new();
+        graph.add_dependency(
+            &name("a.service"),
+            DependencyKind::After,
+            &name("b.service"),
+        );
+        graph.add_dependency(
+            &name("b.service"),
+            DependencyKind::After,
+            &name("c.service"),
+        );
+        graph.add_dependency(
+            &name("a.service"),
+            DependencyKind::Before,
+            &name("c.service"),
+        );
+        graph.add_dependency(
+            &name("d.service"),
+            DependencyKind::Wants,
+            &name("a.service"),
+        );
+
+        let cycle = graph.find_cycle().unwrap();
+        assert_eq!(
+            names(&cycle),
+            ["a.service", "c.service", "b.service", "a.service"]
+        );
+        let err = graph.topological_order().unwrap_err();
+        assert!(err.to_string().contains("ordering cycle"), "{}", err);
+    }
+
+    #[test]
+    fn test_from_search_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let etc = root.join("etc");
+        let usr = root.join("usr");
+        fs::create_dir_all(etc.join("multi-user.target.wants")).unwrap();
+        fs::create_dir_all(usr.join("local-fs.target.requires")).unwrap();
+        fs::write(
+            usr.join("multi-user.target"),
+            "[Unit]\nAfter=local-fs.target\n",
+        )
+        .unwrap();
+        fs::write(usr.join("local-fs.target"), "[Unit]\n").unwrap();
+        fs::write(usr.join("tmp.mount"), "[Unit]\nBefore=local-fs.target\n").unwrap();
+        fs::write(
+            usr.join("getty@.service"),
+            "[Unit]\nAfter=local-fs.target dev-%i.device\n",
+        )
+        .unwrap();
+        symlink("/dev/null", etc.join("masked.service")).unwrap();
+        symlink(
+            usr.join("getty@.service"),
+            etc.join("multi-user.target.wants/getty@tty1.service"),
+        )
+        .unwrap();
+        symlink(
+            usr.join("tmp.mount"),
+            usr.join("local-fs.target.requires/tmp.mount"),
+        )
+        .unwrap();
+
+        let graph = UnitGraph::from_search_paths(&[&etc, &usr]).unwrap();
+        assert_eq!(
+            names(graph.units()),
+            [
+                "dev-tty1.device",
+                "getty@tty1.service",
+                "local-fs.target",
+                "multi-user.target",
+                "tmp.mount"
+            ]
+        );
+        let target = name("multi-user.target");
+        assert_eq!(
+            names(graph.dependencies(&target, DependencyKind::Wants)),
+            ["getty@tty1.service"]
+        );
+        let local_fs = name("local-fs.target");
+        assert_eq!(
+            names(graph.dependencies(&local_fs, DependencyKind::Requires)),
+            ["tmp.mount"]
+        );
+        assert_eq!(
+            names(graph.topological_order().unwrap()),
+            [
+                "dev-tty1.device",
+                "tmp.mount",
+                "local-fs.target",
+                "getty@tty1.service",
+                "multi-user.target"
+            ]
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use super::file::{split_words, UnitFile};
+use super::name::UnitName;
+use crate::errors::{Context, SdError};
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+/// Default directory for system units enabled by the administrator.
+pub const SYSTEM_CONFIG_DIR: &str = "/etc/systemd/system";
+
+/// Settings from the `[Install]` section of a unit file.
+///
+/// These are only used by `systemctl enable`/`disable`, to manage the
+/// symlinks which hook a unit into the dependency tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstallSection {
+    wanted_by: Vec<String>,
+    required_by: Vec<String>,
+    upheld_by: Vec<String>,
+    alias: Vec<String>,
+    also: Vec<String>,
+    default_instance: Option<String>,
+}
+
+/// Symlink created when enabling a unit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symlink {
+    path: PathBuf,
+    target: PathBuf,
+}
+
+impl InstallSection {
+    /// Collect the `[Install]` settings of a unit file.
+    pub fn from_unit_file(unit: &UnitFile) -> Result<Self, SdError> {
+        let section = match unit.section("Install") {
+            Some(section) => section,
+            None => return Ok(Self::default()),
+        };
+        let words = |key: &str| -> Result<Vec<String>, SdError> {
+            let mut words = vec![];
+            for value in section.values(key) {
+                words.extend(split_words(value)?);
+            }
+            Ok(words)
+        };
+        Ok(Self {
+            wanted_by: words("WantedBy")?,
+            required_by: words("RequiredBy")?,
+            upheld_by: words("UpheldBy")?,
+            alias: words("Alias")?,
+            also: words("Also")?,
+            default_instance: section
+                .value("DefaultInstance")
+                .filter(|value| !value.is_empty())
+                .map(String::from),
+        })
+    }
+
+    /// Return the units listed in `WantedBy=`.
+    pub fn wanted_by(&self) -> &[String] {
+        &self.wanted_by
+    }
+
+    /// Return the units listed in `RequiredBy=`.
+    pub fn required_by(&self) -> &[String] {
+        &self.required_by
+    }
+
+    /// Return the units listed in `UpheldBy=`.
+    pub fn upheld_by(&self) -> &[String] {
+        &self.upheld_by
+    }
+
+    /// Return the names listed in `Alias=`.
+    pub fn alias(&self) -> &[String] {
+        &self.alias
+    }
+
+    /// Return the units listed in `Also=`.
+    pub fn also(&self) -> &[String] {
+        &self.also
+    }
+
+    /// Return the `DefaultInstance=` of a template unit.
+    pub fn default_instance(&self) -> Option<&str> {
+        self.default_instance.as_deref()
+    }
+
+    /// Whether the section has no settings creating symlinks or pulling in
+    /// other units, i.e. the unit is "static".
+    pub fn is_empty(&self) -> bool {
+        self.wanted_by.is_empty()
+            && self.required_by.is_empty()
+            && self.upheld_by.is_empty()
+            && self.alias.is_empty()
+            && self.also.is_empty()
+    }
+
+    /// Compute the symlinks `systemctl enable` would create for `name`.
+    ///
+    /// `unit_path` is the location of the unit file (e.g.
+    /// `/usr/lib/systemd/system/foo.service`) and `config_dir` the directory
+    /// holding the symlinks (e.g. [`SYSTEM_CONFIG_DIR`]), both as seen from
+    /// the target system. Templates are enabled with their `DefaultInstance=`,
+    /// if any, otherwise only their aliases are created.
+    ///
+    /// Units listed in `Also=` are not followed: they are returned by
+    /// [`also`](Self::also), for the caller to enable as well.
+    pub fn enable_symlinks(
+        &self,
+        name: &UnitName,
+        unit_path: impl AsRef<Path>,
+        config_dir: impl AsRef<Path>,
+    ) -> Result<Vec<Symlink>, SdError> {
+        let unit_path = unit_path.as_ref();
+        let config_dir = config_dir.as_ref();
+        let instance = match (name.is_template(), &self.default_instance) {
+            (true, Some(instance)) => Some(name.instantiate(&expand(instance, name)?)?),
+            _ => None,
+        };
+        let link_name = instance.as_ref().unwrap_or(name);
+
+        let mut symlinks = vec![];
+        let mut push = |path: PathBuf| {
+            let link = Symlink {
+                path,
+                target: unit_path.to_path_buf(),
+            };
+            if !symlinks.contains(&link) {
+                symlinks.push(link);
+            }
+        };
+
+        for alias in &self.alias {
+            let alias = UnitName::new(&expand(alias, name)?)?;
+            let alias = check_alias(name, alias)?;
+            push(config_dir.join(alias.as_str()));
+        }
+        if !link_name.is_template() {
+            let dependencies = [
+                (&self.wanted_by, "wants"),
+                (&self.required_by, "requires"),
+                (&self.upheld_by, "upholds"),
+            ];
+            for (units, kind) in dependencies {
+                for unit in units {
+                    let unit = UnitName::new(&expand(unit, link_name)?)?;
+                    let dir = format!("{}.{}", unit.as_str(), kind);
+                    push(config_dir.join(dir).join(link_name.as_str()));
+                }
+            }
+        }
+        Ok(symlinks)
+    }
+}
+
+/// Validate an alias, instantiating template aliases for instances.
+fn check_alias(name: &UnitName, alias: UnitName) -> Result<UnitName, SdError> {
+    if alias.unit_type() != name.unit_type() {
+        return Err(format!(
+            "alias '{}' of unit '{}' has a different type",
+            alias.as_str(),
+            name.as_str()
+        )
+        .into());
+    }
+    match (name.instance(), alias.is_template()) {
+        (Some(instance), true) => alias.instantiate(instance),
+        (_, template) if template == name.is_template() => Ok(alias),
+        _ => Err(format!(
+            "alias '{}' and unit '{}' must both be templates or not",
+            alias.as_str(),
+            name.as_str()
+        )
+        .into()),
+    }
+}
+
+/// Expand the unit name specifiers supported in `[Install]` settings.
+pub(super) fn expand(value: &str, name: &UnitName) -> Result<String, SdError> {
+    let mut output = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => output.push('%'),
+            Some('n') => output.push_str(name.as_str()),
+            Some('N') => output.push_str(name.as_str().rsplit_once('.').map_or("", |(s, _)| s)),
+            Some('p') => output.push_str(name.prefix()),
+            Some('i') => output.push_str(name.instance().unwrap_or_default()),
+            Some(other) => {
+                return Err(format!("unsupported specifier '%{}' in '{}'", other, value).into())
+            }
+            None => return Err(format!("incomplete specifier in '{}'", value).into()),
+        }
+    }
+    Ok(output)
+}
+
+impl Symlink {
+    /// Return the path of the symlink.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the target of the symlink.
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// Create this symlink under `root`, creating parent directories.
+    ///
+    /// An existing symlink with the same target is left untouched, while
+    /// any other existing file is an error.
+    pub fn create(&self, root: impl AsRef<Path>) -> Result<(), SdError> {
+        let path = self.path_under(root.as_ref());
+        match fs::read_link(&path) {
+            Ok(target) if target == self.target => return Ok(()),
+            Ok(_) => {
+                return Err(
+                    format!("'{}' already exists with another target", path.display()).into(),
+                )
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(format!(
+                    "'{}' already exists and is not a symlink: {}",
+                    path.display(),
+                    e
+                )
+                .into())
+            }
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create '{}'", parent.display()))?;
+        }
+        symlink(&self.target, &path)
+            .with_context(|| format!("failed to create symlink '{}'", path.display()))
+    }
+
+    /// Remove this symlink from `root`, if it exists and has the expected target.
+    ///
+    /// Returns whether the symlink was removed.
+    pub fn remove(&self, root: impl AsRef<Path>) -> Result<bool, SdError> {
+        let path = self.path_under(root.as_ref());
+        match fs::read_link(&path) {
+            Ok(target) if target == self.target => {
+                fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove '{}'", path.display()))?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn path_under(&self, root: &Path) -> PathBuf {
+        root.join(self.path.strip_prefix("/").unwrap_or(&self.path))
+    }
+}
+
+/// Create all `symlinks` under `root`, like `systemctl --root enable`.
+pub fn apply_symlinks(symlinks: &[Symlink], root: impl AsRef<Path>) -> Result<(), SdError> {
+    symlinks
+        .iter()
+        .try_for_each(|link| link.create(root.as_ref()))
+}
+
+/// Remove all `symlinks` from `root`, like `systemctl --root disable`.
+pub fn remove_symlinks(symlinks: &[Symlink], root: impl AsRef<Path>) -> Result<(), SdError> {
+    for link in symlinks {
+        link.remove(root.as_ref())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const UNIT_DIR: &str = "/usr/lib/systemd/system";
+
+    fn links(unit: &str, name: &str) -> Vec<(String, String)> {
+        let install = InstallSection::from_unit_file(&UnitFile::parse(unit).unwrap()).unwrap();
+        let name = UnitName::new(name).unwrap();
+        let unit_path =
+            Path::new(UNIT_DIR).join(name.template().as_ref().unwrap_or(&name).as_str());
+        install
+            .enable_symlinks(&name, unit_path, SYSTEM_CONFIG_DIR)
+            .unwrap()
+            .into_iter()
+            .map(|link| {
+                let path = link.path().strip_prefix(SYSTEM_CONFIG_DIR).unwrap();
+                let target = link.target().strip_prefix(UNIT_DIR).unwrap();
+                (path.display().to_string(), target.display().to_string())
+            })
+            .collect()
+    }
+
+    fn pair(path: &str, target: &str) -> (String, String) {
+        (path.to_string(), target.to_string())
+    }
+
+    #[test]
+    fn test_enable_symlinks() {
+        let unit = "[Install]
+WantedBy=multi-user.target graphical.target
+RequiredBy=
+RequiredBy=network.target
+Alias=bar.service
+Also=baz.socket
+";
+        assert_eq!(
+            links(unit, "foo.service"),
+            vec![
+                pair("bar.service", "foo.service"),
+                pair("multi-user.target.wants/foo.service", "foo.service"),
+                pair("graphical.target.wants/foo.service", "foo.service"),
+                pair("network.target.requires/foo.service", "foo.service"),
+            ]
+        );
+        let install = InstallSection::from_unit_file(&UnitFile::parse(unit).unwrap()).unwrap();
+        assert_eq!(install.also(), ["baz.socket"]);
+        assert!(!install.is_empty());
+
+        let template = "[Install]
+WantedBy=getty.target
+Alias=tty@.service
+DefaultInstance=tty1
+";
+        assert_eq!(
+            links(template, "getty@.service"),
+            vec![
+                pair("tty@.service", "getty@.service"),
+                pair("getty.target.wants/getty@tty1.service", "getty@.service"),
+            ]
+        );
+        assert_eq!(
+            links(template, "getty@tty2.service"),
+            vec![
+                pair("tty@tty2.service", "getty@.service"),
+                pair("getty.target.wants/getty@tty2.service", "getty@.service"),
+            ]
+        );
+        assert_eq!(
+            links("[Install]\nWantedBy=sockets.target\n", "foo@.socket"),
+            vec![]
+        );
+        assert_eq!(
+            links(
+                "[Install]\nAlias=%p-alias.service\nWantedBy=%i.target\n",
+                "foo@x.service"
+            ),
+            vec![
+                pair("foo-alias.service", "foo@.service"),
+                pair("x.target.wants/foo@x.service", "foo@.service"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_alias() {
+        let name = UnitName::new("foo.service").unwrap();
+        for unit in [
+            "[Install]\nAlias=foo.socket\n",
+            "[Install]\nAlias=foo@.service\n",
+            "[Install]\nAlias=%z.service\n",
+        ] {
+            let install = InstallSection::from_unit_file(&UnitFile::parse(unit).unwrap()).unwrap();
+            install
+                .enable_symlinks(&name, "/foo.service", SYSTEM_CONFIG_DIR)
+                .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_apply_remove() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let install = InstallSection::from_unit_file(
+            &UnitFile::parse("[Install]\nWantedBy=multi-user.target\nAlias=bar.service\n").unwrap(),
+        )
+        .unwrap();
+        let name = UnitName::new("foo.service").unwrap();
+        let links = install
+            .enable_symlinks(
+                &name,
+                "/usr/lib/systemd/system/foo.service",
+                SYSTEM_CONFIG_DIR,
+            )
+            .unwrap();
+
+        apply_symlinks(&links, root).unwrap();
+        apply_symlinks(&links, root).unwrap();
+        let wants = root.join("etc/systemd/system/multi-user.target.wants/foo.service");
+        assert_eq!(
+            fs::read_link(&wants).unwrap(),
+            Path::new("/usr/lib/systemd/system/foo.service")
+        );
+
+        let other = Symlink {
+            path: links[0].path().to_path_buf(),
+            target: PathBuf::from("/elsewhere"),
+        };
+        other.create(root).unwrap_err();
+        assert!(!other.remove(root).unwrap());
+
+        remove_symlinks(&links, root).unwrap();
+        assert!(fs::symlink_metadata(&wants).is_err());
+    }
+}
@@ -0,0 +1,429 @@
+use super::file::{is_masked, UnitFile};
+use super::install::InstallSection;
+use super::name::UnitName;
+use crate::activation::{EnvSource, ProcessEnv};
+use crate::errors::{Context, SdError};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Scope of a service manager instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// The system service manager (PID 1).
+    System,
+    /// The per-user service manager of the current user.
+    User,
+}
+
+/// Enablement state of a unit file, as reported by `systemctl list-unit-files`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnitFileState {
+    /// Enabled through symlinks in a persistent directory.
+    Enabled,
+    /// Enabled through symlinks in a runtime directory only.
+    EnabledRuntime,
+    /// Linked from outside the search path.
+    Linked,
+    /// Alias of another unit file.
+    Alias,
+    /// Masked persistently.
+    Masked,
+    /// Masked at runtime only.
+    MaskedRuntime,
+    /// Without `[Install]` section, cannot be enabled.
+    Static,
+    /// Can be enabled, but is not.
+    Disabled,
+    /// Created by a generator.
+    Generated,
+    /// Created at runtime through the manager API.
+    Transient,
+    /// The unit file could not be parsed.
+    Bad,
+}
+
+impl UnitFileState {
+    /// Return the state name, as used by `systemctl`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnitFileState::Enabled => "enabled",
+            UnitFileState::EnabledRuntime => "enabled-runtime",
+            UnitFileState::Linked => "linked",
+            UnitFileState::Alias => "alias",
+            UnitFileState::Masked => "masked",
+            UnitFileState::MaskedRuntime => "masked-runtime",
+            UnitFileState::Static => "static",
+            UnitFileState::Disabled => "disabled",
+            UnitFileState::Generated => "generated",
+            UnitFileState::Transient => "transient",
+            UnitFileState::Bad => "bad",
+        }
+    }
+}
+
+impl fmt::Display for UnitFileState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Return the unit search path for `scope`, from highest to lowest precedence.
+///
+/// This follows `systemd.unit(5)`, including the control, transient and
+/// generator directories, the XDG base directories for user units, and the
+/// `$SYSTEMD_UNIT_PATH` override (where a trailing `:` appends the defaults).
+pub fn search_paths(scope: Scope) -> Vec<PathBuf> {
+    search_paths_with_env(scope, &ProcessEnv)
+}
+
+fn search_paths_with_env(scope: Scope, env: &impl EnvSource) -> Vec<PathBuf> {
+    let defaults = match scope {
+        Scope::System => system_paths(),
+        Scope::User => user_paths(env),
+    };
+    let mut paths: Vec<PathBuf> = match env.get("SYSTEMD_UNIT_PATH") {
+        Some(custom) => {
+            let mut paths: Vec<PathBuf> = custom
+                .split(':')
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .collect();
+            if custom.ends_with(':') {
+                paths.extend(defaults);
+            }
+            paths
+        }
+        None => defaults,
+    };
+    let mut seen = HashSet::new();
+    paths.retain(|path| path.is_absolute() && seen.insert(path.clone()));
+    paths
+}
+
+fn system_paths() -> Vec<PathBuf> {
+    [
+        "/etc/systemd/system.control",
+        "/run/systemd/system.control",
+        "/run/systemd/transient",
+        "/run/systemd/generator.early",
+        "/etc/systemd/system",
+        "/etc/systemd/system.attached",
+        "/run/systemd/system",
+        "/run/systemd/system.attached",
+        "/run/systemd/generator",
+        "/usr/local/lib/systemd/system",
+        "/usr/lib/systemd/system",
+        "/lib/systemd/system",
+        "/run/systemd/generator.late",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect()
+}
+
+fn user_paths(env: &impl EnvSource) -> Vec<PathBuf> {
+    let var = |key: &str| env.get(key).filter(|value| value.starts_with('/'));
+    let home = var("HOME");
+    let xdg_home = |key: &str, default: &str| {
+        var(key)
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|home| Path::new(home).join(default)))
+    };
+    let xdg_dirs = |key: &str, default: &str| -> Vec<PathBuf> {
+        env.get(key)
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| default.to_string())
+            .split(':')
+            .filter(|dir| dir.starts_with('/'))
+            .map(|dir| Path::new(dir).join("systemd/user"))
+            .collect()
+    };
+    let config_home = xdg_home("XDG_CONFIG_HOME", ".config");
+    let data_home = xdg_home("XDG_DATA_HOME", ".local/share");
+    let runtime = var("XDG_RUNTIME_DIR").map(PathBuf::from);
+    let under = |base: &Option<PathBuf>, path: &str| base.as_ref().map(|base| base.join(path));
+
+    let mut paths = vec![];
+    paths.extend(under(&config_home, "systemd/user.control"));
+    paths.extend(under(&runtime, "systemd/user.control"));
+    paths.extend(under(&runtime, "systemd/transient"));
+    paths.extend(under(&runtime, "systemd/generator.early"));
+    paths.extend(under(&config_home, "systemd/user"));
+    paths.extend(xdg_dirs("XDG_CONFIG_DIRS", "/etc/xdg"));
+    paths.push(PathBuf::from("/etc/systemd/user"));
+    paths.extend(under(&runtime, "systemd/user"));
+    paths.push(PathBuf::from("/run/systemd/user"));
+    paths.extend(under(&runtime, "systemd/generator"));
+    paths.extend(under(&data_home, "systemd/user"));
+    paths.extend(xdg_dirs("XDG_DATA_DIRS", "/usr/local/share:/usr/share"));
+    paths.push(PathBuf::from("/usr/local/lib/systemd/user"));
+    paths.push(PathBuf::from("/usr/lib/systemd/user"));
+    paths.extend(under(&runtime, "systemd/generator.late"));
+    paths
+}
+
+/// List unit files in the default search path for `scope`.
+///
+/// See [`list_unit_files_in`].
+pub fn list_unit_files(scope: Scope) -> Result<Vec<(UnitName, PathBuf, UnitFileState)>, SdError> {
+    list_unit_files_in(&search_paths(scope))
+}
+
+/// List unit files found in `search_paths`, with their enablement state.
+///
+/// Directories are considered from the highest to the lowest precedence:
+/// a unit file overrides the ones with the same name in later directories.
+/// Results are sorted by unit name, without requiring a running manager.
+pub fn list_unit_files_in<P: AsRef<Path>>(
+    search_paths: &[P],
+) -> Result<Vec<(UnitName, PathBuf, UnitFileState)>, SdError> {
+    let dirs: Vec<&Path> = search_paths.iter().map(AsRef::as_ref).collect();
+
+    let mut units = BTreeMap::new();
+    // Names linked from `.wants/`, `.requires/` and `.upholds/` directories,
+    // and whether all such links are at runtime.
+    let mut linked: BTreeMap<String, bool> = BTreeMap::new();
+    for dir in &dirs {
+        for (file_name, path) in read_dir_sorted(dir)? {
+            let is_dependency_dir = [".wants", ".requires", ".upholds"]
+                .iter()
+                .any(|suffix| file_name.ends_with(suffix));
+            if is_dependency_dir && path.is_dir() {
+                for (link, _) in read_dir_sorted(&path)? {
+                    let runtime = linked.entry(link).or_insert(true);
+                    *runtime &= is_runtime(dir);
+                }
+                continue;
+            }
+            let name = match UnitName::new(&file_name) {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            units.entry(file_name).or_insert((name, path, *dir));
+        }
+    }
+
+    let mut result = vec![];
+    for (_, (name, path, dir)) in units {
+        let state = unit_file_state(&name, &path, dir, &dirs, &linked);
+        result.push((name, path, state));
+    }
+    Ok(result)
+}
+
+/// Return the `(file name, path)` pairs in a directory, ignoring missing ones.
+fn read_dir_sorted(dir: &Path) -> Result<Vec<(String, PathBuf)>, SdError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound || e.raw_os_error() == Some(libc::ENOTDIR) => {
+            return Ok(vec![])
+        }
+        Err(e) => {
+            return Err(format!("failed to read directory '{}': {}", dir.display(), e).into())
+        }
+    };
+    let mut result = vec![];
+    for entry in entries {
+        let entry =
+            entry.with_context(|| format!("failed to read directory '{}'", dir.display()))?;
+        if let Some(name) = entry.file_name().to_str() {
+            result.push((name.to_string(), entry.path()));
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+fn is_runtime(dir: &Path) -> bool {
+    dir.starts_with("/run")
+}
+
+fn unit_file_state(
+    name: &UnitName,
+    path: &Path,
+    dir: &Path,
+    dirs: &[&Path],
+    linked: &BTreeMap<String, bool>,
+) -> UnitFileState {
+    if is_masked(path) {
+        return if is_runtime(dir) {
+            UnitFileState::MaskedRuntime
+        } else {
+            UnitFileState::Masked
+        };
+    }
+    let dir_name = dir.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    if dir_name.starts_with("generator") {
+        return UnitFileState::Generated;
+    }
+    if dir_name == "transient" {
+        return UnitFileState::Transient;
+    }
+    if let Ok(target) = fs::read_link(path) {
+        let target = dir.join(target);
+        if target.file_name() != path.file_name() {
+            return UnitFileState::Alias;
+        }
+        if !dirs.iter().any(|dir| target.parent() == Some(dir)) {
+            return UnitFileState::Linked;
+        }
+    }
+
+    let install =
+        match UnitFile::from_path(path).and_then(|unit| InstallSection::from_unit_file(&unit)) {
+            Ok(install) => install,
+            Err(_) => return UnitFileState::Bad,
+        };
+    if install.is_empty() {
+        return UnitFileState::Static;
+    }
+
+    // Templates are enabled through any of their instances.
+    let template_prefix = name.is_template().then(|| format!("{}@", name.prefix()));
+    let suffix = format!(".{}", name.suffix());
+    let mut links = linked.iter().filter(|(link, _)| {
+        *link == name.as_str()
+            || template_prefix.as_ref().map_or(false, |prefix| {
+                link.starts_with(prefix) && link.ends_with(&suffix)
+            })
+    });
+    let aliased = install.alias().iter().any(|alias| {
+        dirs.iter()
+            .filter(|dir| !is_runtime(dir))
+            .any(|dir| dir.join(alias).symlink_metadata().is_ok())
+    });
+    match links.next() {
+        _ if aliased => UnitFileState::Enabled,
+        Some((_, runtime)) if *runtime && links.all(|(_, runtime)| *runtime) => {
+            UnitFileState::EnabledRuntime
+        }
+        Some(_) => UnitFileState::Enabled,
+        None => UnitFileState::Disabled,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_search_paths() {
+        let system = search_paths_with_env(Scope::System, &HashMap::new());
+        assert_eq!(system[0], Path::new("/etc/systemd/system.control"));
+        let etc = system
+            .iter()
+            .position(|p| p == Path::new("/etc/systemd/system"));
+        let usr = system
+            .iter()
+            .position(|p| p == Path::new("/usr/lib/systemd/system"));
+        assert!(etc < usr);
+        assert_eq!(
+            system.last().unwrap(),
+            Path::new("/run/systemd/generator.late")
+        );
+
+        let env: HashMap<String, String> = [
+            ("HOME", "/home/user"),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+            ("XDG_DATA_DIRS", "/usr/share"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let user = search_paths_with_env(Scope::User, &env);
+        assert_eq!(
+            user[0],
+            Path::new("/home/user/.config/systemd/user.control")
+        );
+        assert!(user.contains(&PathBuf::from("/home/user/.local/share/systemd/user")));
+        assert!(user.contains(&PathBuf::from("/etc/xdg/systemd/user")));
+        assert!(user.contains(&PathBuf::from("/usr/share/systemd/user")));
+        assert_eq!(
+            user.last().unwrap(),
+            Path::new("/run/user/1000/systemd/generator.late")
+        );
+
+        let mut env = HashMap::new();
+        env.insert(
+            "SYSTEMD_UNIT_PATH".to_string(),
+            "/custom:relative".to_string(),
+        );
+        assert_eq!(
+            search_paths_with_env(Scope::System, &env),
+            vec![PathBuf::from("/custom")]
+        );
+        env.insert("SYSTEMD_UNIT_PATH".to_string(), "/custom:".to_string());
+        let paths = search_paths_with_env(Scope::System, &env);
+        assert_eq!(paths[0], Path::new("/custom"));
+        assert_eq!(paths.len(), system.len() + 1);
+    }
+
+    #[test]
+    fn test_list_unit_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let etc = root.join("etc");
+        let usr = root.join("usr");
+        let generator = root.join("generator");
+        for dir in [&etc, &usr, &generator] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let write = |path: PathBuf, content: &str| fs::write(path, content).unwrap();
+        let install = "[Install]\nWantedBy=multi-user.target\nAlias=alias.service\n";
+        write(usr.join("enabled.service"), install);
+        write(usr.join("disabled.service"), install);
+        write(usr.join("static.service"), "[Unit]\nDescription=Static\n");
+        write(usr.join("bad.service"), "[Unit\n");
+        write(usr.join("overridden.service"), install);
+        write(etc.join("overridden.service"), "[Unit]\n");
+        write(
+            usr.join("getty@.service"),
+            "[Install]\nWantedBy=getty.target\n",
+        );
+        write(generator.join("generated.mount"), "[Mount]\n");
+        write(usr.join("not-a-unit.txt"), "");
+        symlink("/dev/null", etc.join("masked.service")).unwrap();
+        symlink("enabled.service", usr.join("other.service")).unwrap();
+        fs::create_dir_all(etc.join("multi-user.target.wants")).unwrap();
+        symlink(
+            usr.join("enabled.service"),
+            etc.join("multi-user.target.wants/enabled.service"),
+        )
+        .unwrap();
+        fs::create_dir_all(etc.join("getty.target.wants")).unwrap();
+        symlink(
+            usr.join("getty@.service"),
+            etc.join("getty.target.wants/getty@tty1.service"),
+        )
+        .unwrap();
+
+        let units = list_unit_files_in(&[&etc, &generator, &usr]).unwrap();
+        let states: Vec<(&str, UnitFileState)> = units
+            .iter()
+            .map(|(name, _, state)| (name.as_str(), *state))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("bad.service", UnitFileState::Bad),
+                ("disabled.service", UnitFileState::Disabled),
+                ("enabled.service", UnitFileState::Enabled),
+                ("generated.mount", UnitFileState::Generated),
+                ("getty@.service", UnitFileState::Enabled),
+                ("masked.service", UnitFileState::Masked),
+                ("other.service", UnitFileState::Alias),
+                ("overridden.service", UnitFileState::Static),
+                ("static.service", UnitFileState::Static),
+            ]
+        );
+        let overridden = units
+            .iter()
+            .find(|(name, _, _)| name.as_str() == "overridden.service");
+        assert_eq!(overridden.unwrap().1, etc.join("overridden.service"));
+    }
+}
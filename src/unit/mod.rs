@@ -0,0 +1,263 @@
+use crate::errors::SdError;
+pub use calendar::CalendarSpec;
+pub(crate) use condition::native_architecture;
+pub use condition::{Condition, ConditionKind, Conditions, Verdict};
+pub use install::{apply_symlinks, remove_symlinks, InstallSection, Symlink, SYSTEM_CONFIG_DIR};
+pub use lookup::{list_unit_files, list_unit_files_in, search_paths, Scope, UnitFileState};
+pub use name::{instantiate, validate_name, UnitName, UnitNameError, UnitType, ValidatedName};
+pub use timespan::{format_timespan, parse_timespan};
+pub use timestamp::Timestamp;
+
+mod calendar;
+mod condition;
+mod datetime;
+pub mod file;
+pub mod graph;
+mod install;
+mod lookup;
+mod name;
+mod timespan;
+mod timestamp;
+
+/// Unit name escaping, like `systemd-escape`.
+///
+/// Slashes are turned into dashes, a leading dot and all characters other
+/// than ASCII alphanumerics, `:`, `_` and `.` are escaped as `\xNN`.
+pub fn escape(name: &str) -> String {
+    name.bytes()
+        .enumerate()
+        .map(|(n, b)| escape_byte(b, n))
+        .collect()
+}
+
+/// Unit name escaping, like `systemd-escape`.
+#[deprecated(since = "0.8.0", note = "use `escape` instead")]
+pub fn escape_name(name: &str) -> String {
+    escape(name)
+}
+
+/// Path escaping, like `systemd-escape --path`.
+///
+/// Redundant slashes are dropped, and the root directory is escaped as `-`.
+/// Other components are kept as-is: a `.` component is escaped as `\x2e`,
+/// and `..` components are not rejected. Use [`try_escape_path`] to refuse
+/// paths which [`unescape_path`] cannot turn back into the same path.
+pub fn escape_path(name: &str) -> String {
+    let components: Vec<&str> = name.split('/').filter(|c| !c.is_empty()).collect();
+    if components.is_empty() {
+        return "-".to_string();
+    }
+
+    escape(&components.join("/"))
+}
+
+/// Path escaping, like `systemd-escape --path`, rejecting non-normalized paths.
+///
+/// This works like [`escape_path`], but fails on paths with `.` or `..`
+/// components, which systemd does not accept in path-based unit names.
+pub fn try_escape_path(name: &str) -> Result<String, SdError> {
+    if let Some(c) = name.split('/').find(|c| *c == "." || *c == "..") {
+        return Err(format!("path '{}' contains a '{}' component", name, c).into());
+    }
+
+    Ok(escape_path(name))
+}
+
+/// Unit name unescaping, like `systemd-escape --unescape`.
+///
+/// Dashes are turned into slashes, and `\xNN` sequences into the
+/// corresponding bytes, which must form valid UTF-8.
+pub fn unescape(name: &str) -> Result<String, SdError> {
+    let input = name.as_bytes();
+    let mut output = Vec::with_capacity(input.len());
+    let mut index = 0;
+    while index < input.len() {
+        match input[index] {
+            b'-' => output.push(b'/'),
+            b'\\' => {
+                let byte = input
+                    .get(index + 1..index + 4)
+                    .filter(|seq| seq[0] == b'x')
+                    .and_then(|seq| std::str::from_utf8(&seq[1..]).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("invalid escape sequence at offset {}", index))?;
+                output.push(byte);
+                index += 3;
+            }
+            b => output.push(b),
+        }
+        index += 1;
+    }
+
+    String::from_utf8(output).map_err(|e| format!("unescaped name is not UTF-8: {}", e).into())
+}
+
+/// Path unescaping, like `systemd-escape --unescape --path`.
+///
+/// The result is an absolute path, which must be normalized (i.e. without
+/// `.` or `..` components, nor redundant slashes).
+pub fn unescape_path(name: &str) -> Result<String, SdError> {
+    if name == "-" {
+        return Ok("/".to_string());
+    }
+
+    let path = unescape(name)?;
+    let normalized = path
+        .split('/')
+        .all(|c| !c.is_empty() && c != "." && c != "..");
+    if !normalized {
+        return Err(format!("unescaped path '/{}' is not normalized", path).into());
+    }
+
+    Ok(format!("/{}", path))
+}
+
+fn escape_byte(b: u8, index: usize) -> String {
+    let c = char::from(b);
+    match c {
+        '/' => '-'.to_string(),
+        ':' | '_' | '0'..='9' | 'a'..='z' | 'A'..='Z' => c.to_string(),
+        '.' if index > 0 => c.to_string(),
+        _ => format!(r#"\x{:02x}"#, b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::unit::*;
+    use quickcheck::quickcheck;
+
+    quickcheck! {
+        fn test_byte_escape_length(xs: u8, n: usize) -> bool {
+            let out = escape_byte(xs, n);
+            out.len() == 1 || out.len() == 4
+        }
+    }
+
+    #[test]
+    fn test_name_escape() {
+        let cases = vec![
+            // leave empty string empty
+            (r#""#, r#""#),
+            // escape leading dot
+            (r#".foo/.bar"#, r#"\x2efoo-.bar"#),
+            // escape disallowed
+            (r#"///..\-!#??///"#, r#"---..\x5c\x2d\x21\x23\x3f\x3f---"#),
+            // escape real-world example
+            (
+                r#"user-cloudinit@/var/lib/coreos/vagrant/vagrantfile-user-data.service"#,
+                r#"user\x2dcloudinit\x40-var-lib-coreos-vagrant-vagrantfile\x2duser\x2ddata.service"#,
+            ),
+        ];
+
+        for t in cases {
+            let res = escape(t.0);
+            assert_eq!(res, t.1.to_string());
+            assert_eq!(unescape(&res).unwrap(), t.0);
+        }
+    }
+
+    #[test]
+    fn test_path_escape() {
+        let cases = vec![
+            // turn empty string path into escaped /
+            (r#""#, r#"-"#),
+            // turn redundant ////s into single escaped /
+            (r#"/////////"#, r#"-"#),
+            // remove all redundant ////s
+            (r#"///foo////bar/////tail//////"#, r#"foo-bar-tail"#),
+            // escape leading dot
+            (r#"."#, r#"\x2e"#),
+            (r#"/."#, r#"\x2e"#),
+            (r#"/////////.///////////////"#, r#"\x2e"#),
+            (r#"....."#, r#"\x2e...."#),
+            (r#"/.foo/.bar"#, r#"\x2efoo-.bar"#),
+            (r#".foo/.bar"#, r#"\x2efoo-.bar"#),
+            // escape disallowed
+            (r#"///..\-!#??///"#, r#"\x2e.\x5c\x2d\x21\x23\x3f\x3f"#),
+        ];
+
+        for t in cases {
+            let res = escape_path(t.0);
+            assert_eq!(res, t.1.to_string());
+        }
+    }
+
+    #[test]
+    fn test_try_escape_path() {
+        assert_eq!(try_escape_path("/foo//bar/").unwrap(), "foo-bar");
+        assert_eq!(try_escape_path("/").unwrap(), "-");
+        assert_eq!(try_escape_path("/foo../.bar").unwrap(), r#"foo..-.bar"#);
+        for invalid in &["/foo/../bar", "..", "/foo/.", "./foo"] {
+            try_escape_path(invalid).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_unescape() {
+        let cases = vec![
+            (r#""#, r#""#),
+            (r#"foo-bar\x2dbaz\x2e"#, r#"foo/bar-baz."#),
+            (r#"\x41b"#, r#"Ab"#),
+            (r#"h\xc3\xa9llo"#, r#"héllo"#),
+        ];
+        for t in cases {
+            assert_eq!(unescape(t.0).unwrap(), t.1);
+        }
+
+        for invalid in &[r#"a\xzz"#, r#"a\n"#, r#"a\x4"#, r#"\xff"#] {
+            unescape(invalid).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_unescape_path() {
+        let cases = vec![
+            (r#"-"#, r#"/"#),
+            (r#"foo-bar"#, r#"/foo/bar"#),
+            (r#"\x2efoo-.bar"#, r#"/.foo/.bar"#),
+            (r#"foo\x2e\x2e"#, r#"/foo.."#),
+        ];
+        for t in cases {
+            assert_eq!(unescape_path(t.0).unwrap(), t.1);
+        }
+
+        for invalid in &[r#"foo--bar"#, r#"foo-"#, r#"foo-..-bar"#, r#"\x2e"#] {
+            unescape_path(invalid).unwrap_err();
+        }
+    }
+
+    quickcheck! {
+        fn test_escape_roundtrip(xs: String) -> bool {
+            unescape(&escape(&xs)).unwrap() == xs
+        }
+    }
+
+    quickcheck! {
+        fn test_path_escape_nonempty(xs: String) -> bool {
+            let out = escape_path(&xs);
+            !out.is_empty()
+        }
+    }
+
+    quickcheck! {
+        fn test_path_escape_no_slash(xs: String) -> bool {
+            let out = escape_path(&xs);
+            !out.contains('/')
+        }
+    }
+
+    quickcheck! {
+        fn test_path_escape_no_dash_runs(xs: String) -> bool {
+            let out = escape_path(&xs);
+            !out.contains("--")
+        }
+    }
+
+    quickcheck! {
+        fn test_path_escape_no_leading_dot(xs: String) -> bool {
+            let out = escape_path(&xs);
+            !out.starts_with('.')
+        }
+    }
+}
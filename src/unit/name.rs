@@ -0,0 +1,510 @@
+use crate::errors::SdError;
+use std::fmt;
+use std::str::FromStr;
+
+/// Maximum length of a unit name.
+pub(crate) const UNIT_NAME_MAX: usize = 255;
+
+/// Characters allowed in unit name prefixes, besides ASCII alphanumerics.
+const PREFIX_EXTRA_CHARS: &[u8] = b":-_.\\";
+
+/// Type of a unit, as given by its name suffix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum UnitType {
+    Service,
+    Socket,
+    Target,
+    Mount,
+    Automount,
+    Swap,
+    Timer,
+    Path,
+    Slice,
+    Scope,
+    Device,
+}
+
+impl UnitType {
+    /// All unit types.
+    pub const ALL: [UnitType; 11] = [
+        UnitType::Service,
+        UnitType::Socket,
+        UnitType::Target,
+        UnitType::Mount,
+        UnitType::Automount,
+        UnitType::Swap,
+        UnitType::Timer,
+        UnitType::Path,
+        UnitType::Slice,
+        UnitType::Scope,
+        UnitType::Device,
+    ];
+
+    /// Return the name suffix for this type, without the leading dot.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnitType::Service => "service",
+            UnitType::Socket => "socket",
+            UnitType::Target => "target",
+            UnitType::Mount => "mount",
+            UnitType::Automount => "automount",
+            UnitType::Swap => "swap",
+            UnitType::Timer => "timer",
+            UnitType::Path => "path",
+            UnitType::Slice => "slice",
+            UnitType::Scope => "scope",
+            UnitType::Device => "device",
+        }
+    }
+
+    /// Whether units of this type can be templates, like `foo@.service`.
+    pub fn may_template(&self) -> bool {
+        matches!(
+            self,
+            UnitType::Service
+                | UnitType::Socket
+                | UnitType::Target
+                | UnitType::Timer
+                | UnitType::Path
+        )
+    }
+
+    /// Return the type of the unit `name`, based on its suffix.
+    pub fn from_unit_name(name: &str) -> Result<Self, SdError> {
+        let (_, suffix) = name
+            .rsplit_once('.')
+            .ok_or_else(|| format!("unit name '{}' has no type suffix", name))?;
+        suffix.parse()
+    }
+}
+
+impl FromStr for UnitType {
+    type Err = SdError;
+
+    /// Parse a unit type from its suffix (e.g. `service`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        UnitType::ALL
+            .iter()
+            .find(|t| t.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown unit type '{}'", s).into())
+    }
+}
+
+impl fmt::Display for UnitType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Unit name, possibly a template (`foo@.service`) or an instance of one
+/// (`foo@bar.service`).
+///
+/// # Examples
+///
+/// ```rust
+/// # fn doctest_unit_name() -> Result<(), libsystemd::errors::SdError> {
+/// use libsystemd::unit::UnitName;
+///
+/// let template: UnitName = "getty@.service".parse()?;
+/// assert!(template.is_template());
+/// let instance = template.instantiate("tty1")?;
+/// assert_eq!(instance.as_str(), "getty@tty1.service");
+/// assert_eq!(instance.instance(), Some("tty1"));
+/// assert_eq!(instance.template(), Some(template));
+/// # Ok(())
+/// # }
+/// # doctest_unit_name().unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct UnitName {
+    name: String,
+}
+
+impl UnitName {
+    /// Parse and validate a unit name.
+    ///
+    /// See [`validate_name`] for detailed validation errors.
+    pub fn new(name: &str) -> Result<Self, SdError> {
+        validate_name(name)
+            .map(ValidatedName::into_name)
+            .map_err(|e| format!("invalid unit name '{}': {}", name, e).into())
+    }
+
+    /// Return the full unit name.
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the unit name without instance and type suffix (e.g. `foo`
+    /// for `foo@bar.service`).
+    pub fn prefix(&self) -> &str {
+        let stem = self.stem();
+        stem.split_once('@').map_or(stem, |(prefix, _)| prefix)
+    }
+
+    /// Return the type suffix, without the leading dot (e.g. `service`).
+    pub fn suffix(&self) -> &str {
+        self.name.rsplit_once('.').map_or("", |(_, suffix)| suffix)
+    }
+
+    /// Return the unit type.
+    pub fn unit_type(&self) -> UnitType {
+        // The suffix is validated on construction.
+        self.suffix()
+            .parse()
+            .expect("unit name with invalid type suffix")
+    }
+
+    /// Whether this is a template unit name, like `foo@.service`.
+    pub fn is_template(&self) -> bool {
+        self.raw_instance() == Some("")
+    }
+
+    /// Whether this is an instance of a template, like `foo@bar.service`.
+    pub fn is_instance(&self) -> bool {
+        self.instance().is_some()
+    }
+
+    /// Return the instance name of an instantiated unit (e.g. `bar` for
+    /// `foo@bar.service`).
+    pub fn instance(&self) -> Option<&str> {
+        self.raw_instance().filter(|instance| !instance.is_empty())
+    }
+
+    /// Return the template of a template or instance unit name (e.g.
+    /// `foo@.service` for `foo@bar.service`).
+    pub fn template(&self) -> Option<UnitName> {
+        self.raw_instance()?;
+        Some(UnitName {
+            name: format!("{}@.{}", self.prefix(), self.suffix()),
+        })
+    }
+
+    /// Instantiate a template unit name with the given instance.
+    ///
+    /// Instance names are not escaped: use [`escape`](super::escape) first
+    /// to derive them from arbitrary strings.
+    pub fn instantiate(&self, instance: &str) -> Result<UnitName, SdError> {
+        if !self.is_template() {
+            return Err(format!("unit name '{}' is not a template", self.name).into());
+        }
+        if instance.is_empty() {
+            return Err("empty instance name".into());
+        }
+        UnitName::new(&format!("{}@{}.{}", self.prefix(), instance, self.suffix()))
+    }
+
+    /// Return the unit name without the type suffix.
+    fn stem(&self) -> &str {
+        self.name.rsplit_once('.').map_or("", |(stem, _)| stem)
+    }
+
+    /// Return the instance part, empty for templates.
+    fn raw_instance(&self) -> Option<&str> {
+        self.stem().split_once('@').map(|(_, instance)| instance)
+    }
+}
+
+/// Instantiate the template unit `template` (e.g. `foo@.service`) with
+/// the given instance.
+pub fn instantiate(template: &str, instance: &str) -> Result<UnitName, SdError> {
+    UnitName::new(template)?.instantiate(instance)
+}
+
+/// Reason why a unit name is invalid.
+#[derive(thiserror::Error, Clone, Debug, PartialEq, Eq)]
+pub enum UnitNameError {
+    /// The name is empty.
+    #[error("empty unit name")]
+    Empty,
+    /// The name is longer than the maximum length.
+    #[error("unit name is {len} characters long, the maximum is {max}")]
+    TooLong { len: usize, max: usize },
+    /// The name has no `.type` suffix.
+    #[error("missing unit type suffix")]
+    MissingSuffix,
+    /// The suffix is not a known unit type.
+    #[error("unknown unit type suffix '{0}'")]
+    InvalidSuffix(String),
+    /// The part before the instance or suffix is empty.
+    #[error("empty unit name prefix")]
+    EmptyPrefix,
+    /// The prefix contains a forbidden character, at the given byte offset.
+    #[error("invalid character {character:?} at position {position}")]
+    InvalidPrefixCharacter { character: char, position: usize },
+    /// The instance contains a forbidden character, at the given byte offset.
+    #[error("invalid character {character:?} in instance at position {position}")]
+    InvalidInstanceCharacter { character: char, position: usize },
+    /// Units of this type cannot be templated.
+    #[error("{0} units do not support templates")]
+    TemplateNotSupported(UnitType),
+}
+
+impl From<UnitNameError> for SdError {
+    fn from(arg: UnitNameError) -> Self {
+        arg.to_string().into()
+    }
+}
+
+/// Unit name which passed validation, by kind.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ValidatedName {
+    /// A regular unit name, like `foo.service`.
+    Plain(UnitName),
+    /// A template unit name, like `foo@.service`.
+    Template(UnitName),
+    /// An instance of a template, like `foo@bar.service`.
+    Instance(UnitName),
+}
+
+impl ValidatedName {
+    /// Return the validated unit name.
+    pub fn name(&self) -> &UnitName {
+        match self {
+            ValidatedName::Plain(name)
+            | ValidatedName::Template(name)
+            | ValidatedName::Instance(name) => name,
+        }
+    }
+
+    /// Convert into the validated unit name.
+    pub fn into_name(self) -> UnitName {
+        match self {
+            ValidatedName::Plain(name)
+            | ValidatedName::Template(name)
+            | ValidatedName::Instance(name) => name,
+        }
+    }
+}
+
+/// Validate a unit name, with a precise error on failure.
+///
+/// This checks the length limit, the type suffix, the allowed characters
+/// in the prefix (ASCII alphanumerics and `:-_.\`) and in the instance
+/// (additionally `@`), and whether the unit type supports templates.
+pub fn validate_name(name: &str) -> Result<ValidatedName, UnitNameError> {
+    if name.is_empty() {
+        return Err(UnitNameError::Empty);
+    }
+    if name.len() > UNIT_NAME_MAX {
+        return Err(UnitNameError::TooLong {
+            len: name.len(),
+            max: UNIT_NAME_MAX,
+        });
+    }
+    let (stem, suffix) = name.rsplit_once('.').ok_or(UnitNameError::MissingSuffix)?;
+    let unit_type: UnitType = suffix
+        .parse()
+        .map_err(|_| UnitNameError::InvalidSuffix(suffix.to_string()))?;
+
+    let (prefix, instance) = match stem.split_once('@') {
+        Some((prefix, instance)) => (prefix, Some(instance)),
+        None => (stem, None),
+    };
+    if prefix.is_empty() {
+        return Err(UnitNameError::EmptyPrefix);
+    }
+    if let Some(position) = prefix.bytes().position(|b| !is_prefix_char(b)) {
+        return Err(UnitNameError::InvalidPrefixCharacter {
+            character: char_at(name, position),
+            position,
+        });
+    }
+    if let Some(instance) = instance {
+        if !unit_type.may_template() {
+            return Err(UnitNameError::TemplateNotSupported(unit_type));
+        }
+        let offset = prefix.len() + 1;
+        if let Some(index) = instance
+            .bytes()
+            .position(|b| b != b'@' && !is_prefix_char(b))
+        {
+            let position = offset + index;
+            return Err(UnitNameError::InvalidInstanceCharacter {
+                character: char_at(name, position),
+                position,
+            });
+        }
+    }
+
+    let unit = UnitName {
+        name: name.to_string(),
+    };
+    Ok(match instance {
+        None => ValidatedName::Plain(unit),
+        Some("") => ValidatedName::Template(unit),
+        Some(_) => ValidatedName::Instance(unit),
+    })
+}
+
+/// Return the character starting at (or containing) byte offset `position`.
+fn char_at(s: &str, position: usize) -> char {
+    let start = (0..=position)
+        .rev()
+        .find(|i| s.is_char_boundary(*i))
+        .unwrap_or(0);
+    s[start..].chars().next().unwrap_or_default()
+}
+
+fn is_prefix_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || PREFIX_EXTRA_CHARS.contains(&b)
+}
+
+impl FromStr for UnitName {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl fmt::Display for UnitName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl AsRef<str> for UnitName {
+    fn as_ref(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unit_name() {
+        let plain = UnitName::new("sshd.service").unwrap();
+        assert!(!plain.is_template());
+        assert!(!plain.is_instance());
+        assert_eq!(plain.prefix(), "sshd");
+        assert_eq!(plain.suffix(), "service");
+        assert_eq!(plain.unit_type(), UnitType::Service);
+        assert_eq!(plain.instance(), None);
+        assert_eq!(plain.template(), None);
+        plain.instantiate("foo").unwrap_err();
+
+        let template = UnitName::new("getty@.service").unwrap();
+        assert!(template.is_template());
+        assert!(!template.is_instance());
+        assert_eq!(template.prefix(), "getty");
+        assert_eq!(template.template().unwrap(), template);
+
+        let instance = UnitName::new("systemd-fsck@dev-disk-by\\x2duuid-1234.service").unwrap();
+        assert!(instance.is_instance());
+        assert!(!instance.is_template());
+        assert_eq!(instance.instance(), Some("dev-disk-by\\x2duuid-1234"));
+        assert_eq!(
+            instance.template().unwrap().as_str(),
+            "systemd-fsck@.service"
+        );
+
+        let nested = UnitName::new("foo@bar@baz.socket").unwrap();
+        assert_eq!(nested.prefix(), "foo");
+        assert_eq!(nested.instance(), Some("bar@baz"));
+    }
+
+    #[test]
+    fn test_unit_type() {
+        for unit_type in UnitType::ALL.iter() {
+            assert_eq!(
+                unit_type.to_string().parse::<UnitType>().unwrap(),
+                *unit_type
+            );
+        }
+        assert_eq!(
+            UnitType::from_unit_name("dev-sda.device").unwrap(),
+            UnitType::Device
+        );
+        assert_eq!(
+            UnitType::from_unit_name("foo@bar.socket").unwrap(),
+            UnitType::Socket
+        );
+        UnitType::from_unit_name("foo").unwrap_err();
+        "Service".parse::<UnitType>().unwrap_err();
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let unit = instantiate("foo@.service", "bar").unwrap();
+        assert_eq!(unit.to_string(), "foo@bar.service");
+        instantiate("foo@.service", "").unwrap_err();
+        instantiate("foo@.service", "with space").unwrap_err();
+        instantiate("foo@baz.service", "bar").unwrap_err();
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(matches!(
+            validate_name("foo.service"),
+            Ok(ValidatedName::Plain(_))
+        ));
+        assert!(matches!(
+            validate_name("foo@.socket"),
+            Ok(ValidatedName::Template(_))
+        ));
+        let instance = validate_name("foo@bar.timer").unwrap();
+        assert!(matches!(instance, ValidatedName::Instance(_)));
+        assert_eq!(instance.name().as_str(), "foo@bar.timer");
+
+        let long = format!("{}.service", "a".repeat(UNIT_NAME_MAX));
+        let cases = [
+            ("", UnitNameError::Empty),
+            (
+                long.as_str(),
+                UnitNameError::TooLong {
+                    len: UNIT_NAME_MAX + 8,
+                    max: UNIT_NAME_MAX,
+                },
+            ),
+            ("foo", UnitNameError::MissingSuffix),
+            (
+                "foo.Service",
+                UnitNameError::InvalidSuffix("Service".into()),
+            ),
+            ("@bar.service", UnitNameError::EmptyPrefix),
+            (
+                "foo bar.service",
+                UnitNameError::InvalidPrefixCharacter {
+                    character: ' ',
+                    position: 3,
+                },
+            ),
+            (
+                "foo@b\u{e9}r.service",
+                UnitNameError::InvalidInstanceCharacter {
+                    character: '\u{e9}',
+                    position: 5,
+                },
+            ),
+            (
+                "dev-sda@1.device",
+                UnitNameError::TemplateNotSupported(UnitType::Device),
+            ),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(validate_name(name).unwrap_err(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let long = format!("{}.service", "a".repeat(UNIT_NAME_MAX));
+        for name in &[
+            "",
+            "foo",
+            "foo.",
+            ".service",
+            "@bar.service",
+            "foo bar.service",
+            "foo.Service",
+            "foo.unknown",
+            long.as_str(),
+        ] {
+            UnitName::new(name).unwrap_err();
+        }
+    }
+}
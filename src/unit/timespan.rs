@@ -0,0 +1,236 @@
+use crate::errors::SdError;
+use std::fmt::Write;
+use std::time::Duration;
+
+const USEC_PER_MSEC: u64 = 1_000;
+const USEC_PER_SEC: u64 = 1_000_000;
+const USEC_PER_MINUTE: u64 = 60 * USEC_PER_SEC;
+const USEC_PER_HOUR: u64 = 60 * USEC_PER_MINUTE;
+const USEC_PER_DAY: u64 = 24 * USEC_PER_HOUR;
+const USEC_PER_WEEK: u64 = 7 * USEC_PER_DAY;
+const USEC_PER_MONTH: u64 = 2_629_800 * USEC_PER_SEC;
+const USEC_PER_YEAR: u64 = 31_557_600 * USEC_PER_SEC;
+
+/// Unit suffixes accepted when parsing, in matching order.
+const PARSE_UNITS: [(&str, u64); 30] = [
+    ("seconds", USEC_PER_SEC),
+    ("second", USEC_PER_SEC),
+    ("sec", USEC_PER_SEC),
+    ("s", USEC_PER_SEC),
+    ("minutes", USEC_PER_MINUTE),
+    ("minute", USEC_PER_MINUTE),
+    ("min", USEC_PER_MINUTE),
+    ("months", USEC_PER_MONTH),
+    ("month", USEC_PER_MONTH),
+    ("M", USEC_PER_MONTH),
+    ("msec", USEC_PER_MSEC),
+    ("ms", USEC_PER_MSEC),
+    ("m", USEC_PER_MINUTE),
+    ("hours", USEC_PER_HOUR),
+    ("hour", USEC_PER_HOUR),
+    ("hr", USEC_PER_HOUR),
+    ("h", USEC_PER_HOUR),
+    ("days", USEC_PER_DAY),
+    ("day", USEC_PER_DAY),
+    ("d", USEC_PER_DAY),
+    ("weeks", USEC_PER_WEEK),
+    ("week", USEC_PER_WEEK),
+    ("w", USEC_PER_WEEK),
+    ("years", USEC_PER_YEAR),
+    ("year", USEC_PER_YEAR),
+    ("y", USEC_PER_YEAR),
+    ("usec", 1),
+    ("us", 1),
+    ("\u{3bc}s", 1),
+    ("\u{b5}s", 1),
+];
+
+/// Unit suffixes used when formatting, from the largest.
+const FORMAT_UNITS: [(&str, u64); 9] = [
+    ("y", USEC_PER_YEAR),
+    ("month", USEC_PER_MONTH),
+    ("w", USEC_PER_WEEK),
+    ("d", USEC_PER_DAY),
+    ("h", USEC_PER_HOUR),
+    ("min", USEC_PER_MINUTE),
+    ("s", USEC_PER_SEC),
+    ("ms", USEC_PER_MSEC),
+    ("us", 1),
+];
+
+/// Parse a time span, like systemd's `parse_sec()`.
+///
+/// The input is a sequence of numbers (possibly with a fractional part),
+/// each followed by an optional unit, e.g. `1h 30min 10s` or `1.5h`.
+/// Numbers without a unit are seconds. The value is truncated to
+/// microseconds.
+///
+/// The special value `infinity` is returned as [`Duration::MAX`].
+pub fn parse_timespan(input: &str) -> Result<Duration, SdError> {
+    let input = input.trim();
+    if input == "infinity" {
+        return Ok(Duration::MAX);
+    }
+    let invalid = || SdError::from(format!("invalid time span '{}'", input));
+    if input.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut total: u64 = 0;
+    let mut rest = input;
+    while !rest.is_empty() {
+        let int_len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if int_len == 0 {
+            return Err(invalid());
+        }
+        let int: u64 = rest[..int_len].parse().map_err(|_| invalid())?;
+        rest = &rest[int_len..];
+        let mut frac = "";
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let frac_len = after_dot
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after_dot.len());
+            frac = &after_dot[..frac_len];
+            rest = &after_dot[frac_len..];
+        }
+
+        rest = rest.trim_start();
+        let multiplier = match PARSE_UNITS.iter().find(|(unit, _)| rest.starts_with(unit)) {
+            Some((unit, multiplier)) => {
+                rest = rest[unit.len()..].trim_start();
+                *multiplier
+            }
+            None => USEC_PER_SEC,
+        };
+
+        let mut value = int.checked_mul(multiplier).ok_or_else(invalid)?;
+        let mut scale = multiplier;
+        for digit in frac.bytes() {
+            scale /= 10;
+            value = u64::from(digit - b'0')
+                .checked_mul(scale)
+                .and_then(|frac| value.checked_add(frac))
+                .ok_or_else(invalid)?;
+        }
+        total = total
+            .checked_add(value)
+            .filter(|total| *total != u64::MAX)
+            .ok_or_else(invalid)?;
+    }
+
+    Ok(Duration::from_micros(total))
+}
+
+/// Format a time span, like systemd's `format_timespan()`.
+///
+/// The output uses multiple units (e.g. `1h 30min 10s`), and spans below a
+/// minute with a fractional part are formatted as decimals (e.g.
+/// `1.500000s`). Precision is truncated to microseconds, and durations
+/// beyond the representable range are formatted as `infinity`.
+pub fn format_timespan(span: Duration) -> String {
+    let mut usec = match u64::try_from(span.as_micros()) {
+        Ok(usec) if usec != u64::MAX => usec,
+        _ => return "infinity".to_string(),
+    };
+    if usec == 0 {
+        return "0".to_string();
+    }
+
+    let mut output = String::new();
+    for (unit, unit_usec) in FORMAT_UNITS {
+        if usec == 0 {
+            break;
+        }
+        if usec < unit_usec {
+            continue;
+        }
+        if !output.is_empty() {
+            output.push(' ');
+        }
+        let (whole, remainder) = (usec / unit_usec, usec % unit_usec);
+        if usec < USEC_PER_MINUTE && remainder > 0 {
+            let digits = unit_usec.to_string().len() - 1;
+            let _ = write!(
+                output,
+                "{}.{:0digits$}{}",
+                whole,
+                remainder,
+                unit,
+                digits = digits
+            );
+            break;
+        }
+        let _ = write!(output, "{}{}", whole, unit);
+        usec = remainder;
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_timespan() {
+        let cases = [
+            ("1h 30min 10s", 5_410_000_000),
+            ("90.5s", 90_500_000),
+            ("1.5h", 5_400_000_000),
+            ("2d", 172_800_000_000),
+            ("1y", 31_557_600_000_000),
+            ("1M", 2_629_800_000_000),
+            ("1w 2d", 777_600_000_000),
+            ("0", 0),
+            ("5", 5_000_000),
+            ("1500ms", 1_500_000),
+            ("1us", 1),
+            ("1.000001s", 1_000_001),
+            ("1min30.25s", 90_250_000),
+            (" 3 min ", 180_000_000),
+            ("1h30", 3_630_000_000),
+            ("1 h 2", 3_602_000_000),
+            ("59.9999999s", 59_999_999),
+            ("2\u{b5}s", 2),
+        ];
+        for (input, usec) in cases {
+            let span = parse_timespan(input).unwrap();
+            assert_eq!(span, Duration::from_micros(usec), "input: {}", input);
+        }
+        assert_eq!(parse_timespan("infinity").unwrap(), Duration::MAX);
+
+        for input in [
+            "",
+            "-1s",
+            "1.2.3s",
+            "1sx",
+            "s",
+            "99999999999y",
+            "18446744073709551.999ms",
+        ] {
+            parse_timespan(input).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_format_timespan() {
+        let cases = [
+            (5_410_000_000, "1h 30min 10s"),
+            (90_500_000, "1min 30.500000s"),
+            (5_400_000_000, "1h 30min"),
+            (2_629_800_000_000, "1month"),
+            (0, "0"),
+            (1_500_000, "1.500000s"),
+            (1_500, "1.500ms"),
+            (1, "1us"),
+            (61_000_000, "1min 1s"),
+            (8_640_000_000_000_000, "273y 9month 1w 5d 19h 30min"),
+        ];
+        for (usec, expected) in cases {
+            assert_eq!(format_timespan(Duration::from_micros(usec)), expected);
+        }
+        assert_eq!(format_timespan(Duration::MAX), "infinity");
+        assert_eq!(format_timespan(Duration::from_nanos(999)), "0");
+    }
+}
@@ -0,0 +1,403 @@
+use super::datetime::{days_in_month, local_zone_name, DateTime, USEC_PER_SEC, WEEKDAYS};
+use super::timespan::parse_timespan;
+use crate::errors::SdError;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Point in time, with microsecond precision, as used by systemd.
+///
+/// Timestamps can be parsed from the formats described in `systemd.time(7)`
+/// and accepted by e.g. `journalctl --since`:
+///
+///  * absolute times, like `Fri 2024-03-01 10:30:00 UTC`, `2024-03-01` or
+///    `10:30` (today);
+///  * `now`, `today`, `yesterday` and `tomorrow`;
+///  * time spans relative to now, like `-2h`, `+1d`, `2h ago` or `3min left`;
+///  * UNIX times, like `@1700000000`.
+///
+/// Absolute times are in local time, unless followed by `UTC`, by the name
+/// of the local timezone, or by a numeric UTC offset (e.g. `+04` or
+/// `-03:30`, as used by some timezones for their abbreviation).
+///
+/// `Display` formats timestamps in local time, like systemd does (e.g.
+/// `Fri 2024-03-01 11:30:00 CET`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    usec: u64,
+}
+
+impl Timestamp {
+    /// Create a timestamp from microseconds since the UNIX epoch.
+    pub fn from_micros(usec: u64) -> Self {
+        Self { usec }
+    }
+
+    /// Return the microseconds since the UNIX epoch.
+    pub fn as_micros(&self) -> u64 {
+        self.usec
+    }
+
+    /// Return the current time.
+    pub fn now() -> Self {
+        Self::try_from(SystemTime::now()).unwrap_or(Self { usec: 0 })
+    }
+
+    /// Parse a timestamp, resolving relative expressions against the current time.
+    pub fn parse(input: &str) -> Result<Self, SdError> {
+        Self::parse_relative_to(input, Self::now())
+    }
+
+    /// Parse a timestamp, resolving relative expressions against `now`.
+    pub fn parse_relative_to(input: &str, now: Timestamp) -> Result<Self, SdError> {
+        Self::parse_inner(input.trim(), now)
+            .map_err(|e| format!("invalid timestamp '{}': {}", input, e.msg).into())
+    }
+
+    fn parse_inner(input: &str, now: Timestamp) -> Result<Self, SdError> {
+        let relative = |span: &str, forward: bool| -> Result<Self, SdError> {
+            let span = u64::try_from(parse_timespan(span)?.as_micros())
+                .map_err(|_| SdError::from("time span out of range"))?;
+            let usec = if forward {
+                now.usec.checked_add(span)
+            } else {
+                now.usec.checked_sub(span)
+            };
+            usec.map(Self::from_micros)
+                .ok_or_else(|| "timestamp out of range".into())
+        };
+
+        match input {
+            "" => return Err("empty timestamp".into()),
+            "now" => return Ok(now),
+            "today" => return now.local_midnight(0),
+            "yesterday" => return now.local_midnight(-1),
+            "tomorrow" => return now.local_midnight(1),
+            _ => {}
+        }
+        if let Some(rest) = input.strip_prefix('@') {
+            if !rest.starts_with(|c: char| c.is_ascii_digit())
+                || !rest.chars().all(|c| c.is_ascii_digit() || c == '.')
+            {
+                return Err(format!("invalid UNIX time '{}'", rest).into());
+            }
+            return u64::try_from(parse_timespan(rest)?.as_micros())
+                .map(Self::from_micros)
+                .map_err(|_| "UNIX time out of range".into());
+        }
+        if let Some(rest) = input.strip_prefix('+') {
+            return relative(rest, true);
+        }
+        if let Some(rest) = input.strip_prefix('-') {
+            return relative(rest, false);
+        }
+        if let Some(rest) = input.strip_suffix(" ago") {
+            return relative(rest, false);
+        }
+        if let Some(rest) = input.strip_suffix(" left") {
+            return relative(rest, true);
+        }
+        Self::parse_absolute(input, now)
+    }
+
+    /// Parse `[weekday] [date] [time] [zone]`.
+    fn parse_absolute(input: &str, now: Timestamp) -> Result<Self, SdError> {
+        let mut words: Vec<&str> = input.split_whitespace().collect();
+        let mut zone = None;
+        let mut offset = None;
+        if let Some(last) = words.last() {
+            if words.len() > 1 {
+                offset = parse_utc_offset(last);
+                if offset.is_some() || last.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    zone = Some(*last);
+                    words.pop();
+                }
+            }
+        }
+        // Explicit offsets are handled by shifting the UTC time.
+        let utc = offset.is_some() || zone.map_or(false, |zone| zone.eq_ignore_ascii_case("UTC"));
+        let offset = offset.unwrap_or(0);
+
+        let mut words = words.into_iter().peekable();
+        let weekday = match words.next_if(|w| w.starts_with(|c: char| c.is_ascii_alphabetic())) {
+            Some(word) => Some(parse_weekday(word)?),
+            None => None,
+        };
+        let date = words.next_if(|w| !w.contains(':'));
+        let time = words.next();
+        if let Some(word) = words.next() {
+            return Err(format!("unexpected '{}'", word).into());
+        }
+        if date.is_none() && time.is_none() {
+            return Err("missing date or time".into());
+        }
+
+        let mut dt = match date {
+            Some(date) => parse_date(date)?,
+            None => {
+                let today = DateTime::from_unix(now.usec as i64 + offset, utc)
+                    .ok_or("current time out of range")?;
+                DateTime::date(today.year, today.month, today.day)
+            }
+        };
+        if let Some(time) = time {
+            parse_time(time, &mut dt)?;
+        }
+        if let Some(weekday) = weekday {
+            if weekday != dt.weekday() {
+                return Err("weekday does not match date".into());
+            }
+        }
+
+        let usec = dt
+            .to_unix(utc)
+            .and_then(|usec| usec.checked_sub(offset))
+            .and_then(|usec| u64::try_from(usec).ok())
+            .ok_or("time out of range or nonexistent")?;
+        let timestamp = Self::from_micros(usec);
+        if let Some(zone) = zone {
+            if !utc && local_zone_name(usec as i64).as_deref() != Some(zone) {
+                return Err(format!("unsupported timezone '{}'", zone).into());
+            }
+        }
+        Ok(timestamp)
+    }
+
+    /// Local midnight, `days` days away from this timestamp.
+    fn local_midnight(&self, days: i64) -> Result<Self, SdError> {
+        DateTime::from_unix(self.usec as i64, false)
+            .map(|dt| dt.add_days(days))
+            .and_then(|dt| dt.to_unix(false))
+            .and_then(|usec| u64::try_from(usec).ok())
+            .map(Self::from_micros)
+            .ok_or_else(|| "timestamp out of range".into())
+    }
+
+    /// Format this timestamp in UTC, e.g. `Fri 2024-03-01 10:30:00 UTC`.
+    pub fn format_utc(&self) -> String {
+        self.format(true)
+    }
+
+    fn format(&self, utc: bool) -> String {
+        let usec = self.usec as i64;
+        let dt = match DateTime::from_unix(usec, utc) {
+            Some(dt) => dt,
+            None => return format!("@{}", self.usec / USEC_PER_SEC as u64),
+        };
+        let zone = if utc {
+            Some("UTC".to_string())
+        } else {
+            local_zone_name(usec)
+        };
+        let mut output = format!(
+            "{} {:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            WEEKDAYS[dt.weekday() as usize].0,
+            dt.year,
+            dt.month,
+            dt.day,
+            dt.hour,
+            dt.minute,
+            dt.second
+        );
+        if let Some(zone) = zone {
+            output.push(' ');
+            output.push_str(&zone);
+        }
+        output
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format(false))
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<SystemTime> for Timestamp {
+    type Error = SdError;
+
+    fn try_from(value: SystemTime) -> Result<Self, Self::Error> {
+        let elapsed = value
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| "time before the UNIX epoch")?;
+        let usec = u64::try_from(elapsed.as_micros()).map_err(|_| "time out of range")?;
+        Ok(Self::from_micros(usec))
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(value: Timestamp) -> Self {
+        UNIX_EPOCH + Duration::from_micros(value.usec)
+    }
+}
+
+/// Parse a numeric UTC offset (`+HH`, `+HHMM` or `+HH:MM`), in microseconds.
+fn parse_utc_offset(zone: &str) -> Option<i64> {
+    let (sign, rest) = match zone.split_at(zone.len().min(1)) {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    if !rest.bytes().all(|b| b.is_ascii_digit() || b == b':') {
+        return None;
+    }
+    let (hours, minutes) = match (rest.len(), rest.split_once(':')) {
+        (2, None) => (rest, "00"),
+        (4, None) => rest.split_at(2),
+        (5, Some((hours, minutes))) if hours.len() == 2 => (hours, minutes),
+        _ => return None,
+    };
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60) * USEC_PER_SEC)
+}
+
+fn parse_weekday(name: &str) -> Result<i64, SdError> {
+    let lower = name.to_ascii_lowercase();
+    WEEKDAYS
+        .iter()
+        .position(|(short, long)| lower == short.to_ascii_lowercase() || lower == *long)
+        .map(|index| index as i64)
+        .ok_or_else(|| format!("invalid weekday '{}'", name).into())
+}
+
+fn parse_number(input: &str, min: i64, max: i64) -> Result<i64, SdError> {
+    input
+        .parse::<i64>()
+        .ok()
+        .filter(|value| !input.starts_with('+') && (min..=max).contains(value))
+        .ok_or_else(|| format!("invalid value '{}'", input).into())
+}
+
+/// Parse `YYYY-MM-DD`, with two-digit years mapped to 1970-2069.
+fn parse_date(input: &str) -> Result<DateTime, SdError> {
+    let fields: Vec<&str> = input.split('-').collect();
+    let (year, month, day) = match fields.as_slice() {
+        [year, month, day] => (year, month, day),
+        _ => return Err(format!("invalid date '{}'", input).into()),
+    };
+    let mut year_value = parse_number(year, 0, 9999)?;
+    if year.len() <= 2 {
+        year_value += if year_value < 70 { 2000 } else { 1900 };
+    }
+    let month = parse_number(month, 1, 12)?;
+    let day = parse_number(day, 1, days_in_month(year_value, month))?;
+    Ok(DateTime::date(year_value, month, day))
+}
+
+/// Parse `HH:MM[:SS[.ffffff]]`.
+fn parse_time(input: &str, dt: &mut DateTime) -> Result<(), SdError> {
+    let fields: Vec<&str> = input.split(':').collect();
+    let (hour, minute, second) = match fields.as_slice() {
+        [hour, minute] => (hour, minute, None),
+        [hour, minute, second] => (hour, minute, Some(second)),
+        _ => return Err(format!("invalid time '{}'", input).into()),
+    };
+    dt.hour = parse_number(hour, 0, 23)?;
+    dt.minute = parse_number(minute, 0, 59)?;
+    if let Some(second) = second {
+        let (whole, frac) = match second.split_once('.') {
+            Some((whole, frac)) => (whole, frac),
+            None => (*second, ""),
+        };
+        dt.second = parse_number(whole, 0, 60)?;
+        if !frac.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("invalid seconds '{}'", second).into());
+        }
+        let mut scale = USEC_PER_SEC;
+        for digit in frac.bytes() {
+            scale /= 10;
+            dt.usec += i64::from(digit - b'0') * scale;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Fri 2026-10-16 12:08:05.5 UTC
+    const NOW: Timestamp = Timestamp {
+        usec: 1_792_152_485_500_000,
+    };
+
+    fn parse(input: &str) -> u64 {
+        Timestamp::parse_relative_to(input, NOW)
+            .unwrap()
+            .as_micros()
+    }
+
+    #[test]
+    fn test_parse_absolute() {
+        assert_eq!(parse("Fri 2024-03-01 10:30:00 UTC"), 1_709_289_000_000_000);
+        assert_eq!(parse("2024-03-01 10:30:00.5 UTC"), 1_709_289_000_500_000);
+        assert_eq!(parse("24-03-01 10:30 UTC"), 1_709_289_000_000_000);
+        assert_eq!(parse("2024-03-01 UTC"), 1_709_251_200_000_000);
+        assert_eq!(parse("10:30 UTC"), 1_792_146_600_000_000);
+        assert_eq!(parse("friday 2024-03-01 10:30 utc"), 1_709_289_000_000_000);
+        assert_eq!(parse("Fri 2024-03-01 14:30:00 +04"), 1_709_289_000_000_000);
+        assert_eq!(parse("2024-03-01 07:00 -03:30"), 1_709_289_000_000_000);
+        assert_eq!(parse("2024-03-01 16:00 +0530"), 1_709_289_000_000_000);
+
+        for input in [
+            "",
+            "Thu 2024-03-01 UTC",
+            "2024-03-01 25:00",
+            "2024-02-30",
+            "2024-03-01T10:30:00 UTC",
+            "2024-03-01 10:30:00 XYZ",
+            "2024-03-01 10:30:00 +4",
+            "2024-03-01 10:30:00 +24",
+            "10",
+        ] {
+            Timestamp::parse_relative_to(input, NOW).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_parse_relative() {
+        assert_eq!(parse("now"), NOW.as_micros());
+        assert_eq!(parse("@1700000000"), 1_700_000_000_000_000);
+        assert_eq!(parse("@1700000000.25"), 1_700_000_000_250_000);
+        assert_eq!(parse("-2h"), NOW.as_micros() - 7_200_000_000);
+        assert_eq!(parse("2h ago"), NOW.as_micros() - 7_200_000_000);
+        assert_eq!(parse("+1d"), NOW.as_micros() + 86_400_000_000);
+        assert_eq!(parse("3min left"), NOW.as_micros() + 180_000_000);
+
+        let today = parse("today");
+        assert_eq!(parse("yesterday"), today - 86_400_000_000);
+        assert!(today <= NOW.as_micros());
+        assert!(parse("tomorrow") > NOW.as_micros());
+
+        assert_eq!(
+            Timestamp::parse_relative_to("@1", Timestamp::from_micros(0))
+                .unwrap()
+                .as_micros(),
+            1_000_000
+        );
+        Timestamp::parse_relative_to("@1h", NOW).unwrap_err();
+        Timestamp::parse_relative_to("-1y", Timestamp::from_micros(0)).unwrap_err();
+    }
+
+    #[test]
+    fn test_format() {
+        let ts = Timestamp::from_micros(1_709_289_000_500_000);
+        assert_eq!(ts.format_utc(), "Fri 2024-03-01 10:30:00 UTC");
+        let parsed = Timestamp::parse(&ts.to_string()).unwrap();
+        assert_eq!(parsed.as_micros(), 1_709_289_000_000_000);
+
+        let time = SystemTime::from(ts);
+        assert_eq!(Timestamp::try_from(time).unwrap(), ts);
+    }
+}
@@ -0,0 +1,595 @@
+use crate::errors::SdError;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fmt, fs};
+
+/// Virtualization technology, as reported by `systemd-detect-virt`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Virtualization {
+    /// No virtualization detected.
+    None,
+    // Virtual machines.
+    Kvm,
+    Amazon,
+    Qemu,
+    Bochs,
+    Xen,
+    Uml,
+    Vmware,
+    Oracle,
+    Microsoft,
+    Zvm,
+    Parallels,
+    Bhyve,
+    Qnx,
+    Acrn,
+    PowerVm,
+    Apple,
+    Sre,
+    Google,
+    /// Unknown virtual machine.
+    VmOther,
+    // Containers.
+    SystemdNspawn,
+    LxcLibvirt,
+    Lxc,
+    Openvz,
+    Docker,
+    Podman,
+    Rkt,
+    Wsl,
+    Proot,
+    Pouch,
+    /// Unknown container.
+    ContainerOther,
+}
+
+/// Mapping between virtualization technologies and their `systemd-detect-virt` names.
+const NAMES: &[(Virtualization, &str)] = &[
+    (Virtualization::None, "none"),
+    (Virtualization::Kvm, "kvm"),
+    (Virtualization::Amazon, "amazon"),
+    (Virtualization::Qemu, "qemu"),
+    (Virtualization::Bochs, "bochs"),
+    (Virtualization::Xen, "xen"),
+    (Virtualization::Uml, "uml"),
+    (Virtualization::Vmware, "vmware"),
+    (Virtualization::Oracle, "oracle"),
+    (Virtualization::Microsoft, "microsoft"),
+    (Virtualization::Zvm, "zvm"),
+    (Virtualization::Parallels, "parallels"),
+    (Virtualization::Bhyve, "bhyve"),
+    (Virtualization::Qnx, "qnx"),
+    (Virtualization::Acrn, "acrn"),
+    (Virtualization::PowerVm, "powervm"),
+    (Virtualization::Apple, "apple"),
+    (Virtualization::Sre, "sre"),
+    (Virtualization::Google, "google"),
+    (Virtualization::VmOther, "vm-other"),
+    (Virtualization::SystemdNspawn, "systemd-nspawn"),
+    (Virtualization::LxcLibvirt, "lxc-libvirt"),
+    (Virtualization::Lxc, "lxc"),
+    (Virtualization::Openvz, "openvz"),
+    (Virtualization::Docker, "docker"),
+    (Virtualization::Podman, "podman"),
+    (Virtualization::Rkt, "rkt"),
+    (Virtualization::Wsl, "wsl"),
+    (Virtualization::Proot, "proot"),
+    (Virtualization::Pouch, "pouch"),
+    (Virtualization::ContainerOther, "container-other"),
+];
+
+impl Virtualization {
+    /// Return the name used by `systemd-detect-virt` for this technology.
+    pub fn as_str(&self) -> &'static str {
+        NAMES
+            .iter()
+            .find(|(virt, _)| virt == self)
+            .map(|(_, name)| *name)
+            .unwrap_or("none")
+    }
+
+    /// Return whether this is a virtual machine technology.
+    pub fn is_vm(&self) -> bool {
+        !self.is_none() && !self.is_container()
+    }
+
+    /// Return whether this is a container technology.
+    pub fn is_container(&self) -> bool {
+        matches!(
+            self,
+            Virtualization::SystemdNspawn
+                | Virtualization::LxcLibvirt
+                | Virtualization::Lxc
+                | Virtualization::Openvz
+                | Virtualization::Docker
+                | Virtualization::Podman
+                | Virtualization::Rkt
+                | Virtualization::Wsl
+                | Virtualization::Proot
+                | Virtualization::Pouch
+                | Virtualization::ContainerOther
+        )
+    }
+
+    /// Return whether no virtualization was detected.
+    pub fn is_none(&self) -> bool {
+        *self == Virtualization::None
+    }
+}
+
+impl fmt::Display for Virtualization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Virtualization {
+    type Err = SdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(virt, _)| *virt)
+            .ok_or_else(|| format!("unknown virtualization technology '{}'", s).into())
+    }
+}
+
+/// Detect the virtualization technology in use, like `systemd-detect-virt`.
+///
+/// Containers take precedence over virtual machines, as a container
+/// may run inside a virtual machine.
+pub fn detect() -> Virtualization {
+    match detect_container() {
+        Virtualization::None => detect_vm(),
+        container => container,
+    }
+}
+
+/// Detect whether we run inside a virtual machine, like `systemd-detect-virt --vm`.
+///
+/// This inspects DMI vendor strings, CPUID hypervisor information,
+/// the device tree and other hypervisor-specific interfaces.
+pub fn detect_vm() -> Virtualization {
+    // Some hypervisors are better identified through DMI, even if they use
+    // KVM under the hood or cloak as another hypervisor in CPUID.
+    let dmi = detect_vm_dmi();
+    if matches!(
+        dmi,
+        Virtualization::Oracle
+            | Virtualization::Xen
+            | Virtualization::Amazon
+            | Virtualization::Parallels
+    ) {
+        return dmi;
+    }
+
+    let mut other = false;
+    let detectors: [fn() -> Virtualization; 3] = [detect_vm_uml, detect_vm_xen, detect_vm_cpuid];
+    for detector in detectors {
+        match detector() {
+            Virtualization::None => {}
+            Virtualization::VmOther => other = true,
+            virt => return virt,
+        }
+    }
+
+    match dmi {
+        Virtualization::None => {}
+        Virtualization::VmOther => other = true,
+        virt => return virt,
+    }
+
+    let detectors: [fn() -> Virtualization; 3] =
+        [detect_vm_device_tree, detect_vm_hypervisor, detect_vm_zvm];
+    for detector in detectors {
+        match detector() {
+            Virtualization::None => {}
+            Virtualization::VmOther => other = true,
+            virt => return virt,
+        }
+    }
+
+    if other {
+        Virtualization::VmOther
+    } else {
+        Virtualization::None
+    }
+}
+
+/// Detect whether we run inside a container, like `systemd-detect-virt --container`.
+///
+/// This inspects markers left by container managers, the environment
+/// of PID 1 and cgroup hints.
+pub fn detect_container() -> Virtualization {
+    // OpenVZ exposes `/proc/vz` in containers, and `/proc/bc` on the host.
+    if Path::new("/proc/vz").exists() && !Path::new("/proc/bc").exists() {
+        return Virtualization::Openvz;
+    }
+
+    if let Ok(osrelease) = fs::read_to_string("/proc/sys/kernel/osrelease") {
+        if osrelease.contains("Microsoft") || osrelease.contains("WSL") {
+            return Virtualization::Wsl;
+        }
+    }
+
+    if is_traced_by_proot() {
+        return Virtualization::Proot;
+    }
+
+    let manager = read_trimmed("/run/host/container-manager")
+        .or_else(|| read_trimmed("/run/systemd/container"))
+        .or_else(container_from_pid1_env);
+    if let Some(name) = manager {
+        return container_from_name(&name);
+    }
+
+    match detect_container_files() {
+        Virtualization::None => fs::read_to_string("/proc/1/cgroup")
+            .map(|content| container_from_cgroup(&content))
+            .unwrap_or(Virtualization::None),
+        virt => virt,
+    }
+}
+
+/// Read a file and return its content without surrounding whitespace, if not empty.
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_string())
+    }
+}
+
+/// Read the `container` variable from the environment of PID 1, if accessible.
+fn container_from_pid1_env() -> Option<String> {
+    let environ = fs::read("/proc/1/environ").ok()?;
+    environ
+        .split(|b| *b == 0)
+        .find_map(|var| var.strip_prefix(b"container="))
+        .filter(|value| !value.is_empty())
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+/// Check whether the current process is being traced by `proot`.
+fn is_traced_by_proot() -> bool {
+    let status = match fs::read_to_string("/proc/self/status") {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let tracer = status
+        .lines()
+        .find_map(|line| line.strip_prefix("TracerPid:"))
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+        .filter(|pid| *pid != 0);
+
+    match tracer {
+        Some(pid) => read_trimmed(format!("/proc/{}/comm", pid)).as_deref() == Some("proot"),
+        None => false,
+    }
+}
+
+/// Detect containers from marker files left by container engines.
+fn detect_container_files() -> Virtualization {
+    if Path::new("/run/.containerenv").exists() {
+        Virtualization::Podman
+    } else if Path::new("/.dockerenv").exists() {
+        Virtualization::Docker
+    } else {
+        Virtualization::None
+    }
+}
+
+/// Map the name advertised by a container manager to a container technology.
+fn container_from_name(name: &str) -> Virtualization {
+    match name {
+        // Generic OCI runtimes, try to narrow them down.
+        "oci" => match detect_container_files() {
+            Virtualization::None => Virtualization::ContainerOther,
+            virt => virt,
+        },
+        _ => match name.parse::<Virtualization>() {
+            Ok(virt) if virt.is_container() => virt,
+            _ => Virtualization::ContainerOther,
+        },
+    }
+}
+
+/// Guess the container technology from the content of `/proc/1/cgroup`.
+fn container_from_cgroup(content: &str) -> Virtualization {
+    for line in content.lines() {
+        let path = match line.splitn(3, ':').nth(2) {
+            Some(p) => p,
+            None => continue,
+        };
+        if path.contains("/docker/") || path.contains("/docker-") {
+            return Virtualization::Docker;
+        }
+        if path.contains("/libpod-") || path.contains("/machine.slice/libpod") {
+            return Virtualization::Podman;
+        }
+        if path.contains("/lxc.payload") || path.starts_with("/lxc/") {
+            return Virtualization::Lxc;
+        }
+        if path.contains("/kubepods") {
+            return Virtualization::ContainerOther;
+        }
+    }
+    Virtualization::None
+}
+
+/// Detect hypervisors from DMI vendor strings.
+fn detect_vm_dmi() -> Virtualization {
+    const DMI_FILES: &[&str] = &[
+        "/sys/class/dmi/id/product_name",
+        "/sys/class/dmi/id/sys_vendor",
+        "/sys/class/dmi/id/board_vendor",
+        "/sys/class/dmi/id/bios_vendor",
+        "/sys/class/dmi/id/product_version",
+    ];
+
+    DMI_FILES
+        .iter()
+        .filter_map(read_trimmed)
+        .map(|vendor| vm_from_dmi_vendor(&vendor))
+        .find(|virt| !virt.is_none())
+        .unwrap_or(Virtualization::None)
+}
+
+/// Map a DMI vendor string to a hypervisor.
+fn vm_from_dmi_vendor(vendor: &str) -> Virtualization {
+    const DMI_VENDORS: &[(&str, Virtualization)] = &[
+        ("KVM", Virtualization::Kvm),
+        ("OpenStack", Virtualization::Kvm),
+        ("KubeVirt", Virtualization::Kvm),
+        ("Amazon EC2", Virtualization::Amazon),
+        ("QEMU", Virtualization::Qemu),
+        ("VMware", Virtualization::Vmware),
+        ("VMW", Virtualization::Vmware),
+        ("innotek GmbH", Virtualization::Oracle),
+        ("VirtualBox", Virtualization::Oracle),
+        ("Oracle Corporation", Virtualization::Oracle),
+        ("Xen", Virtualization::Xen),
+        ("Bochs", Virtualization::Bochs),
+        ("Parallels", Virtualization::Parallels),
+        ("BHYVE", Virtualization::Bhyve),
+        ("Hyper-V", Virtualization::Microsoft),
+        ("Apple Virtualization", Virtualization::Apple),
+        ("Google Compute Engine", Virtualization::Google),
+    ];
+
+    DMI_VENDORS
+        .iter()
+        .find(|(prefix, _)| vendor.starts_with(prefix))
+        .map(|(_, virt)| *virt)
+        .unwrap_or(Virtualization::None)
+}
+
+/// Detect hypervisors from the CPUID hypervisor leaf.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[allow(unused_unsafe)]
+fn detect_vm_cpuid() -> Virtualization {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::__cpuid;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::__cpuid;
+
+    // SAFETY: CPUID is available on all x86_64 CPUs and all
+    // x86 ones able to run a modern Linux kernel.
+    let features = unsafe { __cpuid(1) };
+    // Bit 31 of ECX is reserved for use by hypervisors.
+    if features.ecx & (1 << 31) == 0 {
+        return Virtualization::None;
+    }
+
+    let leaf = unsafe { __cpuid(0x4000_0000) };
+    let mut signature = [0u8; 12];
+    signature[0..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+    signature[4..8].copy_from_slice(&leaf.ecx.to_le_bytes());
+    signature[8..12].copy_from_slice(&leaf.edx.to_le_bytes());
+    vm_from_cpuid_signature(&signature)
+}
+
+/// Detect hypervisors from the CPUID hypervisor leaf.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn detect_vm_cpuid() -> Virtualization {
+    Virtualization::None
+}
+
+/// Map a CPUID hypervisor vendor signature to a hypervisor.
+#[cfg_attr(
+    not(any(target_arch = "x86", target_arch = "x86_64")),
+    allow(dead_code)
+)]
+fn vm_from_cpuid_signature(signature: &[u8; 12]) -> Virtualization {
+    const CPUID_VENDORS: &[(&[u8], Virtualization)] = &[
+        (b"XenVMMXenVMM", Virtualization::Xen),
+        (b"KVMKVMKVM", Virtualization::Kvm),
+        (b"Linux KVM Hv", Virtualization::Kvm),
+        (b"TCGTCGTCGTCG", Virtualization::Qemu),
+        (b"VMwareVMware", Virtualization::Vmware),
+        (b"Microsoft Hv", Virtualization::Microsoft),
+        (b"bhyve bhyve ", Virtualization::Bhyve),
+        (b"QNXQVMBSQG", Virtualization::Qnx),
+        (b"ACRNACRNACRN", Virtualization::Acrn),
+        (b"SRESRESRESRE", Virtualization::Sre),
+        (b"Apple VZ", Virtualization::Apple),
+    ];
+
+    // Shorter signatures are padded with NUL bytes.
+    let len = signature
+        .iter()
+        .rposition(|b| *b != 0)
+        .map(|pos| pos + 1)
+        .unwrap_or(0);
+    let signature = &signature[..len];
+
+    CPUID_VENDORS
+        .iter()
+        .find(|(vendor, _)| *vendor == signature)
+        .map(|(_, virt)| *virt)
+        .unwrap_or(Virtualization::VmOther)
+}
+
+/// Detect User-Mode Linux from CPU information.
+fn detect_vm_uml() -> Virtualization {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let is_uml = cpuinfo.lines().any(|line| {
+        line.strip_prefix("vendor_id")
+            .and_then(|rest| rest.split_once(':'))
+            .map(|(_, vendor)| vendor.trim() == "User Mode Linux")
+            .unwrap_or(false)
+    });
+    if is_uml {
+        Virtualization::Uml
+    } else {
+        Virtualization::None
+    }
+}
+
+/// Detect Xen guests from the Xen-specific procfs interface.
+fn detect_vm_xen() -> Virtualization {
+    let capabilities = match fs::read_to_string("/proc/xen/capabilities") {
+        Ok(c) => c,
+        Err(_) => return Virtualization::None,
+    };
+    // The control domain (dom0) is the virtualization host, not a guest.
+    if capabilities.split(',').any(|cap| cap.trim() == "control_d") {
+        Virtualization::None
+    } else {
+        Virtualization::Xen
+    }
+}
+
+/// Detect hypervisors advertised through the device tree.
+fn detect_vm_device_tree() -> Virtualization {
+    if let Ok(compatible) = fs::read("/proc/device-tree/hypervisor/compatible") {
+        let compatible = compatible.split(|b| *b == 0).next().unwrap_or_default();
+        return match compatible {
+            b"linux,kvm" => Virtualization::Kvm,
+            b"xen" => Virtualization::Xen,
+            b"vmware" => Virtualization::Vmware,
+            _ => Virtualization::VmOther,
+        };
+    }
+
+    let device_tree = Path::new("/proc/device-tree");
+    if device_tree.join("ibm,partition-name").exists()
+        && device_tree.join("hmc-managed?").exists()
+        && !device_tree.join("chosen/qemu,graphic-width").exists()
+    {
+        return Virtualization::PowerVm;
+    }
+
+    let has_fw_cfg = fs::read_dir(device_tree)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .any(|entry| entry.file_name().to_string_lossy().starts_with("fw-cfg"))
+        })
+        .unwrap_or(false);
+    if has_fw_cfg {
+        return Virtualization::Qemu;
+    }
+
+    Virtualization::None
+}
+
+/// Detect hypervisors advertised through sysfs.
+fn detect_vm_hypervisor() -> Virtualization {
+    match read_trimmed("/sys/hypervisor/type").as_deref() {
+        Some("xen") => Virtualization::Xen,
+        Some(_) => Virtualization::VmOther,
+        None => Virtualization::None,
+    }
+}
+
+/// Detect z/VM and KVM guests on s390x.
+fn detect_vm_zvm() -> Virtualization {
+    let sysinfo = match fs::read_to_string("/proc/sysinfo") {
+        Ok(s) => s,
+        Err(_) => return Virtualization::None,
+    };
+    let control_program = sysinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("VM00 Control Program:"))
+        .map(str::trim);
+
+    match control_program {
+        Some(cp) if cp.contains("z/VM") => Virtualization::Zvm,
+        Some(_) => Virtualization::Kvm,
+        None => Virtualization::None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_names_roundtrip() {
+        for (virt, name) in NAMES {
+            assert_eq!(virt.as_str(), *name);
+            assert_eq!(virt.to_string(), *name);
+            assert_eq!(name.parse::<Virtualization>().unwrap(), *virt);
+            assert!(!(virt.is_vm() && virt.is_container()));
+        }
+        assert!(Virtualization::Kvm.is_vm());
+        assert!(Virtualization::Docker.is_container());
+        assert!(!Virtualization::None.is_vm());
+        assert!(!Virtualization::None.is_container());
+        "vbox".parse::<Virtualization>().unwrap_err();
+    }
+
+    #[test]
+    fn test_vm_identifiers() {
+        assert_eq!(
+            vm_from_cpuid_signature(b"KVMKVMKVM\0\0\0"),
+            Virtualization::Kvm
+        );
+        assert_eq!(
+            vm_from_cpuid_signature(b"Microsoft Hv"),
+            Virtualization::Microsoft
+        );
+        assert_eq!(
+            vm_from_cpuid_signature(b"Apple VZ\0\0\0\0"),
+            Virtualization::Apple
+        );
+        assert_eq!(
+            vm_from_cpuid_signature(b"NewVendorSig"),
+            Virtualization::VmOther
+        );
+
+        assert_eq!(vm_from_dmi_vendor("QEMU"), Virtualization::Qemu);
+        assert_eq!(vm_from_dmi_vendor("Amazon EC2"), Virtualization::Amazon);
+        assert_eq!(vm_from_dmi_vendor("innotek GmbH"), Virtualization::Oracle);
+        assert_eq!(vm_from_dmi_vendor("Dell Inc."), Virtualization::None);
+    }
+
+    #[test]
+    fn test_container_identifiers() {
+        assert_eq!(
+            container_from_name("systemd-nspawn"),
+            Virtualization::SystemdNspawn
+        );
+        assert_eq!(
+            container_from_name("lxc-libvirt"),
+            Virtualization::LxcLibvirt
+        );
+        assert_eq!(container_from_name("kvm"), Virtualization::ContainerOther);
+        assert_eq!(
+            container_from_name("unknown"),
+            Virtualization::ContainerOther
+        );
+
+        let docker = "12:pids:/docker/0123456789abcdef\n0::/docker/0123456789abcdef\n";
+        assert_eq!(container_from_cgroup(docker), Virtualization::Docker);
+        let lxc = "0::/lxc.payload.test/init.scope\n";
+        assert_eq!(container_from_cgroup(lxc), Virtualization::Lxc);
+        let host = "0::/init.scope\n";
+        assert_eq!(container_from_cgroup(host), Virtualization::None);
+    }
+}
@@ -0,0 +1,20 @@
+#![cfg(feature = "tokio")]
+
+use std::env;
+
+use libsystemd::daemon::{notify_async, notify_listener, NotifyState, NotifyTarget};
+
+/// Async notifications must reach the socket referenced by `$NOTIFY_SOCKET`.
+///
+/// This is the only test in this binary, as it changes the process environment.
+#[tokio::test]
+async fn test_notify_async_from_env() {
+    let name = format!("libsystemd-rs-notify-async-{}", std::process::id());
+    let listener = notify_listener(NotifyTarget::Abstract(name.as_bytes())).unwrap();
+
+    env::set_var("NOTIFY_SOCKET", format!("@{}", name));
+    assert!(notify_async(true, &[NotifyState::Stopping]).await.unwrap());
+    assert_eq!(listener.recv().unwrap().state(), &[NotifyState::Stopping]);
+    assert!(env::var_os("NOTIFY_SOCKET").is_none());
+    assert!(!notify_async(false, &[NotifyState::Ready]).await.unwrap());
+}
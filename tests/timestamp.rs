@@ -0,0 +1,34 @@
+use std::env;
+
+use libsystemd::unit::Timestamp;
+
+extern "C" {
+    fn tzset();
+}
+
+/// Formatted timestamps must parse back in any local timezone, including
+/// ones using numeric abbreviations (e.g. `Asia/Dubai` or `America/Sao_Paulo`).
+///
+/// This is the only test in this binary, as it changes the process timezone.
+#[test]
+fn test_timestamp_roundtrip_timezones() {
+    let ts = Timestamp::from_micros(1_709_289_000_000_000);
+    // POSIX TZ rules, not depending on tzdata being installed.
+    let zones = [
+        ("UTC0", "Fri 2024-03-01 10:30:00 UTC"),
+        ("<+04>-4", "Fri 2024-03-01 14:30:00 +04"),
+        ("<-03>3", "Fri 2024-03-01 07:30:00 -03"),
+        ("<+0530>-5:30", "Fri 2024-03-01 16:00:00 +0530"),
+        ("CET-1CEST,M3.5.0,M10.5.0/3", "Fri 2024-03-01 11:30:00 CET"),
+    ];
+    for (tz, expected) in zones {
+        env::set_var("TZ", tz);
+        // SAFETY: no other thread is running in this test binary.
+        unsafe { tzset() };
+
+        let formatted = ts.to_string();
+        assert_eq!(formatted, expected, "TZ={}", tz);
+        let parsed = Timestamp::parse(&formatted).unwrap();
+        assert_eq!(parsed, ts, "TZ={}", tz);
+    }
+}